@@ -0,0 +1,43 @@
+//! Times tokenizing, parsing and evaluating a generated script, with `cargo bench`.
+//! It has no dependencies, so the numbers are rough; compare them between runs on the same machine.
+
+use std::time::Instant;
+
+use pale::{Interpreter, Options, TokenStream};
+
+// A script with lots of small statements and calls, since that is where the allocations add up.
+fn script(functions: usize) -> String {
+    let mut source = String::new();
+    for i in 0..functions {
+        source.push_str(&format!(
+            "(let ((f{i} (lambda (a b) + a (* b 2) (- a b))))\n  list (f{i} 1 2) (f{i} 3 4) [\"text\" :key {i}])\n"
+        ));
+    }
+    source
+}
+
+fn time(name: &str, runs: u32, mut f: impl FnMut()) {
+    f(); // Warms up
+    let started = Instant::now();
+    for _ in 0..runs {
+        f();
+    }
+    let each = started.elapsed() / runs;
+    println!("{name:<14} {each:>10.3?} per run");
+}
+
+fn main() {
+    let source = script(2000);
+    let options = Options::default();
+    time("tokenize", 20, || {
+        TokenStream::new(source.chars(), "bench", &options)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+    });
+    time("eval", 20, || {
+        Interpreter::new().eval(&source, "bench").unwrap();
+    });
+    time("eval_compiled", 20, || {
+        Interpreter::new().eval_compiled(&source, "bench").unwrap();
+    });
+}
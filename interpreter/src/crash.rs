@@ -0,0 +1,93 @@
+use std::{
+    backtrace::Backtrace,
+    env, fs,
+    panic::{self, PanicHookInfo},
+    path::PathBuf,
+    process,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const ISSUES_URL: &str = "https://github.com/FeistyKit/pale/issues/new";
+
+// Whether scripts are being run by the VM, which is only known once the arguments have been read.
+static VM: AtomicBool = AtomicBool::new(false);
+// How many crash reports this process has written, so that threads crashing at once don't write over each other's.
+static DUMPS: AtomicUsize = AtomicUsize::new(0);
+
+// Tells the reporter which backend runs the scripts, for the reports it writes after this.
+pub fn set_backend(vm: bool) {
+    VM.store(vm, Ordering::Relaxed);
+}
+
+fn backend() -> &'static str {
+    match VM.load(Ordering::Relaxed) {
+        true => "bytecode VM",
+        false => "tree-walking interpreter",
+    }
+}
+
+// Replaces the default panic message with a report that explains what happened and how to report it.
+// Nothing is ever sent anywhere: the details are written to a crash dump file that the user can choose to attach to an issue.
+pub fn install_reporter() {
+    panic::set_hook(Box::new(|info| {
+        let dump = write_dump(info);
+        eprintln!("The Pale interpreter crashed! This is a bug in Pale, not in your script.");
+        eprintln!();
+        eprintln!("    pale version: {}", pale::VERSION);
+        eprintln!("    backend: {}", backend());
+        match pale::evaluating_location() {
+            Some(loc) => eprintln!("    evaluating: {loc}"),
+            None => eprintln!("    evaluating: nothing (the crash happened outside of evaluation)"),
+        }
+        eprintln!("    reason: {}", message(info));
+        eprintln!();
+        eprintln!("To help get it fixed, please try to cut the script down to the smallest program that still crashes,");
+        eprintln!("(`pale reduce <script>` can do this for you), and then open an issue with it at <{ISSUES_URL}>.");
+        match dump {
+            Some(path) => eprintln!(
+                "A crash report was written to {}, which you can attach to the issue.",
+                path.display()
+            ),
+            None => eprintln!("A crash report could not be written."),
+        }
+    }));
+}
+
+fn message(info: &PanicHookInfo) -> String {
+    let payload = info
+        .payload()
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| info.payload().downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown".to_string());
+    match info.location() {
+        Some(l) => format!("{payload} (at {l})"),
+        None => payload,
+    }
+}
+
+fn write_dump(info: &PanicHookInfo) -> Option<PathBuf> {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let name = match DUMPS.fetch_add(1, Ordering::Relaxed) {
+        0 => format!("pale-crash-{time}-{}.txt", process::id()),
+        n => format!("pale-crash-{time}-{}-{n}.txt", process::id()),
+    };
+    let path = env::temp_dir().join(name);
+    let location = pale::evaluating_location()
+        .map(|l| l.to_string())
+        .unwrap_or_else(|| "none".to_string());
+    let args: Vec<String> = env::args().collect();
+    let report = format!(
+        "pale version: {}\nbackend: {}\nevaluating: {location}\nreason: {}\narguments: {args:?}\nos: {}\n\nbacktrace:\n{}\n",
+        pale::VERSION,
+        backend(),
+        message(info),
+        env::consts::OS,
+        Backtrace::force_capture(),
+    );
+    fs::write(&path, report).ok().map(|_| path)
+}
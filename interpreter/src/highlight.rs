@@ -0,0 +1,115 @@
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+
+use pale::{highlight, Highlight, Options, Session, Span};
+use rustyline::{completion::Completer, highlight::Highlighter, hint::Hinter, validate::Validator};
+
+const RESET: &str = "\x1b[0m";
+/// The opening delimiter that matches the closing one just before the cursor.
+const MATCHING: &str = "\x1b[1;7m";
+/// Names that aren't bound, which are probably typos.
+const UNKNOWN: &str = "\x1b[31m";
+
+/// Colours the line being written as it is typed.
+pub struct Colours {
+    options: Options,
+    known: BTreeSet<String>, // The names bound in the session, or earlier in the input
+}
+
+impl Colours {
+    pub fn new(options: Options) -> Self {
+        Self {
+            options,
+            known: BTreeSet::new(),
+        }
+    }
+
+    /// Learns which names are bound, from the session and the lines of the input that came before this one.
+    pub fn update(&mut self, session: &Session, entry: &str) {
+        self.known = session.names().map(str::to_string).collect();
+        for span in highlight(entry, &self.options) {
+            if span.kind == Highlight::Binding {
+                self.known.insert(entry[span.range].to_string());
+            }
+        }
+    }
+}
+
+// Where the opening delimiter is that matches the closing one just before `pos`.
+fn matching_open(line: &str, spans: &[Span], pos: usize) -> Option<usize> {
+    let mut opens = Vec::new();
+    for span in spans.iter().filter(|s| s.kind == Highlight::Delimiter) {
+        let start = span.range.start;
+        match &line[span.range.clone()] {
+            "(" | "[" | "{" => opens.push(start),
+            ")" | "]" | "}" => {
+                let open = opens.pop();
+                if span.range.end == pos {
+                    return open;
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+impl Highlighter for Colours {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        let spans = highlight(line, &self.options);
+        let bound: BTreeSet<&str> = spans
+            .iter()
+            .filter(|s| s.kind == Highlight::Binding)
+            .map(|s| &line[s.range.clone()])
+            .collect();
+        let matching = matching_open(line, &spans, pos);
+        let mut out = String::with_capacity(line.len() * 2);
+        let mut last = 0;
+        for span in &spans {
+            // Comments inside of interpolated strings are already part of the string.
+            if span.range.start < last {
+                continue;
+            }
+            let text = &line[span.range.clone()];
+            let style = if matching == Some(span.range.start) {
+                Some(MATCHING)
+            } else if span.kind == Highlight::Identifier
+                && !self.known.contains(text)
+                && !bound.contains(text)
+            {
+                Some(UNKNOWN)
+            } else {
+                span.kind.ansi()
+            };
+            out.push_str(&line[last..span.range.start]);
+            match style {
+                Some(style) => {
+                    out.push_str(style);
+                    out.push_str(text);
+                    out.push_str(RESET);
+                }
+                None => out.push_str(text),
+            }
+            last = span.range.end;
+        }
+        out.push_str(&line[last..]);
+        Cow::Owned(out)
+    }
+
+    // The matching delimiter changes as the cursor moves, so every change is highlighted again.
+    fn highlight_char(&self, _line: &str, _pos: usize, _forced: bool) -> bool {
+        true
+    }
+}
+
+impl Completer for Colours {
+    type Candidate = String;
+}
+
+impl Hinter for Colours {
+    type Hint = String;
+}
+
+impl Validator for Colours {}
+
+impl rustyline::Helper for Colours {}
@@ -1,41 +1,520 @@
 #![allow(clippy::or_fun_call)]
-use clap::Parser;
-use pale::{run_lisp, run_lisp_dumped};
-use std::{error, fs};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use pale::{
+    dump_forms, dump_scope, dump_tokens, format_source, lint, read_forms, render_highlighted,
+    run_lisp_in, DumpFormat, Edition, ErrorCategory, FormatOptions, HighlightFormat, Interpreter,
+    Level, LintConfig, LispErrors, Options, Profiler, Rule, TokenStream,
+};
+use std::fmt::{self, Display};
+use std::io::{self, IsTerminal, Read, Write};
+use std::{error, fs, process};
+
+mod crash;
+#[cfg(feature = "highlighting")]
+mod highlight;
+mod reduce;
+mod repl;
+mod watch;
+
+/// The name that code given on the command line is reported with.
+const COMMAND_NAME: &str = "<provided>";
+/// The name that scripts read from stdin are reported with.
+const STDIN_NAME: &str = "<stdin>";
+/// The config file that `pale lint` reads if it isn't given one.
+const LINT_CONFIG: &str = "pale-lint.conf";
+
+/// What `pale` exits with when a script fails while it is running, or can't be read.
+/// A script that stops itself with `(exit n)` exits with `n` instead.
+const RUNTIME_ERROR: i32 = 1;
+/// What `pale` exits with when a script can't be parsed, so none of it was run.
+const SYNTAX_ERROR: i32 = 2;
+/// What `pale` exits with when it was given arguments that don't make sense.
+const USAGE_ERROR: i32 = 3;
+
+// A mistake in how `pale` was run, rather than in the script it was given.
+#[derive(Debug)]
+struct Usage(String);
+
+impl Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl error::Error for Usage {}
+
+fn usage(message: &str) -> Box<dyn error::Error> {
+    Box::new(Usage(message.to_string()))
+}
 
 #[derive(Parser, Debug)]
-#[clap(author, version, about)]
+#[clap(author, version, about, args_conflicts_with_subcommands = true)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// The inputs are code to run, rather than the paths of scripts.
     #[clap(short = 'c', long = "command")]
     is_command: bool,
 
-    #[clap(short, long)]
-    debug: bool,
+    /// An expression to evaluate. Can be given more than once, and they are evaluated in order along with the scripts. The value of the last one is printed.
+    #[clap(
+        short = 'e',
+        long = "eval",
+        value_name = "EXPR",
+        multiple_occurrences = true,
+        conflicts_with = "is-command"
+    )]
+    exprs: Vec<String>,
+
+    /// Run the script again whenever it is saved, showing its output and diagnostics afresh each time.
+    /// Only the top-level forms that changed, and the ones that use what they define, are evaluated again.
+    #[clap(
+        long,
+        requires = "inputs",
+        conflicts_with_all = &["is-command", "exprs", "call-main", "dump-tokens", "dump-ast", "dump-scope"]
+    )]
+    watch: bool,
+
+    /// Print the value of every expression and script, rather than only the last expression's.
+    #[clap(long)]
+    print_all: bool,
+
+    /// Automatically close statements left open at the end of the input.
+    #[clap(long)]
+    friendly_parens: bool,
+
+    /// After running the input, call its `main` function with the arguments after the first script.
+    #[clap(long)]
+    call_main: bool,
+
+    /// The edition of the language to use, unless the input starts with `(pale-edition N)`.
+    #[clap(long, default_value_t = Edition::default())]
+    edition: Edition,
+
+    /// Run the input by compiling it to bytecode for a VM, instead of walking through the parsed code.
+    #[clap(long)]
+    vm: bool,
+
+    /// Count the calls to each function and time them, printing a table of them to stderr when the input has finished.
+    #[clap(long, conflicts_with = "watch")]
+    profile: bool,
+
+    /// Leave out the functions that the prelude defines, like `inc` and `second`, so that their names can be used for something else.
+    #[clap(long)]
+    no_prelude: bool,
+
+    /// Write the tokens as JSON to the file, or to stdout if there isn't one. The input isn't run unless `--dump-scope` is also given.
+    #[clap(long, value_name = "PATH", min_values = 0, require_equals = true)]
+    dump_tokens: Option<Option<String>>,
+
+    /// Write the parsed forms to the file, or to stdout if there isn't one. The input isn't run unless `--dump-scope` is also given.
+    #[clap(long, value_name = "PATH", min_values = 0, require_equals = true)]
+    dump_ast: Option<Option<String>>,
+
+    /// After running the input, write what it bound to the file, or to stdout if there isn't one.
+    #[clap(long, value_name = "PATH", min_values = 0, require_equals = true)]
+    dump_scope: Option<Option<String>>,
+
+    /// How `--dump-ast` and `--dump-scope` are written: `json`, or `sexpr` for one form or binding on each line. Tokens are always JSON.
+    #[clap(long, value_name = "FORMAT", default_value_t = DumpFormat::Json)]
+    dump_format: DumpFormat,
 
-    input: Option<String>,
+    /// The REPL's prompt.
+    #[clap(long, value_name = "TEXT")]
+    prompt: Option<String>,
+
+    /// The REPL's prompt for the lines of an input that is continued over several lines.
+    #[clap(long, value_name = "TEXT")]
+    continuation_prompt: Option<String>,
+
+    /// Whether the REPL prints the result of each input.
+    #[clap(long, value_name = "on|off")]
+    echo: Option<String>,
+
+    /// Whether the REPL says how long each input took.
+    #[clap(long, value_name = "on|off")]
+    time: Option<String>,
+
+    /// Evaluate a file and then start the REPL, so that what it defines can be tried out. Can be given more than once.
+    #[clap(
+        long,
+        value_name = "FILE",
+        multiple_occurrences = true,
+        conflicts_with = "inputs"
+    )]
+    load: Vec<String>,
+
+    /// The scripts to run in order, all in the same scope, with `-` reading one from stdin.
+    /// Without any, a script piped to stdin is run, and otherwise the REPL starts.
+    /// With `--call-main`, only the first is a script and the rest are passed to `main`.
+    inputs: Vec<String>,
 }
 
-fn main() -> Result<(), Box<dyn error::Error>> {
-    let args = Args::parse();
-    let (source, file) = if args.is_command {
-        if let Some(s) = args.input {
-            (s, "<provided>".to_string())
-        } else {
-            return Err("A command must be provided!".into());
+// A piece of source to run, and the name that its errors are reported with.
+struct Source {
+    text: String,
+    name: String,
+    expr: bool, // Whether it was given with `-e`, which has its value printed
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Shrink a script down to the smallest one that still fails in the same way, for reporting bugs.
+    Reduce {
+        file: String,
+
+        /// How the script fails: `exit-code=N`, `stdout-contains=TEXT`, or `stderr-contains=TEXT`.
+        #[clap(long, default_value = "exit-code=101")]
+        check: reduce::Check,
+
+        /// Where to write the reduced script, instead of printing it.
+        #[clap(short, long)]
+        output: Option<String>,
+    },
+    /// Print a script with canonical indentation and spacing.
+    Fmt {
+        #[clap(required_unless_present = "stdin")]
+        file: Option<String>,
+
+        /// How long a line can be before statements are broken over several lines.
+        #[clap(long, default_value_t = FormatOptions::default().width)]
+        width: usize,
+
+        /// Overwrite the file instead of printing it.
+        #[clap(short, long)]
+        write: bool,
+
+        /// Read the script from stdin, for editors that format on save.
+        #[clap(long, conflicts_with_all = &["file", "write"])]
+        stdin: bool,
+    },
+    /// Print a script with its syntax highlighted, for code samples in docs and on websites.
+    Highlight {
+        file: String,
+
+        /// `html` for `<span>`s with classes to style, or `ansi` for a terminal.
+        #[clap(long, default_value_t = HighlightFormat::Ansi)]
+        format: HighlightFormat,
+    },
+    /// Check a script for code that is probably a mistake, without running it.
+    Lint {
+        file: String,
+
+        /// A lint to leave out. Can be given more than once.
+        #[clap(long, value_name = "LINT", multiple_occurrences = true)]
+        allow: Vec<Rule>,
+
+        /// A lint to warn about, which they all do by default. Can be given more than once.
+        #[clap(long, value_name = "LINT", multiple_occurrences = true)]
+        warn: Vec<Rule>,
+
+        /// A lint to report as an error, which makes `pale lint` fail. Can be given more than once.
+        #[clap(long, value_name = "LINT", multiple_occurrences = true)]
+        deny: Vec<Rule>,
+
+        /// A file with a `lint = level` on each line, like `unused-binding = allow`. Defaults to `pale-lint.conf` if there is one in the current directory.
+        #[clap(long, value_name = "FILE")]
+        config: Option<String>,
+    },
+}
+
+fn main() {
+    crash::install_reporter();
+    if let Err(e) = run() {
+        process::exit(report(&*e));
+    }
+}
+
+// Prints the error, giving the code to exit with for it.
+fn report(e: &(dyn error::Error + 'static)) -> i32 {
+    if let Some(errs) = e.downcast_ref::<LispErrors>() {
+        // Exiting on purpose has nothing to say.
+        if !errs.is_empty() {
+            eprintln!("{errs}");
         }
-    } else {
-        if let Some(s) = args.input {
-            (fs::read_to_string(&s).unwrap(), s)
-        } else {
-            // TODOOOOO: Running the interpreter off standard input.
-            return Err("Running in REPL mode is not yet implemented!".into());
+        return match errs.category() {
+            ErrorCategory::Syntax => SYNTAX_ERROR,
+            ErrorCategory::Runtime => RUNTIME_ERROR,
+            ErrorCategory::Exit(code) => code,
+        };
+    }
+    eprintln!("{e}");
+    match e.is::<Usage>() {
+        true => USAGE_ERROR,
+        false => RUNTIME_ERROR,
+    }
+}
+
+fn run() -> Result<(), Box<dyn error::Error>> {
+    let matches = match Args::command().try_get_matches() {
+        Ok(matches) => matches,
+        // Clap exits successfully after printing `--help` or `--version`.
+        Err(e) if !e.use_stderr() => e.exit(),
+        Err(e) => {
+            let _ = e.print();
+            process::exit(USAGE_ERROR);
+        }
+    };
+    let mut args = Args::from_arg_matches(&matches)?;
+    crash::set_backend(args.vm);
+    match args.command {
+        Some(Command::Reduce {
+            file,
+            check,
+            output,
+        }) => {
+            let reduced = reduce::reduce(&file, check)?;
+            match output {
+                Some(path) => fs::write(path, reduced)?,
+                None => print!("{reduced}"),
+            }
+            return Ok(());
+        }
+        Some(Command::Fmt {
+            file,
+            width,
+            write,
+            stdin: _, // Clap makes sure that there's no file when reading from stdin
+        }) => {
+            let (source, file) = match file {
+                Some(file) => (read_file(&file)?, file),
+                None => (read_stdin()?, STDIN_NAME.to_string()),
+            };
+            let formatted = format_source(&source, &file, &FormatOptions { width })?;
+            if write {
+                fs::write(&file, formatted)?;
+            } else {
+                print!("{formatted}");
+            }
+            return Ok(());
+        }
+        Some(Command::Highlight { file, format }) => {
+            let source = read_source(&file)?;
+            let highlighted = render_highlighted(&source, &Options::default(), format);
+            match format {
+                HighlightFormat::Html => println!("{highlighted}"),
+                HighlightFormat::Ansi => print!("{highlighted}"),
+            }
+            return Ok(());
+        }
+        Some(Command::Lint {
+            file,
+            allow,
+            warn,
+            deny,
+            config,
+        }) => {
+            let (path, required) = match config {
+                Some(path) => (path, true),
+                None => (LINT_CONFIG.to_string(), false),
+            };
+            let mut lints = match fs::read_to_string(&path) {
+                Ok(text) => LintConfig::parse(&text).map_err(|e| usage(&format!("{path}: {e}")))?,
+                Err(e) if e.kind() == io::ErrorKind::NotFound && !required => LintConfig::default(),
+                Err(e) => return Err(format!("Could not read {path}: {e}").into()),
+            };
+            for (rules, level) in [
+                (allow, Level::Allow),
+                (warn, Level::Warn),
+                (deny, Level::Deny),
+            ] {
+                for rule in rules {
+                    lints.set(rule, level);
+                }
+            }
+            let source = read_source(&file)?;
+            let diagnostics = lint(&source, &file, &Options::default(), &lints);
+            if !diagnostics.is_empty() {
+                println!("{diagnostics}");
+            }
+            if diagnostics.is_fatal() {
+                process::exit(1);
+            }
+            return Ok(());
         }
+        None => {}
+    }
+    let main_args = match args.call_main && !args.inputs.is_empty() {
+        true => args.inputs.split_off(1),
+        false => Vec::new(),
+    };
+    let options = Options {
+        friendly_parens: args.friendly_parens,
+        call_main: args.call_main,
+        main_args,
+        edition: args.edition,
+        vm: args.vm,
+        prelude: !args.no_prelude,
+        profiler: args.profile.then(Profiler::default),
+        ..Options::default()
     };
-    if !args.debug {
-        // Clap makes it true by default
-        run_lisp(&source, &file)?;
-    } else {
-        run_lisp_dumped(&source, &file)?;
+    let profiler = options.profiler.clone();
+    if args.watch {
+        let [path] = args.inputs.as_slice() else {
+            return Err(usage("Only one script can be watched at a time!"));
+        };
+        if path == "-" {
+            return Err(usage("A script read from stdin can't be watched!"));
+        }
+        watch::watch(path, options)?;
+        return Ok(());
+    }
+    // The expressions and scripts are run in the order they were given in.
+    let mut sources = Vec::new();
+    let inputs = matches.indices_of("inputs").into_iter().flatten();
+    for (index, input) in inputs.zip(&args.inputs) {
+        let source = match (args.is_command, input.as_str()) {
+            (true, _) => Source {
+                text: input.clone(),
+                name: COMMAND_NAME.to_string(),
+                expr: false,
+            },
+            (false, "-") => Source {
+                text: read_stdin()?,
+                name: STDIN_NAME.to_string(),
+                expr: false,
+            },
+            (false, path) => Source {
+                text: read_file(path)?,
+                name: path.to_string(),
+                expr: false,
+            },
+        };
+        sources.push((index, source));
+    }
+    let exprs = matches.indices_of("exprs").into_iter().flatten();
+    for (index, expr) in exprs.zip(&args.exprs) {
+        let source = Source {
+            text: expr.clone(),
+            name: COMMAND_NAME.to_string(),
+            expr: true,
+        };
+        sources.push((index, source));
+    }
+    sources.sort_by_key(|(index, _)| *index);
+    let mut sources: Vec<Source> = sources.into_iter().map(|(_, source)| source).collect();
+    if sources.is_empty() {
+        if args.is_command {
+            return Err(usage("A command must be provided!"));
+        }
+        // A script piped in is run all at once like a file, rather than a line at a time like the REPL would.
+        if !args.load.is_empty() || io::stdin().is_terminal() {
+            let mut config = repl::Config::load().map_err(|e| usage(&e))?;
+            for (key, value) in [
+                ("prompt", args.prompt),
+                ("continuation-prompt", args.continuation_prompt),
+                ("echo", args.echo),
+                ("time", args.time),
+            ] {
+                if let Some(value) = value {
+                    config
+                        .set(key, &value)
+                        .map_err(|e| usage(&format!("--{key}: {e}")))?;
+                }
+            }
+            let code = repl::run(options, config, &args.load)?;
+            print_profile(&profiler);
+            if code != 0 {
+                process::exit(code);
+            }
+            return Ok(());
+        }
+        sources.push(Source {
+            text: read_stdin()?,
+            name: STDIN_NAME.to_string(),
+            expr: false,
+        });
+    }
+    if args.dump_tokens.is_some() || args.dump_ast.is_some() {
+        let [source] = &sources[..] else {
+            return Err(usage("Only one script can be dumped at a time!"));
+        };
+        if let Some(path) = &args.dump_tokens {
+            let tokens = TokenStream::new(source.text.chars(), source.name.as_str(), &options)
+                .collect::<Result<Vec<_>, _>>()?;
+            write_dump(path, |out| dump_tokens(&tokens, out))?;
+        }
+        if let Some(path) = &args.dump_ast {
+            let forms = read_forms(&source.text, &source.name, &options)?;
+            write_dump(path, |out| dump_forms(&forms, args.dump_format, out))?;
+        }
+        if args.dump_scope.is_none() {
+            return Ok(());
+        }
+    }
+    let mut interpreter = Interpreter::with_options(options);
+    let ran = run_all(&mut interpreter, &sources, args.print_all);
+    // What was bound before an error is still worth seeing, and so is where the time went.
+    if let Some(path) = &args.dump_scope {
+        write_dump(path, |out| dump_scope(&interpreter, args.dump_format, out))?;
     }
+    print_profile(&profiler);
+    ran?;
     Ok(())
 }
+
+// Runs the sources one after another in the interpreter, printing the values that were asked for.
+// `main` is called after the last one if it should be.
+fn run_all(
+    interpreter: &mut Interpreter,
+    sources: &[Source],
+    print_all: bool,
+) -> Result<(), LispErrors> {
+    for (i, source) in sources.iter().enumerate() {
+        let last = i + 1 == sources.len();
+        let (value, warnings) = if last {
+            run_lisp_in(interpreter, &source.text, &source.name)?
+        } else {
+            let value = interpreter.eval(&source.text, &source.name)?;
+            (value, interpreter.take_warnings())
+        };
+        if !warnings.is_empty() {
+            eprintln!("{warnings}");
+        }
+        if print_all || (last && source.expr) {
+            println!("{value}");
+        }
+    }
+    Ok(())
+}
+
+// Prints the table of calls that `--profile` asked for, if it did.
+fn print_profile(profiler: &Option<Profiler>) {
+    if let Some(profiler) = profiler {
+        eprintln!("{}", profiler.profile());
+    }
+}
+
+// Writes a dump to the file, or to stdout if there is no file.
+fn write_dump(
+    path: &Option<String>,
+    dump: impl FnOnce(&mut Box<dyn Write>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut out: Box<dyn Write> = match path {
+        Some(path) => Box::new(io::BufWriter::new(fs::File::create(path)?)),
+        None => Box::new(io::stdout().lock()),
+    };
+    dump(&mut out)?;
+    out.flush()
+}
+
+fn read_stdin() -> io::Result<String> {
+    let mut source = String::new();
+    io::stdin().read_to_string(&mut source)?;
+    Ok(source)
+}
+
+// Reads the script at the path, or from stdin if the path is `-`.
+fn read_source(path: &str) -> io::Result<String> {
+    match path {
+        "-" => read_stdin(),
+        _ => read_file(path),
+    }
+}
+
+// Reads the file at the path, with an error that says which file couldn't be read.
+fn read_file(path: &str) -> io::Result<String> {
+    fs::read_to_string(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("Could not read `{path}`: {e}")))
+}
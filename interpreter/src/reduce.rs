@@ -0,0 +1,156 @@
+use pale::{read_forms, Form, Options};
+use std::{env, error, fs, process::Command, str::FromStr};
+
+/// What has to happen when running a script for it to count as still failing.
+#[derive(Debug, Clone)]
+pub enum Check {
+    ExitCode(i32),
+    StdoutContains(String),
+    StderrContains(String),
+}
+
+impl FromStr for Check {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some(("exit-code", code)) => code
+                .parse()
+                .map(Check::ExitCode)
+                .map_err(|_| format!("`{code}` is not an exit code!")),
+            Some(("stdout-contains", text)) => Ok(Check::StdoutContains(text.to_string())),
+            Some(("stderr-contains", text)) => Ok(Check::StderrContains(text.to_string())),
+            _ => Err(format!(
+                "Unknown check `{s}`! Use `exit-code=N`, `stdout-contains=TEXT`, or `stderr-contains=TEXT`."
+            )),
+        }
+    }
+}
+
+struct Reducer {
+    check: Check,
+    scratch: std::path::PathBuf,
+    runs: usize,
+}
+
+impl Reducer {
+    // Runs the interpreter on the program, returning whether it still fails in the same way.
+    fn fails(&mut self, program: &[Form]) -> Result<bool, Box<dyn error::Error>> {
+        self.runs += 1;
+        fs::write(&self.scratch, print(program))?;
+        let output = Command::new(env::current_exe()?)
+            .arg(&self.scratch)
+            .output()?;
+        Ok(match &self.check {
+            Check::ExitCode(code) => output.status.code() == Some(*code),
+            Check::StdoutContains(text) => String::from_utf8_lossy(&output.stdout).contains(text),
+            Check::StderrContains(text) => String::from_utf8_lossy(&output.stderr).contains(text),
+        })
+    }
+
+    // Removes as many of the items at the path as possible, in chunks that get smaller as they stop being removable.
+    fn remove_items(
+        &mut self,
+        program: &mut Vec<Form>,
+        path: &[usize],
+    ) -> Result<bool, Box<dyn error::Error>> {
+        let mut changed = false;
+        let mut chunk = (items_at(program, path).len() / 2).max(1);
+        loop {
+            let mut i = 0;
+            while i < items_at(program, path).len() {
+                let mut candidate = program.clone();
+                let items = items_at(&mut candidate, path);
+                let end = (i + chunk).min(items.len());
+                items.drain(i..end);
+                if self.fails(&candidate)? {
+                    *program = candidate;
+                    changed = true;
+                } else {
+                    i += chunk;
+                }
+            }
+            if chunk == 1 {
+                return Ok(changed);
+            }
+            chunk /= 2;
+        }
+    }
+
+    // Tries replacing each statement with one of the things inside of it, and then reduces what is inside of it.
+    fn reduce_children(
+        &mut self,
+        program: &mut Vec<Form>,
+        path: &mut Vec<usize>,
+    ) -> Result<bool, Box<dyn error::Error>> {
+        let mut changed = self.remove_items(program, path)?;
+        let mut i = 0;
+        while i < items_at(program, path).len() {
+            let inner = match &items_at(program, path)[i] {
+                Form::List { items, .. } => items.clone(),
+                Form::Atom(_) => {
+                    i += 1;
+                    continue;
+                }
+            };
+            for replacement in inner {
+                let mut candidate = program.clone();
+                items_at(&mut candidate, path)[i] = replacement;
+                if self.fails(&candidate)? {
+                    *program = candidate;
+                    changed = true;
+                    break;
+                }
+            }
+            if let Form::List { .. } = items_at(program, path)[i] {
+                path.push(i);
+                changed |= self.reduce_children(program, path)?;
+                path.pop();
+            }
+            i += 1;
+        }
+        Ok(changed)
+    }
+}
+
+// The items of the statement at the path, where the empty path is the top level of the program.
+fn items_at<'a>(program: &'a mut Vec<Form>, path: &[usize]) -> &'a mut Vec<Form> {
+    let mut items = program;
+    for &i in path {
+        items = match &mut items[i] {
+            Form::List { items, .. } => items,
+            Form::Atom(_) => unreachable!("Paths only lead through statements"),
+        };
+    }
+    items
+}
+
+fn print(program: &[Form]) -> String {
+    let mut res = String::new();
+    for form in program {
+        res.push_str(&form.to_string());
+        res.push('\n');
+    }
+    res
+}
+
+/// Shrinks the script down to a smaller one that still fails the check, and returns its source.
+pub fn reduce(file: &str, check: Check) -> Result<String, Box<dyn error::Error>> {
+    let source = fs::read_to_string(file)?;
+    let mut program = read_forms(&source, file, &Options::default())?;
+    let mut reducer = Reducer {
+        check,
+        scratch: env::temp_dir().join(format!("pale-reduce-{}.pale", std::process::id())),
+        runs: 0,
+    };
+    if !reducer.fails(&program)? {
+        return Err(format!(
+            "{file} doesn't fail the check ({:?}), so there is nothing to reduce!",
+            reducer.check
+        )
+        .into());
+    }
+    while reducer.reduce_children(&mut program, &mut Vec::new())? {}
+    fs::remove_file(&reducer.scratch).ok();
+    eprintln!("Reduced {file} after {} runs.", reducer.runs);
+    Ok(print(&program))
+}
@@ -0,0 +1,397 @@
+use std::{env, fs, io, mem, path::PathBuf};
+
+use pale::{is_incomplete, ErrorCategory, Options, Session};
+
+/// What inputs are called in diagnostics.
+const INPUT_NAME: &str = "<repl>";
+
+/// How the REPL looks and what it shows.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub prompt: String,
+    /// Shown while an input is continued over several lines, until its statements are closed.
+    pub continuation: String,
+    /// Whether to print the result of each input.
+    pub echo: bool,
+    /// Whether to say how long each input took.
+    pub time: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            prompt: "> ".to_string(),
+            continuation: ".. ".to_string(),
+            echo: true,
+            time: false,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the config file and then the `PALE_*` environment variables, each overriding what came before.
+    ///
+    /// The config file has a `setting = value` on each line, and lines starting with `#` are comments.
+    /// Values can be quoted to keep the spaces at their ends, like `prompt = "λ "`.
+    pub fn load() -> Result<Self, String> {
+        let mut config = Config::default();
+        if let Some(path) = config_path() {
+            match fs::read_to_string(&path) {
+                Ok(text) => {
+                    for (n, line) in text.lines().enumerate() {
+                        let line = line.trim();
+                        if line.is_empty() || line.starts_with('#') {
+                            continue;
+                        }
+                        let set = match line.split_once('=') {
+                            Some((key, value)) => config.set(key.trim(), unquote(value.trim())),
+                            None => Err("expected `setting = value`".to_string()),
+                        };
+                        set.map_err(|e| format!("{}:{}: {e}", path.display(), n + 1))?;
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(format!("Could not read {}: {e}", path.display())),
+            }
+        }
+        for (key, var) in [
+            ("prompt", "PALE_PROMPT"),
+            ("continuation-prompt", "PALE_CONTINUATION_PROMPT"),
+            ("echo", "PALE_ECHO"),
+            ("time", "PALE_TIME"),
+        ] {
+            if let Ok(value) = env::var(var) {
+                config.set(key, &value).map_err(|e| format!("{var}: {e}"))?;
+            }
+        }
+        Ok(config)
+    }
+
+    /// Changes a setting, by the name it has in the config file.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "prompt" => self.prompt = value.to_string(),
+            "continuation-prompt" => self.continuation = value.to_string(),
+            "echo" => self.echo = switch(value)?,
+            "time" => self.time = switch(value)?,
+            _ => return Err(format!("unknown setting `{key}`")),
+        }
+        Ok(())
+    }
+}
+
+// `$XDG_CONFIG_HOME/pale/repl.conf`, or `~/.config/pale/repl.conf`.
+fn config_path() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("pale").join("repl.conf"))
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+fn switch(value: &str) -> Result<bool, String> {
+    match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("expected `on` or `off`, not `{value}`")),
+    }
+}
+
+/// Evaluates lines from standard input one after another in the same session, until the input ends.
+///
+/// The files in `load` are evaluated first, so that what they define can be used.
+/// Gives the code that an input asked to exit with, or 0 if the REPL was left some other way.
+pub fn run(options: Options, config: Config, load: &[String]) -> io::Result<i32> {
+    let mut repl = Repl {
+        session: Session::new(options),
+        config,
+        quit: false,
+        code: 0,
+    };
+    for path in load {
+        repl.load(path);
+    }
+    if repl.quit {
+        return Ok(repl.code);
+    }
+    let mut input = Input::new(repl.session.options())?;
+    let mut entry = String::new();
+    loop {
+        let prompt = if entry.is_empty() {
+            &repl.config.prompt
+        } else {
+            &repl.config.continuation
+        };
+        input.update(&repl.session, &entry);
+        match input.read_line(prompt)? {
+            Line::Text(line) => {
+                if !entry.is_empty() {
+                    entry.push('\n');
+                }
+                entry.push_str(&line);
+            }
+            // Ctrl-C throws away what has been written so far.
+            #[cfg(feature = "line-editing")]
+            Line::Interrupted => {
+                entry.clear();
+                continue;
+            }
+            Line::End => {
+                // What is left is evaluated anyway, so that the error saying what wasn't closed is shown.
+                if !entry.trim().is_empty() {
+                    repl.eval(entry.trim(), INPUT_NAME);
+                }
+                break;
+            }
+        }
+        if entry.trim().is_empty() {
+            entry.clear();
+            continue;
+        }
+        if is_incomplete(&entry, repl.session.options()) {
+            continue;
+        }
+        input.add_history(&entry);
+        let entry = mem::take(&mut entry);
+        let entry = entry.trim();
+        if !repl.command(entry) {
+            repl.eval(entry, INPUT_NAME);
+        }
+        if repl.quit {
+            break;
+        }
+    }
+    input.save_history();
+    Ok(repl.code)
+}
+
+enum Line {
+    Text(String),
+    /// Ctrl-C, which only the line editor catches.
+    #[cfg(feature = "line-editing")]
+    Interrupted,
+    End,
+}
+
+#[cfg(feature = "highlighting")]
+type Editor = rustyline::Editor<crate::highlight::Colours, rustyline::history::DefaultHistory>;
+#[cfg(all(feature = "line-editing", not(feature = "highlighting")))]
+type Editor = rustyline::DefaultEditor;
+
+/// Reads lines with arrow keys, Emacs-style keybindings, and history that is kept between sessions.
+#[cfg(feature = "line-editing")]
+struct Input {
+    editor: Editor,
+    history: Option<std::path::PathBuf>,
+}
+
+#[cfg(feature = "line-editing")]
+impl Input {
+    fn new(_options: &Options) -> io::Result<Self> {
+        use rustyline::{Config, EditMode};
+        let config = Config::builder().edit_mode(EditMode::Emacs).build();
+        let mut editor = Editor::with_config(config).map_err(io::Error::other)?;
+        #[cfg(feature = "highlighting")]
+        editor.set_helper(Some(crate::highlight::Colours::new(_options.clone())));
+        let history =
+            std::env::var_os("HOME").map(|home| std::path::Path::new(&home).join(".pale_history"));
+        if let Some(path) = &history {
+            // There won't be a history file the first time.
+            let _ = editor.load_history(path);
+        }
+        Ok(Self { editor, history })
+    }
+
+    fn read_line(&mut self, prompt: &str) -> io::Result<Line> {
+        use rustyline::error::ReadlineError;
+        match self.editor.readline(prompt) {
+            Ok(line) => Ok(Line::Text(line)),
+            Err(ReadlineError::Interrupted) => Ok(Line::Interrupted),
+            Err(ReadlineError::Eof) => Ok(Line::End),
+            Err(e) => Err(io::Error::other(e)),
+        }
+    }
+
+    // Tells the highlighter which names are bound, including those bound by the lines of the input so far.
+    fn update(&mut self, _session: &Session, _entry: &str) {
+        #[cfg(feature = "highlighting")]
+        if let Some(colours) = self.editor.helper_mut() {
+            colours.update(_session, _entry);
+        }
+    }
+
+    // Inputs that span several lines are kept as one entry.
+    fn add_history(&mut self, entry: &str) {
+        let _ = self.editor.add_history_entry(entry);
+    }
+
+    fn save_history(&mut self) {
+        if let Some(path) = &self.history {
+            if let Err(e) = self.editor.save_history(path) {
+                eprintln!("Could not save the history to {}: {e}", path.display());
+            }
+        }
+    }
+}
+
+/// Reads plain lines from standard input.
+#[cfg(not(feature = "line-editing"))]
+struct Input {
+    line: String,
+}
+
+#[cfg(not(feature = "line-editing"))]
+impl Input {
+    fn new(_options: &Options) -> io::Result<Self> {
+        Ok(Self {
+            line: String::new(),
+        })
+    }
+
+    fn read_line(&mut self, prompt: &str) -> io::Result<Line> {
+        use io::{BufRead, Write};
+        print!("{prompt}");
+        io::stdout().flush()?;
+        self.line.clear();
+        if io::stdin().lock().read_line(&mut self.line)? == 0 {
+            println!();
+            return Ok(Line::End);
+        }
+        Ok(Line::Text(
+            self.line.trim_end_matches(['\r', '\n']).to_string(),
+        ))
+    }
+
+    fn update(&mut self, _session: &Session, _entry: &str) {}
+
+    fn add_history(&mut self, _entry: &str) {}
+
+    fn save_history(&mut self) {}
+}
+
+struct Repl {
+    session: Session,
+    config: Config,
+    quit: bool,
+    code: i32, // What `(exit n)` asked to exit with
+}
+
+impl Repl {
+    fn eval(&mut self, source: &str, name: &str) {
+        match self.session.eval(source, name) {
+            Ok(evaluation) => {
+                if !evaluation.warnings.is_empty() {
+                    eprintln!("{}", evaluation.warnings);
+                }
+                if self.config.echo {
+                    println!("{evaluation}");
+                }
+                if self.config.time {
+                    println!("took {:?}", evaluation.elapsed);
+                }
+            }
+            Err(e) => match e.category() {
+                ErrorCategory::Exit(code) => {
+                    self.code = code;
+                    self.quit = true;
+                }
+                _ => eprintln!("{e}"),
+            },
+        }
+    }
+
+    fn load(&mut self, path: &str) {
+        match fs::read_to_string(path) {
+            Ok(source) => self.eval(&source, path),
+            Err(e) => eprintln!("Could not read {path}: {e}"),
+        }
+    }
+
+    // Runs the input if it is a command like `:full`, returning whether it was one.
+    // Anything else that starts with `:` is a keyword, and is evaluated like any other input.
+    fn command(&mut self, input: &str) -> bool {
+        let (name, arg) = match input.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, Some(arg.trim())),
+            None => (input, None),
+        };
+        let mut limits = self.session.print_limits();
+        match (name, arg) {
+            (":help", None) => {
+                print!("{COMMANDS}");
+                #[cfg(feature = "line-editing")]
+                print!("\n{KEYS}");
+            }
+            (":quit", None) => self.quit = true,
+            (":env", None) => {
+                let bindings = self.session.bindings();
+                if bindings.is_empty() {
+                    println!("Nothing has been defined yet.");
+                }
+                for def in bindings {
+                    println!("{def}");
+                }
+            }
+            (":reset", None) => {
+                self.session.reset();
+                println!("Everything that was defined has been forgotten.");
+            }
+            (":load", Some(path)) => self.load(path),
+            (":load", None) => eprintln!("usage: :load FILE"),
+            (":full", None) => println!("{}", self.session.full()),
+            (":time" | ":echo", Some(arg)) if switch(arg).is_ok() => {
+                self.config.set(&name[1..], arg).unwrap();
+            }
+            (":time" | ":echo", _) => eprintln!("usage: {name} on|off"),
+            (":print-length" | ":print-depth", Some(arg)) if limit(arg).is_some() => {
+                let limit = limit(arg).unwrap();
+                match name {
+                    ":print-length" => limits.length = limit,
+                    _ => limits.depth = limit,
+                }
+                self.session.set_print_limits(limits);
+            }
+            (":print-length" | ":print-depth", _) => eprintln!("usage: {name} N|none"),
+            _ => return false,
+        }
+        true
+    }
+}
+
+const COMMANDS: &str = "\
+Commands:
+  :help                  Show this message.
+  :env                   List everything that has been defined.
+  :load FILE             Run a file, keeping what it defines.
+  :reset                 Forget everything that has been defined.
+  :full                  Print the last result without cutting it short.
+  :print-length N|none   How many items of a list or map to print.
+  :print-depth N|none    How deeply nested lists and maps can be printed.
+  :time on|off           Say how long each input takes.
+  :echo on|off           Print the result of each input.
+  :quit                  Leave the REPL. Ctrl-D does the same.
+";
+
+#[cfg(feature = "line-editing")]
+const KEYS: &str = "\
+Keys:
+  Up/Down, Ctrl-P/N      Go through the history.
+  Ctrl-R                 Search the history.
+  Ctrl-A/E               Go to the start or end of the line.
+  Ctrl-W, Ctrl-K         Delete the word before the cursor, or the rest of the line.
+  Ctrl-C                 Throw away the current input.
+";
+
+// Parses the argument to `:print-length` or `:print-depth`, where `none` means there is no limit.
+fn limit(arg: &str) -> Option<Option<usize>> {
+    match arg {
+        "none" => Some(None),
+        n => n.parse().ok().map(Some),
+    }
+}
@@ -0,0 +1,68 @@
+use pale::{ErrorCategory, Options, Session};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use std::{fs, thread};
+
+/// How often the script is checked for changes.
+const POLL: Duration = Duration::from_millis(200);
+/// Clears the terminal and moves the cursor back to the top.
+const CLEAR: &str = "\x1b[2J\x1b[H";
+
+// When the file was last changed, or `None` if it can't be read right now, e.g. while an editor is replacing it.
+fn modified(path: impl AsRef<Path>) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Runs the script, and then runs it again on a clear screen whenever it or a file it imports or loads changes, until the process is stopped.
+///
+/// The files are checked every so often rather than waiting to be told about changes, which works the same everywhere and is quick enough for someone saving a file.
+/// What the script binds is kept between runs, and [`Session::reload`] only evaluates the forms that need it.
+/// When one of the other files changes the session starts again, since the modules it imported are kept until then.
+pub fn watch(path: &str, options: Options) -> io::Result<()> {
+    let mut session = Session::new(options);
+    let mut seen = None;
+    // When each of the files that the script read was changed, as of the last run.
+    let mut read: BTreeMap<PathBuf, Option<SystemTime>> = BTreeMap::new();
+    loop {
+        let now = modified(path);
+        let stale = read.iter().any(|(file, when)| modified(file) != *when);
+        if now.is_some() && (now != seen || stale) {
+            seen = now;
+            if stale {
+                session.reset();
+            }
+            print!("{CLEAR}");
+            io::stdout().flush()?;
+            match fs::read_to_string(path) {
+                Ok(source) => match session.reload(&source, path) {
+                    Ok(reloaded) => {
+                        if !reloaded.warnings.is_empty() {
+                            eprintln!("{}", reloaded.warnings);
+                        }
+                        eprintln!(
+                            "Evaluated {} of {} form(s).",
+                            reloaded.evaluated.len(),
+                            reloaded.forms
+                        );
+                    }
+                    Err(e) => match e.category() {
+                        ErrorCategory::Exit(code) => eprintln!("The script exited with {code}."),
+                        _ => eprintln!("{e}"),
+                    },
+                },
+                Err(e) => eprintln!("Could not read {path}: {e}"),
+            }
+            read = session
+                .files_read()
+                .map(|file| (file.to_path_buf(), modified(file)))
+                .collect();
+            match read.len() {
+                0 => eprintln!("Watching {path} for changes..."),
+                n => eprintln!("Watching {path} and the {n} file(s) it reads for changes..."),
+            }
+        }
+        thread::sleep(POLL);
+    }
+}
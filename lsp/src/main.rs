@@ -0,0 +1,407 @@
+//! A Language Server Protocol server for Pale, which editors run and talk to over stdin and stdout.
+//!
+//! It reports the errors in each open file as it changes, finds where names are bound, shows what the intrinsics do when hovering over them, and lists the names each file binds.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{self, BufReader};
+use std::{error, fs};
+
+use pale::{intrinsic_doc, symbols, Location, Options, Severity, SymbolKind, Symbols};
+use serde_json::{json, Value};
+
+mod rpc;
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<String, String>, // The text of each open document, by its URI
+    // The other files that each document's diagnostics were published to, so that they can be cleared once it has none in them.
+    published: HashMap<String, BTreeSet<String>>,
+    shutting_down: bool,
+}
+
+impl Server {
+    // Handles a request, giving its result.
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1, // The whole document is sent on every change
+                    "definitionProvider": true,
+                    "hoverProvider": true,
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": { "name": "pale-lsp", "version": pale::VERSION },
+            })),
+            "shutdown" => {
+                self.shutting_down = true;
+                Ok(Value::Null)
+            }
+            "textDocument/definition" => {
+                let (uri, symbols, line, col) = self.at(params)?;
+                Ok(match symbols.definition_at(line, col) {
+                    // An imported name goes to where its module provides it.
+                    Some(def) => match &def.from {
+                        Some(from) => json!({
+                            "uri": self.uri_for(&from.filename, &uri),
+                            "range": self.range(from, ""),
+                        }),
+                        None => json!({ "uri": uri, "range": self.range(&def.loc, &def.name) }),
+                    },
+                    None => Value::Null,
+                })
+            }
+            "textDocument/hover" => {
+                let (_, symbols, line, col) = self.at(params)?;
+                Ok(match hover(&symbols, line, col) {
+                    Some(text) => json!({ "contents": { "kind": "markdown", "value": text } }),
+                    None => Value::Null,
+                })
+            }
+            "textDocument/documentSymbol" => {
+                let uri = uri(params)?;
+                let symbols = self.symbols(uri)?;
+                Ok(symbols
+                    .definitions
+                    .iter()
+                    .filter(|d| d.kind == SymbolKind::Let)
+                    .map(|d| {
+                        json!({
+                            "name": d.name,
+                            "kind": 13, // Variable
+                            "range": self.range(&d.loc, &d.name),
+                            "selectionRange": self.range(&d.loc, &d.name),
+                        })
+                    })
+                    .collect())
+            }
+            _ => Err((-32601, format!("Unknown method `{method}`"))),
+        }
+    }
+
+    // Handles a notification, giving the notifications to send back.
+    fn notify(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.to_string(), text.to_string());
+                self.diagnostics(uri)
+            }
+            "textDocument/didChange" => {
+                // Only whole documents are asked for, so the last change has all of the text.
+                let changes = params["contentChanges"].as_array();
+                if let Some(text) = changes.and_then(|c| c.last()?["text"].as_str()) {
+                    self.documents.insert(uri.to_string(), text.to_string());
+                }
+                self.diagnostics(uri)
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                // Clears the diagnostics, since the editor won't ask about the file again.
+                let others = self.published.remove(uri).unwrap_or_default();
+                [uri.to_string()]
+                    .into_iter()
+                    .chain(others)
+                    .map(|to| publish(&to, Vec::new()))
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn symbols(&self, uri: &str) -> Result<Symbols, (i64, String)> {
+        match self.documents.get(uri) {
+            // Imports are found relative to the file, so it is named by its path if it has one.
+            Some(text) => Ok(symbols(text, &path_of(uri), &Options::default())),
+            None => Err((-32602, format!("`{uri}` isn't open"))),
+        }
+    }
+
+    // The document and position that a request is about.
+    fn at(&self, params: &Value) -> Result<(String, Symbols, usize, usize), (i64, String)> {
+        let uri = uri(params)?;
+        let position = &params["position"];
+        let (line, col) = match (position["line"].as_u64(), position["character"].as_u64()) {
+            (Some(line), Some(col)) => (line as usize, col as usize),
+            _ => return Err((-32602, "Missing position".to_string())),
+        };
+        let text = self.documents.get(uri).map_or("", String::as_str);
+        let col = from_utf16(text.lines().nth(line).unwrap_or_default(), col);
+        Ok((uri.to_string(), self.symbols(uri)?, line, col))
+    }
+
+    // The text of the file at the path, from the editor if it is open there, or else from the disk.
+    fn text_of(&self, path: &str) -> Option<String> {
+        match self.documents.iter().find(|(uri, _)| path_of(uri) == path) {
+            Some((_, text)) => Some(text.clone()),
+            None => fs::read_to_string(path).ok(),
+        }
+    }
+
+    // The URI of the file at the path: the one the editor knows it by if it is open there, or else its `file://` URI.
+    fn uri_for(&self, path: &str, current: &str) -> String {
+        let mut open = [current]
+            .into_iter()
+            .chain(self.documents.keys().map(String::as_str));
+        match open.find(|uri| path_of(uri) == path) {
+            Some(uri) => uri.to_string(),
+            None => uri_of(path),
+        }
+    }
+
+    // The range that the name written at `loc` covers, in the columns of the file it is in.
+    fn range(&self, loc: &Location, name: &str) -> Value {
+        let text = self.text_of(&loc.filename).unwrap_or_default();
+        range(loc, name, text.lines().nth(loc.line).unwrap_or_default())
+    }
+
+    // Publishes the document's diagnostics, each one to the file it is in, and clears them from the files it no longer has any in.
+    // The ones in other documents that are open are left to those documents, which publish their own.
+    fn diagnostics(&mut self, uri: &str) -> Vec<Value> {
+        let mut by_file: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+        by_file.insert(uri.to_string(), Vec::new());
+        if let Ok(symbols) = self.symbols(uri) {
+            for d in symbols.diagnostics.diagnostics() {
+                let to = self.uri_for(&d.loc.filename, uri);
+                if to != uri && self.documents.contains_key(&to) {
+                    continue;
+                }
+                let mut message = d.message.clone();
+                for note in &d.notes {
+                    message.push('\n');
+                    message.push_str(&note.message);
+                }
+                by_file.entry(to).or_default().push(json!({
+                    "range": self.range(&d.loc, " "),
+                    "severity": match d.severity {
+                        Severity::Error => 1,
+                        Severity::Warning => 2,
+                    },
+                    "source": "pale",
+                    "message": message,
+                }));
+            }
+        }
+        let others = by_file.keys().filter(|to| *to != uri).cloned().collect();
+        for old in self
+            .published
+            .insert(uri.to_string(), others)
+            .unwrap_or_default()
+        {
+            by_file.entry(old).or_default();
+        }
+        by_file
+            .into_iter()
+            .map(|(to, diagnostics)| publish(&to, diagnostics))
+            .collect()
+    }
+}
+
+fn uri(params: &Value) -> Result<&str, (i64, String)> {
+    params["textDocument"]["uri"]
+        .as_str()
+        .ok_or((-32602, "Missing document".to_string()))
+}
+
+// The range that the name written at `loc` covers, with `line` being the text of the line it is on.
+fn range(loc: &Location, name: &str, line: &str) -> Value {
+    let start = to_utf16(line, loc.col);
+    json!({
+        "start": { "line": loc.line, "character": start },
+        "end": { "line": loc.line, "character": start + name.encode_utf16().count() },
+    })
+}
+
+// Pale counts columns in bytes of UTF-8, but the protocol counts them in UTF-16 code units.
+fn to_utf16(line: &str, col: usize) -> usize {
+    (line.char_indices())
+        .take_while(|&(i, _)| i < col)
+        .map(|(_, c)| c.len_utf16())
+        .sum()
+}
+
+fn from_utf16(line: &str, character: usize) -> usize {
+    let mut units = 0;
+    (line.char_indices())
+        .find(|&(_, c)| {
+            units += c.len_utf16();
+            units > character
+        })
+        .map_or(line.len(), |(i, _)| i)
+}
+
+// The path that a `file://` URI names, with escapes like `%20` decoded. Other URIs are used as they are.
+fn path_of(uri: &str) -> String {
+    let Some(path) = uri.strip_prefix("file://") else {
+        return uri.to_string();
+    };
+    let mut bytes = Vec::new();
+    let mut rest = path.as_bytes();
+    while let Some((&b, after)) = rest.split_first() {
+        match after.get(..2) {
+            Some(hex) if b == b'%' && hex.iter().all(u8::is_ascii_hexdigit) => {
+                let hex = std::str::from_utf8(hex).expect("hex digits are ASCII");
+                bytes.push(u8::from_str_radix(hex, 16).expect("they were checked"));
+                rest = &after[2..];
+            }
+            _ => {
+                bytes.push(b);
+                rest = after;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+// The `file://` URI of the path, escaping what can't be written in one as it is.
+fn uri_of(path: &str) -> String {
+    let mut uri = String::from("file://");
+    for b in path.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(char::from(b))
+            }
+            _ => uri.push_str(&format!("%{b:02X}")),
+        }
+    }
+    uri
+}
+
+fn publish(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+// What to show when hovering over the position: how an intrinsic is used, or where a name was bound.
+fn hover(symbols: &Symbols, line: usize, col: usize) -> Option<String> {
+    let name = symbols.name_at(line, col)?;
+    match symbols.definition_at(line, col) {
+        Some(def) => {
+            let how = match def.kind {
+                SymbolKind::Let => "Bound by `let`",
+                SymbolKind::Parameter => "A parameter",
+                SymbolKind::Import => "Imported",
+            };
+            let from = match &def.from {
+                Some(from) => format!(" from `{}`", from.filename),
+                None => String::new(),
+            };
+            Some(format!(
+                "`{name}`\n\n{how}{from} on line {}.",
+                def.loc.line + 1
+            ))
+        }
+        None => {
+            let (usage, doc) = intrinsic_doc(name)?.split_once('\n')?;
+            Some(format!("```pale\n{usage}\n```\n{doc}"))
+        }
+    }
+}
+
+fn main() -> Result<(), Box<dyn error::Error>> {
+    let mut input = BufReader::new(io::stdin().lock());
+    let mut output = io::stdout().lock();
+    let mut server = Server::default();
+    while let Some(message) = rpc::read(&mut input)? {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        if method == "exit" {
+            std::process::exit(if server.shutting_down { 0 } else { 1 });
+        }
+        match message.get("id") {
+            Some(id) => {
+                let response = match server.request(method, params) {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err((code, error)) => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": code, "message": error },
+                    }),
+                };
+                rpc::write(&mut output, &response)?;
+            }
+            None => {
+                for notification in server.notify(method, params) {
+                    rpc::write(&mut output, &notification)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::{from_utf16, path_of, to_utf16, uri_of, Server};
+
+    fn open(server: &mut Server, uri: &str, text: &str) -> Vec<Value> {
+        let params = json!({ "textDocument": { "uri": uri, "text": text } });
+        server.notify("textDocument/didOpen", &params)
+    }
+
+    #[test]
+    fn test_columns_and_uris() {
+        // `é` is two bytes and one UTF-16 code unit, and `😀` is four bytes and two code units.
+        assert_eq!(to_utf16("é😀x", 6), 3);
+        assert_eq!(from_utf16("é😀x", 3), 6);
+        assert_eq!(from_utf16("é😀x", 2), 2);
+        assert_eq!(from_utf16("é😀x", 10), 7);
+        assert_eq!(path_of("file:///tmp/a%20b/%C3%A9.pale"), "/tmp/a b/é.pale");
+        assert_eq!(path_of("file:///tmp/100%"), "/tmp/100%");
+        assert_eq!(path_of("untitled:Untitled-1"), "untitled:Untitled-1");
+        assert_eq!(uri_of("/tmp/a b/é.pale"), "file:///tmp/a%20b/%C3%A9.pale");
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let mut server = Server::default();
+        let uri = "file:///tmp/main.pale";
+        let published = open(&mut server, uri, "(print \"😀\" y)");
+        assert_eq!(published.len(), 1);
+        let params = &published[0]["params"];
+        assert_eq!(params["uri"], uri);
+        let diagnostic = &params["diagnostics"][0];
+        assert_eq!(diagnostic["message"], "Unknown identifier `y`!");
+        assert_eq!(diagnostic["severity"], 1);
+        assert_eq!(
+            diagnostic["range"],
+            json!({ "start": { "line": 0, "character": 12 }, "end": { "line": 0, "character": 13 } })
+        );
+        // Positions sent by the editor are in UTF-16 code units too.
+        let params =
+            json!({ "textDocument": { "uri": uri }, "position": { "line": 0, "character": 12 } });
+        let (_, _, _, col) = server.at(&params).unwrap();
+        assert_eq!(col, 14);
+
+        // An error in an imported file is published to that file.
+        let dir = std::env::temp_dir().join(format!("pale lsp-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("lib.pale"), "(print missing)\n(provide)").unwrap();
+        let dir = std::fs::canonicalize(&dir).unwrap();
+        let main = uri_of(dir.join("main.pale").to_str().unwrap());
+        let lib = uri_of(dir.join("lib.pale").to_str().unwrap());
+        assert!(main.contains("pale%20lsp-"), "{main}");
+        let published = open(&mut server, &main, "(import \"lib.pale\")");
+        let to = |published: &[Value], uri: &str| {
+            published
+                .iter()
+                .find(|p| p["params"]["uri"] == uri)
+                .map(|p| p["params"]["diagnostics"].as_array().unwrap().len())
+        };
+        assert_eq!(to(&published, &lib), Some(1), "{published:?}");
+        assert_eq!(to(&published, &main), Some(0), "{published:?}");
+        // Once the document stops importing it, its diagnostics there are cleared.
+        let params = json!({
+            "textDocument": { "uri": main },
+            "contentChanges": [{ "text": "(print 1)" }],
+        });
+        let published = server.notify("textDocument/didChange", &params);
+        assert_eq!(to(&published, &lib), Some(0), "{published:?}");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,79 @@
+//! Reading and writing JSON-RPC messages the way the Language Server Protocol frames them, with a `Content-Length` header before each one.
+
+use std::io::{self, BufRead, Write};
+
+use serde_json::Value;
+
+/// Reads the next message, or `None` if the input has ended.
+pub fn read<R: BufRead>(input: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message without a Content-Length",
+        )
+    })?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn write<W: Write>(output: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, ErrorKind};
+
+    use serde_json::json;
+
+    #[test]
+    fn test_framing() {
+        let mut framed = Vec::new();
+        let first = json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize" });
+        // The length is in bytes, which isn't the number of chars.
+        let second = json!({ "jsonrpc": "2.0", "method": "note", "params": "é😀" });
+        super::write(&mut framed, &first).unwrap();
+        super::write(&mut framed, &second).unwrap();
+        let text = String::from_utf8(framed.clone()).unwrap();
+        assert!(text.starts_with("Content-Length: 46\r\n\r\n{"), "{text}");
+        let mut input = Cursor::new(framed);
+        assert_eq!(super::read(&mut input).unwrap(), Some(first));
+        assert_eq!(super::read(&mut input).unwrap(), Some(second));
+        assert_eq!(super::read(&mut input).unwrap(), None);
+
+        // Other headers are skipped, and the name of the length isn't case sensitive.
+        let body = r#"{"id":2}"#;
+        let mut input = Cursor::new(format!(
+            "content-type: application/vscode-jsonrpc\r\ncontent-length: {}\r\n\r\n{body}",
+            body.len()
+        ));
+        assert_eq!(super::read(&mut input).unwrap(), Some(json!({ "id": 2 })));
+        let mut input = Cursor::new(format!("\r\n{body}"));
+        let err = super::read(&mut input).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        // A message that ends early is an error, rather than the end of the input.
+        let mut input = Cursor::new("Content-Length: 100\r\n\r\n{}");
+        assert!(super::read(&mut input).is_err());
+    }
+}
@@ -0,0 +1,81 @@
+//! `#[derive(IntoLisp, FromLisp)]` for structs, which is used through the `derive` feature of `pale` rather than directly.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident};
+
+// The fields of the struct, with the names of the keywords they are stored under.
+fn named_fields(input: &DeriveInput) -> syn::Result<Vec<(&Ident, String)>> {
+    let fields = match &input.data {
+        Data::Struct(s) => match &s.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "only structs with named fields can be converted to and from maps",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "only structs can be converted to and from maps",
+            ))
+        }
+    };
+    Ok(fields
+        .iter()
+        .filter_map(|f| f.ident.as_ref())
+        .map(|ident| {
+            let name = ident.to_string();
+            let name = name.strip_prefix("r#").unwrap_or(&name).replace('_', "-");
+            (ident, name)
+        })
+        .collect())
+}
+
+/// Makes the struct into a map from keywords named after its fields to their values.
+#[proc_macro_derive(IntoLisp)]
+pub fn derive_into_lisp(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let fields = match named_fields(&input) {
+        Ok(fields) => fields,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let (idents, keys): (Vec<_>, Vec<_>) = fields.into_iter().unzip();
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    quote! {
+        impl #impl_generics ::pale::IntoLisp for #name #ty_generics #where_clause {
+            fn into_lisp(self) -> ::pale::Value {
+                ::pale::Value::from(::std::vec![
+                    #((::pale::Value::keyword(#keys), ::pale::IntoLisp::into_lisp(self.#idents))),*
+                ])
+            }
+        }
+    }
+    .into()
+}
+
+/// Reads the struct from a map, the way `IntoLisp` writes it.
+#[proc_macro_derive(FromLisp)]
+pub fn derive_from_lisp(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let fields = match named_fields(&input) {
+        Ok(fields) => fields,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let (idents, keys): (Vec<_>, Vec<_>) = fields.into_iter().unzip();
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    quote! {
+        impl #impl_generics ::pale::FromLisp for #name #ty_generics #where_clause {
+            fn from_lisp(value: ::pale::Value) -> ::std::result::Result<Self, ::pale::ConversionError> {
+                ::std::result::Result::Ok(Self {
+                    #(#idents: ::pale::FromLisp::from_lisp(value.field(#keys)?)?),*
+                })
+            }
+        }
+    }
+    .into()
+}
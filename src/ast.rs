@@ -2,433 +2,2093 @@
 
 use crate::callable::IntrinsicOp;
 use crate::error::LispErrors;
-use crate::tokens::{KeyWord, Token, TokenType};
-use crate::types::LispType;
+use crate::eval::{eval, with_options};
+use crate::names::{Name, Names};
+use crate::options::{Edition, Options};
+use crate::symbols::SymbolKind;
+use crate::sync::{Lock, Once, ReadGuard, Shared, WriteGuard};
+use crate::tokens::{tokenize_with, KeyWord, Token, TokenType, Tokenized};
+use crate::types::{LispType, TypeName};
+use crate::vm::{self, Code};
 use crate::Location;
 use std::{
-    cell::{Ref, RefCell, RefMut},
-    collections::BTreeMap,
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, HashMap},
     fmt::Display,
-    rc::Rc,
+    fs,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
 };
 
+#[cfg(not(feature = "sync"))]
+use std::cell::Cell;
+#[cfg(feature = "sync")]
+use std::sync::atomic::AtomicUsize;
+
+#[cfg(not(feature = "sync"))]
+thread_local! {
+    // How many slots are alive on this thread, so that leaks can be noticed.
+    static LIVE: Cell<usize> = const { Cell::new(0) };
+}
+// With the `sync` feature values can be dropped on a different thread from the one that made them, so they are counted together.
+#[cfg(feature = "sync")]
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+// Counts a slot that has been made, or one that has been dropped.
+#[cfg(not(feature = "sync"))]
+fn count_live(made: bool) {
+    LIVE.with(|l| l.set(if made { l.get() + 1 } else { l.get() - 1 }));
+}
+#[cfg(feature = "sync")]
+fn count_live(made: bool) {
+    match made {
+        true => LIVE.fetch_add(1, Ordering::Relaxed),
+        false => LIVE.fetch_sub(1, Ordering::Relaxed),
+    };
+}
+
+thread_local! {
+    // The values of slots that have been dropped, which are dropped one after another rather than inside each other, so that long chains of them don't run out of Rust stack.
+    static DROPPING: RefCell<Option<Vec<LispType>>> = const { RefCell::new(None) };
+}
+
+/// How many values are alive on this thread, counting each slot that a name is bound to as well as the values themselves.
+/// With the `sync` feature, it counts the values on every thread.
+///
+/// Values are reference counted, so this goes back down as soon as nothing refers to them,
+/// except for values that refer to each other, like a channel that was sent into itself, which are only freed by [`collect_cycles`](crate::collect_cycles).
+/// It is meant for tests and tools looking for values that are kept alive for longer than they should be.
+#[cfg(not(feature = "sync"))]
+pub fn live_values() -> usize {
+    LIVE.with(Cell::get)
+}
+#[cfg(feature = "sync")]
+pub fn live_values() -> usize {
+    LIVE.load(Ordering::Relaxed)
+}
+
 #[derive(Debug, PartialEq)]
-pub struct Var {
-    pub(crate) dat: Rc<RefCell<LispType>>,
+pub(crate) struct Slot {
+    value: Lock<LispType>,
 }
 
-impl Display for Var {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", *self.get())
+impl Drop for Slot {
+    fn drop(&mut self) {
+        count_live(false);
+        let value = self.value.replace(LispType::Nil);
+        // If this thread is already exiting the value is dropped as usual.
+        let _ = DROPPING.try_with(move |dropping| {
+            match &mut *dropping.borrow_mut() {
+                Some(queue) => return queue.push(value),
+                none => *none = Some(vec![value]),
+            }
+            loop {
+                let next = dropping.borrow_mut().as_mut().and_then(Vec::pop);
+                match next {
+                    Some(value) => drop(value),
+                    None => break,
+                }
+            }
+            *dropping.borrow_mut() = None;
+        });
     }
 }
 
 #[derive(Debug, PartialEq)]
-pub(crate) struct Statement {
-    pub(crate) args: Vec<Var>,
-    pub(crate) op: Var, // The inner value must be callable, so this won't panic (I hope)
-    pub(crate) res: RefCell<Option<Var>>,
-    pub(crate) loc: Location,
+pub struct Var {
+    pub(crate) dat: Shared<Slot>,
 }
 
-impl Statement {
-    pub(crate) fn resolve(&self) -> Result<Var, LispErrors> {
-        let r = self.op.get().unwrap_func().call(&self.args, &self.loc);
-        if let Ok(s) = &r {
-            *self.res.borrow_mut() = Some(s.new_ref());
-        }
-        r
+impl Display for Var {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", *self.get())
     }
 }
 
 #[allow(dead_code)]
 impl Var {
     pub(crate) fn new<T: Into<LispType>>(i: T) -> Var {
+        count_live(true);
         Var {
-            dat: Rc::new(RefCell::new(i.into())),
+            dat: Shared::new(Slot {
+                value: Lock::new(i.into()),
+            }),
         }
     }
     pub(crate) fn new_ref(&self) -> Var {
         Var {
-            dat: Rc::clone(&self.dat),
+            dat: Shared::clone(&self.dat),
         }
     }
-    pub(crate) fn get(&self) -> Ref<LispType> {
-        self.dat.borrow()
+    pub(crate) fn get(&self) -> ReadGuard<'_, LispType> {
+        self.dat.value.read()
     }
-    pub(crate) fn get_mut(&self) -> RefMut<LispType> {
-        self.dat.borrow_mut()
+    pub(crate) fn get_mut(&self) -> WriteGuard<'_, LispType> {
+        self.dat.value.write()
     }
-    pub(crate) fn resolve(&self) -> Result<Self, LispErrors> {
-        match &*self.dat.borrow() {
-            LispType::Statement(s) => s.resolve(),
-            _ => Ok(self.new_ref()),
+    /// The value that the variable refers to, following the slots that names are bound to.
+    pub(crate) fn resolve(&self) -> Var {
+        match &*self.dat.value.read() {
+            LispType::Var(v) => v.resolve(),
+            _ => self.new_ref(),
         }
     }
+    /// Makes this slot refer to the value.
+    pub(crate) fn bind(&self, value: Var) {
+        *self.dat.value.write() = LispType::Var(value);
+    }
+    pub(crate) fn same(&self, other: &Var) -> bool {
+        Shared::ptr_eq(&self.dat, &other.dat)
+    }
     pub(crate) fn unwrap(self) -> LispType {
-        Rc::try_unwrap(self.dat).unwrap().into_inner()
+        Shared::try_unwrap(self.dat)
+            .unwrap()
+            .value
+            .replace(LispType::Nil)
     }
 }
 
+/// The names that can be used while parsing, and the slots they are bound to.
+/// Names are only looked up here while parsing, since `Expr::Var` refers to where its value is kept directly, so evaluating doesn't touch strings at all.
 #[derive(Debug)]
 pub(crate) struct Scope {
-    pub(crate) vars: BTreeMap<String, Var>,
+    // Every name that has been bound here, which the maps of names are keyed by so that looking one up in each frame only hashes a number.
+    names: Names,
+    // The names bound at the top level, which stay around for later evaluations.
+    vars: HashMap<Name, Var>,
+    // The names bound inside the functions that are being parsed, innermost last, which go away once the function has been parsed.
+    frames: Vec<FrameScope>,
+    pub(crate) modules: Modules,
+    // The names that the modules imported here bind without providing them, so that using one can say why it can't be.
+    private: Vec<Private>,
+    /// Whether `import` and `load` can read files, which it can't once [`Capability::Files`](crate::Capability::Files) has been denied.
+    pub(crate) files: bool,
+}
+
+/// The modules that have been imported, so that each file is only loaded and run once however many times it is imported.
+#[derive(Debug, Default)]
+pub(crate) struct Modules {
+    loaded: BTreeMap<PathBuf, Shared<Module>>,
+    // The files that are being parsed, each one imported or loaded by the one before it, so that cycles can be caught.
+    loading: Vec<Reading>,
+    // The names bound by deferred imports of modules that are still being parsed.
+    deferred: Vec<Deferred>,
+    /// Every file that has been imported or loaded.
+    pub(crate) read: BTreeSet<PathBuf>,
 }
 
+// A file that is being parsed, and the `import` or `load` that reads it.
+#[derive(Debug)]
+struct Reading {
+    path: PathBuf,
+    written: String, // The path as it is written in the file that reads it, which messages use
+    keyword: String,
+    loc: Location, // Where the path is written in the file that reads it
+}
+
+// A name that a deferred import bound before the module providing it had been parsed, to a slot that is filled once it has been.
+#[derive(Debug)]
+struct Deferred {
+    path: PathBuf,
+    written: String, // The path as it is written in the import
+    name: String,
+    slot: Var,
+    loc: Location, // Where the name is written in the import
+}
+
+// A name that an imported module binds at its top level without providing it.
+#[derive(Debug)]
+struct Private {
+    name: String, // As it would be written here, with the prefix of the import
+    path: String, // The path of the module, as it is written in the import
+    loc: Location,
+}
+
+/// A file that has been imported, and the bindings it provides.
+#[derive(Debug)]
+pub(crate) struct Module {
+    program: Program,
+    // The names it binds at its top level that it doesn't provide.
+    private: Vec<String>,
+    ran: AtomicBool,
+}
+
+impl Module {
+    /// Runs the module's code unless it has already been, so that what it provides is bound.
+    pub(crate) fn run(&self) -> Result<(), LispErrors> {
+        if !self.ran.swap(true, Ordering::Relaxed) {
+            self.program.resolve()?;
+        }
+        Ok(())
+    }
+}
+
+// The names bound inside of one function that is being parsed, which each call keeps in its own frame of slots.
+#[derive(Debug, Default)]
+struct FrameScope {
+    names: HashMap<Name, (usize, Var)>, // Which slot of the frame each name is in, and the slot that tells it apart
+    // Where the value of each slot comes from: the slot of the function around this one that it captures, or `None` if it is bound by the call.
+    slots: Vec<Option<usize>>,
+}
+
+impl FrameScope {
+    // The slot that holds the slot `outer` of the function around this one, which is added the first time it is needed.
+    fn capture(&mut self, outer: usize) -> usize {
+        match self.slots.iter().position(|&s| s == Some(outer)) {
+            Some(slot) => slot,
+            None => {
+                self.slots.push(Some(outer));
+                self.slots.len() - 1
+            }
+        }
+    }
+}
+
+impl Scope {
+    /// What the name is bound to at the top level, if it is bound.
+    pub(crate) fn global(&self, name: &str) -> Option<&Var> {
+        self.vars.get(&self.names.find(name)?)
+    }
+
+    /// Binds the name at the top level, giving what it was bound to before.
+    pub(crate) fn bind_global(&mut self, name: &str, var: Var) -> Option<Var> {
+        self.vars.insert(self.names.intern(name), var)
+    }
+
+    /// Unbinds the name at the top level, giving what it was bound to.
+    pub(crate) fn unbind_global(&mut self, name: &str) -> Option<Var> {
+        self.vars.remove(&self.names.find(name)?)
+    }
+
+    /// The names bound at the top level and what they are bound to, in alphabetical order.
+    pub(crate) fn globals(&self) -> Vec<(&str, &Var)> {
+        let mut globals: Vec<_> = self
+            .vars
+            .iter()
+            .map(|(&name, var)| (self.names.text(name), var))
+            .collect();
+        globals.sort_unstable_by_key(|&(name, _)| name);
+        globals
+    }
+
+    fn get(&self, name: &str) -> Option<&Var> {
+        let name = self.names.find(name)?;
+        self.frames
+            .iter()
+            .rev()
+            .find_map(|frame| frame.names.get(&name).map(|(_, var)| var))
+            .or_else(|| self.vars.get(&name))
+    }
+
+    // Where the value of the name is kept. A name bound in a function around the one being parsed is captured by each function in between.
+    fn place(&mut self, name: &str) -> Option<Place> {
+        let name = self.names.find(name)?;
+        let Some(depth) = self
+            .frames
+            .iter()
+            .rposition(|frame| frame.names.contains_key(&name))
+        else {
+            return self.vars.get(&name).map(|var| Place::Global(var.new_ref()));
+        };
+        let (mut slot, var) = &self.frames[depth].names[&name];
+        let var = var.new_ref();
+        for frame in &mut self.frames[depth + 1..] {
+            slot = frame.capture(slot);
+        }
+        Some(Place::Local(slot, var))
+    }
+
+    // Binds the name in the innermost frame, or at the top level if no function is being parsed.
+    fn insert(&mut self, name: &str, var: Var) -> Place {
+        let name = self.names.intern(name);
+        match self.frames.last_mut() {
+            Some(frame) => {
+                let slot = frame.slots.len();
+                frame.slots.push(None);
+                frame.names.insert(name, (slot, var.new_ref()));
+                Place::Local(slot, var)
+            }
+            None => {
+                self.vars.insert(name, var.new_ref());
+                Place::Global(var)
+            }
+        }
+    }
+}
+
+/// The intrinsics that every scope starts with, by name.
+pub(crate) fn intrinsics() -> Vec<(&'static str, IntrinsicOp)> {
+    let mut all = vec![
+        ("print", IntrinsicOp::Print),
+        ("eprint", IntrinsicOp::EPrint),
+        ("read-line", IntrinsicOp::ReadLine),
+        ("+", IntrinsicOp::Add),
+        ("-", IntrinsicOp::Subtract),
+        ("*", IntrinsicOp::Multiply),
+        ("list", IntrinsicOp::List),
+        ("make-map", IntrinsicOp::MakeMap),
+        ("cons", IntrinsicOp::Cons),
+        ("car", IntrinsicOp::Car),
+        ("cdr", IntrinsicOp::Cdr),
+        ("str", IntrinsicOp::Str),
+        ("exit", IntrinsicOp::Exit),
+        ("values", IntrinsicOp::Values),
+        ("quotient/remainder", IntrinsicOp::QuotientRemainder),
+        ("make-parameter", IntrinsicOp::MakeParameter),
+        ("eq?", IntrinsicOp::Eq),
+        ("eqv?", IntrinsicOp::Eqv),
+        ("equal?", IntrinsicOp::Equal),
+        ("type-of", IntrinsicOp::TypeOf),
+        ("string->int", IntrinsicOp::StringToInt),
+        ("int->string", IntrinsicOp::IntToString),
+        ("float->int", IntrinsicOp::FloatToInt),
+        ("string->float", IntrinsicOp::StringToFloat),
+        ("list->string", IntrinsicOp::ListToString),
+        ("gensym", IntrinsicOp::Gensym),
+        ("csv-read", IntrinsicOp::CsvRead),
+        ("csv-write", IntrinsicOp::CsvWrite),
+        ("bytes", IntrinsicOp::Bytes),
+        ("bytes-length", IntrinsicOp::BytesLength),
+        ("bytes->list", IntrinsicOp::BytesToList),
+        ("bytes-slice", IntrinsicOp::BytesSlice),
+        ("read-bytes", IntrinsicOp::ReadBytes),
+        ("bytes->hex", IntrinsicOp::BytesToHex),
+        ("hex->bytes", IntrinsicOp::HexToBytes),
+        ("bytes->base64", IntrinsicOp::BytesToBase64),
+        ("base64->bytes", IntrinsicOp::Base64ToBytes),
+        ("utf8->string", IntrinsicOp::Utf8ToString),
+        ("string->utf8", IntrinsicOp::StringToUtf8),
+        ("connect", IntrinsicOp::Connect),
+        ("send", IntrinsicOp::Send),
+        ("recv", IntrinsicOp::Recv),
+        ("close", IntrinsicOp::Close),
+        ("channel", IntrinsicOp::Channel),
+        #[cfg(feature = "sync")]
+        ("spawn", IntrinsicOp::Spawn),
+        ("pmap", IntrinsicOp::Pmap),
+        ("force", IntrinsicOp::Force),
+        ("after", IntrinsicOp::After),
+        ("every", IntrinsicOp::Every),
+        ("cancel-timer", IntrinsicOp::CancelTimer),
+        ("run-events", IntrinsicOp::RunEvents),
+        ("open-input-string", IntrinsicOp::OpenInputString),
+        ("open-output-string", IntrinsicOp::OpenOutputString),
+        ("get-output-string", IntrinsicOp::GetOutputString),
+        ("open-input-file", IntrinsicOp::OpenInputFile),
+        ("open-output-file", IntrinsicOp::OpenOutputFile),
+        ("current-input-port", IntrinsicOp::CurrentInputPort),
+        ("current-output-port", IntrinsicOp::CurrentOutputPort),
+        ("read-char", IntrinsicOp::ReadChar),
+        ("write-string", IntrinsicOp::WriteString),
+        ("close-port", IntrinsicOp::ClosePort),
+        ("now", IntrinsicOp::Now),
+        ("date", IntrinsicOp::Date),
+        ("parse-date", IntrinsicOp::ParseDate),
+        ("format-date", IntrinsicOp::FormatDate),
+        ("date-parts", IntrinsicOp::DateParts),
+        ("duration", IntrinsicOp::Duration),
+        ("duration->seconds", IntrinsicOp::DurationToSeconds),
+        ("date->epoch", IntrinsicOp::DateToEpoch),
+        ("epoch->date", IntrinsicOp::EpochToDate),
+        #[cfg(feature = "json")]
+        ("json-parse", IntrinsicOp::JsonParse),
+        #[cfg(feature = "json")]
+        ("json-stringify", IntrinsicOp::JsonStringify),
+        #[cfg(feature = "config")]
+        ("read-config", IntrinsicOp::ReadConfig),
+        #[cfg(feature = "regex")]
+        ("regex-match?", IntrinsicOp::RegexMatch),
+        #[cfg(feature = "regex")]
+        ("regex-find", IntrinsicOp::RegexFind),
+        #[cfg(feature = "regex")]
+        ("regex-captures", IntrinsicOp::RegexCaptures),
+        #[cfg(feature = "regex")]
+        ("regex-replace", IntrinsicOp::RegexReplace),
+        #[cfg(feature = "net")]
+        ("http-get", IntrinsicOp::HttpGet),
+        #[cfg(feature = "net")]
+        ("http-post", IntrinsicOp::HttpPost),
+    ];
+    all.extend(
+        TypeName::ALL
+            .into_iter()
+            .map(|kind| (kind.predicate(), IntrinsicOp::Is(kind))),
+    );
+    all
+}
+
+/// The source of the prelude, which defines functions in Pale on top of the intrinsics.
+const PRELUDE: &str = include_str!("prelude.pale");
+
+impl Scope {
+    /// A scope with the intrinsics, and with what the prelude defines unless the options leave it out.
+    pub(crate) fn new(options: &Options) -> Self {
+        let mut scope = Scope::default();
+        if options.prelude {
+            // The prelude is read the same way whatever the options are, e.g. however small the source limits are.
+            let defaults = Options::default();
+            let toks =
+                tokenize_with(PRELUDE, "<prelude>", &defaults).expect("The prelude can be read");
+            make_program(&toks.tokens, &mut scope, toks.edition, &defaults)
+                .and_then(|program| program.resolve())
+                .expect("The prelude can be run");
+        }
+        scope
+    }
+}
+
+/// Only the intrinsics, without the prelude.
 impl std::default::Default for Scope {
     fn default() -> Self {
-        let items = [
-            ("print", IntrinsicOp::Print),
-            ("+", IntrinsicOp::Add),
-            ("-", IntrinsicOp::Subtract),
-            ("*", IntrinsicOp::Multiply),
-        ];
+        let mut names = Names::default();
+        let vars = intrinsics()
+            .into_iter()
+            .map(|(name, op)| (names.intern(name), Var::new(op)))
+            .collect();
         Scope {
-            vars: items
-                .into_iter()
-                .map(|x| (x.0.to_string(), Var::new(x.1)))
-                .collect(),
+            names,
+            vars,
+            frames: Vec::new(),
+            modules: Modules::default(),
+            private: Vec::new(),
+            files: true,
         }
     }
 }
 
+/// Code that has been parsed and had its names looked up, ready to be evaluated.
+///
+/// The parts of it are shared so that evaluating can keep hold of the ones it still has to get to.
 #[derive(Debug)]
-struct AstParser<'a> {
-    ts: &'a [Token],
-    idents: &'a mut Scope,
-    start: &'a Location,
-    open_stack: Vec<usize>,
-    args: Vec<Var>,
-    loc: Option<Location>,
-    status: AstParserStatus,
+pub(crate) enum Expr {
+    Literal(LispType),
+    /// A name, which refers to the place that it was bound to when it was parsed.
+    Var(Place),
+    Call {
+        op: Shared<Expr>,
+        args: Vec<Shared<Expr>>,
+        tail: Option<Shared<Expr>>, // What comes after a `.` in the arguments
+        loc: Location,
+        /// Where each of the arguments starts, for errors about a particular one.
+        arg_locs: Shared<[Location]>,
+    },
+    /// Binds each value to its pattern in order, and then evaluates the body if there is one.
+    Let {
+        bindings: Vec<Binding>,
+        body: Option<Shared<Expr>>,
+    },
+    /// Gives each parameter its value while the body is evaluated, and then puts back what they were.
+    Parameterize {
+        bindings: Vec<(Shared<Expr>, Shared<Expr>)>,
+        body: Shared<Expr>,
+        loc: Location,
+    },
+    If {
+        cond: Shared<Expr>,
+        then: Shared<Expr>,
+        otherwise: Option<Shared<Expr>>,
+        loc: Location,
+    },
+    Lambda(Shared<Lambda>),
+    Do(Shared<Loop>),
+    /// Evaluates each expression in order, giving the value of the last one, or `nil` if there aren't any.
+    Seq(Vec<Shared<Expr>>),
+    /// Runs the module the first time it is reached, which binds what it provides. Its names were bound while parsing.
+    Import(Shared<Module>),
+    // `quote` has to wait for a symbol type to turn the names in what it quotes into.
 }
 
-#[derive(Debug, Clone)]
-enum AstParserStatus {
-    Normal,
-    Identifiers(usize, Vec<usize>),
+/// One binding of a `let`: the pattern, and the value that is bound to it.
+pub(crate) type Binding = (Shared<Pattern>, Shared<Expr>);
+
+/// The slots of a call to a function written in Pale, which hold its parameters, what its body binds and what it captured.
+/// Each call has its own, so that a function can be in the middle of more than one call at a time.
+pub(crate) type Locals = Shared<[Var]>;
+
+/// Where the value of a name is kept.
+#[derive(Debug)]
+pub(crate) enum Place {
+    /// A slot at the top level, which stays bound for later evaluations.
+    Global(Var),
+    /// A slot in the frame of the call that is being evaluated.
+    /// The `Var` is never bound, and is only there so that tools can tell names apart.
+    Local(usize, Var),
+}
+
+impl Place {
+    /// The value that the name is bound to, with `locals` being the frame of the call that is being evaluated.
+    pub(crate) fn get(&self, locals: &[Var]) -> Var {
+        match self {
+            Place::Global(var) => var.resolve(),
+            Place::Local(slot, _) => locals[*slot].resolve(),
+        }
+    }
+
+    pub(crate) fn bind(&self, locals: &[Var], value: Var) {
+        match self {
+            Place::Global(var) => var.bind(value),
+            Place::Local(slot, _) => locals[*slot].bind(value),
+        }
+    }
+
+    /// The slot that tells this name apart from other names, even ones with the same name.
+    pub(crate) fn id(&self) -> &Var {
+        match self {
+            Place::Global(var) | Place::Local(_, var) => var,
+        }
+    }
+
+    /// Another reference to the same place.
+    pub(crate) fn new_ref(&self) -> Place {
+        match self {
+            Place::Global(var) => Place::Global(var.new_ref()),
+            Place::Local(slot, var) => Place::Local(*slot, var.new_ref()),
+        }
+    }
 }
 
+/// A function written in Pale with `lambda`.
 #[derive(Debug)]
-enum IdentParserStatus<'a> {
-    Normal,
-    Specific {
-        introducing_loc: &'a Location,
-        ident: Option<&'a str>,
-        has_value: bool, // Whether a value has been inserted in the scope
+pub(crate) struct Lambda {
+    pub(crate) params: Vec<Place>,
+    /// The parameters written after `&key`, along with their names, which are given as `:name value` after the others.
+    pub(crate) keys: Vec<(String, Place)>,
+    /// The parameter written after `&rest`, which is bound to a list of the arguments after the others.
+    pub(crate) rest: Option<Place>,
+    /// Where each call binds the function itself, if it was bound to a name by a `let`, so that it can call itself by that name.
+    pub(crate) itself: Option<Place>,
+    /// The name it was bound to by a `let`, for the profiler.
+    pub(crate) name: Option<Shared<str>>,
+    /// Where the value of each slot in the frame of a call comes from: the slot of the frame around the function that it captures when the function is made,
+    /// or `None` for the ones that the call binds.
+    pub(crate) slots: Vec<Option<usize>>,
+    pub(crate) body: Shared<Expr>,
+    pub(crate) loc: Location,
+    pub(crate) code: Once<Shared<Code>>, // The body compiled for the VM, once it has been called by compiled code
+}
+
+impl Lambda {
+    /// How many arguments the function takes at least, and at most unless it has a `&rest` parameter.
+    /// Each keyword parameter can take two more, its keyword and its value.
+    pub(crate) fn arity(&self) -> (usize, Option<usize>) {
+        let n = self.params.len();
+        (n, self.rest.is_none().then_some(n + 2 * self.keys.len()))
+    }
+}
+
+/// A `do` loop, which has a frame of its own for each time around it so that a function made in one iteration keeps the values from that one.
+#[derive(Debug)]
+pub(crate) struct Loop {
+    /// The loop variables, along with what each one starts as, which is evaluated outside of the loop.
+    pub(crate) vars: Vec<(Place, Shared<Expr>)>,
+    /// What each variable is for the next time around, which is evaluated in the frame of the last one.
+    pub(crate) steps: Vec<Shared<Expr>>,
+    /// The loop ends once this isn't `nil`, giving the value of `result`.
+    pub(crate) test: Shared<Expr>,
+    pub(crate) result: Option<Shared<Expr>>,
+    pub(crate) body: Option<Shared<Expr>>,
+    /// Where the value of each slot in a frame of the loop comes from, like the slots of a [`Lambda`].
+    pub(crate) slots: Vec<Option<usize>>,
+    pub(crate) loc: Location,
+}
+
+impl Loop {
+    /// The frame for the first time around the loop, which captures slots from the frame around it like a function does.
+    pub(crate) fn enter(&self, outer: &[Var], values: &[Var]) -> Locals {
+        self.frame(|_, captured| outer[captured].new_ref(), values)
+    }
+
+    /// The frame for the next time around the loop, which shares what the last one captured.
+    pub(crate) fn next(&self, last: &[Var], values: &[Var]) -> Locals {
+        self.frame(|slot, _| last[slot].new_ref(), values)
+    }
+
+    // Makes a frame with the variables bound to the values, getting each captured slot from `captured`.
+    fn frame(&self, captured: impl Fn(usize, usize) -> Var, values: &[Var]) -> Locals {
+        let locals: Locals = self
+            .slots
+            .iter()
+            .enumerate()
+            .map(|(slot, from)| match from {
+                Some(outer) => captured(slot, *outer),
+                None => Var::new(LispType::Nil),
+            })
+            .collect();
+        for ((place, _), value) in self.vars.iter().zip(values) {
+            place.bind(&locals, value.new_ref());
+        }
+        locals
+    }
+}
+
+/// What a value in a `let` is bound to: a name, or a list of patterns that the items of a list are bound to in turn.
+#[derive(Debug)]
+pub(crate) enum Pattern {
+    Name(Place),
+    List {
+        items: Vec<Pattern>,
+        rest: Option<Box<Pattern>>, // What comes after a `.`, which is bound to the items after the others
+        loc: Location,
+        /// Whether it unpacks the values given by `values` in a `let-values`, rather than a list.
+        multiple: bool,
     },
 }
 
-impl<'a> AstParser<'a> {
-    fn new(ts: &'a [Token], idents: &'a mut Scope, start: &'a Location) -> Self {
-        Self {
-            ts,
-            idents,
-            start,
-            loc: None,
-            open_stack: Vec::new(),
-            args: Vec::new(),
-            status: AstParserStatus::Normal,
+impl Pattern {
+    /// Binds the value to the names in the pattern, erroring if it doesn't have the shape of the pattern.
+    pub(crate) fn bind(&self, value: Var, locals: &[Var]) -> Result<(), LispErrors> {
+        let (items, rest, loc, multiple) = match self {
+            Pattern::Name(place) => {
+                place.bind(locals, value);
+                return Ok(());
+            }
+            Pattern::List {
+                items,
+                rest,
+                loc,
+                multiple,
+            } => (items, rest, loc, *multiple),
+        };
+        let value = value.resolve();
+        // The parts are taken out first, so that the value isn't borrowed while binding them.
+        let (values, tail) = match (&*value.get(), rest) {
+            (LispType::Values(values), _) if multiple => {
+                (values.iter().map(Var::new_ref).collect(), None)
+            }
+            (other, _) if multiple => {
+                return Err(LispErrors::new()
+                    .error(
+                        loc,
+                        format!(
+                        "`let-values` needs the values given by `values`, but this is `{other}`!"
+                    ),
+                    )
+                    .note(None, "Use `let` to unpack a list."))
+            }
+            (LispType::List(values), _) => (values.iter().map(Var::new_ref).collect(), None),
+            (LispType::Pair(car, cdr), Some(_)) if items.len() == 1 => {
+                (vec![car.new_ref()], Some(cdr.new_ref()))
+            }
+            (other, _) => {
+                return Err(LispErrors::new().error(
+                    loc,
+                    format!("Only lists can be unpacked, but this is `{other}`!"),
+                ))
+            }
+        };
+        let fits = match rest {
+            Some(_) => values.len() >= items.len(),
+            None => values.len() == items.len(),
+        };
+        if !fits {
+            let at_least = if rest.is_some() { "at least " } else { "" };
+            let given = match multiple {
+                true => format!("{} were given", values.len()),
+                false => format!("the list has {}", values.len()),
+            };
+            return Err(LispErrors::new().error(
+                loc,
+                format!(
+                    "This pattern takes {at_least}{} value(s), but {given}!",
+                    items.len()
+                ),
+            ));
+        }
+        for (item, value) in items.iter().zip(&values) {
+            item.bind(value.new_ref(), locals)?;
         }
+        if let Some(rest) = rest {
+            let tail = tail.unwrap_or_else(|| {
+                let extra = values[items.len()..].iter().map(Var::resolve).collect();
+                Var::new(LispType::List(extra))
+            });
+            rest.bind(tail, locals)?;
+        }
+        Ok(())
     }
+}
 
+/// What comes before the parameter that collects the rest of the arguments.
+const REST: &str = "&rest";
+
+/// What comes before the keyword parameters.
+const KEY: &str = "&key";
+
+/// A name in the source and the slot it refers to, for tools that want to know what each name means.
+#[derive(Debug)]
+pub(crate) struct NameUse {
+    pub(crate) name: String,
+    pub(crate) loc: Location,
+    pub(crate) var: Var,
+    /// How the name is bound here, or `None` if it is only used here.
+    pub(crate) binds: Option<SymbolKind>,
+    /// Where the module that an imported name came from provides it.
+    pub(crate) from: Option<Location>,
+}
+
+struct Parser<'a> {
+    ts: &'a [Token],
+    idents: &'a mut Scope,
+    edition: Edition,
+    options: &'a Options,       // For reading the modules that are imported
+    uses: Option<Vec<NameUse>>, // Only kept when asked for, since evaluating doesn't need them
+    provided: Vec<Provided>,
+    // The name that the function about to be parsed is bound to by a `let`, and the slot that tells it apart, so that it can call itself.
+    naming: Option<(String, Var)>,
+}
+
+impl Parser<'_> {
+    // Finds the `)` that closes the statement opened at `open`.
+    fn closing(&self, open: usize) -> usize {
+        let mut depth = 0;
+        (open..self.ts.len())
+            .find(|&j| {
+                match self.ts[j].dat {
+                    TokenType::StartStmt => depth += 1,
+                    TokenType::EndStmt => depth -= 1,
+                    _ => {}
+                }
+                depth == 0
+            })
+            .unwrap() // The tokens were balanced before getting here
+    }
+
+    fn lookup(&mut self, id: &str, loc: &Location) -> Result<Place, LispErrors> {
+        match self.idents.place(id) {
+            Some(place) => {
+                self.record(id, loc, place.id(), None);
+                Ok(place)
+            }
+            None => match self.idents.private.iter().find(|p| p.name == id) {
+                Some(private) => Err(LispErrors::new()
+                    .error(loc, format!("`{id}` is private to `{}`!", private.path))
+                    .note(
+                        &private.loc,
+                        "The module is imported here, but doesn't provide it.",
+                    )
+                    .note(None, "Add it to the module's `provide` to use it here.")),
+                None => Err(LispErrors::new().error(loc, format!("Unknown identifier `{id}`!"))),
+            },
+        }
+    }
+
+    fn record(&mut self, name: &str, loc: &Location, var: &Var, binds: Option<SymbolKind>) {
+        if let Some(uses) = &mut self.uses {
+            uses.push(NameUse {
+                name: name.to_string(),
+                loc: loc.clone(),
+                var: var.new_ref(),
+                binds,
+                from: None,
+            });
+        }
+    }
+
+    // Notes that the name is bound by importing it from a module, which provides it at `from`.
+    fn record_import(&mut self, name: &str, loc: &Location, var: &Var, from: &Location) {
+        if let Some(uses) = &mut self.uses {
+            uses.push(NameUse {
+                name: name.to_string(),
+                loc: loc.clone(),
+                var: var.new_ref(),
+                binds: Some(SymbolKind::Import),
+                from: Some(from.clone()),
+            });
+        }
+    }
+
+    // Makes a new slot for the name in the scope.
     fn introduce_identifier(
         &mut self,
         ident: &str,
-        value: Option<Var>,
         loc: &Location,
-    ) -> Result<(), LispErrors> {
-        let value = value.unwrap_or(Var::new(LispType::Nil));
-        let ident = ident.to_string();
-        if self.idents.vars.contains_key(&ident) {
+        kind: SymbolKind,
+    ) -> Result<Place, LispErrors> {
+        self.introduce_as(ident, loc, kind, Var::new(LispType::Nil))
+    }
+
+    // Binds the name in the scope, with `var` telling it apart from other names.
+    fn introduce_as(
+        &mut self,
+        ident: &str,
+        loc: &Location,
+        kind: SymbolKind,
+        var: Var,
+    ) -> Result<Place, LispErrors> {
+        if self.idents.get(ident).is_some() && !self.edition.allows_shadowing() {
             //TODO(#12): Shadowing
             return Err(LispErrors::new()
                 .error(loc, "Shadowing is not currently allowed!")
                 .note(None, "Change its name."));
         }
-        self.idents.vars.insert(ident, value);
-        Ok(())
+        self.record(ident, loc, &var, Some(kind));
+        Ok(self.idents.insert(ident, var))
     }
 
-    fn process_identifiers(&mut self, tokens: &[Token]) -> Result<(), LispErrors> {
-        let mut to_introduce: Vec<(&str, Option<Var>, &Location)> = Vec::new();
-        let mut status = IdentParserStatus::Normal;
-        for tok in tokens {
-            match (&tok.dat, &mut status) {
-                (TokenType::Ident(id), IdentParserStatus::Normal) => {
-                    to_introduce.push((id, None, &tok.loc))
-                }
-                (TokenType::StartStmt, IdentParserStatus::Normal) => {
-                    status = IdentParserStatus::Specific {
-                        introducing_loc: &tok.loc,
-                        ident: None,
-                        has_value: false,
-                    }
-                }
-                (
-                    TokenType::StartStmt,
-                    IdentParserStatus::Specific {
-                        introducing_loc: _,
-                        ident: None,
-                        has_value: _,
-                    },
-                ) => {
-                    return Err(
-                        LispErrors::new().error(&tok.loc, "Variable names must be literals!")
-                    )
-                }
-                (
-                    TokenType::Ident(id),
-                    IdentParserStatus::Specific {
-                        introducing_loc: l,
-                        ident: None,
-                        has_value: _,
-                    },
-                ) => {
-                    status = IdentParserStatus::Specific {
-                        introducing_loc: l,
-                        ident: Some(id),
-                        has_value: false,
-                    }
-                }
-                (
-                    TokenType::Ident(id),
-                    IdentParserStatus::Specific {
-                        introducing_loc: l,
-                        ident: Some(new_id),
-                        has_value: false,
-                    },
-                ) => match self.idents.vars.get(id.as_str()) {
-                    None => {
-                        return Err(LispErrors::new()
-                            .error(&tok.loc, format!("Unknown identifier {id:?}!")))
-                    }
-                    Some(s) => {
-                        to_introduce.push((new_id, Some(s.new_ref()), &tok.loc));
-                        status = IdentParserStatus::Specific {
-                            introducing_loc: l,
-                            ident: Some(new_id),
-                            has_value: true,
-                        }
-                    }
-                },
-                (
-                    TokenType::Ident(_),
-                    IdentParserStatus::Specific {
-                        introducing_loc: l,
-                        ident: Some(_),
-                        has_value: true,
-                    },
-                ) => {
+    // Errors unless the statement starting with the keyword at `kw` is at the top level, where what it binds stays bound.
+    fn top_level(&self, kw: usize) -> Result<(), LispErrors> {
+        match self.idents.frames.is_empty() {
+            true => Ok(()),
+            false => Err(LispErrors::new()
+                .error(
+                    &self.ts[kw].loc,
+                    format!("`{}` can't be used inside of a function!", self.ts[kw].dat),
+                )
+                .note(None, "Move it to the top level of the file.")),
+        }
+    }
+
+    // Parses the expression starting at `i`, giving it along with where the next one starts.
+    fn expr(&mut self, i: usize) -> Result<(Expr, usize), LispErrors> {
+        let tok = &self.ts[i];
+        match &tok.dat {
+            TokenType::StartStmt => {
+                let close = self.closing(i);
+                Ok((self.statement(i, close)?, close + 1))
+            }
+            TokenType::Recognizable(value) => Ok((Expr::Literal(value.clone()), i + 1)),
+            TokenType::Ident(id) => Ok((Expr::Var(self.lookup(id, &tok.loc)?), i + 1)),
+            TokenType::KeyWord(k) => Err(LispErrors::new().error(
+                &tok.loc,
+                format!("`{k}` can only come at the start of a statement!"),
+            )),
+            TokenType::Dot => Err(LispErrors::new()
+                .error(&tok.loc, "`.` is not allowed here!")
+                .note(None, "It can only come before the last argument of a call.")),
+            TokenType::EndStmt => {
+                unreachable!("Expressions never start with a closing parenthesis")
+            }
+        }
+    }
+
+    // Parses the statement from the `(` at `open` to the `)` at `close`.
+    fn statement(&mut self, open: usize, close: usize) -> Result<Expr, LispErrors> {
+        let first = &self.ts[open + 1];
+        match &first.dat {
+            TokenType::EndStmt => {
+                Err(LispErrors::new().error(&first.loc, "Empty statements are not allowed!"))
+            }
+            _ => self.rest(open + 1, close),
+        }
+    }
+
+    // Parses the tokens from `start` up to the `)` at `close` as a statement, which is how the bodies of `let` and `lambda` are written.
+    fn rest(&mut self, start: usize, close: usize) -> Result<Expr, LispErrors> {
+        match &self.ts[start].dat {
+            TokenType::KeyWord(KeyWord::Let | KeyWord::LetValues) => self.let_expr(start, close),
+            TokenType::KeyWord(KeyWord::Parameterize) => self.parameterize(start, close),
+            TokenType::KeyWord(KeyWord::Do) => self.do_loop(start, close),
+            TokenType::KeyWord(KeyWord::If) => self.if_expr(start, close),
+            TokenType::KeyWord(KeyWord::Lambda) => self.lambda(start, close),
+            TokenType::KeyWord(KeyWord::Delay) => self.delay(start, close),
+            TokenType::KeyWord(KeyWord::Import) => self.import(start, close),
+            TokenType::KeyWord(KeyWord::Provide) => self.provide(start, close),
+            TokenType::KeyWord(KeyWord::ReExport) => self.re_export(start, close),
+            TokenType::KeyWord(KeyWord::Load) => self.load(start, close),
+            _ => self.call(start, close),
+        }
+    }
+
+    // Parses the tokens up to the `)` at `close` as a call, with the first of them being the function.
+    fn call(&mut self, start: usize, close: usize) -> Result<Expr, LispErrors> {
+        let ts = self.ts;
+        let mut items = Vec::new();
+        let mut locs = Vec::new();
+        let mut dot: Option<(usize, usize)> = None; // Which token the `.` is, and how many items came before it
+        let mut i = start;
+        while i < close {
+            if let TokenType::Dot = ts[i].dat {
+                if let Some((first, _)) = dot {
                     return Err(LispErrors::new()
-                        .error(l, "Identifier not allowed here!")
-                        .note(*l, "Remove it"))
+                        .error(&ts[i].loc, "Only one `.` is allowed in a statement!")
+                        .note(&ts[first].loc, "The first one is here."));
                 }
-                (
-                    TokenType::Recognizable(value),
-                    IdentParserStatus::Specific {
-                        introducing_loc: l,
-                        ident: Some(id),
-                        has_value: _,
-                    },
-                ) => {
-                    to_introduce.push((id, Some(Var::new(value.clone())), &tok.loc));
-                    status = IdentParserStatus::Specific {
-                        introducing_loc: l,
-                        ident: Some(id),
-                        has_value: true,
-                    }
-                }
-                (
-                    TokenType::EndStmt,
-                    IdentParserStatus::Specific {
-                        introducing_loc: l,
-                        ident: Some(_),
-                        has_value: false,
-                    },
-                ) => {
+                if items.len() < 2 {
                     return Err(LispErrors::new()
-                        .error(
-                            l,
-                            "Variable defined in parentheses must have an initial value.",
-                        )
-                        .note(*l, "Remove the parentheses around it."))
+                        .error(&ts[i].loc, "`.` must come after an argument!")
+                        .note(None, "Use `[a . b]` to make a pair."));
                 }
-                (
-                    TokenType::EndStmt,
-                    IdentParserStatus::Specific {
-                        introducing_loc: _,
-                        ident: Some(_),
-                        has_value: true,
-                    },
-                ) => {
-                    status = IdentParserStatus::Normal;
-                }
-                (TokenType::KeyWord(_), _) => {
-                    return Err(LispErrors::new().error(
-                        &tok.loc,
-                        "Keywords are not allowed in variable assignments!",
-                    ))
-                }
-                (
-                    TokenType::StartStmt,
-                    &mut IdentParserStatus::Specific {
-                        introducing_loc: _,
-                        ident: Some(_id),
-                        has_value: false,
+                dot = Some((i, items.len()));
+                i += 1;
+                continue;
+            }
+            let (item, next) = self.expr(i)?;
+            items.push(Shared::new(item));
+            locs.push(ts[i].loc.clone());
+            i = next;
+        }
+        let tail = match dot {
+            Some((_, before)) if items.len() == before + 1 => {
+                locs.pop();
+                items.pop()
+            }
+            Some((i, _)) => {
+                return Err(
+                    LispErrors::new().error(&ts[i].loc, "Exactly one value must come after `.`!")
+                )
+            }
+            None => None,
+        };
+        let loc = ts[start].loc.clone();
+        let op = items.remove(0);
+        if let Expr::Literal(_) = *op {
+            // TODOO(#8): Making raw lists
+            return Err(LispErrors::new()
+                .error(&loc, "Raw lists are not available (Yet...)!")
+                .note(None, "This is not a function.")
+                .note(None, "Use the `list` intrinsic to convert this to a list."));
+        }
+        Ok(Expr::Call {
+            op,
+            args: items,
+            tail,
+            loc,
+            arg_locs: locs.drain(1..).collect(),
+        })
+    }
+
+    // Parses a `let` or `let-values` from the keyword at `kw`: its bindings, and then the body if there is one.
+    fn let_expr(&mut self, kw: usize, close: usize) -> Result<Expr, LispErrors> {
+        let open = kw + 1;
+        let multiple = self.ts[kw].dat == TokenType::KeyWord(KeyWord::LetValues);
+        if open == close || !matches!(self.ts[open].dat, TokenType::StartStmt) {
+            return Err(LispErrors::new()
+                .error(
+                    &self.ts[kw].loc,
+                    format!("`{}` must be followed by its bindings!", self.ts[kw].dat),
+                )
+                .note(
+                    None,
+                    match multiple {
+                        true => {
+                            "Write them like `(let-values (((q r) (quotient/remainder 7 2))) ...)`."
+                        }
+                        false => "Write them like `(let ((x 1) (y 2)) ...)`.",
                     },
-                ) => {
-                    return Err(
-                        LispErrors::new().error(
-                            &tok.loc,
-                            "Variables must be literals or other values (not expressions)!",
-                        ), // .note(
-                           //     None,
-                           //     "You can express this as `(let {_id}) (set id <value>)`",
-                           // )
-                           // @set
-                           // TODOO(#13): arbitrary values in `let` expressions
-                    );
+                ));
+        }
+        let end = self.closing(open);
+        let bindings = self.bindings(open + 1, end, multiple)?;
+        let body = if end + 1 < close {
+            Some(Shared::new(self.rest(end + 1, close)?))
+        } else {
+            None
+        };
+        Ok(Expr::Let { bindings, body })
+    }
+
+    // Parses the bindings of a `let` up to `end`, introducing each one as soon as it has been read so that later ones can use it.
+    // In a `let-values`, which is `multiple`, each one unpacks the values given by `values`.
+    fn bindings(
+        &mut self,
+        start: usize,
+        end: usize,
+        multiple: bool,
+    ) -> Result<Vec<Binding>, LispErrors> {
+        let ts = self.ts;
+        let mut bindings = Vec::new();
+        let mut i = start;
+        while i < end {
+            let tok = &ts[i];
+            match &tok.dat {
+                TokenType::Ident(_) if multiple => return Err(values_usage(&tok.loc)),
+                TokenType::Ident(id) => {
+                    let place = self.introduce_identifier(id, &tok.loc, SymbolKind::Let)?;
+                    bindings.push((
+                        Shared::new(Pattern::Name(place)),
+                        Shared::new(Expr::Literal(LispType::Nil)),
+                    ));
+                    i += 1;
                 }
-                (
-                    TokenType::StartStmt,
-                    &mut IdentParserStatus::Specific {
-                        introducing_loc: _,
-                        ident: Some(_id),
-                        has_value: true,
-                    },
-                ) => {
-                    return Err(LispErrors::new()
-                        .error(&tok.loc, "Unknown opening parenthesis.")
-                        .note(&tok.loc, "Delete it."));
+                TokenType::StartStmt => {
+                    let close = self.closing(i);
+                    bindings.push(self.binding(i, close, multiple)?);
+                    i = close + 1;
                 }
-                (TokenType::EndStmt, _) => unreachable!(),
-                (TokenType::Recognizable(_), IdentParserStatus::Normal) => {
+                TokenType::Recognizable(_) => {
                     return Err(LispErrors::new()
                         .error(&tok.loc, "Unknown literal in `let` statement.")
                         .note(None, "Bind it to a variable name.")
                         .note(&tok.loc, "Delete it."))
                 }
-                (
-                    TokenType::Recognizable(_),
-                    IdentParserStatus::Specific {
-                        introducing_loc: _,
-                        ident: None,
-                        has_value: _,
-                    },
-                ) => {
-                    return Err(LispErrors::new().error(&tok.loc, "Cannot assign to literal value!"))
+                other => return Err(not_assignable(other, &tok.loc)),
+            }
+        }
+        Ok(bindings)
+    }
+
+    // Parses one `(name value)` or `((pattern...) value)` binding, from the `(` at `open` to the `)` at `close`.
+    fn binding(
+        &mut self,
+        open: usize,
+        close: usize,
+        multiple: bool,
+    ) -> Result<Binding, LispErrors> {
+        let ts = self.ts;
+        let (loc, name_tok) = (&ts[open].loc, &ts[open + 1]);
+        let (name, value_at) = match &name_tok.dat {
+            TokenType::StartStmt => ("a pattern".to_string(), self.closing(open + 1) + 1),
+            _ if multiple => return Err(values_usage(&name_tok.loc)),
+            TokenType::Ident(id) => (format!("`{id}`"), open + 2),
+            TokenType::Recognizable(_) => {
+                return Err(
+                    LispErrors::new().error(&name_tok.loc, "Cannot assign to literal value!")
+                )
+            }
+            TokenType::EndStmt => {
+                return Err(
+                    LispErrors::new().error(&name_tok.loc, "Empty statements are not allowed!")
+                )
+            }
+            other => return Err(not_assignable(other, &name_tok.loc)),
+        };
+        if value_at == close {
+            return Err(LispErrors::new()
+                .error(
+                    loc,
+                    "Variable defined in parentheses must have an initial value.",
+                )
+                .note(loc, "Remove the parentheses around it."));
+        }
+        let value_tok = &ts[value_at];
+        // A function bound to a name can call itself by it, since each call binds the name to the function in its own frame.
+        let named = match (&name_tok.dat, &value_tok.dat, ts.get(value_at + 1)) {
+            (TokenType::Ident(id), TokenType::StartStmt, Some(next))
+                if next.dat == TokenType::KeyWord(KeyWord::Lambda) =>
+            {
+                Some((id, Var::new(LispType::Nil)))
+            }
+            _ => None,
+        };
+        self.naming = named
+            .as_ref()
+            .map(|(id, var)| (id.to_string(), var.new_ref()));
+        let (value, next) = match &value_tok.dat {
+            TokenType::KeyWord(_) | TokenType::Dot => {
+                return Err(not_assignable(&value_tok.dat, &value_tok.loc))
+            }
+            _ => self.expr(value_at)?,
+        };
+        if next != close {
+            return Err(LispErrors::new()
+                .error(
+                    &ts[next].loc,
+                    format!("Only one value can be bound to {name}!"),
+                )
+                .note(&ts[next].loc, "Delete it."));
+        }
+        // The names are only introduced after the value, so the value can't use them unless it is a function.
+        let (mut pattern, _) = match named {
+            Some((id, inner)) => {
+                // The function used its own slot for its name, since the one here would keep the function alive for as long as the function keeps it.
+                // Tools see them as the same name.
+                let var = Var::new(LispType::Nil);
+                for use_ in self.uses.iter_mut().flatten() {
+                    if use_.var.same(&inner) {
+                        use_.var = var.new_ref();
+                    }
                 }
+                let place = self.introduce_as(id, &name_tok.loc, SymbolKind::Let, var)?;
+                (Pattern::Name(place), open + 2)
             }
+            None => self.pattern(open + 1)?,
+        };
+        if let Pattern::List { multiple: m, .. } = &mut pattern {
+            *m = multiple;
+        }
+        Ok((Shared::new(pattern), Shared::new(value)))
+    }
+
+    // Parses a `parameterize` from the keyword at `kw`: the parameters with their values, and then the body.
+    fn parameterize(&mut self, kw: usize, close: usize) -> Result<Expr, LispErrors> {
+        let ts = self.ts;
+        let open = kw + 1;
+        if open == close || !matches!(ts[open].dat, TokenType::StartStmt) {
+            return Err(parameterize_usage(&ts[kw].loc));
         }
-        for (ident, value, loc) in to_introduce {
-            self.introduce_identifier(ident, value, loc)?;
+        let end = self.closing(open);
+        if end + 1 == close {
+            return Err(LispErrors::new()
+                .error(&ts[kw].loc, "`parameterize` must have a body!")
+                .note(
+                    None,
+                    "Its values are only given for as long as the body is evaluated.",
+                ));
         }
-        Ok(())
+        let mut bindings = Vec::new();
+        let mut i = open + 1;
+        while i < end {
+            let pair_close = match ts[i].dat {
+                TokenType::StartStmt => self.closing(i),
+                _ => return Err(parameterize_usage(&ts[i].loc)),
+            };
+            if i + 1 == pair_close {
+                return Err(parameterize_usage(&ts[i].loc));
+            }
+            let (parameter, next) = self.expr(i + 1)?;
+            if next == pair_close {
+                return Err(parameterize_usage(&ts[i].loc));
+            }
+            let (value, after) = self.expr(next)?;
+            if after != pair_close {
+                return Err(LispErrors::new()
+                    .error(
+                        &ts[after].loc,
+                        "Only one value can be given to a parameter!",
+                    )
+                    .note(&ts[after].loc, "Delete it."));
+            }
+            bindings.push((Shared::new(parameter), Shared::new(value)));
+            i = pair_close + 1;
+        }
+        let body = self.rest(end + 1, close)?;
+        Ok(Expr::Parameterize {
+            bindings,
+            body: Shared::new(body),
+            loc: ts[kw].loc.clone(),
+        })
+    }
+
+    // Parses the pattern starting at `i`, introducing its names, and gives it along with where the next token after it is.
+    fn pattern(&mut self, i: usize) -> Result<(Pattern, usize), LispErrors> {
+        let ts = self.ts;
+        let tok = &ts[i];
+        let open = match &tok.dat {
+            TokenType::Ident(id) => {
+                let place = self.introduce_identifier(id, &tok.loc, SymbolKind::Let)?;
+                return Ok((Pattern::Name(place), i + 1));
+            }
+            TokenType::StartStmt => i,
+            TokenType::Recognizable(_) => {
+                return Err(LispErrors::new().error(&tok.loc, "Cannot assign to literal value!"))
+            }
+            other => return Err(not_assignable(other, &tok.loc)),
+        };
+        let close = self.closing(open);
+        let mut items = Vec::new();
+        let mut rest = None;
+        let mut i = open + 1;
+        while i < close {
+            if let TokenType::Dot = ts[i].dat {
+                let after = match ts[i + 1].dat {
+                    TokenType::StartStmt => self.closing(i + 1) + 1,
+                    _ => i + 2,
+                };
+                if items.is_empty() || i + 1 == close || after != close {
+                    return Err(LispErrors::new()
+                        .error(
+                            &ts[i].loc,
+                            "`.` in a pattern must come between the other names and one more for the rest!",
+                        )
+                        .note(None, "Write it like `((first . rest) my-list)`."));
+                }
+                rest = Some(Box::new(self.pattern(i + 1)?.0));
+                break;
+            }
+            let (item, next) = self.pattern(i)?;
+            items.push(item);
+            i = next;
+        }
+        if items.is_empty() && rest.is_none() {
+            return Err(LispErrors::new().error(&tok.loc, "Empty patterns are not allowed!"));
+        }
+        Ok((
+            Pattern::List {
+                items,
+                rest,
+                loc: tok.loc.clone(),
+                multiple: false,
+            },
+            close + 1,
+        ))
     }
 
-    fn parse(mut self) -> Result<Statement, LispErrors> {
-        if self.ts.len() < 2 {
-            return Err(LispErrors::new().error(self.start, "Empty statements are not allowed!"));
+    // Parses `(if condition then otherwise)` from the keyword at `kw`, where `otherwise` can be left out.
+    fn if_expr(&mut self, kw: usize, close: usize) -> Result<Expr, LispErrors> {
+        let mut parts = Vec::new();
+        let mut i = kw + 1;
+        while i < close {
+            let (part, next) = self.expr(i)?;
+            parts.push(part);
+            i = next;
         }
-        let mut start_idx = 0;
-        if let TokenType::StartStmt = self.ts[start_idx].dat {
-            start_idx = 1;
+        if !(2..=3).contains(&parts.len()) {
+            return Err(LispErrors::new()
+                .error(
+                    &self.ts[kw].loc,
+                    format!(
+                        "`if` takes a condition and one or two values, but was given {} part(s)!",
+                        parts.len()
+                    ),
+                )
+                .note(None, "Write it like `(if condition then otherwise)`."));
         }
-        let mut end_idx = self.ts.len() - 1;
-        if let TokenType::EndStmt = self.ts[end_idx].dat {
-            end_idx -= 1;
+        let mut parts = parts.into_iter().map(Shared::new);
+        Ok(Expr::If {
+            cond: parts.next().unwrap(),
+            then: parts.next().unwrap(),
+            otherwise: parts.next(),
+            loc: self.ts[kw].loc.clone(),
+        })
+    }
+
+    // Parses `(lambda (params...) body...)` from the keyword at `kw`. The parameters are only in scope in the body.
+    fn lambda(&mut self, kw: usize, close: usize) -> Result<Expr, LispErrors> {
+        let ts = self.ts;
+        let open = kw + 1;
+        if open == close || !matches!(ts[open].dat, TokenType::StartStmt) {
+            return Err(LispErrors::new()
+                .error(&ts[kw].loc, "`lambda` must be followed by its parameters!")
+                .note(None, "Write them like `(lambda (x y) + x y)`."));
         }
-        if start_idx > end_idx {
-            return Err(LispErrors::new().error(self.start, "Empty statements are not allowed!"));
+        let end = self.closing(open);
+        if end + 1 == close {
+            return Err(LispErrors::new()
+                .error(&ts[kw].loc, "`lambda` needs a body!")
+                .note(
+                    None,
+                    "Write it after the parameters, like `(lambda (x y) + x y)`.",
+                ));
         }
-        for i in start_idx..=end_idx {
-            match (&mut self.status, &self.ts[i].dat) {
-                (AstParserStatus::Normal, TokenType::StartStmt) => {
-                    self.open_stack.push(i);
-                }
-                (AstParserStatus::Normal, TokenType::EndStmt) => {
-                    if let Some(o) = self.open_stack.pop() {
-                        if self.open_stack.is_empty() {
-                            self.args.push(Var::new(make_ast(
-                                &self.ts[o..=i],
-                                self.idents,
-                                &self.ts[o + 1].loc,
-                            )?));
-                        }
-                    } else {
-                        return Err(LispErrors::new()
-                            .error(&self.ts[i].loc, "Unmatched closing parentheses!")
-                            .note(None, "Delete it."));
-                    }
-                }
-                (AstParserStatus::Normal, TokenType::KeyWord(word)) => match word {
-                    KeyWord::Let => {
-                        self.status = AstParserStatus::Identifiers(i, Vec::new());
+        let mut names = Vec::new();
+        let mut keys = Vec::new();
+        let mut key: Option<usize> = None; // Where `&key` is, once it has been seen
+        let mut rest = None;
+        let mut i = open + 1;
+        while i < end {
+            let tok = &ts[i];
+            match &tok.dat {
+                TokenType::Ident(id) if id == REST => match &ts[i + 1..end] {
+                    [Token {
+                        dat: TokenType::Ident(name),
+                        loc,
+                    }] if name != REST && name != KEY => {
+                        rest = Some((name.as_str(), loc));
+                        break;
                     }
+                    _ => return Err(LispErrors::new()
+                        .error(
+                            &tok.loc,
+                            "`&rest` must be followed by one name, at the end of the parameters!",
+                        )
+                        .note(None, "Write it like `(lambda (first &rest others) ...)`.")),
                 },
-                (AstParserStatus::Normal, TokenType::Recognizable(n)) => {
-                    if self.open_stack.is_empty() {
-                        self.args.push(Var::new(n.clone()));
-                    }
-                }
-                (AstParserStatus::Normal, TokenType::Ident(id)) => match self.idents.vars.get(id) {
-                    None => {
+                TokenType::Ident(id) if id == KEY => match key {
+                    Some(first) => {
                         return Err(LispErrors::new()
-                            .error(&self.ts[i].loc, format!("Unknown identifier `{id}`!")))
-                    }
-                    Some(s) => {
-                        if self.open_stack.is_empty() {
-                            self.args.push(s.new_ref());
-                            self.loc = Some(self.ts[i].loc.clone());
-                        }
+                            .error(&tok.loc, "`&key` can only be given once!")
+                            .note(&ts[first].loc, "It is first given here."))
                     }
+                    None => key = Some(i),
                 },
-                (AstParserStatus::Identifiers(_, positions), TokenType::StartStmt) => {
-                    positions.push(i)
-                }
-                (AstParserStatus::Identifiers(start, positions), TokenType::EndStmt) => {
-                    positions.pop();
-                    if positions.is_empty() {
-                        let t = *start; // For some reason this is required for the borrow checker to allow it.
-                        self.process_identifiers(&self.ts[t + 2..i])?;
-                        self.status = AstParserStatus::Normal;
-                    }
-                }
-                (_, _) => {}
+                TokenType::Ident(id) if key.is_some() => keys.push((id.as_str(), &tok.loc)),
+                TokenType::Ident(id) => names.push((id.as_str(), &tok.loc)),
+                _ => return Err(LispErrors::new().error(&tok.loc, "Parameters must be names!")),
+            }
+            i += 1;
+        }
+        if let Some(key) = key {
+            if keys.is_empty() || rest.is_some() {
+                return Err(LispErrors::new()
+                    .error(
+                        &ts[key].loc,
+                        "`&key` must be followed by the names of the keyword parameters, and can't be used with `&rest`!",
+                    )
+                    .note(None, "Write it like `(lambda (shape &key width height) ...)`."));
             }
         }
-        if !self.open_stack.is_empty() {
+        let naming = self.naming.take();
+        self.idents.frames.push(FrameScope::default());
+        let name = naming.as_ref().map(|(name, _)| Shared::from(name.as_str()));
+        let itself = naming.map(|(name, var)| self.idents.insert(&name, var));
+        let params = names
+            .into_iter()
+            .map(|(name, loc)| self.introduce_identifier(name, loc, SymbolKind::Parameter))
+            .collect::<Result<Vec<_>, _>>()
+            .and_then(|params| {
+                let keys = keys
+                    .into_iter()
+                    .map(|(name, loc)| {
+                        let place = self.introduce_identifier(name, loc, SymbolKind::Parameter)?;
+                        Ok((name.to_string(), place))
+                    })
+                    .collect::<Result<Vec<_>, LispErrors>>()?;
+                let rest = rest
+                    .map(|(name, loc)| self.introduce_identifier(name, loc, SymbolKind::Parameter))
+                    .transpose()?;
+                Ok((params, keys, rest))
+            });
+        let body = params.and_then(|params| Ok((params, self.rest(end + 1, close)?)));
+        // The parameters and whatever the body bound go back out of scope, whether or not the body could be parsed.
+        let frame = self.idents.frames.pop().unwrap();
+        let ((params, keys, rest), body) = body?;
+        Ok(Expr::Lambda(Shared::new(Lambda {
+            params,
+            keys,
+            rest,
+            itself,
+            name,
+            slots: frame.slots,
+            body: Shared::new(body),
+            loc: ts[kw].loc.clone(),
+            code: Once::new(),
+        })))
+    }
+
+    // Parses `(delay expr)` from the keyword at `kw`. The expression becomes the body of a function with no parameters, which the `delay` intrinsic makes into a promise.
+    fn delay(&mut self, kw: usize, close: usize) -> Result<Expr, LispErrors> {
+        let ts = self.ts;
+        let loc = &ts[kw].loc;
+        if kw + 1 == close {
+            return Err(LispErrors::new()
+                .error(loc, "`delay` needs an expression to delay!")
+                .note(None, "Write it like `(delay (+ 1 2))`."));
+        }
+        self.idents.frames.push(FrameScope::default());
+        let body = self.expr(kw + 1);
+        // Whatever the expression bound goes back out of scope, whether or not it could be parsed.
+        let frame = self.idents.frames.pop().unwrap();
+        let (body, next) = body?;
+        if next != close {
+            return Err(LispErrors::new()
+                .error(&ts[next].loc, "`delay` can only delay one expression!")
+                .note(None, "Put them in a `list`, or in a `let`'s body."));
+        }
+        let thunk = Expr::Lambda(Shared::new(Lambda {
+            params: Vec::new(),
+            keys: Vec::new(),
+            rest: None,
+            itself: None,
+            name: None,
+            slots: frame.slots,
+            body: Shared::new(body),
+            loc: loc.clone(),
+            code: Once::new(),
+        }));
+        let delay = Var::new(LispType::Func(Box::new(IntrinsicOp::Delay)));
+        Ok(Expr::Call {
+            op: Shared::new(Expr::Var(Place::Global(delay))),
+            args: vec![Shared::new(thunk)],
+            tail: None,
+            loc: loc.clone(),
+            arg_locs: Shared::new([ts[kw + 1].loc.clone()]),
+        })
+    }
+
+    // Parses a `do` loop from the keyword at `kw`: its variables, the clause that ends it, and then the body if there is one.
+    fn do_loop(&mut self, kw: usize, close: usize) -> Result<Expr, LispErrors> {
+        let ts = self.ts;
+        let open = kw + 1;
+        if open == close || ts[open].dat != TokenType::StartStmt {
+            return Err(do_usage(&ts[kw].loc));
+        }
+        let end = self.closing(open);
+        let test_open = end + 1;
+        if test_open == close || ts[test_open].dat != TokenType::StartStmt {
+            return Err(do_usage(&ts[kw].loc));
+        }
+        // The starting values are evaluated outside of the loop, so they are parsed before its variables are introduced.
+        let mut vars = Vec::new();
+        let mut i = open + 1;
+        while i < end {
+            let spec_close = match ts[i].dat {
+                TokenType::StartStmt => self.closing(i),
+                _ => return Err(do_var_usage(&ts[i].loc)),
+            };
+            let (TokenType::Ident(name), true) = (&ts[i + 1].dat, i + 2 < spec_close) else {
+                return Err(do_var_usage(&ts[i].loc));
+            };
+            let (init, step) = self.expr(i + 2)?;
+            vars.push((name, &ts[i + 1].loc, Shared::new(init), step, spec_close));
+            i = spec_close + 1;
+        }
+        self.idents.frames.push(FrameScope::default());
+        let parts = self.loop_parts(vars, test_open, close);
+        // The variables and whatever the loop bound go back out of scope, whether or not it could be parsed.
+        let frame = self.idents.frames.pop().unwrap();
+        let (vars, steps, test, result, body) = parts?;
+        Ok(Expr::Do(Shared::new(Loop {
+            vars,
+            steps,
+            test,
+            result,
+            body,
+            slots: frame.slots,
+            loc: ts[kw].loc.clone(),
+        })))
+    }
+
+    // Parses the parts of a `do` loop that are evaluated inside of it, in the frame that has just been made for it.
+    // Each variable comes with its starting value, where its step starts and where its parentheses close.
+    fn loop_parts(
+        &mut self,
+        vars: Vec<(&String, &Location, Shared<Expr>, usize, usize)>,
+        test_open: usize,
+        close: usize,
+    ) -> Result<LoopParts, LispErrors> {
+        let ts = self.ts;
+        let mut places = Vec::new();
+        for (name, loc, init, _, _) in &vars {
+            let place = self.introduce_identifier(name, loc, SymbolKind::Let)?;
+            places.push((place, Shared::clone(init)));
+        }
+        let mut steps = Vec::new();
+        for ((place, _), (name, _, _, step, spec_close)) in places.iter().zip(&vars) {
+            // A variable without a step stays the same each time around.
+            if *step == *spec_close {
+                steps.push(Shared::new(Expr::Var(place.new_ref())));
+                continue;
+            }
+            let (expr, after) = self.expr(*step)?;
+            if after != *spec_close {
+                return Err(LispErrors::new()
+                    .error(
+                        &ts[after].loc,
+                        format!("Only one step can be given to `{name}`!"),
+                    )
+                    .note(&ts[after].loc, "Delete it."));
+            }
+            steps.push(Shared::new(expr));
+        }
+        let test_close = self.closing(test_open);
+        if test_open + 1 == test_close {
             return Err(LispErrors::new()
                 .error(
-                    &self.ts[self.open_stack.pop().unwrap()].loc,
-                    "Unmatched opening parentheses!",
+                    &ts[test_open].loc,
+                    "A `do` loop needs a condition to end it!",
                 )
-                .note(None, "Deleting it might fix this error."));
+                .note(None, "Write it like `((eqv? i 10) result)`."));
         }
-        let s = self.args.remove(0);
-        if let LispType::Func(_) = *s.get() {
+        let (test, next) = self.expr(test_open + 1)?;
+        let result = match next == test_close {
+            true => None,
+            false => {
+                let (result, after) = self.expr(next)?;
+                if after != test_close {
+                    return Err(LispErrors::new()
+                        .error(&ts[after].loc, "A `do` loop can only give one result!")
+                        .note(&ts[after].loc, "Delete it."));
+                }
+                Some(Shared::new(result))
+            }
+        };
+        let body = if test_close + 1 < close {
+            Some(Shared::new(self.rest(test_close + 1, close)?))
         } else {
-            // TODOO(#8): Making raw lists
-            return Err(LispErrors::new()
-                .error(self.start, "Raw lists are not available (Yet...)!")
-                .note(None, "This is not a function.")
-                .note(None, "Use the `list` intrinsic to convert this to a list."));
+            None
+        };
+        Ok((places, steps, Shared::new(test), result, body))
+    }
+
+    // Parses `(import "path")` from the keyword at `kw`, loading the module if it hasn't been already.
+    fn import(&mut self, kw: usize, close: usize) -> Result<Expr, LispErrors> {
+        let (module, _) = self.import_names(kw, close)?;
+        Ok(module.map_or(Expr::Literal(LispType::Nil), Expr::Import))
+    }
+
+    // Parses `(re-export "path")` from the keyword at `kw`, which imports the module and provides what it bound to the files that import this one.
+    fn re_export(&mut self, kw: usize, close: usize) -> Result<Expr, LispErrors> {
+        let (module, bound) = self.import_names(kw, close)?;
+        self.provided.extend(bound);
+        Ok(module.map_or(Expr::Literal(LispType::Nil), Expr::Import))
+    }
+
+    // Loads the module that the `import` or `re-export` at `kw` names, and binds what it provides, giving the module and the names that were bound.
+    // Each name is bound as `prefix/name` if there is a prefix, after it has been renamed if it is one of the names to rename.
+    // A deferred import only binds the names it lists, and if the module is still being parsed, because it is part of a cycle, they are bound before it has been and there is no module to give.
+    fn import_names(
+        &mut self,
+        kw: usize,
+        close: usize,
+    ) -> Result<(Option<Shared<Module>>, Vec<Provided>), LispErrors> {
+        self.top_level(kw)?;
+        let ts = self.ts;
+        let loc = &ts[kw].loc;
+        let keyword = &ts[kw].dat;
+        let usage = || {
+            LispErrors::new()
+                .error(
+                    loc,
+                    format!("`{keyword}` takes the path of a module, and optionally a prefix, names to rename and names to defer!"),
+                )
+                .note(
+                    None,
+                    format!("Write it like `({keyword} \"lib/math.pale\" :as math :rename ((square sq)))`."),
+                )
+        };
+        let path = match ts.get(kw + 1).map(|t| &t.dat) {
+            Some(TokenType::Recognizable(LispType::Str(path))) if kw + 1 < close => path,
+            _ => return Err(usage()),
+        };
+        let mut prefix = None;
+        let mut renames = Vec::new();
+        let mut deferred = None;
+        let mut i = kw + 2;
+        while i < close {
+            let option = match &ts[i].dat {
+                TokenType::Recognizable(LispType::Keyword(option)) if i + 1 < close => option,
+                _ => return Err(usage()),
+            };
+            match (option.as_str(), &ts[i + 1].dat) {
+                ("as", TokenType::Ident(name)) if prefix.is_none() => {
+                    prefix = Some(name);
+                    i += 2;
+                }
+                ("rename", TokenType::StartStmt) => {
+                    let end = self.closing(i + 1);
+                    renames.extend(rename_pairs(&ts[i + 2..end]).ok_or_else(usage)?);
+                    i = end + 1;
+                }
+                ("defer", TokenType::StartStmt) if deferred.is_none() => {
+                    let end = self.closing(i + 1);
+                    let names = &ts[i + 2..end];
+                    if !names.iter().all(|t| matches!(t.dat, TokenType::Ident(_))) {
+                        return Err(usage());
+                    }
+                    deferred = Some(names);
+                    i = end + 1;
+                }
+                _ => return Err(usage()),
+            }
+        }
+        self.check_files(kw)?;
+        let full = self.find(path, &ts[kw + 1].loc)?;
+        let parsing = self.idents.modules.loading.iter().any(|r| r.path == full);
+        let (module, provided): (_, Vec<Provided>) = match deferred {
+            Some(names) if parsing => {
+                let provided = names
+                    .iter()
+                    .map(|tok| {
+                        let name = tok.dat.to_string();
+                        let slot = Var::new(LispType::Nil);
+                        crate::cycles::track_slot(&slot);
+                        self.idents.modules.deferred.push(Deferred {
+                            path: full.clone(),
+                            written: path.to_string(),
+                            name: name.clone(),
+                            slot: slot.new_ref(),
+                            loc: tok.loc.clone(),
+                        });
+                        Provided {
+                            name,
+                            var: slot,
+                            loc: tok.loc.clone(),
+                        }
+                    })
+                    .collect();
+                (None, provided)
+            }
+            _ => {
+                let module = self.load_module(path, kw)?;
+                let all = &module.program.provided;
+                let provided = match deferred {
+                    Some(names) => names
+                        .iter()
+                        .map(|tok| {
+                            all.iter()
+                                .find(|p| tok.dat.to_string() == p.name)
+                                .map(Provided::new_ref)
+                                .ok_or_else(|| {
+                                    doesnt_provide(path, &tok.dat, &tok.loc, &module.private)
+                                })
+                        })
+                        .collect::<Result<_, _>>()?,
+                    None => all.iter().map(Provided::new_ref).collect(),
+                };
+                (Some(module), provided)
+            }
+        };
+        if let Some((old, new)) = renames
+            .iter()
+            .find(|(old, _)| !provided.iter().any(|p| p.name == **old))
+        {
+            return Err(LispErrors::new().error(
+                &new.loc,
+                format!("`{path}` doesn't provide `{old}`, so it can't be renamed!"),
+            ));
+        }
+        let mut bound = Vec::new();
+        for p in &provided {
+            // A renamed name is bound where its new name is written, and the others where the path is.
+            let (name, at) = match renames.iter().find(|(old, _)| **old == p.name) {
+                Some((_, new)) => (new.dat.to_string(), &new.loc),
+                None => (p.name.clone(), &ts[kw + 1].loc),
+            };
+            let name = match prefix {
+                Some(prefix) => format!("{prefix}/{name}"),
+                None => name,
+            };
+            // Importing a module again binds the names to what they are already bound to, which doesn't shadow anything.
+            let shadows = self.idents.get(&name).is_some_and(|old| !old.same(&p.var));
+            if shadows && !self.edition.allows_shadowing() {
+                return Err(LispErrors::new()
+                    .error(loc, format!("Importing `{name}` would shadow it!"))
+                    .note(&p.loc, format!("`{path}` provides it from here."))
+                    .note(None, "Import the module with a prefix, like `:as lib`, or rename it with `:rename`."));
+            }
+            self.record_import(&name, at, &p.var, &p.loc);
+            self.idents.insert(&name, p.var.new_ref());
+            bound.push(Provided {
+                name,
+                var: p.var.new_ref(),
+                loc: p.loc.clone(),
+            });
+        }
+        for name in module.iter().flat_map(|module| &module.private) {
+            self.idents.private.push(Private {
+                name: match prefix {
+                    Some(prefix) => format!("{prefix}/{name}"),
+                    None => name.clone(),
+                },
+                path: path.to_string(),
+                loc: ts[kw + 1].loc.clone(),
+            });
+        }
+        Ok((module, bound))
+    }
+
+    // It is an error to use the keyword at `kw` if files can't be read.
+    fn check_files(&self, kw: usize) -> Result<(), LispErrors> {
+        if self.idents.files {
+            return Ok(());
+        }
+        let tok = &self.ts[kw];
+        Err(LispErrors::new()
+            .error(&tok.loc, format!("`{}` can't read files here!", tok.dat))
+            .note(None, "The interpreter doesn't allow scripts to use files."))
+    }
+
+    // Finds the module at the path, and parses it if it hasn't been already.
+    fn load_module(&mut self, path: &str, kw: usize) -> Result<Shared<Module>, LispErrors> {
+        let loc = &self.ts[kw + 1].loc;
+        let full = self.locate(path, kw)?;
+        if let Some(module) = self.idents.modules.loaded.get(&full) {
+            return Ok(Shared::clone(module));
+        }
+        let toks = self.read(&full, path, loc)?;
+        // The module can only use the intrinsics and prelude functions that the file importing it can.
+        let mut scope = Scope {
+            modules: std::mem::take(&mut self.idents.modules),
+            ..Scope::new(self.options)
+        };
+        let parent = &*self.idents;
+        scope
+            .vars
+            .retain(|&name, _| parent.global(scope.names.text(name)).is_some());
+        scope.modules.loading.push(self.reading(&full, path, kw));
+        let (program, _) = parse(&toks.tokens, &mut scope, toks.edition, self.options, None);
+        scope.modules.loading.pop();
+        self.idents.modules = std::mem::take(&mut scope.modules);
+        let waiting = self.take_deferred(&full);
+        let program = program?;
+        let parent = &*self.idents;
+        let private: Vec<String> = (scope.globals().into_iter())
+            .filter(|(name, var)| {
+                parent.global(name).is_none_or(|old| !old.same(var))
+                    && !program.provided.iter().any(|p| p.name == *name)
+            })
+            .map(|(name, _)| name.to_string())
+            .collect();
+        fill_deferred(waiting, &program.provided, &private)?;
+        let module = Shared::new(Module {
+            program,
+            private,
+            ran: AtomicBool::new(false),
+        });
+        self.idents
+            .modules
+            .loaded
+            .insert(full, Shared::clone(&module));
+        Ok(module)
+    }
+
+    // Parses `(load "path")` from the keyword at `kw`, which parses the file's forms as if they were written here, so that what they bind is bound here too.
+    fn load(&mut self, kw: usize, close: usize) -> Result<Expr, LispErrors> {
+        self.top_level(kw)?;
+        let ts = self.ts;
+        let path = match &ts[kw + 1..close] {
+            [tok] => match &tok.dat {
+                TokenType::Recognizable(LispType::Str(path)) => path,
+                _ => return Err(load_usage(&ts[kw].loc)),
+            },
+            _ => return Err(load_usage(&ts[kw].loc)),
+        };
+        self.check_files(kw)?;
+        let full = self.locate(path, kw)?;
+        let toks = self.read(&full, path, &ts[kw + 1].loc)?;
+        self.idents
+            .modules
+            .loading
+            .push(self.reading(&full, path, kw));
+        let (program, uses) = parse(
+            &toks.tokens,
+            self.idents,
+            toks.edition,
+            self.options,
+            self.uses.take(),
+        );
+        self.idents.modules.loading.pop();
+        self.uses = uses;
+        let waiting = self.take_deferred(&full);
+        let program = program?;
+        fill_deferred(waiting, &program.provided, &[])?;
+        self.provided.extend(program.provided);
+        Ok(Expr::Seq(program.forms))
+    }
+
+    // Where the file at the path is, which is relative to the directory of the file being parsed.
+    fn find(&self, path: &str, loc: &Location) -> Result<PathBuf, LispErrors> {
+        let dir = Path::new(&*loc.filename).parent().unwrap_or(Path::new(""));
+        fs::canonicalize(dir.join(path))
+            .map_err(|e| LispErrors::new().error(loc, format!("Could not read `{path}`: {e}")))
+    }
+
+    // Where the file that the `import` or `load` at `kw` reads is.
+    // It is an error if the file is already being read, since it would go on reading itself forever.
+    fn locate(&self, path: &str, kw: usize) -> Result<PathBuf, LispErrors> {
+        let loc = &self.ts[kw + 1].loc;
+        let full = self.find(path, loc)?;
+        let loading = &self.idents.modules.loading;
+        if let Some(start) = loading.iter().position(|r| r.path == full) {
+            let mut err = LispErrors::new()
+                .error(loc, format!("Reading `{path}` here would make a cycle!"))
+                .note(None, "The files read each other like this:");
+            for r in loading[start + 1..]
+                .iter()
+                .chain([&self.reading(&full, path, kw)])
+            {
+                err = err.note(&r.loc, format!("It {}s `{}` here.", r.keyword, r.written));
+            }
+            return Err(err.note(
+                None,
+                "An import can be deferred to break the cycle, like `(import \"a.pale\" :defer (f))`, which binds `f` before `a.pale` has been parsed.",
+            ));
+        }
+        Ok(full)
+    }
+
+    fn reading(&self, full: &Path, path: &str, kw: usize) -> Reading {
+        Reading {
+            path: full.to_path_buf(),
+            written: path.to_string(),
+            keyword: self.ts[kw].dat.to_string(),
+            loc: self.ts[kw + 1].loc.clone(),
+        }
+    }
+
+    // Takes the names that deferred imports bound while the file at the path was being parsed.
+    fn take_deferred(&mut self, full: &Path) -> Vec<Deferred> {
+        let (waiting, rest) = std::mem::take(&mut self.idents.modules.deferred)
+            .into_iter()
+            .partition(|d| d.path == full);
+        self.idents.modules.deferred = rest;
+        waiting
+    }
+
+    fn read(&mut self, full: &Path, path: &str, loc: &Location) -> Result<Tokenized, LispErrors> {
+        self.idents.modules.read.insert(full.to_path_buf());
+        let source = fs::read_to_string(full)
+            .map_err(|e| LispErrors::new().error(loc, format!("Could not read `{path}`: {e}")))?;
+        tokenize_with(&source, full.to_string_lossy().as_ref(), self.options)
+    }
+
+    // Parses `(provide names...)` from the keyword at `kw`, which lets files that import this one use the names.
+    fn provide(&mut self, kw: usize, close: usize) -> Result<Expr, LispErrors> {
+        self.top_level(kw)?;
+        for tok in &self.ts[kw + 1..close] {
+            let TokenType::Ident(name) = &tok.dat else {
+                return Err(LispErrors::new()
+                    .error(&tok.loc, "Only names can be provided!")
+                    .note(None, "Write it like `(provide square cube)`."));
+            };
+            let Place::Global(var) = self.lookup(name, &tok.loc)? else {
+                unreachable!("Only the top level is parsed here, which has no frame")
+            };
+            self.provided.push(Provided {
+                name: name.clone(),
+                var,
+                loc: tok.loc.clone(),
+            });
+        }
+        Ok(Expr::Literal(LispType::Nil))
+    }
+}
+
+// Binds the names that deferred imports of a file bound to what the file provides, now that it has been parsed.
+fn fill_deferred(
+    waiting: Vec<Deferred>,
+    provided: &[Provided],
+    private: &[String],
+) -> Result<(), LispErrors> {
+    for d in waiting {
+        match provided.iter().find(|p| p.name == d.name) {
+            Some(p) => d.slot.bind(p.var.new_ref()),
+            None => return Err(doesnt_provide(&d.written, &d.name, &d.loc, private)),
+        }
+    }
+    Ok(())
+}
+
+// `private` is what the module binds without providing it, which it is pointed out if it is one of.
+fn doesnt_provide(
+    path: &dyn Display,
+    name: &dyn Display,
+    loc: &Location,
+    private: &[String],
+) -> LispErrors {
+    let errors = LispErrors::new().error(loc, format!("`{path}` doesn't provide `{name}`!"));
+    match private.contains(&name.to_string()) {
+        true => errors.note(
+            None,
+            format!("`{name}` is private to it. Add it to its `provide` to use it here."),
+        ),
+        false => errors,
+    }
+}
+
+// The `(old new)` pairs that a `:rename` is given, or `None` if they aren't written that way.
+fn rename_pairs(ts: &[Token]) -> Option<Vec<(&String, &Token)>> {
+    ts.chunks(4)
+        .map(|pair| match pair {
+            [open, old, new, close] => match (&open.dat, &old.dat, &new.dat, &close.dat) {
+                (
+                    TokenType::StartStmt,
+                    TokenType::Ident(old),
+                    TokenType::Ident(_),
+                    TokenType::EndStmt,
+                ) => Some((old, new)),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+fn load_usage(loc: &Location) -> LispErrors {
+    LispErrors::new()
+        .error(loc, "`load` takes the path of a file!")
+        .note(None, "Write it like `(load \"util.pale\")`.")
+}
+
+// The error for a binding in a `let-values` that isn't a list of names.
+fn values_usage(loc: &Location) -> LispErrors {
+    LispErrors::new()
+        .error(loc, "`let-values` binds lists of names!")
+        .note(
+            None,
+            "Write them like `(let-values (((q r) (quotient/remainder 7 2))) ...)`.",
+        )
+}
+
+// The variables, steps, condition, result and body of a `do` loop.
+type LoopParts = (
+    Vec<(Place, Shared<Expr>)>,
+    Vec<Shared<Expr>>,
+    Shared<Expr>,
+    Option<Shared<Expr>>,
+    Option<Shared<Expr>>,
+);
+
+fn do_usage(loc: &Location) -> LispErrors {
+    LispErrors::new()
+        .error(
+            loc,
+            "`do` must be followed by its variables and the condition that ends it!",
+        )
+        .note(
+            None,
+            "Write it like `(do ((i 0 (+ i 1))) ((eqv? i 10) i) print i)`.",
+        )
+}
+
+fn do_var_usage(loc: &Location) -> LispErrors {
+    LispErrors::new()
+        .error(
+            loc,
+            "Each variable of a `do` loop is a name, its starting value and what it steps to!",
+        )
+        .note(
+            None,
+            "Write it like `(i 0 (+ i 1))`, or leave out the step.",
+        )
+}
+
+fn parameterize_usage(loc: &Location) -> LispErrors {
+    LispErrors::new()
+        .error(
+            loc,
+            "`parameterize` must be followed by parameters and their values!",
+        )
+        .note(None, "Write them like `(parameterize ((indent 4)) ...)`.")
+}
+
+fn not_assignable(tok: &TokenType, loc: &Location) -> LispErrors {
+    match tok {
+        TokenType::Dot => {
+            LispErrors::new().error(loc, "`.` is not allowed in variable assignments!")
         }
-        Ok(Statement {
-            args: self.args,
-            op: s,
-            res: RefCell::new(None),
-            loc: self.loc.unwrap(),
+        _ => LispErrors::new().error(loc, "Keywords are not allowed in variable assignments!"),
+    }
+}
+
+/// A whole source file: every top-level form in it, in order.
+#[derive(Debug)]
+pub(crate) struct Program {
+    pub(crate) forms: Vec<Shared<Expr>>,
+    /// The names that the file gives to the files that import it, with `provide` and `re-export`.
+    pub(crate) provided: Vec<Provided>,
+}
+
+/// A name that a module provides.
+#[derive(Debug)]
+pub(crate) struct Provided {
+    pub(crate) name: String,
+    pub(crate) var: Var,
+    /// Where the name is written in the `provide` that first provided it, which is kept when it is re-exported.
+    pub(crate) loc: Location,
+}
+
+impl Provided {
+    fn new_ref(&self) -> Provided {
+        Provided {
+            name: self.name.clone(),
+            var: self.var.new_ref(),
+            loc: self.loc.clone(),
+        }
+    }
+}
+
+impl Program {
+    /// Evaluates each of the forms in order, giving the value of the last one.
+    pub(crate) fn resolve(&self) -> Result<Var, LispErrors> {
+        let mut last = Var::new(LispType::Nil);
+        for form in &self.forms {
+            last = eval(form)?;
+        }
+        Ok(last)
+    }
+
+    /// Evaluates the forms with the options, compiling them for the VM first if `compiled` is set.
+    pub(crate) fn run(&self, options: &Options, compiled: bool) -> Result<Var, LispErrors> {
+        with_options(options, || match compiled {
+            true => vm::run(Shared::new(Code::program(&self.forms))),
+            false => self.resolve(),
         })
     }
 }
 
-pub(crate) fn make_ast(
+// Splits the tokens into the top-level forms, carrying on past structural errors to find the rest of them.
+pub(crate) fn split_forms(ts: &[Token]) -> (Vec<RangeInclusive<usize>>, LispErrors) {
+    let mut forms = Vec::new();
+    let mut errs = LispErrors::new();
+    let mut open = None;
+    let mut depth = 0;
+    let mut i = 0;
+    while i < ts.len() {
+        match (&ts[i].dat, depth) {
+            (TokenType::StartStmt, 0) => {
+                open = Some(i);
+                depth = 1;
+            }
+            (TokenType::StartStmt, _) => depth += 1,
+            (TokenType::EndStmt, 0) => {
+                errs = errs
+                    .error(&ts[i].loc, "Unmatched closing parentheses!")
+                    .note(None, "Delete it.")
+            }
+            (TokenType::EndStmt, _) => {
+                depth -= 1;
+                if depth == 0 {
+                    forms.push(open.take().unwrap()..=i);
+                }
+            }
+            (_, 0) => forms.push(i..=i),
+            (_, _) => {}
+        }
+        i += 1;
+        if i < ts.len() {
+            continue;
+        }
+        if let Some(o) = open.take() {
+            errs = errs.error(&ts[o].loc, "Unmatched opening parentheses!");
+            // A statement at the start of a line was probably meant to be a top-level one, so carry on from there.
+            match (o + 1..ts.len())
+                .find(|&j| ts[j].dat == TokenType::StartStmt && ts[j].loc.col == 0)
+            {
+                Some(j) => {
+                    errs = errs.note(&ts[j].loc, "It might need to be closed before this.");
+                    depth = 0;
+                    i = j;
+                }
+                None => errs = errs.note(None, "Deleting it might fix this error."),
+            }
+        }
+    }
+    (forms, errs)
+}
+
+/// Parses every top-level form, reporting the errors in all of them rather than only the first.
+pub(crate) fn make_program(
+    ts: &[Token],
+    idents: &mut Scope,
+    edition: Edition,
+    options: &Options,
+) -> Result<Program, LispErrors> {
+    parse(ts, idents, edition, options, None).0
+}
+
+/// Like `make_program`, but also giving every name in the forms that could be parsed and what it refers to.
+pub(crate) fn trace_program(
+    ts: &[Token],
+    idents: &mut Scope,
+    edition: Edition,
+    options: &Options,
+) -> (Result<Program, LispErrors>, Vec<NameUse>) {
+    let (program, uses) = parse(ts, idents, edition, options, Some(Vec::new()));
+    (program, uses.unwrap_or_default())
+}
+
+fn parse(
     ts: &[Token],
     idents: &mut Scope,
-    start: &Location,
-) -> Result<Statement, LispErrors> {
-    let ast_parser = AstParser::new(ts, idents, start);
-    ast_parser.parse()
+    edition: Edition,
+    options: &Options,
+    uses: Option<Vec<NameUse>>,
+) -> (Result<Program, LispErrors>, Option<Vec<NameUse>>) {
+    let (ranges, mut errs) = split_forms(ts);
+    let mut parser = Parser {
+        ts,
+        idents,
+        edition,
+        options,
+        uses,
+        provided: Vec::new(),
+        naming: None,
+    };
+    let mut forms = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        let tok = &ts[*range.start()];
+        let form = match &tok.dat {
+            TokenType::KeyWord(_) => Err(LispErrors::new()
+                .error(&tok.loc, "Keywords can only be used inside of statements!")),
+            TokenType::Dot => {
+                Err(LispErrors::new().error(&tok.loc, "`.` can only be used inside of statements!"))
+            }
+            _ => parser.expr(*range.start()).map(|(form, _)| form),
+        };
+        match form {
+            Ok(f) => forms.push(Shared::new(f)),
+            Err(e) => errs.extend(e),
+        }
+    }
+    let program = if errs.is_empty() {
+        Ok(Program {
+            forms,
+            provided: parser.provided,
+        })
+    } else {
+        Err(errs.syntax())
+    };
+    (program, parser.uses)
 }
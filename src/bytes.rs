@@ -0,0 +1,118 @@
+use crate::ast::Var;
+use crate::error::LispErrors;
+use crate::types::LispType;
+use crate::Location;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// `(bytes ...)`, which `#u8(...)` is read as: the integers from 0 to 255 that it was given, as bytes.
+pub(crate) fn from_items(items: &[Var], loc: &Location) -> Result<Var, LispErrors> {
+    let bytes = items
+        .iter()
+        .map(|item| match *item.resolve().get() {
+            LispType::Integer(i) => u8::try_from(i).map_err(|_| {
+                LispErrors::new()
+                    .error(loc, format!("`{i}` is not a byte!"))
+                    .note(None, "Bytes have to be integers from 0 to 255.")
+            }),
+            ref other => Err(LispErrors::new()
+                .error(loc, format!("`{other}` is not a byte!"))
+                .note(None, "Bytes have to be integers from 0 to 255.")),
+        })
+        .collect::<Result<Vec<u8>, _>>()?;
+    Ok(Var::new(bytes))
+}
+
+/// The bytes from `start` up to `end`, or to the end if it isn't given.
+pub(crate) fn slice(
+    bytes: &[u8],
+    start: isize,
+    end: Option<isize>,
+    loc: &Location,
+) -> Result<Var, LispErrors> {
+    let len = bytes.len() as isize;
+    let end = end.unwrap_or(len);
+    if start < 0 || end < start || end > len {
+        return Err(LispErrors::new()
+            .error(
+                loc,
+                format!("Can't take the bytes from {start} to {end} of {len} bytes!"),
+            )
+            .note(
+                None,
+                "The start can't be after the end, and neither can be past the last byte.",
+            ));
+    }
+    Ok(Var::new(bytes[start as usize..end as usize].to_vec()))
+}
+
+/// The bytes as two lowercase hexadecimal digits each.
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Reads two hexadecimal digits of either case for each byte.
+pub(crate) fn from_hex(text: &str, loc: &Location) -> Result<Var, LispErrors> {
+    let invalid = || {
+        LispErrors::new()
+            .error(loc, format!("`{text}` is not hexadecimal!"))
+            .note(None, "Each byte has to be two of `0-9`, `a-f` or `A-F`.")
+    };
+    if !text.len().is_multiple_of(2) || !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let bytes = (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).map_err(|_| invalid()))
+        .collect::<Result<Vec<u8>, _>>()?;
+    Ok(Var::new(bytes))
+}
+
+/// The bytes in standard base64, padded with `=`.
+pub(crate) fn to_base64(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        // Three bytes make four characters, and one or two make two or three and padding.
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+/// Reads standard base64, with or without the padding at the end.
+pub(crate) fn from_base64(text: &str, loc: &Location) -> Result<Var, LispErrors> {
+    let invalid = || {
+        LispErrors::new()
+            .error(loc, format!("`{text}` is not base64!"))
+            .note(
+                None,
+                "It can only have `A-Z`, `a-z`, `0-9`, `+` and `/` in it, and `=` at the end.",
+            )
+    };
+    let digits = text.trim_end_matches('=');
+    if text.len() - digits.len() > 2 || digits.len() % 4 == 1 {
+        return Err(invalid());
+    }
+    let mut bytes = Vec::with_capacity(digits.len() * 3 / 4);
+    for chunk in digits.as_bytes().chunks(4) {
+        let mut n = 0u32;
+        for (i, c) in chunk.iter().enumerate() {
+            let digit = BASE64.iter().position(|d| d == c).ok_or_else(invalid)?;
+            n |= (digit as u32) << (18 - 6 * i);
+        }
+        // Two characters make one byte, three make two, and four make three.
+        for i in 0..chunk.len() - 1 {
+            bytes.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Ok(Var::new(bytes))
+}
@@ -1,32 +1,715 @@
+use crate::ast::{Lambda, Locals};
 use crate::error::LispErrors;
-use crate::types::LispType;
+use crate::eval::{eval_in, events, parameter_value, profiled, streams};
+use crate::port::Port;
+use crate::profile::Callee;
+use crate::socket::Socket;
+use crate::streams::Output;
+use crate::sync::{Shared, ThreadSafe};
+use crate::types::{LispType, TypeName};
+use crate::value::Value;
 use crate::Location;
 use crate::Var;
 use std::fmt::Debug;
-pub trait Callable: Debug {
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub trait Callable: Debug + ThreadSafe {
     fn call(&self, args: &[Var], loc_called: &Location) -> Result<Var, LispErrors>;
+
+    /// Calls with the arguments from before a `.`, and the value after it.
+    /// By default the value has to be a list, which is spread out into the rest of the arguments.
+    fn call_dotted(
+        &self,
+        args: &[Var],
+        tail: &Var,
+        loc_called: &Location,
+    ) -> Result<Var, LispErrors> {
+        spread(self, args, tail, loc_called)
+    }
+
+    /// The function if it is written in Pale, so that the evaluator can evaluate its body itself.
+    fn as_function(&self) -> Option<&Function> {
+        None
+    }
+
+    /// The intrinsic if it is one, so that the evaluator can do the ones that call functions written in Pale itself.
+    fn as_intrinsic(&self) -> Option<&IntrinsicOp> {
+        None
+    }
+
+    /// The parameter if it was made by `make-parameter`, so that `parameterize` can give it a value.
+    fn as_parameter(&self) -> Option<&Parameter> {
+        None
+    }
+
+    /// Whether calling it only computes its result, so that `pmap` can call it from several threads at once.
+    /// Functions written in Pale are, since what they call is checked when they call it, but the host's functions aren't, since there is no telling what they do.
+    fn is_pure(&self) -> bool {
+        false
+    }
+
+    /// What the profiler counts calls to it as.
+    fn callee(&self) -> Callee;
 }
 
-#[derive(Debug)]
+// Calls the function with the list after a `.` spread out into the rest of the arguments.
+fn spread<C: Callable + ?Sized>(
+    f: &C,
+    args: &[Var],
+    tail: &Var,
+    loc_called: &Location,
+) -> Result<Var, LispErrors> {
+    f.call(&spread_args(args, tail, loc_called)?, loc_called)
+}
+
+/// The arguments with the list after a `.` spread out after them.
+pub(crate) fn spread_args(
+    args: &[Var],
+    tail: &Var,
+    loc_called: &Location,
+) -> Result<Vec<Var>, LispErrors> {
+    let tail = tail.resolve();
+    let mut all = args.iter().map(Var::new_ref).collect::<Vec<_>>();
+    match &*tail.get() {
+        LispType::List(items) => all.extend(items.iter().map(Var::new_ref)),
+        LispType::Nil => {}
+        other => {
+            return Err(LispErrors::new()
+                .error(
+                    loc_called,
+                    format!("Only a list can come after `.` here, but it is `{other}`!"),
+                )
+                .note(
+                    None,
+                    "Only `list` can make a list with something else at the end.",
+                ))
+        }
+    }
+    Ok(all)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum IntrinsicOp {
     Add,
     Subtract,
     Print,
+    EPrint,
+    ReadLine,
     Multiply,
+    List,
+    MakeMap,
+    Cons,
+    Car,
+    Cdr,
+    Str,
+    Exit,
+    Values,
+    QuotientRemainder,
+    MakeParameter,
+    Eq,
+    Eqv,
+    Equal,
+    Is(TypeName),
+    TypeOf,
+    StringToInt,
+    IntToString,
+    FloatToInt,
+    StringToFloat,
+    ListToString,
+    Gensym,
+    CsvRead,
+    CsvWrite,
+    Bytes,
+    BytesLength,
+    BytesToList,
+    BytesSlice,
+    ReadBytes,
+    BytesToHex,
+    HexToBytes,
+    BytesToBase64,
+    Base64ToBytes,
+    Utf8ToString,
+    StringToUtf8,
+    Connect,
+    Send,
+    Recv,
+    Close,
+    Channel,
+    #[cfg(feature = "sync")]
+    Spawn,
+    Pmap,
+    Delay,
+    Force,
+    After,
+    Every,
+    CancelTimer,
+    RunEvents,
+    OpenInputString,
+    OpenOutputString,
+    GetOutputString,
+    OpenInputFile,
+    OpenOutputFile,
+    CurrentInputPort,
+    CurrentOutputPort,
+    ReadChar,
+    WriteString,
+    ClosePort,
+    Now,
+    Date,
+    ParseDate,
+    FormatDate,
+    DateParts,
+    Duration,
+    DurationToSeconds,
+    DateToEpoch,
+    EpochToDate,
+    #[cfg(feature = "json")]
+    JsonParse,
+    #[cfg(feature = "json")]
+    JsonStringify,
+    #[cfg(feature = "config")]
+    ReadConfig,
+    #[cfg(feature = "regex")]
+    RegexMatch,
+    #[cfg(feature = "regex")]
+    RegexFind,
+    #[cfg(feature = "regex")]
+    RegexCaptures,
+    #[cfg(feature = "regex")]
+    RegexReplace,
+    #[cfg(feature = "net")]
+    HttpGet,
+    #[cfg(feature = "net")]
+    HttpPost,
+}
+
+/// What an intrinsic can do besides computing its result, so that scripts that can't be trusted can be kept from doing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Capability {
+    /// Writing to the output streams, with `print` and `eprint`.
+    Output,
+    /// Reading from the input stream, with `read-line`.
+    Input,
+    /// Reading and writing files, with `csv-read`, `csv-write`, `read-bytes` and `read-config`, and reading other scripts with `import` and `load`.
+    Files,
+    /// Using the network, with `connect`, which sockets can only come from, and `http-get` and `http-post`.
+    Network,
+}
+
+impl IntrinsicOp {
+    /// What the intrinsic needs to be allowed to do, if it does more than compute its result.
+    pub(crate) fn capability(&self) -> Option<Capability> {
+        match self {
+            IntrinsicOp::Print | IntrinsicOp::EPrint => Some(Capability::Output),
+            IntrinsicOp::ReadLine | IntrinsicOp::CurrentInputPort => Some(Capability::Input),
+            IntrinsicOp::CurrentOutputPort => Some(Capability::Output),
+            IntrinsicOp::OpenInputFile | IntrinsicOp::OpenOutputFile => Some(Capability::Files),
+            IntrinsicOp::CsvRead | IntrinsicOp::CsvWrite | IntrinsicOp::ReadBytes => {
+                Some(Capability::Files)
+            }
+            #[cfg(feature = "config")]
+            IntrinsicOp::ReadConfig => Some(Capability::Files),
+            IntrinsicOp::Connect => Some(Capability::Network),
+            #[cfg(feature = "net")]
+            IntrinsicOp::HttpGet | IntrinsicOp::HttpPost => Some(Capability::Network),
+            _ => None,
+        }
+    }
+
+    /// Whether the intrinsic only computes its result from its arguments, rather than also changing something or depending on when it is called.
+    pub(crate) fn is_pure(&self) -> bool {
+        match self {
+            IntrinsicOp::Exit
+            | IntrinsicOp::Gensym
+            | IntrinsicOp::Now
+            | IntrinsicOp::Channel
+            | IntrinsicOp::Send
+            | IntrinsicOp::Recv
+            | IntrinsicOp::Close
+            | IntrinsicOp::After
+            | IntrinsicOp::Every
+            | IntrinsicOp::CancelTimer
+            | IntrinsicOp::RunEvents
+            | IntrinsicOp::OpenInputString
+            | IntrinsicOp::OpenOutputString
+            | IntrinsicOp::GetOutputString
+            | IntrinsicOp::ReadChar
+            | IntrinsicOp::WriteString
+            | IntrinsicOp::ClosePort => false,
+            #[cfg(feature = "sync")]
+            IntrinsicOp::Spawn => false,
+            _ => self.capability().is_none(),
+        }
+    }
+
+    /// How many arguments the intrinsic takes at least, and at most if there is a limit.
+    pub(crate) fn arity(&self) -> (usize, Option<usize>) {
+        match self {
+            IntrinsicOp::Add | IntrinsicOp::Multiply => (0, None),
+            IntrinsicOp::Subtract => (1, None),
+            IntrinsicOp::Print
+            | IntrinsicOp::EPrint
+            | IntrinsicOp::Car
+            | IntrinsicOp::Cdr
+            | IntrinsicOp::MakeParameter
+            | IntrinsicOp::Is(_)
+            | IntrinsicOp::TypeOf => (1, Some(1)),
+            IntrinsicOp::ReadLine => (0, Some(0)),
+            IntrinsicOp::Exit | IntrinsicOp::Gensym => (0, Some(1)),
+            IntrinsicOp::StringToInt | IntrinsicOp::IntToString | IntrinsicOp::FloatToInt => {
+                (1, Some(2))
+            }
+            IntrinsicOp::StringToFloat | IntrinsicOp::ListToString => (1, Some(1)),
+            IntrinsicOp::CsvRead => (1, None),
+            IntrinsicOp::CsvWrite => (2, None),
+            IntrinsicOp::Bytes => (0, None),
+            IntrinsicOp::BytesSlice => (2, Some(3)),
+            IntrinsicOp::BytesLength
+            | IntrinsicOp::BytesToList
+            | IntrinsicOp::ReadBytes
+            | IntrinsicOp::BytesToHex
+            | IntrinsicOp::HexToBytes
+            | IntrinsicOp::BytesToBase64
+            | IntrinsicOp::Base64ToBytes
+            | IntrinsicOp::Utf8ToString
+            | IntrinsicOp::StringToUtf8
+            | IntrinsicOp::Close
+            | IntrinsicOp::OpenInputString
+            | IntrinsicOp::GetOutputString
+            | IntrinsicOp::OpenInputFile
+            | IntrinsicOp::OpenOutputFile
+            | IntrinsicOp::ReadChar
+            | IntrinsicOp::ClosePort => (1, Some(1)),
+            IntrinsicOp::OpenOutputString
+            | IntrinsicOp::CurrentInputPort
+            | IntrinsicOp::CurrentOutputPort => (0, Some(0)),
+            IntrinsicOp::WriteString => (2, Some(2)),
+            IntrinsicOp::Now => (0, Some(0)),
+            IntrinsicOp::Date => (3, Some(6)),
+            IntrinsicOp::FormatDate => (1, Some(2)),
+            IntrinsicOp::Duration => (0, None),
+            IntrinsicOp::ParseDate
+            | IntrinsicOp::DateParts
+            | IntrinsicOp::DurationToSeconds
+            | IntrinsicOp::DateToEpoch
+            | IntrinsicOp::EpochToDate => (1, Some(1)),
+            IntrinsicOp::Connect | IntrinsicOp::Recv => (1, Some(2)),
+            IntrinsicOp::Send => (2, Some(2)),
+            IntrinsicOp::Channel => (0, Some(0)),
+            #[cfg(feature = "sync")]
+            IntrinsicOp::Spawn => (1, Some(1)),
+            IntrinsicOp::Pmap => (2, Some(2)),
+            IntrinsicOp::Delay | IntrinsicOp::Force => (1, Some(1)),
+            IntrinsicOp::After | IntrinsicOp::Every => (2, Some(2)),
+            IntrinsicOp::CancelTimer => (1, Some(1)),
+            IntrinsicOp::RunEvents => (0, Some(0)),
+            #[cfg(feature = "json")]
+            IntrinsicOp::JsonParse | IntrinsicOp::JsonStringify => (1, Some(1)),
+            #[cfg(feature = "config")]
+            IntrinsicOp::ReadConfig => (1, Some(1)),
+            #[cfg(feature = "regex")]
+            IntrinsicOp::RegexMatch | IntrinsicOp::RegexFind | IntrinsicOp::RegexCaptures => {
+                (2, Some(2))
+            }
+            #[cfg(feature = "regex")]
+            IntrinsicOp::RegexReplace => (3, Some(3)),
+            #[cfg(feature = "net")]
+            IntrinsicOp::HttpGet => (1, Some(2)),
+            #[cfg(feature = "net")]
+            IntrinsicOp::HttpPost => (2, Some(3)),
+            IntrinsicOp::Cons
+            | IntrinsicOp::QuotientRemainder
+            | IntrinsicOp::Eq
+            | IntrinsicOp::Eqv
+            | IntrinsicOp::Equal => (2, Some(2)),
+            IntrinsicOp::List | IntrinsicOp::MakeMap | IntrinsicOp::Str | IntrinsicOp::Values => {
+                (0, None)
+            }
+        }
+    }
+
+    /// How the intrinsic is called and what it does, for showing in editors.
+    pub(crate) fn doc(&self) -> &'static str {
+        match self {
+            IntrinsicOp::Add => "(+ integers...)\nAdds the integers together, giving 0 if there aren't any, or adds durations to a date or to each other. It is an error if the sum doesn't fit in an integer.",
+            IntrinsicOp::Subtract => "(- first integers...)\nSubtracts the rest of the integers from the first, or negates it if it is the only one, or subtracts durations from a date or duration. Subtracting one date from another gives the duration between them.",
+            IntrinsicOp::Multiply => "(* integers...)\nMultiplies the integers together, giving 1 if there aren't any. It is an error if the product doesn't fit in an integer.",
+            IntrinsicOp::Print => "(print value)\nWrites the value and a newline to the output.",
+            IntrinsicOp::EPrint => "(eprint value)\nWrites the value and a newline to the error output.",
+            IntrinsicOp::ReadLine => "(read-line)\nReads a line from the input without its line ending, or gives `nil` at the end of it.",
+            IntrinsicOp::List => "(list values...)\nMakes a list of the values, which is what `[...]` does.",
+            IntrinsicOp::MakeMap => "(make-map key value ...)\nMakes a map from the keys to the values, which is what `{...}` does.",
+            IntrinsicOp::Cons => "(cons first rest)\nPuts `first` onto the front of the list `rest`, or makes a pair if `rest` isn't a list.",
+            IntrinsicOp::Car => "(car pair)\nThe first item of a pair or a list.",
+            IntrinsicOp::Cdr => "(cdr pair)\nThe rest of a pair or a list after the first item.",
+            IntrinsicOp::Str => "(str values...)\nJoins the values together into a string.",
+            IntrinsicOp::Exit => "(exit code)\nStops the script, which makes `pale` exit with the code, or 0 if it is left out.",
+            IntrinsicOp::Values => "(values values...)\nGives all of the values at once, for `let-values` to bind.",
+            IntrinsicOp::QuotientRemainder => "(quotient/remainder dividend divisor)\nDivides the integers, giving both the quotient and the remainder as two values.",
+            IntrinsicOp::Eq => "(eq? a b)\nWhether `a` and `b` are the very same value, like a list bound to two names. Gives `:true` or `nil`.",
+            IntrinsicOp::Eqv => "(eqv? a b)\nWhether `a` and `b` are equal numbers, strings or keywords, or the very same list, pair, map or function. Gives `:true` or `nil`.",
+            IntrinsicOp::Equal => "(equal? a b)\nWhether `a` and `b` are equal, comparing lists, pairs and maps item by item. Gives `:true` or `nil`.",
+            IntrinsicOp::Is(TypeName::Integer) => "(integer? value)\nWhether the value is an integer. Gives `:true` or `nil`.",
+            IntrinsicOp::Is(TypeName::Float) => "(float? value)\nWhether the value is a float. Gives `:true` or `nil`.",
+            IntrinsicOp::Is(TypeName::String) => "(string? value)\nWhether the value is a string. Gives `:true` or `nil`.",
+            IntrinsicOp::Is(TypeName::Keyword) => "(keyword? value)\nWhether the value is a keyword like `:name`. Gives `:true` or `nil`.",
+            IntrinsicOp::Is(TypeName::Nil) => "(nil? value)\nWhether the value is `nil`. Gives `:true` or `nil`.",
+            IntrinsicOp::Is(TypeName::List) => "(list? value)\nWhether the value is a list, which `nil` is not. Gives `:true` or `nil`.",
+            IntrinsicOp::Is(TypeName::Pair) => "(pair? value)\nWhether the value is a pair that isn't a list. Gives `:true` or `nil`.",
+            IntrinsicOp::Is(TypeName::Map) => "(map? value)\nWhether the value is a map. Gives `:true` or `nil`.",
+            IntrinsicOp::Is(TypeName::Bytes) => "(bytes? value)\nWhether the value is bytes, like `#u8(1 2 3)`. Gives `:true` or `nil`.",
+            IntrinsicOp::Is(TypeName::Promise) => "(promise? value)\nWhether the value is a promise made by `delay`. Gives `:true` or `nil`.",
+            IntrinsicOp::Is(TypeName::Channel) => "(channel? value)\nWhether the value is a channel made by `channel` or `spawn`. Gives `:true` or `nil`.",
+            IntrinsicOp::Is(TypeName::Socket) => "(socket? value)\nWhether the value is a socket made by `connect`. Gives `:true` or `nil`.",
+            IntrinsicOp::Is(TypeName::Port) => "(port? value)\nWhether the value is a port, like one made by `open-input-string`. Gives `:true` or `nil`.",
+            IntrinsicOp::Is(TypeName::Date) => "(date? value)\nWhether the value is a date, like one made by `date` or `now`. Gives `:true` or `nil`.",
+            IntrinsicOp::Is(TypeName::Duration) => "(duration? value)\nWhether the value is a duration, like one made by `duration`. Gives `:true` or `nil`.",
+            IntrinsicOp::Is(TypeName::Function) => "(callable? value)\nWhether the value can be called, like a function or a parameter. Gives `:true` or `nil`.",
+            IntrinsicOp::Is(TypeName::Values) => "(values? value)\nWhether the value is several values given by `values`. Gives `:true` or `nil`.",
+            IntrinsicOp::TypeOf => "(type-of value)\nWhat kind of value it is, as a keyword like `:integer`, `:string` or `:function`.",
+            IntrinsicOp::StringToInt => "(string->int string radix)\nReads the string as an integer, in base 10 unless the radix from 2 to 36 is given.",
+            IntrinsicOp::IntToString => "(int->string integer radix)\nWrites the integer as a string, in base 10 unless the radix from 2 to 36 is given.",
+            IntrinsicOp::FloatToInt => "(float->int float mode)\nTurns the float into an integer, rounding towards zero unless the mode is `:floor`, `:round` or `:ceil`.",
+            IntrinsicOp::StringToFloat => "(string->float string)\nReads the string as a float.",
+            IntrinsicOp::ListToString => "(list->string list)\nJoins a list of strings and integers into a string, with each integer standing for the character with that code point.",
+            #[cfg(feature = "json")]
+            IntrinsicOp::JsonParse => "(json-parse string)\nReads JSON, with objects becoming maps from strings, arrays becoming lists, `true` becoming `:true`, and `false` and `null` becoming `nil`.",
+            #[cfg(feature = "json")]
+            IntrinsicOp::JsonStringify => "(json-stringify value)\nWrites the value as JSON, with `:true` as `true`, other keywords as strings and `nil` as `null`.",
+            #[cfg(feature = "config")]
+            IntrinsicOp::ReadConfig => "(read-config path)\nReads a TOML or YAML file, going by its extension, with tables becoming maps from strings and arrays becoming lists.",
+            #[cfg(feature = "regex")]
+            IntrinsicOp::RegexMatch => "(regex-match? pattern string)\nChecks whether the regular expression matches anywhere in the string.",
+            #[cfg(feature = "regex")]
+            IntrinsicOp::RegexFind => "(regex-find pattern string)\nThe first part of the string that the regular expression matches, or `nil` if it doesn't match.",
+            #[cfg(feature = "regex")]
+            IntrinsicOp::RegexCaptures => "(regex-captures pattern string)\nThe groups of the first match as a list, with the whole match first, or as a map from their numbers and names if any are named. Gives `nil` if it doesn't match.",
+            #[cfg(feature = "regex")]
+            IntrinsicOp::RegexReplace => "(regex-replace pattern string replacement)\nReplaces every match in the string, with `$1` or `${name}` in the replacement standing for that group of the match.",
+            #[cfg(feature = "net")]
+            IntrinsicOp::HttpGet => "(http-get url headers)\nFetches the url, giving `{:status s :headers h :body b}` with the body as a string, or bytes if it isn't text.",
+            #[cfg(feature = "net")]
+            IntrinsicOp::HttpPost => "(http-post url body headers)\nSends the body, a string or bytes, to the url, giving `{:status s :headers h :body b}`.",
+            IntrinsicOp::CsvRead => "(csv-read path :headers h :delimiter d)\nReads a CSV file as a list of rows, which are lists of strings. With `:headers :true`, the first row names the columns and the rest are maps from them.",
+            IntrinsicOp::CsvWrite => "(csv-write path rows :headers h :delimiter d)\nWrites the rows, which are lists, to a CSV file. With `:headers [...]`, they are written first and rows can also be maps from them.",
+            IntrinsicOp::Bytes => "(bytes integers...)\nMakes bytes from the integers from 0 to 255, which is what `#u8(...)` does.",
+            IntrinsicOp::BytesLength => "(bytes-length bytes)\nHow many bytes there are.",
+            IntrinsicOp::BytesToList => "(bytes->list bytes)\nThe bytes as a list of integers.",
+            IntrinsicOp::BytesSlice => "(bytes-slice bytes start end)\nThe bytes from `start` up to but not including `end`, or to the end if it is left out.",
+            IntrinsicOp::ReadBytes => "(read-bytes path)\nReads the whole of a file as bytes.",
+            IntrinsicOp::BytesToHex => "(bytes->hex bytes)\nWrites the bytes as a string of two lowercase hexadecimal digits each.",
+            IntrinsicOp::HexToBytes => "(hex->bytes string)\nReads a string of hexadecimal digits, two for each byte.",
+            IntrinsicOp::BytesToBase64 => "(bytes->base64 bytes)\nWrites the bytes as a string of base64.",
+            IntrinsicOp::Base64ToBytes => "(base64->bytes string)\nReads a string of base64, which doesn't have to be padded with `=`.",
+            IntrinsicOp::Utf8ToString => "(utf8->string bytes)\nReads the bytes as UTF-8 text.",
+            IntrinsicOp::StringToUtf8 => "(string->utf8 string)\nThe bytes of the string's UTF-8 encoding.",
+            IntrinsicOp::Connect => "(connect address timeout)\nConnects to `host:port` over TCP, or `unix:path` over a Unix socket, giving a socket. The timeout is in milliseconds, and applies to connecting, `send` and `recv`.",
+            IntrinsicOp::Send => "(send socket data)\nWrites the string or bytes to the socket, giving how many bytes were sent. Given a channel instead, `(send channel value)` puts the value on the end of it and gives it back.",
+            IntrinsicOp::Recv => "(recv socket most)\nWaits for bytes from the socket, giving at most `most` of them (4096 if it is left out), or `nil` once the other end has finished. Given a channel instead, `(recv channel)` takes the value at its front, waiting for one if it is empty, or gives `nil` once it is closed and empty.",
+            IntrinsicOp::Close => "(close socket)\nCloses the socket, after which it can't be used. A channel can't be sent to once it is closed, but what is already in it can still be received.",
+            IntrinsicOp::Channel => "(channel)\nMakes an empty channel, which values can be sent to with `send` and taken from in the same order with `recv`.",
+            #[cfg(feature = "sync")]
+            IntrinsicOp::Spawn => "(spawn function)\nCalls the function with no arguments on a thread of its own, giving a channel that `recv` gets its result from.",
+            IntrinsicOp::Delay => "(delay expr)\nA promise to evaluate the expression the first time it is forced, which `delay` makes by giving this a function with no parameters.",
+            IntrinsicOp::Force => "(force promise)\nThe value of a promise made by `delay`, which is only evaluated the first time it is forced. Anything else is given back as it is.",
+            IntrinsicOp::After => "(after ms function)\nSets a timer that calls the function with no arguments once the milliseconds or duration have passed, giving an id for `cancel-timer`. It is called by `run-events`.",
+            IntrinsicOp::Every => "(every ms function)\nSets a timer that calls the function with no arguments each time the milliseconds or duration pass, giving an id for `cancel-timer`. It is called by `run-events`.",
+            IntrinsicOp::CancelTimer => "(cancel-timer id)\nStops the timer that `after` or `every` gave the id for. Gives `:true` if it was still set, or `nil` otherwise.",
+            IntrinsicOp::RunEvents => "(run-events)\nWaits for each timer set by `after` and `every` and calls its function when it is due, until none are left.",
+            IntrinsicOp::Pmap => "(pmap function list)\nCalls the function on each item of the list, on several threads at once with the `sync` feature, giving the results in order. The function can only call pure functions.",
+            IntrinsicOp::OpenInputString => "(open-input-string string)\nMakes a port that reads the characters of the string.",
+            IntrinsicOp::OpenOutputString => "(open-output-string)\nMakes a port that keeps what is written to it, which `get-output-string` gives.",
+            IntrinsicOp::GetOutputString => "(get-output-string port)\nEverything that has been written to a port made by `open-output-string`.",
+            IntrinsicOp::OpenInputFile => "(open-input-file path)\nMakes a port that reads the file.",
+            IntrinsicOp::OpenOutputFile => "(open-output-file path)\nMakes a port that writes to the file, emptying it first if it already exists.",
+            IntrinsicOp::CurrentInputPort => "(current-input-port)\nA port that reads from the input, like `read-line` does.",
+            IntrinsicOp::CurrentOutputPort => "(current-output-port)\nA port that writes to the output, like `print` does.",
+            IntrinsicOp::ReadChar => "(read-char port)\nReads the next character from the port as a string, or gives `nil` at the end of it.",
+            IntrinsicOp::WriteString => "(write-string string port)\nWrites the string to the port.",
+            IntrinsicOp::ClosePort => "(close-port port)\nCloses the port, after which it can't be used.",
+            IntrinsicOp::Now => "(now)\nThe current date and time.",
+            IntrinsicOp::Date => "(date year month day hour minute second)\nMakes a date in UTC, at midnight unless the time of day is given.",
+            IntrinsicOp::ParseDate => "(parse-date string)\nReads a date like `2024-03-01`, `2024-03-01T09:30:00Z` or `2024-03-01 09:30+02:00`, taking it to be in UTC if it has no offset.",
+            IntrinsicOp::FormatDate => "(format-date date pattern)\nWrites the date with `%Y`, `%m`, `%d`, `%H`, `%M`, `%S` and the like in the pattern replaced by its parts, or like `2024-03-01T09:30:00Z` if there is no pattern.",
+            IntrinsicOp::DateParts => "(date-parts date)\nThe parts of the date as a map, from `:year`, `:month`, `:day`, `:hour`, `:minute`, `:second`, `:millisecond` and `:weekday`.",
+            IntrinsicOp::Duration => "(duration :weeks w :days d :hours h :minutes m :seconds s :milliseconds ms)\nMakes a duration of the total of the units that are given.",
+            IntrinsicOp::DurationToSeconds => "(duration->seconds duration)\nHow many whole seconds the duration is.",
+            IntrinsicOp::DateToEpoch => "(date->epoch date)\nHow many whole seconds the date is after 1970-01-01T00:00:00Z.",
+            IntrinsicOp::EpochToDate => "(epoch->date seconds)\nThe date that many seconds after 1970-01-01T00:00:00Z.",
+            IntrinsicOp::Gensym => "(gensym prefix)\nMakes a keyword that is different from every other one, which starts with the prefix if it is given.",
+            IntrinsicOp::MakeParameter => "(make-parameter default)\nMakes a parameter, which gives `default` when it is called unless `parameterize` has given it another value.",
+        }
+    }
+}
+
+/// Checks that exactly `n` arguments were given to the intrinsic called `name`.
+pub(crate) fn expect_args(
+    args: &[Var],
+    n: usize,
+    name: &str,
+    loc: &Location,
+) -> Result<(), LispErrors> {
+    if args.len() == n {
+        Ok(())
+    } else {
+        Err(LispErrors::new().error(
+            loc,
+            format!(
+                "`{name}` takes {n} argument(s), but was given {}!",
+                args.len()
+            ),
+        ))
+    }
+}
+
+// The error for when the result of the arithmetic intrinsic called `name` doesn't fit in an integer.
+fn overflowed(name: &str, loc: &Location) -> LispErrors {
+    LispErrors::new()
+        .error(
+            loc,
+            format!("The result of `{name}` doesn't fit in an integer!"),
+        )
+        .note(
+            None,
+            format!("Integers go from {} to {}.", isize::MIN, isize::MAX),
+        )
+}
+
+// Checks that the intrinsic called `name` was given `n` arguments, and maybe one more that is optional.
+fn expect_optional_arg(
+    args: &[Var],
+    n: usize,
+    name: &str,
+    loc: &Location,
+) -> Result<(), LispErrors> {
+    if args.len() == n || args.len() == n + 1 {
+        Ok(())
+    } else {
+        Err(LispErrors::new().error(
+            loc,
+            format!(
+                "`{name}` takes {n} or {} argument(s), but was given {}!",
+                n + 1,
+                args.len()
+            ),
+        ))
+    }
+}
+
+// Takes the argument as a string, or says that the intrinsic called `name` needs one.
+fn string_arg(arg: &Var, name: &str, loc: &Location) -> Result<String, LispErrors> {
+    match &*arg.resolve().get() {
+        LispType::Str(s) => Ok(s.clone()),
+        other => Err(LispErrors::new().error(
+            loc,
+            format!("`{name}` needs a string, but was given `{other}`!"),
+        )),
+    }
+}
+
+// Takes the argument as bytes, or says that the intrinsic called `name` needs them.
+fn bytes_arg(arg: &Var, name: &str, loc: &Location) -> Result<Vec<u8>, LispErrors> {
+    match &*arg.resolve().get() {
+        LispType::Bytes(bytes) => Ok(bytes.clone()),
+        other => Err(LispErrors::new().error(
+            loc,
+            format!("`{name}` needs bytes, but was given `{other}`!"),
+        )),
+    }
+}
+
+// Takes the argument as a date, or says that the intrinsic called `name` needs one.
+fn date_arg(arg: &Var, name: &str, loc: &Location) -> Result<i64, LispErrors> {
+    match *arg.resolve().get() {
+        LispType::Date(millis) => Ok(millis),
+        ref other => Err(LispErrors::new().error(
+            loc,
+            format!("`{name}` needs a date, but was given `{other}`!"),
+        )),
+    }
+}
+
+// Takes the argument as a port, or says that the intrinsic called `name` needs one.
+fn port_arg(arg: &Var, name: &str, loc: &Location) -> Result<Shared<Port>, LispErrors> {
+    match &*arg.resolve().get() {
+        LispType::Port(port) => Ok(port.clone()),
+        other => Err(LispErrors::new().error(
+            loc,
+            format!("`{name}` needs a port, but was given `{other}`!"),
+        )),
+    }
+}
+
+// Takes the argument as a socket, or says that the intrinsic called `name` needs one, since it isn't a channel either.
+fn socket_arg(arg: &Var, name: &str, loc: &Location) -> Result<Shared<Socket>, LispErrors> {
+    match &*arg.resolve().get() {
+        LispType::Socket(socket) => Ok(socket.clone()),
+        other => Err(LispErrors::new().error(
+            loc,
+            format!("`{name}` needs a socket or a channel, but was given `{other}`!"),
+        )),
+    }
+}
+
+// Takes the argument as an integer, or says that the intrinsic called `name` needs one.
+fn int_arg(arg: &Var, name: &str, loc: &Location) -> Result<isize, LispErrors> {
+    match *arg.resolve().get() {
+        LispType::Integer(i) => Ok(i),
+        ref other => Err(LispErrors::new().error(
+            loc,
+            format!("`{name}` needs an integer, but was given `{other}`!"),
+        )),
+    }
+}
+
+// The base that the number is written in for `string->int` and `int->string`, which is 10 unless it is given.
+fn radix_arg(radix: Option<&Var>, name: &str, loc: &Location) -> Result<u32, LispErrors> {
+    let Some(radix) = radix else {
+        return Ok(10);
+    };
+    match int_arg(radix, name, loc)? {
+        r @ 2..=36 => Ok(r as u32),
+        r => Err(LispErrors::new()
+            .error(loc, format!("`{r}` is not a radix!"))
+            .note(None, "It has to be from 2 to 36.")),
+    }
+}
+
+// Writes the integer in the base, with letters for the digits after 9.
+fn to_radix(i: isize, radix: u32) -> String {
+    let mut n = i.unsigned_abs();
+    let mut digits = Vec::new();
+    loop {
+        let digit = (n % radix as usize) as u32;
+        digits.push(char::from_digit(digit, radix).expect("The digit is less than the radix"));
+        n /= radix as usize;
+        if n == 0 {
+            break;
+        }
+    }
+    if i < 0 {
+        digits.push('-');
+    }
+    digits.iter().rev().collect()
+}
+
+// Turns the float into an integer for `float->int`, rounding it the way that the mode says.
+fn float_to_int(args: &[Var], loc: &Location) -> Result<isize, LispErrors> {
+    let f = match *args[0].resolve().get() {
+        LispType::Floating(f) => f,
+        LispType::Integer(i) => return Ok(i),
+        ref other => {
+            return Err(LispErrors::new().error(
+                loc,
+                format!("`float->int` needs a float, but was given `{other}`!"),
+            ))
+        }
+    };
+    let rounded = match args.get(1).map(|mode| mode.resolve().get().clone()) {
+        None => f.trunc(),
+        Some(LispType::Keyword(mode)) if mode == "floor" => f.floor(),
+        Some(LispType::Keyword(mode)) if mode == "round" => f.round(),
+        Some(LispType::Keyword(mode)) if mode == "ceil" => f.ceil(),
+        Some(other) => {
+            return Err(LispErrors::new()
+                .error(loc, format!("`{other}` is not a way to round!"))
+                .note(None, "Use `:floor`, `:round` or `:ceil`."))
+        }
+    };
+    if !f.is_finite() {
+        return Err(LispErrors::new().error(loc, format!("`{f}` can't be made an integer!")));
+    }
+    // `isize::MAX` can't be written exactly as a float, and rounds up to one past it.
+    if rounded >= isize::MIN as f64 && rounded < isize::MAX as f64 {
+        Ok(rounded as isize)
+    } else {
+        Err(LispErrors::new().error(loc, format!("`{f}` is too big to be an integer!")))
+    }
+}
+
+// How many keywords `gensym` has made, in every interpreter, so that each one has a different number.
+static GENSYMS: AtomicU64 = AtomicU64::new(0);
+
+// Makes a keyword for `gensym` out of the prefix and the next number.
+// `#` followed by a letter starts a reader literal, so no keyword in the source can be the same as one of these.
+fn gensym(prefix: &str) -> LispType {
+    let n = GENSYMS.fetch_add(1, Ordering::Relaxed) + 1;
+    LispType::Keyword(format!("{prefix}#u{n}"))
+}
+
+// Writes the value and a newline for `print` and `eprint`.
+fn write_line(output: &Output, value: &Var, loc: &Location) -> Result<(), LispErrors> {
+    output
+        .write(&format!("{value}\n"))
+        .map_err(|e| LispErrors::new().error(loc, format!("Could not print: {e}")))
+}
+
+// Builds `car` onto the front of `cdr`, which stays a plain list unless `cdr` is something else.
+fn cons(car: Var, cdr: Var) -> Var {
+    let list = match &*cdr.get() {
+        LispType::List(items) => Some(
+            std::iter::once(car.new_ref())
+                .chain(items.iter().map(Var::new_ref))
+                .collect(),
+        ),
+        LispType::Nil => Some(vec![car.new_ref()]),
+        _ => None,
+    };
+    match list {
+        Some(items) => Var::new(LispType::List(items)),
+        None => Var::new(LispType::Pair(car, cdr)),
+    }
+}
+
+// What the comparisons give when something is true, since only `nil` is false.
+fn truth(b: bool) -> Var {
+    match b {
+        true => Var::new(LispType::Keyword("true".to_string())),
+        false => Var::new(LispType::Nil),
+    }
+}
+
+// Compares the values the way that `eq?`, `eqv?` or `equal?` does, which are each looser than the one before.
+fn compare(op: &IntrinsicOp, a: &Var, b: &Var) -> bool {
+    let (a, b) = (a.resolve(), b.resolve());
+    // Everything is the same as itself, even functions, which are never equal otherwise.
+    if a.same(&b) {
+        return true;
+    }
+    let (a, b) = (&*a.get(), &*b.get());
+    match op {
+        // There is only one `nil`, even though each one has a slot of its own.
+        IntrinsicOp::Eq => matches!((a, b), (LispType::Nil, LispType::Nil)),
+        // Lists, pairs, maps and functions are only the same as themselves.
+        IntrinsicOp::Eqv => match (a, b) {
+            (
+                LispType::List(_)
+                | LispType::Pair(..)
+                | LispType::Map(_)
+                | LispType::Func(_)
+                | LispType::Values(_),
+                _,
+            ) => false,
+            (a, b) => a == b,
+        },
+        _ => a == b,
+    }
 }
 
 impl Callable for IntrinsicOp {
     fn call(&self, args: &[Var], loc_called: &Location) -> Result<Var, LispErrors> {
         match self {
             IntrinsicOp::Add => {
-                if args.len() < 2 {
-                    println!("{} - Addition requires at least two arguments!", loc_called);
+                if args.first().is_some_and(crate::dates::is_temporal) {
+                    return crate::dates::add(args, loc_called);
                 }
                 // TODO(#11): Addition of floats and integers.
-                let mut sum = 0;
+                let mut sum: isize = 0;
                 for a in args {
-                    if let LispType::Integer(i) = *a.resolve()?.get() {
-                        sum += i;
+                    if let LispType::Integer(i) = *a.resolve().get() {
+                        sum = sum
+                            .checked_add(i)
+                            .ok_or_else(|| overflowed("+", loc_called))?;
                     } else {
                         return Err(LispErrors::new().error(
                             loc_called,
@@ -37,23 +720,11 @@ impl Callable for IntrinsicOp {
                 Ok(Var::new(sum))
             }
             IntrinsicOp::Multiply => {
-                if args.len() < 2 {
-                    println!(
-                        "{} - Multiplication requires at least two arguments!",
-                        loc_called
-                    );
-                }
-                let mut product;
-                let t = args.get(0).unwrap();
-                if let LispType::Integer(i) = *t.resolve()?.get() {
-                    product = i
-                } else {
-                    return Err(LispErrors::new()
-                        .error(loc_called, "Cannot multiply with non-integer type!"));
-                }
-                for a in args.iter().skip(1) {
-                    if let LispType::Integer(i) = *a.resolve()?.get() {
-                        product *= i;
+                let mut product: isize = 1;
+                for a in args {
+                    if let LispType::Integer(i) = *a.resolve().get() {
+                        product =
+                            (product.checked_mul(i)).ok_or_else(|| overflowed("*", loc_called))?;
                     } else {
                         return Err(LispErrors::new()
                             .error(loc_called, "Cannot multiply with non-integer type!"));
@@ -62,24 +733,33 @@ impl Callable for IntrinsicOp {
                 Ok(Var::new(product))
             }
             IntrinsicOp::Subtract => {
-                if args.len() < 2 {
-                    println!(
-                        "{} - Subtraction requires at least two arguments!",
-                        loc_called
-                    );
+                let Some((first, rest)) = args.split_first() else {
+                    return Err(LispErrors::new()
+                        .error(
+                            loc_called,
+                            "`-` takes at least 1 argument(s), but was given 0!",
+                        )
+                        .note(None, "Give it one integer to negate it, like `(- 5)`."));
+                };
+                if crate::dates::is_temporal(first) {
+                    return crate::dates::subtract(args, loc_called);
                 }
-                let mut sum;
-                let t = args.get(0).unwrap();
-                if let LispType::Integer(i) = *t.resolve()?.get() {
-                    sum = i
-                } else {
+                let LispType::Integer(first) = *first.resolve().get() else {
                     return Err(
                         LispErrors::new().error(loc_called, "Cannot subtract from a non-integer!")
                     );
+                };
+                if rest.is_empty() {
+                    let negated = first
+                        .checked_neg()
+                        .ok_or_else(|| overflowed("-", loc_called))?;
+                    return Ok(Var::new(negated));
                 }
-                for a in args.iter().skip(1) {
-                    if let LispType::Integer(i) = *a.resolve()?.get() {
-                        sum -= i;
+                let mut difference = first;
+                for a in rest {
+                    if let LispType::Integer(i) = *a.resolve().get() {
+                        difference = (difference.checked_sub(i))
+                            .ok_or_else(|| overflowed("-", loc_called))?;
                     } else {
                         return Err(LispErrors::new().error(
                             loc_called,
@@ -87,7 +767,7 @@ impl Callable for IntrinsicOp {
                         ));
                     }
                 }
-                Ok(Var::new(sum))
+                Ok(Var::new(difference))
             }
             IntrinsicOp::Print => {
                 if args.len() != 1 {
@@ -95,10 +775,842 @@ impl Callable for IntrinsicOp {
                         .error(loc_called, "Print intrinsic requires only one argument!")
                         .note(None, "Try wrapping this in a statement with `$`."))
                 } else {
-                    println!("{}", args[0]);
+                    write_line(&streams().out, &args[0], loc_called)?;
                     Ok(Var::new(0))
                 }
             }
+            IntrinsicOp::EPrint => {
+                expect_args(args, 1, "eprint", loc_called)?;
+                write_line(&streams().err, &args[0], loc_called)?;
+                Ok(Var::new(0))
+            }
+            IntrinsicOp::ReadLine => {
+                expect_args(args, 0, "read-line", loc_called)?;
+                match streams().input.read_line() {
+                    Ok(Some(line)) => Ok(Var::new(line)),
+                    Ok(None) => Ok(Var::new(LispType::Nil)),
+                    Err(e) => {
+                        Err(LispErrors::new()
+                            .error(loc_called, format!("Could not read a line: {e}")))
+                    }
+                }
+            }
+            IntrinsicOp::List => {
+                let mut items = Vec::with_capacity(args.len());
+                for a in args {
+                    items.push(a.resolve());
+                }
+                Ok(Var::new(LispType::List(items)))
+            }
+            IntrinsicOp::MakeMap => {
+                if !args.len().is_multiple_of(2) {
+                    return Err(LispErrors::new()
+                        .error(loc_called, "Maps require a value for every key!")
+                        .note(None, "Add a value after the last key."));
+                }
+                let mut entries: Vec<(Var, Var)> = Vec::with_capacity(args.len() / 2);
+                for pair in args.chunks(2) {
+                    let (key, value) = (pair[0].resolve(), pair[1].resolve());
+                    // Later keys replace the values of earlier ones that are equal to them.
+                    match entries.iter_mut().find(|(k, _)| *k == key) {
+                        Some(entry) => entry.1 = value,
+                        None => entries.push((key, value)),
+                    }
+                }
+                Ok(Var::new(LispType::Map(entries)))
+            }
+            IntrinsicOp::Str => {
+                let mut s = String::new();
+                for a in args {
+                    s.push_str(&a.resolve().to_string());
+                }
+                Ok(Var::new(s))
+            }
+            IntrinsicOp::Exit => {
+                let code = match args {
+                    [] => 0,
+                    [code] => match *code.resolve().get() {
+                        LispType::Integer(i) => i32::try_from(i).map_err(|_| {
+                            LispErrors::new()
+                                .error(loc_called, format!("`{i}` is too big to be an exit code!"))
+                        })?,
+                        ref other => {
+                            return Err(LispErrors::new().error(
+                                loc_called,
+                                format!("`exit` needs an integer code, but was given `{other}`!"),
+                            ))
+                        }
+                    },
+                    _ => {
+                        return Err(LispErrors::new().error(
+                            loc_called,
+                            format!(
+                                "`exit` takes 0 or 1 argument(s), but was given {}!",
+                                args.len()
+                            ),
+                        ))
+                    }
+                };
+                Err(LispErrors::exit(code))
+            }
+            IntrinsicOp::Values => Ok(Var::new(LispType::Values(
+                args.iter().map(Var::resolve).collect(),
+            ))),
+            IntrinsicOp::QuotientRemainder => {
+                expect_args(args, 2, "quotient/remainder", loc_called)?;
+                let (dividend, divisor) = match (&*args[0].resolve().get(), &*args[1].resolve().get()) {
+                    (&LispType::Integer(a), &LispType::Integer(b)) => (a, b),
+                    (a, b) => {
+                        return Err(LispErrors::new().error(
+                            loc_called,
+                            format!("`quotient/remainder` needs two integers, but was given `{a}` and `{b}`!"),
+                        ))
+                    }
+                };
+                match (dividend.checked_div(divisor), dividend.checked_rem(divisor)) {
+                    (Some(q), Some(r)) => {
+                        Ok(Var::new(LispType::Values(vec![Var::new(q), Var::new(r)])))
+                    }
+                    _ if divisor == 0 => {
+                        Err(LispErrors::new().error(loc_called, "Cannot divide by zero!"))
+                    }
+                    _ => Err(LispErrors::new().error(
+                        loc_called,
+                        format!("Dividing `{dividend}` by `{divisor}` overflows!"),
+                    )),
+                }
+            }
+            IntrinsicOp::Eq | IntrinsicOp::Eqv | IntrinsicOp::Equal => {
+                let name = match self {
+                    IntrinsicOp::Eq => "eq?",
+                    IntrinsicOp::Eqv => "eqv?",
+                    _ => "equal?",
+                };
+                expect_args(args, 2, name, loc_called)?;
+                Ok(truth(compare(self, &args[0], &args[1])))
+            }
+            IntrinsicOp::Is(kind) => {
+                expect_args(args, 1, kind.predicate(), loc_called)?;
+                Ok(truth(args[0].resolve().get().type_name() == *kind))
+            }
+            IntrinsicOp::TypeOf => {
+                expect_args(args, 1, "type-of", loc_called)?;
+                let name = args[0].resolve().get().type_name().name();
+                Ok(Var::new(LispType::Keyword(name.to_string())))
+            }
+            IntrinsicOp::StringToInt => {
+                expect_optional_arg(args, 1, "string->int", loc_called)?;
+                let s = string_arg(&args[0], "string->int", loc_called)?;
+                let radix = radix_arg(args.get(1), "string->int", loc_called)?;
+                isize::from_str_radix(&s, radix).map(Var::new).map_err(|_| {
+                    let error =
+                        LispErrors::new().error(loc_called, format!("`{s}` is not an integer!"));
+                    match radix {
+                        10 => error,
+                        _ => error.note(None, format!("It was read in base {radix}.")),
+                    }
+                })
+            }
+            IntrinsicOp::IntToString => {
+                expect_optional_arg(args, 1, "int->string", loc_called)?;
+                let i = int_arg(&args[0], "int->string", loc_called)?;
+                let radix = radix_arg(args.get(1), "int->string", loc_called)?;
+                Ok(Var::new(to_radix(i, radix)))
+            }
+            IntrinsicOp::FloatToInt => {
+                expect_optional_arg(args, 1, "float->int", loc_called)?;
+                float_to_int(args, loc_called).map(Var::new)
+            }
+            IntrinsicOp::StringToFloat => {
+                expect_args(args, 1, "string->float", loc_called)?;
+                let s = string_arg(&args[0], "string->float", loc_called)?;
+                s.parse::<f64>().map(Var::new).map_err(|_| {
+                    LispErrors::new().error(loc_called, format!("`{s}` is not a float!"))
+                })
+            }
+            IntrinsicOp::ListToString => {
+                expect_args(args, 1, "list->string", loc_called)?;
+                let list = args[0].resolve();
+                let items = match &*list.get() {
+                    LispType::List(items) => items.iter().map(Var::resolve).collect(),
+                    LispType::Nil => Vec::new(),
+                    other => {
+                        return Err(LispErrors::new().error(
+                            loc_called,
+                            format!("`list->string` needs a list, but was given `{other}`!"),
+                        ))
+                    }
+                };
+                let mut s = String::new();
+                for (i, item) in items.iter().enumerate() {
+                    match *item.get() {
+                        LispType::Str(ref part) => s.push_str(part),
+                        LispType::Integer(code) => match u32::try_from(code).ok().and_then(char::from_u32) {
+                            Some(c) => s.push(c),
+                            None => {
+                                return Err(LispErrors::new().error(
+                                    loc_called,
+                                    format!("`{code}` is not a character, at index {i} of the list!"),
+                                ))
+                            }
+                        },
+                        ref other => {
+                            return Err(LispErrors::new()
+                                .error(
+                                    loc_called,
+                                    format!("`{other}` is not a string or a character, at index {i} of the list!"),
+                                )
+                                .note(None, "Characters are written as their code points, like `97` for `a`."))
+                        }
+                    }
+                }
+                Ok(Var::new(s))
+            }
+            #[cfg(feature = "json")]
+            IntrinsicOp::JsonParse => {
+                expect_args(args, 1, "json-parse", loc_called)?;
+                crate::json::parse(&string_arg(&args[0], "json-parse", loc_called)?, loc_called)
+            }
+            #[cfg(feature = "json")]
+            IntrinsicOp::JsonStringify => {
+                expect_args(args, 1, "json-stringify", loc_called)?;
+                crate::json::stringify(&args[0], loc_called).map(Var::new)
+            }
+            #[cfg(feature = "config")]
+            IntrinsicOp::ReadConfig => {
+                expect_args(args, 1, "read-config", loc_called)?;
+                crate::config::read(
+                    &string_arg(&args[0], "read-config", loc_called)?,
+                    loc_called,
+                )
+            }
+            #[cfg(feature = "regex")]
+            IntrinsicOp::RegexMatch | IntrinsicOp::RegexFind | IntrinsicOp::RegexCaptures => {
+                let name = match self {
+                    IntrinsicOp::RegexMatch => "regex-match?",
+                    IntrinsicOp::RegexFind => "regex-find",
+                    _ => "regex-captures",
+                };
+                expect_args(args, 2, name, loc_called)?;
+                let pattern = string_arg(&args[0], name, loc_called)?;
+                let text = string_arg(&args[1], name, loc_called)?;
+                match self {
+                    IntrinsicOp::RegexMatch => {
+                        crate::regex::is_match(&pattern, &text, loc_called).map(truth)
+                    }
+                    IntrinsicOp::RegexFind => crate::regex::find(&pattern, &text, loc_called),
+                    _ => crate::regex::captures(&pattern, &text, loc_called),
+                }
+            }
+            #[cfg(feature = "regex")]
+            IntrinsicOp::RegexReplace => {
+                expect_args(args, 3, "regex-replace", loc_called)?;
+                let [pattern, text, replacement] =
+                    [0, 1, 2].map(|i| string_arg(&args[i], "regex-replace", loc_called));
+                crate::regex::replace(&pattern?, &text?, &replacement?, loc_called)
+            }
+            #[cfg(feature = "net")]
+            IntrinsicOp::HttpGet => {
+                expect_optional_arg(args, 1, "http-get", loc_called)?;
+                crate::http::get(args, loc_called)
+            }
+            #[cfg(feature = "net")]
+            IntrinsicOp::HttpPost => {
+                expect_optional_arg(args, 2, "http-post", loc_called)?;
+                crate::http::post(args, loc_called)
+            }
+            IntrinsicOp::CsvRead => crate::csv::read(args, loc_called),
+            IntrinsicOp::OpenInputString => {
+                expect_args(args, 1, "open-input-string", loc_called)?;
+                let text = string_arg(&args[0], "open-input-string", loc_called)?;
+                Ok(crate::port::input_string(text))
+            }
+            IntrinsicOp::OpenOutputString => {
+                expect_args(args, 0, "open-output-string", loc_called)?;
+                Ok(crate::port::output_string())
+            }
+            IntrinsicOp::GetOutputString => {
+                expect_args(args, 1, "get-output-string", loc_called)?;
+                port_arg(&args[0], "get-output-string", loc_called)?.output_string(loc_called)
+            }
+            IntrinsicOp::OpenInputFile => {
+                expect_args(args, 1, "open-input-file", loc_called)?;
+                let path = string_arg(&args[0], "open-input-file", loc_called)?;
+                crate::port::input_file(&path, loc_called)
+            }
+            IntrinsicOp::OpenOutputFile => {
+                expect_args(args, 1, "open-output-file", loc_called)?;
+                let path = string_arg(&args[0], "open-output-file", loc_called)?;
+                crate::port::output_file(&path, loc_called)
+            }
+            IntrinsicOp::CurrentInputPort => {
+                expect_args(args, 0, "current-input-port", loc_called)?;
+                Ok(crate::port::current_input())
+            }
+            IntrinsicOp::CurrentOutputPort => {
+                expect_args(args, 0, "current-output-port", loc_called)?;
+                Ok(crate::port::current_output())
+            }
+            IntrinsicOp::ReadChar => {
+                expect_args(args, 1, "read-char", loc_called)?;
+                port_arg(&args[0], "read-char", loc_called)?.read_char(loc_called)
+            }
+            IntrinsicOp::WriteString => {
+                expect_args(args, 2, "write-string", loc_called)?;
+                let text = string_arg(&args[0], "write-string", loc_called)?;
+                port_arg(&args[1], "write-string", loc_called)?.write_string(&text, loc_called)?;
+                Ok(Var::new(LispType::Nil))
+            }
+            IntrinsicOp::ClosePort => {
+                expect_args(args, 1, "close-port", loc_called)?;
+                port_arg(&args[0], "close-port", loc_called)?.close();
+                Ok(Var::new(LispType::Nil))
+            }
+            IntrinsicOp::Now => {
+                expect_args(args, 0, "now", loc_called)?;
+                Ok(crate::dates::now())
+            }
+            IntrinsicOp::Date => {
+                if !(3..=6).contains(&args.len()) {
+                    return Err(LispErrors::new().error(
+                        loc_called,
+                        format!(
+                            "`date` takes from 3 to 6 argument(s), but was given {}!",
+                            args.len()
+                        ),
+                    ));
+                }
+                let parts = args
+                    .iter()
+                    .map(|part| int_arg(part, "date", loc_called))
+                    .collect::<Result<Vec<_>, _>>()?;
+                crate::dates::date(&parts, loc_called)
+            }
+            IntrinsicOp::ParseDate => {
+                expect_args(args, 1, "parse-date", loc_called)?;
+                crate::dates::parse(&string_arg(&args[0], "parse-date", loc_called)?, loc_called)
+            }
+            IntrinsicOp::FormatDate => {
+                expect_optional_arg(args, 1, "format-date", loc_called)?;
+                let date = date_arg(&args[0], "format-date", loc_called)?;
+                let pattern = match args.get(1) {
+                    Some(pattern) => Some(string_arg(pattern, "format-date", loc_called)?),
+                    None => None,
+                };
+                crate::dates::format(date, pattern.as_deref(), loc_called)
+            }
+            IntrinsicOp::DateParts => {
+                expect_args(args, 1, "date-parts", loc_called)?;
+                Ok(crate::dates::parts(date_arg(
+                    &args[0],
+                    "date-parts",
+                    loc_called,
+                )?))
+            }
+            IntrinsicOp::Duration => crate::dates::duration(args, loc_called),
+            IntrinsicOp::DurationToSeconds => {
+                expect_args(args, 1, "duration->seconds", loc_called)?;
+                match *args[0].resolve().get() {
+                    LispType::Duration(millis) => Ok(crate::dates::to_seconds(millis)),
+                    ref other => Err(LispErrors::new().error(
+                        loc_called,
+                        format!("`duration->seconds` needs a duration, but was given `{other}`!"),
+                    )),
+                }
+            }
+            IntrinsicOp::DateToEpoch => {
+                expect_args(args, 1, "date->epoch", loc_called)?;
+                Ok(crate::dates::to_epoch(date_arg(
+                    &args[0],
+                    "date->epoch",
+                    loc_called,
+                )?))
+            }
+            IntrinsicOp::EpochToDate => {
+                expect_args(args, 1, "epoch->date", loc_called)?;
+                let seconds = int_arg(&args[0], "epoch->date", loc_called)?;
+                crate::dates::from_epoch(seconds, loc_called)
+            }
+            IntrinsicOp::Utf8ToString => {
+                expect_args(args, 1, "utf8->string", loc_called)?;
+                let bytes = bytes_arg(&args[0], "utf8->string", loc_called)?;
+                let text = String::from_utf8(bytes).map_err(|e| {
+                    LispErrors::new()
+                        .error(
+                            loc_called,
+                            "`utf8->string` was given bytes that aren't UTF-8!",
+                        )
+                        .note(None, e.to_string())
+                })?;
+                Ok(Var::new(text))
+            }
+            IntrinsicOp::StringToUtf8 => {
+                expect_args(args, 1, "string->utf8", loc_called)?;
+                let text = string_arg(&args[0], "string->utf8", loc_called)?;
+                Ok(Var::new(text.into_bytes()))
+            }
+            IntrinsicOp::Connect => {
+                expect_optional_arg(args, 1, "connect", loc_called)?;
+                let address = string_arg(&args[0], "connect", loc_called)?;
+                let timeout = match args.get(1) {
+                    Some(ms) => Some(int_arg(ms, "connect", loc_called)?),
+                    None => None,
+                };
+                crate::socket::connect(&address, timeout, loc_called)
+            }
+            IntrinsicOp::Send => {
+                expect_args(args, 2, "send", loc_called)?;
+                if let LispType::Channel(channel) = &*args[0].resolve().get() {
+                    return crate::channel::send(channel, &args[1], loc_called);
+                }
+                let socket = socket_arg(&args[0], "send", loc_called)?;
+                let data = match &*args[1].resolve().get() {
+                    LispType::Str(s) => s.as_bytes().to_vec(),
+                    LispType::Bytes(b) => b.clone(),
+                    other => {
+                        return Err(LispErrors::new()
+                            .error(loc_called, format!("`send` can't send `{other}`!"))
+                            .note(None, "It has to be a string or bytes."))
+                    }
+                };
+                crate::socket::send(&socket, &data, loc_called)
+            }
+            IntrinsicOp::Recv => {
+                expect_optional_arg(args, 1, "recv", loc_called)?;
+                if let LispType::Channel(channel) = &*args[0].resolve().get() {
+                    expect_args(args, 1, "recv", loc_called)?;
+                    return crate::channel::recv(channel, loc_called);
+                }
+                let socket = socket_arg(&args[0], "recv", loc_called)?;
+                let most = match args.get(1) {
+                    Some(most) => match int_arg(most, "recv", loc_called)? {
+                        most @ 1.. => most as usize,
+                        most => {
+                            return Err(LispErrors::new().error(
+                                loc_called,
+                                format!("`recv` can't wait for at most {most} bytes!"),
+                            ))
+                        }
+                    },
+                    None => 4096,
+                };
+                crate::socket::recv(&socket, most, loc_called)
+            }
+            IntrinsicOp::Close => {
+                expect_args(args, 1, "close", loc_called)?;
+                if let LispType::Channel(channel) = &*args[0].resolve().get() {
+                    crate::channel::close(channel);
+                    return Ok(Var::new(LispType::Nil));
+                }
+                let socket = socket_arg(&args[0], "close", loc_called)?;
+                crate::socket::close(&socket);
+                Ok(Var::new(LispType::Nil))
+            }
+            IntrinsicOp::Channel => {
+                expect_args(args, 0, "channel", loc_called)?;
+                Ok(crate::channel::channel())
+            }
+            #[cfg(feature = "sync")]
+            IntrinsicOp::Spawn => {
+                expect_args(args, 1, "spawn", loc_called)?;
+                crate::channel::spawn(&args[0], loc_called)
+            }
+            IntrinsicOp::Delay => {
+                expect_args(args, 1, "delay", loc_called)?;
+                Ok(crate::promise::delay(&args[0]))
+            }
+            IntrinsicOp::Force => {
+                expect_args(args, 1, "force", loc_called)?;
+                crate::promise::force(&args[0], loc_called)
+            }
+            IntrinsicOp::After => {
+                expect_args(args, 2, "after", loc_called)?;
+                crate::events::after(&events(), &args[0], &args[1], loc_called)
+            }
+            IntrinsicOp::Every => {
+                expect_args(args, 2, "every", loc_called)?;
+                crate::events::every(&events(), &args[0], &args[1], loc_called)
+            }
+            IntrinsicOp::CancelTimer => {
+                expect_args(args, 1, "cancel-timer", loc_called)?;
+                let id = int_arg(&args[0], "cancel-timer", loc_called)?;
+                Ok(truth(crate::events::cancel(&events(), id)))
+            }
+            IntrinsicOp::RunEvents => {
+                expect_args(args, 0, "run-events", loc_called)?;
+                crate::events::run_events(&events(), loc_called)
+            }
+            IntrinsicOp::Pmap => {
+                expect_args(args, 2, "pmap", loc_called)?;
+                crate::parallel::pmap(&args[0], &args[1], loc_called)
+            }
+            IntrinsicOp::Bytes => crate::bytes::from_items(args, loc_called),
+            IntrinsicOp::BytesLength => {
+                expect_args(args, 1, "bytes-length", loc_called)?;
+                let len = bytes_arg(&args[0], "bytes-length", loc_called)?.len();
+                Ok(Var::new(len as isize))
+            }
+            IntrinsicOp::BytesToList => {
+                expect_args(args, 1, "bytes->list", loc_called)?;
+                let bytes = bytes_arg(&args[0], "bytes->list", loc_called)?;
+                let items = bytes.into_iter().map(|b| Var::new(b as isize)).collect();
+                Ok(Var::new(LispType::List(items)))
+            }
+            IntrinsicOp::BytesSlice => {
+                expect_optional_arg(args, 2, "bytes-slice", loc_called)?;
+                let bytes = bytes_arg(&args[0], "bytes-slice", loc_called)?;
+                let start = int_arg(&args[1], "bytes-slice", loc_called)?;
+                let end = match args.get(2) {
+                    Some(end) => Some(int_arg(end, "bytes-slice", loc_called)?),
+                    None => None,
+                };
+                crate::bytes::slice(&bytes, start, end, loc_called)
+            }
+            IntrinsicOp::ReadBytes => {
+                expect_args(args, 1, "read-bytes", loc_called)?;
+                let path = string_arg(&args[0], "read-bytes", loc_called)?;
+                let bytes = std::fs::read(&path).map_err(|e| {
+                    LispErrors::new().error(loc_called, format!("Could not read `{path}`: {e}"))
+                })?;
+                Ok(Var::new(bytes))
+            }
+            IntrinsicOp::BytesToHex => {
+                expect_args(args, 1, "bytes->hex", loc_called)?;
+                let bytes = bytes_arg(&args[0], "bytes->hex", loc_called)?;
+                Ok(Var::new(crate::bytes::to_hex(&bytes)))
+            }
+            IntrinsicOp::HexToBytes => {
+                expect_args(args, 1, "hex->bytes", loc_called)?;
+                crate::bytes::from_hex(&string_arg(&args[0], "hex->bytes", loc_called)?, loc_called)
+            }
+            IntrinsicOp::BytesToBase64 => {
+                expect_args(args, 1, "bytes->base64", loc_called)?;
+                let bytes = bytes_arg(&args[0], "bytes->base64", loc_called)?;
+                Ok(Var::new(crate::bytes::to_base64(&bytes)))
+            }
+            IntrinsicOp::Base64ToBytes => {
+                expect_args(args, 1, "base64->bytes", loc_called)?;
+                let text = string_arg(&args[0], "base64->bytes", loc_called)?;
+                crate::bytes::from_base64(&text, loc_called)
+            }
+            IntrinsicOp::CsvWrite => crate::csv::write(args, loc_called),
+            IntrinsicOp::Gensym => match args {
+                [] => Ok(Var::new(gensym("g"))),
+                [prefix] => Ok(Var::new(gensym(&string_arg(prefix, "gensym", loc_called)?))),
+                _ => Err(LispErrors::new().error(
+                    loc_called,
+                    format!(
+                        "`gensym` takes 0 or 1 argument(s), but was given {}!",
+                        args.len()
+                    ),
+                )),
+            },
+            IntrinsicOp::MakeParameter => {
+                expect_args(args, 1, "make-parameter", loc_called)?;
+                let default = Var::new(LispType::Nil);
+                default.bind(args[0].resolve());
+                Ok(Var::new(Parameter { default }))
+            }
+            IntrinsicOp::Cons => {
+                expect_args(args, 2, "cons", loc_called)?;
+                Ok(cons(args[0].resolve(), args[1].resolve()))
+            }
+            IntrinsicOp::Car | IntrinsicOp::Cdr => {
+                let name = if let IntrinsicOp::Car = self {
+                    "car"
+                } else {
+                    "cdr"
+                };
+                expect_args(args, 1, name, loc_called)?;
+                let pair = args[0].resolve();
+                let pair = pair.get();
+                match (self, &*pair) {
+                    (IntrinsicOp::Car, LispType::Pair(car, _)) => Ok(car.new_ref()),
+                    (_, LispType::Pair(_, cdr)) => Ok(cdr.new_ref()),
+                    (IntrinsicOp::Car, LispType::List(items)) if !items.is_empty() => {
+                        Ok(items[0].new_ref())
+                    }
+                    (_, LispType::List(items)) if !items.is_empty() => Ok(Var::new(
+                        LispType::List(items[1..].iter().map(Var::new_ref).collect()),
+                    )),
+                    (_, other) => Err(LispErrors::new().error(
+                        loc_called,
+                        format!("`{name}` needs a pair or a list that isn't empty, but was given `{other}`!"),
+                    )),
+                }
+            }
+        }
+    }
+
+    fn call_dotted(
+        &self,
+        args: &[Var],
+        tail: &Var,
+        loc_called: &Location,
+    ) -> Result<Var, LispErrors> {
+        match self {
+            // `[1 2 . 3]` is the same as `(cons 1 (cons 2 3))`.
+            IntrinsicOp::List => {
+                let mut list = tail.resolve();
+                for item in args.iter().rev() {
+                    list = cons(item.resolve(), list);
+                }
+                Ok(list)
+            }
+            _ => spread(self, args, tail, loc_called),
         }
     }
+
+    fn as_intrinsic(&self) -> Option<&IntrinsicOp> {
+        Some(self)
+    }
+
+    fn is_pure(&self) -> bool {
+        IntrinsicOp::is_pure(self)
+    }
+
+    fn callee(&self) -> Callee {
+        Callee::Intrinsic(self.clone())
+    }
+}
+
+/// A function written in Pale with `lambda`, along with the slots it captured from the call it was made in.
+#[derive(Debug)]
+pub(crate) struct Function {
+    lambda: Shared<Lambda>,
+    captured: Vec<Var>,
+}
+
+impl Function {
+    /// Makes the function, with `locals` being the frame of the call that it is made in.
+    pub(crate) fn new(lambda: Shared<Lambda>, locals: &[Var]) -> Self {
+        // The slots themselves are shared rather than their values, so the function sees the same names as the call that made it.
+        let captured = lambda
+            .slots
+            .iter()
+            .flatten()
+            .map(|&slot| locals[slot].new_ref())
+            .collect();
+        Self { lambda, captured }
+    }
+
+    /// What the function refers to: the lambda it was made from, and the slots it captured.
+    pub(crate) fn parts(&self) -> (&Shared<Lambda>, &[Var]) {
+        (&self.lambda, &self.captured)
+    }
+
+    /// Makes a frame for a call and binds the arguments to the parameters in it, giving the function whose body is to be evaluated with it.
+    /// `this` is the value of the function, and `arg_locs` are where the arguments start, for the ones whose location is known.
+    pub(crate) fn bind_args(
+        &self,
+        this: &Var,
+        args: &[Var],
+        loc_called: &Location,
+        arg_locs: &[Location],
+    ) -> Result<(Shared<Lambda>, Locals), LispErrors> {
+        let lambda = &self.lambda;
+        let takes = match lambda.arity() {
+            (min, Some(max)) if min == max && args.len() != min => Some(min.to_string()),
+            (min, _) if args.len() < min => Some(format!("at least {min}")),
+            (_, Some(max)) if args.len() > max => Some(format!("at most {max}")),
+            _ => None,
+        };
+        if let Some(takes) = takes {
+            return Err(LispErrors::new()
+                .error(
+                    loc_called,
+                    format!(
+                        "This function takes {takes} argument(s), but was given {}!",
+                        args.len()
+                    ),
+                )
+                .note(&lambda.loc, "It is defined here."));
+        }
+        let mut captured = self.captured.iter();
+        let locals: Locals = lambda
+            .slots
+            .iter()
+            .map(|slot| match slot {
+                Some(_) => captured.next().unwrap().new_ref(),
+                None => Var::new(LispType::Nil),
+            })
+            .collect();
+        if let Some(itself) = &lambda.itself {
+            itself.bind(&locals, this.new_ref());
+        }
+        let (args, extra) = args.split_at(lambda.params.len());
+        for (param, arg) in lambda.params.iter().zip(args) {
+            param.bind(&locals, arg.new_ref());
+        }
+        if let Some(rest) = &lambda.rest {
+            let extra = extra.iter().map(Var::resolve).collect();
+            rest.bind(&locals, Var::new(LispType::List(extra)));
+        }
+        if !lambda.keys.is_empty() {
+            let n = lambda.params.len();
+            self.bind_keys(extra, &locals, |i| {
+                arg_locs.get(n + i).unwrap_or(loc_called)
+            })?;
+        }
+        Ok((Shared::clone(&self.lambda), locals))
+    }
+
+    // Binds the `:name value` pairs to the keyword parameters, in whatever order they were given. The ones that weren't given stay `nil`.
+    fn bind_keys<'a>(
+        &self,
+        pairs: &[Var],
+        locals: &[Var],
+        loc_of: impl Fn(usize) -> &'a Location,
+    ) -> Result<(), LispErrors> {
+        let keys = &self.lambda.keys;
+        let mut given: Vec<(String, usize)> = Vec::new();
+        for (i, pair) in pairs.chunks(2).enumerate() {
+            let loc = loc_of(2 * i);
+            let name = match &*pair[0].get() {
+                LispType::Keyword(name) => name.clone(),
+                other => {
+                    return Err(LispErrors::new()
+                        .error(loc, format!("Expected a keyword, but found `{other}`!"))
+                        .note(None, format!("Write it like `:{} value`.", keys[0].0)))
+                }
+            };
+            let Some((_, place)) = keys.iter().find(|(key, _)| *key == name) else {
+                let names: Vec<_> = keys.iter().map(|(key, _)| format!("`:{key}`")).collect();
+                return Err(LispErrors::new()
+                    .error(loc, format!("This function has no `:{name}` parameter!"))
+                    .note(None, format!("It takes {}.", names.join(", ")))
+                    .note(&self.lambda.loc, "It is defined here."));
+            };
+            if let Some((_, first)) = given.iter().find(|(key, _)| *key == name) {
+                return Err(LispErrors::new()
+                    .error(loc, format!("`:{name}` was given more than once!"))
+                    .note(loc_of(*first), "It is first given here."));
+            }
+            let [_, value] = pair else {
+                return Err(
+                    LispErrors::new().error(loc, format!("`:{name}` needs a value after it!"))
+                );
+            };
+            place.bind(locals, value.new_ref());
+            given.push((name, 2 * i));
+        }
+        Ok(())
+    }
+}
+
+/// A value made by `make-parameter`, which `parameterize` can give another value for as long as its body is being evaluated.
+#[derive(Debug)]
+pub(crate) struct Parameter {
+    // A slot of its own, which also tells it apart from every other parameter.
+    default: Var,
+}
+
+impl Parameter {
+    /// What tells this parameter apart from the others, even ones with the same default.
+    pub(crate) fn key(&self) -> &Var {
+        &self.default
+    }
+}
+
+impl Callable for Parameter {
+    fn call(&self, args: &[Var], loc_called: &Location) -> Result<Var, LispErrors> {
+        if !args.is_empty() {
+            return Err(LispErrors::new()
+                .error(
+                    loc_called,
+                    format!(
+                        "A parameter takes no arguments, but was given {}!",
+                        args.len()
+                    ),
+                )
+                .note(None, "Use `parameterize` to give it another value."));
+        }
+        Ok(parameter_value(&self.default).unwrap_or_else(|| self.default.resolve()))
+    }
+
+    fn as_parameter(&self) -> Option<&Parameter> {
+        Some(self)
+    }
+
+    fn is_pure(&self) -> bool {
+        true
+    }
+
+    fn callee(&self) -> Callee {
+        Callee::Parameter
+    }
+}
+
+impl Callable for Function {
+    fn call(&self, args: &[Var], loc_called: &Location) -> Result<Var, LispErrors> {
+        // The function is only here by reference, so there is a copy of it to bind to its name.
+        let this = Var::new(Function {
+            lambda: Shared::clone(&self.lambda),
+            captured: self.captured.iter().map(Var::new_ref).collect(),
+        });
+        // Intrinsics like `pmap` call it this way rather than through the evaluator, so it is profiled here.
+        profiled(
+            || self.callee(),
+            || {
+                let (lambda, locals) = self.bind_args(&this, args, loc_called, &[])?;
+                eval_in(&lambda.body, locals)
+            },
+        )
+    }
+
+    fn as_function(&self) -> Option<&Function> {
+        Some(self)
+    }
+
+    fn is_pure(&self) -> bool {
+        true
+    }
+
+    fn callee(&self) -> Callee {
+        Callee::Function(self.lambda.name.clone(), self.lambda.loc.clone())
+    }
+}
+
+/// What a function that the host registered is, so that scripts can call into the host.
+#[cfg(not(feature = "sync"))]
+pub type NativeFn = Shared<dyn Fn(&[Value]) -> Result<Value, String>>;
+/// What a function that the host registered is, so that scripts can call into the host.
+#[cfg(feature = "sync")]
+pub type NativeFn = Shared<dyn Fn(&[Value]) -> Result<Value, String> + Send + Sync>;
+
+/// A function written in Rust by whoever is embedding Pale.
+pub(crate) struct Native {
+    name: Shared<str>,
+    f: NativeFn,
+}
+
+impl Native {
+    pub(crate) fn new(name: &str, f: NativeFn) -> Self {
+        Self {
+            name: name.into(),
+            f,
+        }
+    }
+}
+
+impl Debug for Native {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Native({})", self.name)
+    }
+}
+
+impl Callable for Native {
+    fn call(&self, args: &[Var], loc_called: &Location) -> Result<Var, LispErrors> {
+        let args: Vec<Value> = args.iter().map(|a| Value::new(a.new_ref())).collect();
+        match (self.f)(&args) {
+            Ok(value) => Ok(value.var().new_ref()),
+            Err(e) => {
+                Err(LispErrors::new().error(loc_called, format!("`{}` failed: {e}", self.name)))
+            }
+        }
+    }
+
+    fn callee(&self) -> Callee {
+        Callee::Native(Shared::clone(&self.name))
+    }
 }
@@ -0,0 +1,141 @@
+use std::collections::VecDeque;
+#[cfg(feature = "sync")]
+use std::sync::{Condvar, PoisonError};
+
+use crate::ast::Var;
+use crate::error::LispErrors;
+use crate::sync::{exclusive, Mutable, Shared};
+use crate::types::LispType;
+use crate::Location;
+
+// What goes through a channel: a value, or the errors that a spawned thread stopped with, which `recv` gives to whoever is waiting for it.
+pub(crate) type Message = Result<Var, LispErrors>;
+
+/// A queue made by `channel` or `spawn`, which every copy of the value shares, so closing one closes them all.
+#[derive(Debug)]
+pub(crate) struct Channel {
+    queue: Mutable<Queue>,
+    // Woken whenever something is sent or the channel is closed, for a `recv` that is waiting.
+    #[cfg(feature = "sync")]
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct Queue {
+    messages: VecDeque<Message>,
+    closed: bool,
+}
+
+impl Channel {
+    fn new() -> Shared<Self> {
+        let channel = Shared::new(Channel {
+            queue: Mutable::new(Queue::default()),
+            #[cfg(feature = "sync")]
+            changed: Condvar::new(),
+        });
+        // It can be sent into itself, which only the cycle collector can free.
+        crate::cycles::track_channel(&channel);
+        channel
+    }
+
+    // Puts the message on the end of the queue, or says that the channel has already been closed.
+    fn push(&self, message: Message, loc: &Location) -> Result<(), LispErrors> {
+        let mut queue = exclusive(&self.queue);
+        if queue.closed {
+            return Err(LispErrors::new()
+                .error(loc, "`send` can't use a channel that has been closed!")
+                .note(
+                    None,
+                    "Values can still be received from it until it is empty.",
+                ));
+        }
+        queue.messages.push_back(message);
+        #[cfg(feature = "sync")]
+        self.changed.notify_one();
+        Ok(())
+    }
+
+    /// The values waiting in the channel, for the cycle collector.
+    pub(crate) fn queued(&self) -> Vec<Var> {
+        let queue = exclusive(&self.queue);
+        queue.messages.iter().flatten().map(Var::new_ref).collect()
+    }
+}
+
+impl std::fmt::Display for Channel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<Channel>")
+    }
+}
+
+/// `(channel)`: makes an empty channel.
+pub(crate) fn channel() -> Var {
+    Var::new(LispType::Channel(Channel::new()))
+}
+
+/// `(send channel value)`: puts the value on the end of the channel, giving it back.
+pub(crate) fn send(channel: &Channel, value: &Var, loc: &Location) -> Result<Var, LispErrors> {
+    channel.push(Ok(value.new_ref()), loc)?;
+    Ok(value.new_ref())
+}
+
+/// `(recv channel)`: takes the value at the front of the channel, waiting for one to be sent if it is empty, or gives `nil` once it is closed and empty.
+pub(crate) fn recv(channel: &Channel, loc: &Location) -> Result<Var, LispErrors> {
+    let mut queue = exclusive(&channel.queue);
+    // Without the `sync` feature nothing else can send to it while this waits, so waiting would never end.
+    #[cfg(not(feature = "sync"))]
+    if queue.messages.is_empty() && !queue.closed {
+        return Err(LispErrors::new()
+            .error(loc, "`recv` would wait forever for an empty channel!")
+            .note(None, "Nothing else can send to it while this waits."));
+    }
+    #[cfg(feature = "sync")]
+    while queue.messages.is_empty() && !queue.closed {
+        queue = channel
+            .changed
+            .wait(queue)
+            .unwrap_or_else(PoisonError::into_inner);
+    }
+    let received = queue.messages.pop_front();
+    drop(queue);
+    match received {
+        Some(Ok(value)) => Ok(value),
+        Some(Err(errors)) => Err(errors.note(loc, "It was received here.")),
+        None => Ok(Var::new(LispType::Nil)),
+    }
+}
+
+/// `(close channel)`: stops anything more being sent to the channel. What was sent before can still be received.
+pub(crate) fn close(channel: &Channel) {
+    exclusive(&channel.queue).closed = true;
+    #[cfg(feature = "sync")]
+    channel.changed.notify_all();
+}
+
+/// `(spawn function)`: calls the function with no arguments on a thread of its own, giving a channel that its result is sent to.
+/// If it stops with an error instead, receiving from the channel gives that error.
+#[cfg(feature = "sync")]
+pub(crate) fn spawn(function: &Var, loc: &Location) -> Result<Var, LispErrors> {
+    let function = function.resolve();
+    if !matches!(&*function.get(), LispType::Func(_)) {
+        return Err(LispErrors::new()
+            .error(
+                loc,
+                format!("`spawn` needs a function, but was given `{function}`!"),
+            )
+            .note(None, "Write it like `(spawn (lambda () ...))`."));
+    }
+    let result = Channel::new();
+    let sending = Shared::clone(&result);
+    let loc = loc.clone();
+    crate::eval::spawn(move || {
+        let value = match &*function.get() {
+            LispType::Func(f) => crate::eval::nested(&**f, &[], &loc),
+            _ => unreachable!("it was checked to be a function"),
+        };
+        let value = value.map_err(|e| e.note(&loc, "It happened in the thread spawned here."));
+        drop(sending.push(value, &loc));
+        close(&sending);
+    });
+    Ok(Var::new(LispType::Channel(result)))
+}
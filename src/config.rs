@@ -0,0 +1,81 @@
+use std::path::Path;
+
+use crate::ast::Var;
+use crate::error::LispErrors;
+use crate::types::LispType;
+use crate::Location;
+
+/// Reads a TOML or YAML file, going by its extension, into maps with string keys and lists.
+/// Like JSON, `true` becomes `:true` and `false` becomes `nil`, and TOML's dates are kept as they were written, as strings.
+pub(crate) fn read(path: &str, loc: &Location) -> Result<Var, LispErrors> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| LispErrors::new().error(loc, format!("Could not read `{path}`: {e}")))?;
+    let invalid = |e: &dyn std::fmt::Display| {
+        LispErrors::new().error(loc, format!("`{path}` is not valid: {e}"))
+    };
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("toml") => toml::from_str(&text)
+            .map(from_toml)
+            .map_err(|e| invalid(&e)),
+        Some("yaml" | "yml") => serde_yaml::from_str(&text)
+            .map(from_yaml)
+            .map_err(|e| invalid(&e)),
+        #[cfg(feature = "json")]
+        Some("json") => crate::json::parse(&text, loc),
+        _ => Err(LispErrors::new()
+            .error(
+                loc,
+                format!("`{path}` is not a config file that can be read!"),
+            )
+            .note(None, "Its name has to end in `.toml`, `.yaml` or `.yml`.")),
+    }
+}
+
+fn truth(b: bool) -> LispType {
+    match b {
+        true => LispType::Keyword("true".to_string()),
+        false => LispType::Nil,
+    }
+}
+
+fn from_toml(toml: toml::Value) -> Var {
+    Var::new(match toml {
+        toml::Value::String(s) => LispType::Str(s),
+        toml::Value::Integer(i) => match isize::try_from(i) {
+            Ok(i) => LispType::Integer(i),
+            Err(_) => LispType::Floating(i as f64),
+        },
+        toml::Value::Float(f) => LispType::Floating(f),
+        toml::Value::Boolean(b) => truth(b),
+        toml::Value::Datetime(d) => LispType::Str(d.to_string()),
+        toml::Value::Array(items) => LispType::List(items.into_iter().map(from_toml).collect()),
+        toml::Value::Table(entries) => LispType::Map(
+            entries
+                .into_iter()
+                .map(|(k, v)| (Var::new(k), from_toml(v)))
+                .collect(),
+        ),
+    })
+}
+
+fn from_yaml(yaml: serde_yaml::Value) -> Var {
+    use serde_yaml::Value as Yaml;
+    Var::new(match yaml {
+        Yaml::Null => LispType::Nil,
+        Yaml::Bool(b) => truth(b),
+        Yaml::Number(n) => match n.as_i64().and_then(|i| isize::try_from(i).ok()) {
+            Some(i) => LispType::Integer(i),
+            None => LispType::Floating(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Yaml::String(s) => LispType::Str(s),
+        Yaml::Sequence(items) => LispType::List(items.into_iter().map(from_yaml).collect()),
+        Yaml::Mapping(entries) => LispType::Map(
+            entries
+                .into_iter()
+                .map(|(k, v)| (from_yaml(k), from_yaml(v)))
+                .collect(),
+        ),
+        // Tags like `!Point` are for deserializing into particular types, which there aren't any of here.
+        Yaml::Tagged(tagged) => return from_yaml(tagged.value),
+    })
+}
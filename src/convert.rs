@@ -0,0 +1,99 @@
+use crate::value::{ConversionError, Value};
+
+/// Types that can be given to pale code as a value.
+///
+/// With the `derive` feature, structs with named fields can `#[derive(IntoLisp)]`.
+/// They become maps from keywords named after their fields, with `_` written as `-`, so `port_number` becomes `:port-number`.
+pub trait IntoLisp {
+    fn into_lisp(self) -> Value;
+}
+
+/// Types that can be read out of a value that pale code made.
+///
+/// With the `derive` feature, structs with named fields can `#[derive(FromLisp)]`, reading them from maps the way `IntoLisp` writes them.
+/// Keys that are missing are read as `nil`, so `Option` fields can be left out.
+pub trait FromLisp: Sized {
+    fn from_lisp(value: Value) -> Result<Self, ConversionError>;
+}
+
+impl IntoLisp for Value {
+    fn into_lisp(self) -> Value {
+        self
+    }
+}
+
+impl FromLisp for Value {
+    fn from_lisp(value: Value) -> Result<Self, ConversionError> {
+        Ok(value)
+    }
+}
+
+macro_rules! convert_with_from {
+    ($($t:ty),*) => {
+        $(
+            impl IntoLisp for $t {
+                fn into_lisp(self) -> Value {
+                    Value::from(self)
+                }
+            }
+
+            impl FromLisp for $t {
+                fn from_lisp(value: Value) -> Result<Self, ConversionError> {
+                    value.try_into()
+                }
+            }
+        )*
+    };
+}
+
+convert_with_from!(i64, f64, String);
+
+impl IntoLisp for i32 {
+    fn into_lisp(self) -> Value {
+        Value::from(self)
+    }
+}
+
+impl FromLisp for i32 {
+    fn from_lisp(value: Value) -> Result<Self, ConversionError> {
+        let err = value.mismatch("an integer that fits in 32 bits");
+        i64::try_from(value).map_or(Err(err.clone()), |i| i.try_into().map_err(|_| err))
+    }
+}
+
+impl IntoLisp for &str {
+    fn into_lisp(self) -> Value {
+        Value::from(self)
+    }
+}
+
+impl<T: IntoLisp> IntoLisp for Vec<T> {
+    fn into_lisp(self) -> Value {
+        Value::from(self.into_iter().map(T::into_lisp).collect::<Vec<_>>())
+    }
+}
+
+impl<T: FromLisp> FromLisp for Vec<T> {
+    fn from_lisp(value: Value) -> Result<Self, ConversionError> {
+        Vec::<Value>::try_from(value)?
+            .into_iter()
+            .map(T::from_lisp)
+            .collect()
+    }
+}
+
+/// `None` is `nil`.
+impl<T: IntoLisp> IntoLisp for Option<T> {
+    fn into_lisp(self) -> Value {
+        self.map_or_else(Value::nil, T::into_lisp)
+    }
+}
+
+impl<T: FromLisp> FromLisp for Option<T> {
+    fn from_lisp(value: Value) -> Result<Self, ConversionError> {
+        if value.is_nil() {
+            return Ok(None);
+        }
+        T::from_lisp(value).map(Some)
+    }
+}
@@ -0,0 +1,240 @@
+use crate::ast::Var;
+use crate::error::LispErrors;
+use crate::types::LispType;
+use crate::Location;
+
+// How a file is read or written, from the keyword arguments after the path.
+struct Format {
+    delimiter: char,
+    headers: Option<Var>, // `:true` when reading, or the list of them when writing
+}
+
+impl Format {
+    // Reads the `:delimiter` and `:headers` options of the intrinsic called `name`.
+    fn new(options: &[Var], name: &str, loc: &Location) -> Result<Self, LispErrors> {
+        let mut format = Format {
+            delimiter: ',',
+            headers: None,
+        };
+        for pair in options.chunks(2) {
+            let key = match &*pair[0].resolve().get() {
+                LispType::Keyword(key) => key.clone(),
+                other => {
+                    return Err(LispErrors::new()
+                        .error(loc, format!("Expected an option, but found `{other}`!"))
+                        .note(
+                            None,
+                            format!("Write them like `({name} path :delimiter \";\")`."),
+                        ))
+                }
+            };
+            let [_, value] = pair else {
+                return Err(
+                    LispErrors::new().error(loc, format!("`:{key}` needs a value after it!"))
+                );
+            };
+            let value = value.resolve();
+            match key.as_str() {
+                "delimiter" => {
+                    let mut chars = match &*value.get() {
+                        LispType::Str(s) => s.chars().collect::<Vec<_>>(),
+                        _ => Vec::new(),
+                    };
+                    format.delimiter = match (chars.pop(), chars.is_empty()) {
+                        (Some(c), true) if !matches!(c, '"' | '\n' | '\r') => c,
+                        _ => {
+                            return Err(LispErrors::new()
+                                .error(loc, format!("`{value}` can't be a delimiter!"))
+                                .note(None, "It has to be a string of one character, like \";\"."))
+                        }
+                    };
+                }
+                "headers" => format.headers = Some(value),
+                _ => {
+                    return Err(LispErrors::new()
+                        .error(loc, format!("`{name}` has no `:{key}` option!"))
+                        .note(None, "It takes `:delimiter` and `:headers`."))
+                }
+            }
+        }
+        Ok(format)
+    }
+}
+
+/// `(csv-read path ...)`: the rows of the file as lists of strings, or as maps from the headers with `:headers :true`.
+pub(crate) fn read(args: &[Var], loc: &Location) -> Result<Var, LispErrors> {
+    let path = path_arg(args, "csv-read", loc)?;
+    let format = Format::new(&args[1..], "csv-read", loc)?;
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| LispErrors::new().error(loc, format!("Could not read `{path}`: {e}")))?;
+    let mut rows = parse(&text, format.delimiter)
+        .map_err(|line| {
+            LispErrors::new().error(
+                loc,
+                format!("`{path}` has a quote that is never closed, on line {line}!"),
+            )
+        })?
+        .into_iter();
+    let list =
+        |fields: Vec<String>| Var::new(LispType::List(fields.into_iter().map(Var::new).collect()));
+    let headers = format
+        .headers
+        .is_some_and(|h| !matches!(*h.get(), LispType::Nil));
+    let items = match (headers, rows.next()) {
+        (_, None) => Vec::new(),
+        (false, Some(first)) => std::iter::once(first).chain(rows).map(list).collect(),
+        (true, Some(names)) => rows
+            .enumerate()
+            .map(|(i, fields)| {
+                if fields.len() != names.len() {
+                    return Err(LispErrors::new().error(
+                        loc,
+                        format!(
+                            "Row {} of `{path}` has {} field(s), but there are {} header(s)!",
+                            i + 1,
+                            fields.len(),
+                            names.len()
+                        ),
+                    ));
+                }
+                let entries = names
+                    .iter()
+                    .zip(fields)
+                    .map(|(name, field)| (Var::new(name.as_str()), Var::new(field)))
+                    .collect();
+                Ok(Var::new(LispType::Map(entries)))
+            })
+            .collect::<Result<_, _>>()?,
+    };
+    Ok(Var::new(LispType::List(items)))
+}
+
+/// `(csv-write path rows ...)`: writes the rows, which are lists, or maps from the headers if `:headers` is given.
+pub(crate) fn write(args: &[Var], loc: &Location) -> Result<Var, LispErrors> {
+    let path = path_arg(args, "csv-write", loc)?;
+    let Some(rows) = args.get(1) else {
+        return Err(LispErrors::new().error(loc, "`csv-write` needs the rows to write!"));
+    };
+    let format = Format::new(&args[2..], "csv-write", loc)?;
+    let headers = match &format.headers {
+        Some(headers) => Some(items(headers, "The headers", loc)?),
+        None => None,
+    };
+    let mut text = String::new();
+    if let Some(headers) = &headers {
+        text.push_str(&line(headers, format.delimiter));
+    }
+    for row in items(rows, "The rows", loc)? {
+        let fields = match (&*row.get(), &headers) {
+            (LispType::Map(entries), Some(headers)) => headers
+                .iter()
+                .map(|h| {
+                    entries
+                        .iter()
+                        .find(|(k, _)| *k.resolve().get() == *h.get())
+                        .map_or_else(|| Var::new(LispType::Nil), |(_, v)| v.resolve())
+                })
+                .collect(),
+            (LispType::Map(_), None) => {
+                return Err(LispErrors::new()
+                    .error(loc, "Rows can only be maps when there are headers!")
+                    .note(
+                        None,
+                        "Give them with `:headers [...]`, in the order of the columns.",
+                    ))
+            }
+            _ => items(&row, "Each row", loc)?,
+        };
+        text.push_str(&line(&fields, format.delimiter));
+    }
+    std::fs::write(&path, text)
+        .map_err(|e| LispErrors::new().error(loc, format!("Could not write `{path}`: {e}")))?;
+    Ok(Var::new(LispType::Nil))
+}
+
+// The path that the intrinsic called `name` was given first.
+fn path_arg(args: &[Var], name: &str, loc: &Location) -> Result<String, LispErrors> {
+    match args.first().map(|path| path.resolve()) {
+        Some(path) => match &*path.get() {
+            LispType::Str(path) => Ok(path.clone()),
+            other => Err(LispErrors::new().error(
+                loc,
+                format!("`{name}` needs the path of a file, but was given `{other}`!"),
+            )),
+        },
+        None => Err(LispErrors::new().error(loc, format!("`{name}` needs the path of a file!"))),
+    }
+}
+
+// The items of a list, with `what` saying which list it was if it isn't one.
+fn items(list: &Var, what: &str, loc: &Location) -> Result<Vec<Var>, LispErrors> {
+    match &*list.resolve().get() {
+        LispType::List(items) => Ok(items.iter().map(Var::resolve).collect()),
+        LispType::Nil => Ok(Vec::new()),
+        other => {
+            Err(LispErrors::new().error(loc, format!("{what} must be a list, but is `{other}`!")))
+        }
+    }
+}
+
+// Writes one row, quoting the fields that need it.
+fn line(fields: &[Var], delimiter: char) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|field| {
+            let text = match &*field.get() {
+                LispType::Nil => String::new(),
+                other => other.to_string(),
+            };
+            if text.contains([delimiter, '"', '\n', '\r']) {
+                format!("\"{}\"", text.replace('"', "\"\""))
+            } else {
+                text
+            }
+        })
+        .collect();
+    fields.join(&delimiter.to_string()) + "\n"
+}
+
+/// Splits the text into rows of fields, with quoted fields able to have the delimiter, quotes and newlines in them.
+/// Fails with the line that a quote that is never closed starts on.
+pub(crate) fn parse(text: &str, delimiter: char) -> Result<Vec<Vec<String>>, usize> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = None; // The line that the quote being read started on
+    let mut line = 1;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', Some(_)) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            ('"', Some(_)) => quoted = None,
+            ('"', None) if field.is_empty() => quoted = Some(line),
+            ('\n', Some(_)) => {
+                line += 1;
+                field.push(c);
+            }
+            (_, Some(_)) => field.push(c),
+            ('\r', None) if chars.peek() == Some(&'\n') => {}
+            ('\n', None) => {
+                line += 1;
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (c, None) if c == delimiter => row.push(std::mem::take(&mut field)),
+            (c, None) => field.push(c),
+        }
+    }
+    if let Some(start) = quoted {
+        return Err(start);
+    }
+    // The last line doesn't need a newline at the end of it.
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    Ok(rows)
+}
@@ -0,0 +1,284 @@
+// Frees the reference cycles that reference counting never would.
+// Values can't be changed and a function only refers to its own name through the frame of each call, so cycles can only go through a channel that values were sent into,
+// or a slot that a deferred import bound, through which functions in modules that import each other can refer to each other.
+// Those are kept track of as they are made, and collecting looks at everything they lead to: anything that is referred to more times than it is by what was looked at
+// is referred to from somewhere else, so it and everything it leads to is still alive, and the slots that aren't are emptied to break their cycles.
+// Whatever it can't look inside, like a module or compiled code, only ever makes it keep more alive.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::ast::{Expr, Lambda, Loop, Place, Slot, Var};
+use crate::channel::Channel;
+use crate::promise::Promise;
+use crate::sync::{Shared, Weak};
+use crate::types::LispType;
+
+thread_local! {
+    // The channels and deferred slots made on this thread, which cycles can go through.
+    static ROOTS: RefCell<Vec<Root>> = const { RefCell::new(Vec::new()) };
+}
+
+enum Root {
+    Channel(Weak<Channel>),
+    Slot(Weak<Slot>),
+}
+
+impl Root {
+    fn upgrade(&self) -> Option<Node> {
+        match self {
+            Root::Channel(channel) => channel.upgrade().map(Node::Channel),
+            Root::Slot(slot) => slot.upgrade().map(|dat| Node::Slot(Var { dat })),
+        }
+    }
+
+    fn alive(&self) -> bool {
+        match self {
+            Root::Channel(channel) => channel.strong_count() > 0,
+            Root::Slot(slot) => slot.strong_count() > 0,
+        }
+    }
+}
+
+fn track(root: Root) {
+    // If this thread is already exiting there is nothing left to collect on it.
+    let _ = ROOTS.try_with(|roots| {
+        let mut roots = roots.borrow_mut();
+        // The ones that have been freed are only cleared out when it is full, so that keeping track of them takes as long as growing it would.
+        if roots.len() == roots.capacity() {
+            roots.retain(Root::alive);
+        }
+        roots.push(root);
+    });
+}
+
+/// Keeps track of a channel, which values can be sent into to make a cycle.
+pub(crate) fn track_channel(channel: &Shared<Channel>) {
+    track(Root::Channel(Shared::downgrade(channel)));
+}
+
+/// Keeps track of a slot bound by a deferred import, which the functions of the modules that import each other can refer to each other through.
+pub(crate) fn track_slot(slot: &Var) {
+    track(Root::Slot(Shared::downgrade(&slot.dat)));
+}
+
+/// Frees the values on this thread that are only kept alive by referring to each other, like a channel that was sent into itself, giving how many were freed.
+/// It is done whenever an [`Interpreter`](crate::Interpreter) is dropped, so it only needs to be called by hosts that keep one for a long time.
+///
+/// With the `sync` feature, it only looks at the channels made on this thread, and it mustn't be called while other threads are using them.
+pub fn collect_cycles() -> usize {
+    let roots: Vec<Node> = ROOTS
+        .try_with(|roots| {
+            let mut roots = roots.borrow_mut();
+            roots.retain(Root::alive);
+            roots.iter().filter_map(Root::upgrade).collect()
+        })
+        .unwrap_or_default();
+    let mut graph = Graph::default();
+    let mut stack: Vec<usize> = (roots.into_iter())
+        .filter_map(|root| match graph.add(root) {
+            (added, true) => Some(added),
+            _ => None,
+        })
+        .collect();
+    while let Some(from) = stack.pop() {
+        for node in graph.entries[from].node.children() {
+            let (to, added) = graph.add(node);
+            if added {
+                stack.push(to);
+            }
+            graph.entries[to].internal += 1;
+            graph.entries[from].edges.push(to);
+        }
+    }
+    graph.mark_live();
+    // Every cycle goes through a slot, so emptying them breaks all of them. Their values are only dropped once the graph has let go of them.
+    let freed: Vec<LispType> = graph
+        .entries
+        .iter()
+        .filter(|entry| !entry.live)
+        .filter_map(|entry| match &entry.node {
+            Node::Slot(var) => Some(std::mem::replace(&mut *var.get_mut(), LispType::Nil)),
+            _ => None,
+        })
+        .collect();
+    drop(graph);
+    let count = freed.len();
+    drop(freed);
+    count
+}
+
+/// Collects cycles when it is dropped.
+#[derive(Debug)]
+pub(crate) struct Collector;
+
+impl Drop for Collector {
+    fn drop(&mut self) {
+        collect_cycles();
+    }
+}
+
+// Something that can be referred to from more than one place, and so can be part of a cycle, along with what it refers to.
+enum Node {
+    Slot(Var),
+    Channel(Shared<Channel>),
+    Promise(Shared<Promise>),
+    Lambda(Shared<Lambda>),
+    Expr(Shared<Expr>),
+    Loop(Shared<Loop>),
+}
+
+impl Node {
+    // Where it is, which tells it apart from the others.
+    fn address(&self) -> usize {
+        match self {
+            Node::Slot(var) => Shared::as_ptr(&var.dat) as *const () as usize,
+            Node::Channel(channel) => Shared::as_ptr(channel) as *const () as usize,
+            Node::Promise(promise) => Shared::as_ptr(promise) as *const () as usize,
+            Node::Lambda(lambda) => Shared::as_ptr(lambda) as *const () as usize,
+            Node::Expr(expr) => Shared::as_ptr(expr) as *const () as usize,
+            Node::Loop(l) => Shared::as_ptr(l) as *const () as usize,
+        }
+    }
+
+    // How many times it is referred to, including by the node itself.
+    fn references(&self) -> usize {
+        match self {
+            Node::Slot(var) => Shared::strong_count(&var.dat),
+            Node::Channel(channel) => Shared::strong_count(channel),
+            Node::Promise(promise) => Shared::strong_count(promise),
+            Node::Lambda(lambda) => Shared::strong_count(lambda),
+            Node::Expr(expr) => Shared::strong_count(expr),
+            Node::Loop(l) => Shared::strong_count(l),
+        }
+    }
+
+    // What it refers to, once for each time it does.
+    fn children(&self) -> Vec<Node> {
+        let mut children = Vec::new();
+        match self {
+            Node::Slot(var) => value_children(&var.get(), &mut children),
+            Node::Channel(channel) => children.extend(channel.queued().into_iter().map(Node::Slot)),
+            Node::Promise(promise) => children.push(Node::Slot(promise.held())),
+            Node::Lambda(lambda) => children.push(Node::Expr(Shared::clone(&lambda.body))),
+            Node::Expr(expr) => expr_children(expr, &mut children),
+            Node::Loop(l) => {
+                let exprs = (l.vars.iter().map(|(_, init)| init))
+                    .chain(&l.steps)
+                    .chain([&l.test])
+                    .chain(&l.result)
+                    .chain(&l.body);
+                children.extend(exprs.map(|expr| Node::Expr(Shared::clone(expr))));
+            }
+        }
+        children
+    }
+}
+
+fn value_children(value: &LispType, children: &mut Vec<Node>) {
+    let mut slot = |var: &Var| children.push(Node::Slot(var.new_ref()));
+    match value {
+        LispType::Var(var) => slot(var),
+        LispType::List(items) | LispType::Values(items) => items.iter().for_each(slot),
+        LispType::Pair(car, cdr) => {
+            slot(car);
+            slot(cdr);
+        }
+        LispType::Map(pairs) => pairs.iter().for_each(|(key, value)| {
+            slot(key);
+            slot(value);
+        }),
+        LispType::Func(f) => {
+            if let Some(function) = f.as_function() {
+                let (lambda, captured) = function.parts();
+                captured.iter().for_each(&mut slot);
+                children.push(Node::Lambda(Shared::clone(lambda)));
+            } else if let Some(parameter) = f.as_parameter() {
+                slot(parameter.key());
+            }
+        }
+        LispType::Channel(channel) => children.push(Node::Channel(Shared::clone(channel))),
+        LispType::Promise(promise) => children.push(Node::Promise(Shared::clone(promise))),
+        _ => {}
+    }
+}
+
+fn expr_children(expr: &Expr, children: &mut Vec<Node>) {
+    let mut exprs: Vec<&Shared<Expr>> = Vec::new();
+    match expr {
+        Expr::Literal(value) => value_children(value, children),
+        // The slots of locals are never bound, so they can't be part of a cycle.
+        Expr::Var(Place::Global(var)) => children.push(Node::Slot(var.new_ref())),
+        Expr::Var(Place::Local(..)) | Expr::Import(_) => {}
+        Expr::Call { op, args, tail, .. } => exprs.extend([op].into_iter().chain(args).chain(tail)),
+        Expr::Let { bindings, body } => {
+            exprs.extend(bindings.iter().map(|(_, value)| value).chain(body))
+        }
+        Expr::Parameterize { bindings, body, .. } => exprs.extend(
+            bindings
+                .iter()
+                .flat_map(|(param, value)| [param, value])
+                .chain([body]),
+        ),
+        Expr::If {
+            cond,
+            then,
+            otherwise,
+            ..
+        } => exprs.extend([cond, then].into_iter().chain(otherwise)),
+        Expr::Lambda(lambda) => children.push(Node::Lambda(Shared::clone(lambda))),
+        Expr::Do(l) => children.push(Node::Loop(Shared::clone(l))),
+        Expr::Seq(body) => exprs.extend(body),
+    }
+    children.extend(
+        exprs
+            .into_iter()
+            .map(|expr| Node::Expr(Shared::clone(expr))),
+    );
+}
+
+#[derive(Default)]
+struct Graph {
+    entries: Vec<Entry>,
+    index: HashMap<usize, usize>, // Where the entry for each node is, by its address
+}
+
+struct Entry {
+    node: Node,
+    internal: usize, // How many times it is referred to by the nodes in the graph
+    edges: Vec<usize>,
+    live: bool,
+}
+
+impl Graph {
+    // Adds the node if it isn't already in the graph, giving where its entry is and whether it was added.
+    fn add(&mut self, node: Node) -> (usize, bool) {
+        let address = node.address();
+        if let Some(&i) = self.index.get(&address) {
+            return (i, false);
+        }
+        self.index.insert(address, self.entries.len());
+        self.entries.push(Entry {
+            node,
+            internal: 0,
+            edges: Vec::new(),
+            live: false,
+        });
+        (self.entries.len() - 1, true)
+    }
+
+    // Marks the nodes that are referred to from outside of the graph as alive, along with everything they lead to.
+    fn mark_live(&mut self) {
+        // The graph itself holds one reference to each.
+        let mut stack: Vec<usize> = (self.entries.iter().enumerate())
+            .filter(|(_, entry)| entry.node.references() - 1 > entry.internal)
+            .map(|(i, _)| i)
+            .collect();
+        while let Some(i) = stack.pop() {
+            if std::mem::replace(&mut self.entries[i].live, true) {
+                continue;
+            }
+            stack.extend(self.entries[i].edges.iter().copied());
+        }
+    }
+}
@@ -0,0 +1,546 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::ast::Var;
+use crate::error::LispErrors;
+use crate::types::LispType;
+use crate::Location;
+
+// Dates are kept as milliseconds since 1970-01-01T00:00:00Z, and durations as a number of milliseconds.
+const SECOND: i64 = 1000;
+const MINUTE: i64 = 60 * SECOND;
+const HOUR: i64 = 60 * MINUTE;
+const DAY: i64 = 24 * HOUR;
+
+const WEEKDAYS: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+// What `duration` takes, and how many milliseconds each one is.
+const UNITS: [(&str, i64); 6] = [
+    ("weeks", 7 * DAY),
+    ("days", DAY),
+    ("hours", HOUR),
+    ("minutes", MINUTE),
+    ("seconds", SECOND),
+    ("milliseconds", 1),
+];
+
+/// A date split into the parts of the calendar, in UTC.
+struct Civil {
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: i64,
+    minute: i64,
+    second: i64,
+    milli: i64,
+}
+
+// The number of days from 1970-01-01 to the date, which is negative before it.
+// This and `civil_from_days` are Howard Hinnant's algorithms, which work in 400 year eras since the Gregorian calendar repeats after that.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    // Counting from March puts the leap day at the end of the year.
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let march_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * march_month + 2) / 5 + 1;
+    let month = if march_month < 10 {
+        march_month + 3
+    } else {
+        march_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl Civil {
+    fn from_millis(millis: i64) -> Self {
+        let (year, month, day) = civil_from_days(millis.div_euclid(DAY));
+        let time = millis.rem_euclid(DAY);
+        Civil {
+            year,
+            month,
+            day,
+            hour: time / HOUR,
+            minute: time % HOUR / MINUTE,
+            second: time % MINUTE / SECOND,
+            milli: time % SECOND,
+        }
+    }
+
+    // The milliseconds since the epoch, or what is wrong with the parts if they aren't a real date.
+    fn to_millis(&self) -> Result<i64, String> {
+        if !(0..=9999).contains(&self.year) {
+            return Err(format!("the year {} isn't from 0 to 9999", self.year));
+        }
+        if !(1..=12).contains(&self.month) {
+            return Err(format!("the month {} isn't from 1 to 12", self.month));
+        }
+        let days = days_in_month(self.year, self.month);
+        if !(1..=days).contains(&self.day) {
+            return Err(format!(
+                "{} {} only has {days} days",
+                MONTHS[self.month as usize - 1],
+                self.year
+            ));
+        }
+        for (part, value, most) in [
+            ("hour", self.hour, 23),
+            ("minute", self.minute, 59),
+            ("second", self.second, 59),
+        ] {
+            if !(0..=most).contains(&value) {
+                return Err(format!("the {part} {value} isn't from 0 to {most}"));
+            }
+        }
+        Ok(days_from_civil(self.year, self.month, self.day) * DAY
+            + self.hour * HOUR
+            + self.minute * MINUTE
+            + self.second * SECOND
+            + self.milli)
+    }
+
+    // Monday is 0, the same as the index into `WEEKDAYS`.
+    fn weekday(&self) -> usize {
+        // 1970-01-01 was a Thursday.
+        (days_from_civil(self.year, self.month, self.day) + 3).rem_euclid(7) as usize
+    }
+
+    fn day_of_year(&self) -> i64 {
+        days_from_civil(self.year, self.month, self.day) - days_from_civil(self.year, 1, 1) + 1
+    }
+}
+
+/// Writes the date the way ISO 8601 does, like `2024-03-01T09:30:00Z`, with milliseconds only if it has any.
+pub(crate) fn display_date(millis: i64, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let c = Civil::from_millis(millis);
+    write!(
+        f,
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        c.year, c.month, c.day, c.hour, c.minute, c.second
+    )?;
+    if c.milli != 0 {
+        write!(f, ".{:03}", c.milli)?;
+    }
+    write!(f, "Z")
+}
+
+/// Writes the duration in days, hours, minutes and seconds, like `1d2h30m` or `-1.5s`, leaving out the parts that are 0.
+pub(crate) fn display_duration(millis: i64, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    if millis == 0 {
+        return write!(f, "0s");
+    }
+    if millis < 0 {
+        write!(f, "-")?;
+    }
+    let millis = millis.unsigned_abs();
+    let (day, hour, minute, second) = (DAY as u64, HOUR as u64, MINUTE as u64, SECOND as u64);
+    for (n, unit) in [
+        (millis / day, "d"),
+        (millis % day / hour, "h"),
+        (millis % hour / minute, "m"),
+    ] {
+        if n != 0 {
+            write!(f, "{n}{unit}")?;
+        }
+    }
+    match (millis % minute / second, millis % second) {
+        (0, 0) => Ok(()),
+        (s, 0) => write!(f, "{s}s"),
+        (s, ms) => write!(f, "{s}.{}s", format!("{ms:03}").trim_end_matches('0')),
+    }
+}
+
+/// Whether the value is a date or a duration, which `+` and `-` leave to `add` and `subtract`.
+pub(crate) fn is_temporal(value: &Var) -> bool {
+    matches!(
+        *value.resolve().get(),
+        LispType::Date(_) | LispType::Duration(_)
+    )
+}
+
+fn out_of_range(loc: &Location) -> LispErrors {
+    LispErrors::new().error(loc, "That date or duration is too far away to be kept!")
+}
+
+/// `(+ date durations...)`: the date moved on by the durations, or the durations added together if there is no date.
+pub(crate) fn add(args: &[Var], loc: &Location) -> Result<Var, LispErrors> {
+    let mut date = None;
+    let mut sum: i64 = 0;
+    for a in args {
+        match *a.resolve().get() {
+            LispType::Date(d) if date.is_none() => date = Some(d),
+            LispType::Date(_) => {
+                return Err(LispErrors::new()
+                    .error(loc, "Can't add two dates together!")
+                    .note(
+                        None,
+                        "Subtract one from the other to get the duration between them.",
+                    ))
+            }
+            LispType::Duration(d) => sum = sum.checked_add(d).ok_or_else(|| out_of_range(loc))?,
+            ref other => {
+                return Err(LispErrors::new().error(
+                    loc,
+                    format!("Incompatible types for addition: Duration and {other}"),
+                ))
+            }
+        }
+    }
+    Ok(Var::new(match date {
+        Some(d) => LispType::Date(d.checked_add(sum).ok_or_else(|| out_of_range(loc))?),
+        None => LispType::Duration(sum),
+    }))
+}
+
+/// `(- date durations...)`: the date moved back by the durations.
+/// `(- date date)` is the duration from the second date to the first, and `(- duration durations...)` subtracts durations.
+pub(crate) fn subtract(args: &[Var], loc: &Location) -> Result<Var, LispErrors> {
+    let first = args[0].resolve().get().clone();
+    if let (LispType::Date(a), [_, b]) = (&first, args) {
+        if let LispType::Date(b) = *b.resolve().get() {
+            let between = a.checked_sub(b).ok_or_else(|| out_of_range(loc))?;
+            return Ok(Var::new(LispType::Duration(between)));
+        }
+    }
+    let mut result = match first {
+        LispType::Date(d) | LispType::Duration(d) => d,
+        _ => unreachable!("only dates and durations are given to `subtract`"),
+    };
+    for a in &args[1..] {
+        match *a.resolve().get() {
+            LispType::Duration(d) => {
+                result = result.checked_sub(d).ok_or_else(|| out_of_range(loc))?
+            }
+            LispType::Date(_) if matches!(first, LispType::Date(_)) => {
+                return Err(LispErrors::new()
+                    .error(loc, "Can only subtract one date from another!")
+                    .note(None, "Write it like `(- later earlier)`."))
+            }
+            LispType::Date(_) => {
+                return Err(LispErrors::new().error(loc, "Can't subtract a date from a duration!"))
+            }
+            ref other => {
+                return Err(LispErrors::new().error(
+                    loc,
+                    format!("Cannot subtract `{other}` from a date or duration!"),
+                ))
+            }
+        }
+    }
+    Ok(Var::new(match first {
+        LispType::Date(_) => LispType::Date(result),
+        _ => LispType::Duration(result),
+    }))
+}
+
+/// `(now)`: the current date and time.
+pub(crate) fn now() -> Var {
+    let millis = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_millis() as i64,
+        Err(e) => -(e.duration().as_millis() as i64),
+    };
+    Var::new(LispType::Date(millis))
+}
+
+/// `(date year month day hour minute second)`, where the time of day can be left out.
+pub(crate) fn date(parts: &[isize], loc: &Location) -> Result<Var, LispErrors> {
+    let part = |i: usize| parts.get(i).map_or(0, |&p| p as i64);
+    let civil = Civil {
+        year: part(0),
+        month: part(1),
+        day: part(2),
+        hour: part(3),
+        minute: part(4),
+        second: part(5),
+        milli: 0,
+    };
+    let millis = civil
+        .to_millis()
+        .map_err(|e| LispErrors::new().error(loc, format!("That isn't a date, since {e}!")))?;
+    Ok(Var::new(LispType::Date(millis)))
+}
+
+/// `(parse-date s)`: reads a date written the way ISO 8601 does, like `2024-03-01`, `2024-03-01T09:30:00Z` or `2024-03-01 09:30+02:00`.
+/// Dates without an offset are taken to be in UTC.
+pub(crate) fn parse(text: &str, loc: &Location) -> Result<Var, LispErrors> {
+    let millis = Parser { rest: text }.date().map_err(|e| {
+        LispErrors::new()
+            .error(loc, format!("`{text}` isn't a date, since {e}!"))
+            .note(
+                None,
+                "Dates are written like `2024-03-01` or `2024-03-01T09:30:00Z`.",
+            )
+    })?;
+    Ok(Var::new(LispType::Date(millis)))
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl Parser<'_> {
+    // Takes exactly `len` digits from the front.
+    fn digits(&mut self, len: usize, what: &str) -> Result<i64, String> {
+        match self.rest.get(..len) {
+            Some(d) if d.bytes().all(|b| b.is_ascii_digit()) => {
+                self.rest = &self.rest[len..];
+                Ok(d.parse().unwrap_or(0))
+            }
+            _ => Err(format!("the {what} should be {len} digits")),
+        }
+    }
+
+    // Takes `c` from the front if it is there.
+    fn eat(&mut self, c: char) -> bool {
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, c: char, after: &str) -> Result<(), String> {
+        match self.eat(c) {
+            true => Ok(()),
+            false => Err(format!("there should be a `{c}` after the {after}")),
+        }
+    }
+
+    fn date(mut self) -> Result<i64, String> {
+        let year = self.digits(4, "year")?;
+        self.expect('-', "year")?;
+        let month = self.digits(2, "month")?;
+        self.expect('-', "month")?;
+        let day = self.digits(2, "day")?;
+        let mut civil = Civil {
+            year,
+            month,
+            day,
+            hour: 0,
+            minute: 0,
+            second: 0,
+            milli: 0,
+        };
+        let mut offset = 0;
+        if self.eat('T') || self.eat(' ') {
+            civil.hour = self.digits(2, "hour")?;
+            self.expect(':', "hour")?;
+            civil.minute = self.digits(2, "minute")?;
+            if self.eat(':') {
+                civil.second = self.digits(2, "second")?;
+                if self.eat('.') {
+                    civil.milli = self.fraction()?;
+                }
+            }
+            offset = self.offset()?;
+        }
+        if !self.rest.is_empty() {
+            return Err(format!("it has `{}` after the end", self.rest));
+        }
+        Ok(civil.to_millis()? - offset)
+    }
+
+    // The milliseconds of a fraction of a second, which can have any number of digits.
+    fn fraction(&mut self) -> Result<i64, String> {
+        let len = self.rest.bytes().take_while(u8::is_ascii_digit).count();
+        if len == 0 {
+            return Err("there should be digits after the `.`".to_string());
+        }
+        let digits = format!("{:0<3}", &self.rest[..len.min(3)]);
+        self.rest = &self.rest[len..];
+        Ok(digits.parse().unwrap_or(0))
+    }
+
+    // How far ahead of UTC the time is, from `Z`, `+02:00`, `-0530` or nothing at all.
+    fn offset(&mut self) -> Result<i64, String> {
+        if self.eat('Z') {
+            return Ok(0);
+        }
+        let sign = if self.eat('+') {
+            1
+        } else if self.eat('-') {
+            -1
+        } else {
+            return Ok(0);
+        };
+        let hours = self.digits(2, "hours of the offset")?;
+        self.eat(':');
+        let minutes = self.digits(2, "minutes of the offset")?;
+        Ok(sign * (hours * HOUR + minutes * MINUTE))
+    }
+}
+
+/// `(format-date date pattern)`: writes the date using the `%` codes in the pattern, or the way it is displayed if there isn't one.
+pub(crate) fn format(
+    millis: i64,
+    pattern: Option<&str>,
+    loc: &Location,
+) -> Result<Var, LispErrors> {
+    let Some(pattern) = pattern else {
+        return Ok(Var::new(LispType::Date(millis).to_string()));
+    };
+    let c = Civil::from_millis(millis);
+    let mut text = String::new();
+    let mut chars = pattern.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '%' {
+            text.push(ch);
+            continue;
+        }
+        let part = match chars.next() {
+            Some('Y') => format!("{:04}", c.year),
+            Some('m') => format!("{:02}", c.month),
+            Some('d') => format!("{:02}", c.day),
+            Some('H') => format!("{:02}", c.hour),
+            Some('M') => format!("{:02}", c.minute),
+            Some('S') => format!("{:02}", c.second),
+            Some('L') => format!("{:03}", c.milli),
+            Some('j') => format!("{:03}", c.day_of_year()),
+            Some('F') => format!("{:04}-{:02}-{:02}", c.year, c.month, c.day),
+            Some('T') => format!("{:02}:{:02}:{:02}", c.hour, c.minute, c.second),
+            Some('A') => WEEKDAYS[c.weekday()].to_string(),
+            Some('a') => WEEKDAYS[c.weekday()][..3].to_string(),
+            Some('B') => MONTHS[c.month as usize - 1].to_string(),
+            Some('b') => MONTHS[c.month as usize - 1][..3].to_string(),
+            Some('%') => "%".to_string(),
+            code => {
+                let code = code.map_or(String::new(), String::from);
+                return Err(LispErrors::new()
+                    .error(loc, format!("`%{code}` can't be used to format a date!"))
+                    .note(
+                        None,
+                        "Use `%Y`, `%m`, `%d`, `%H`, `%M`, `%S`, `%L`, `%j`, `%F`, `%T`, `%A`, `%a`, `%B`, `%b` or `%%`.",
+                    ));
+            }
+        };
+        text.push_str(&part);
+    }
+    Ok(Var::new(text))
+}
+
+/// `(date-parts date)`: the parts of the date as a map, like `{:year 2024 :month 3 :day 1 ... :weekday :friday}`.
+pub(crate) fn parts(millis: i64) -> Var {
+    let c = Civil::from_millis(millis);
+    let keyword = |name: &str| Var::new(LispType::Keyword(name.to_string()));
+    let entries = [
+        ("year", c.year),
+        ("month", c.month),
+        ("day", c.day),
+        ("hour", c.hour),
+        ("minute", c.minute),
+        ("second", c.second),
+        ("millisecond", c.milli),
+    ]
+    .into_iter()
+    .map(|(name, n)| (keyword(name), Var::new(n as isize)))
+    .chain(std::iter::once((
+        keyword("weekday"),
+        keyword(&WEEKDAYS[c.weekday()].to_lowercase()),
+    )))
+    .collect();
+    Var::new(LispType::Map(entries))
+}
+
+/// `(duration :days 1 :hours 2 ...)`: a duration of the total of the units given.
+pub(crate) fn duration(args: &[Var], loc: &Location) -> Result<Var, LispErrors> {
+    let units = || {
+        let names: Vec<_> = UNITS.iter().map(|(unit, _)| format!("`:{unit}`")).collect();
+        format!("It takes {}.", names.join(", "))
+    };
+    let mut total: i64 = 0;
+    for pair in args.chunks(2) {
+        let unit = match &*pair[0].resolve().get() {
+            LispType::Keyword(unit) => unit.clone(),
+            other => {
+                return Err(LispErrors::new()
+                    .error(loc, format!("Expected a unit, but found `{other}`!"))
+                    .note(None, "Write it like `(duration :hours 2 :minutes 30)`."))
+            }
+        };
+        let Some((_, size)) = UNITS.iter().find(|(name, _)| *name == unit) else {
+            return Err(LispErrors::new()
+                .error(loc, format!("`duration` has no `:{unit}` unit!"))
+                .note(None, units()));
+        };
+        let Some(value) = pair.get(1) else {
+            return Err(LispErrors::new().error(loc, format!("`:{unit}` needs a value after it!")));
+        };
+        let n = match *value.resolve().get() {
+            LispType::Integer(n) => n as i64,
+            ref other => {
+                return Err(LispErrors::new().error(
+                    loc,
+                    format!("`:{unit}` needs an integer, but was given `{other}`!"),
+                ))
+            }
+        };
+        total = n
+            .checked_mul(*size)
+            .and_then(|ms| total.checked_add(ms))
+            .ok_or_else(|| out_of_range(loc))?;
+    }
+    Ok(Var::new(LispType::Duration(total)))
+}
+
+/// `(epoch->date seconds)`: the date that many seconds after 1970-01-01T00:00:00Z.
+pub(crate) fn from_epoch(seconds: isize, loc: &Location) -> Result<Var, LispErrors> {
+    let millis = (seconds as i64)
+        .checked_mul(SECOND)
+        .ok_or_else(|| out_of_range(loc))?;
+    Ok(Var::new(LispType::Date(millis)))
+}
+
+/// `(date->epoch date)`: the whole seconds since 1970-01-01T00:00:00Z.
+pub(crate) fn to_epoch(millis: i64) -> Var {
+    Var::new(millis.div_euclid(SECOND) as isize)
+}
+
+/// `(duration->seconds duration)`: how many whole seconds the duration is, rounded towards zero.
+pub(crate) fn to_seconds(millis: i64) -> Var {
+    Var::new((millis / SECOND) as isize)
+}
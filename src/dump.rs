@@ -0,0 +1,188 @@
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::ast::Scope;
+use crate::forms::Form;
+use crate::interpreter::Interpreter;
+use crate::tokens::{Location, Token, TokenType};
+use crate::types::LispType;
+
+/// How to write out the parse results, for tools that want to read them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    Json,
+    /// One form per line, printed back out as source.
+    Sexpr,
+}
+
+impl Display for DumpFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DumpFormat::Json => write!(f, "json"),
+            DumpFormat::Sexpr => write!(f, "sexpr"),
+        }
+    }
+}
+
+impl FromStr for DumpFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(DumpFormat::Json),
+            "sexpr" => Ok(DumpFormat::Sexpr),
+            _ => Err(format!(
+                "Unknown dump format `{s}`, expected `json` or `sexpr`!"
+            )),
+        }
+    }
+}
+
+/// Writes the tokens as a JSON array with one object per token.
+pub fn dump_tokens<W: Write>(tokens: &[Token], out: &mut W) -> io::Result<()> {
+    write!(out, "[")?;
+    for (i, tok) in tokens.iter().enumerate() {
+        if i != 0 {
+            write!(out, ",")?;
+        }
+        write_token(tok, out)?;
+    }
+    writeln!(out, "]")
+}
+
+/// Writes the forms in the given format.
+pub fn dump_forms<W: Write>(forms: &[Form], format: DumpFormat, out: &mut W) -> io::Result<()> {
+    match format {
+        DumpFormat::Sexpr => {
+            for form in forms {
+                writeln!(out, "{form}")?;
+            }
+            Ok(())
+        }
+        DumpFormat::Json => {
+            write!(out, "[")?;
+            for (i, form) in forms.iter().enumerate() {
+                if i != 0 {
+                    write!(out, ",")?;
+                }
+                write_form(form, out)?;
+            }
+            writeln!(out, "]")
+        }
+    }
+}
+
+/// Writes what the interpreter's scope binds, other than the intrinsics and the prelude, in the order of their names.
+///
+/// As `sexpr`, each binding is written like `(name value)` on its own line, with the value printed the way `print` prints it.
+pub fn dump_scope<W: Write>(
+    interpreter: &Interpreter,
+    format: DumpFormat,
+    out: &mut W,
+) -> io::Result<()> {
+    let builtin = Scope::new(&interpreter.options);
+    let bindings = interpreter
+        .scope
+        .globals()
+        .into_iter()
+        .filter(|(name, _)| builtin.global(name).is_none());
+    match format {
+        DumpFormat::Sexpr => {
+            for (name, var) in bindings {
+                writeln!(out, "({name} {var})")?;
+            }
+            Ok(())
+        }
+        DumpFormat::Json => {
+            write!(out, "[")?;
+            for (i, (name, var)) in bindings.enumerate() {
+                if i != 0 {
+                    write!(out, ",")?;
+                }
+                write!(out, "{{\"name\":")?;
+                write_string(name, out)?;
+                write!(out, ",\"value\":")?;
+                write_string(&var.to_string(), out)?;
+                write!(out, "}}")?;
+            }
+            writeln!(out, "]")
+        }
+    }
+}
+
+fn write_form<W: Write>(form: &Form, out: &mut W) -> io::Result<()> {
+    match form {
+        Form::Atom(tok) => write_token(tok, out),
+        Form::List { loc, items } => {
+            write!(out, "{{\"kind\":\"list\",")?;
+            write_loc(loc, out)?;
+            write!(out, ",\"items\":[")?;
+            for (i, item) in items.iter().enumerate() {
+                if i != 0 {
+                    write!(out, ",")?;
+                }
+                write_form(item, out)?;
+            }
+            write!(out, "]}}")
+        }
+    }
+}
+
+fn write_token<W: Write>(tok: &Token, out: &mut W) -> io::Result<()> {
+    let kind = match &tok.dat {
+        TokenType::StartStmt => "open",
+        TokenType::EndStmt => "close",
+        TokenType::KeyWord(_) => "keyword",
+        TokenType::Dot => "dot",
+        TokenType::Ident(_) => "ident",
+        TokenType::Recognizable(LispType::Integer(_)) => "integer",
+        TokenType::Recognizable(LispType::Floating(_)) => "float",
+        TokenType::Recognizable(LispType::Str(_)) => "string",
+        TokenType::Recognizable(LispType::Keyword(_)) => "keyword-literal",
+        TokenType::Recognizable(LispType::Nil) => "nil",
+        TokenType::Recognizable(_) => "value",
+    };
+    write!(out, "{{\"kind\":\"{kind}\",")?;
+    write_loc(&tok.loc, out)?;
+    match &tok.dat {
+        TokenType::Recognizable(LispType::Integer(i)) => write!(out, ",\"value\":{i}")?,
+        TokenType::Recognizable(LispType::Floating(fl)) if fl.is_finite() => {
+            write!(out, ",\"value\":{fl:?}")?
+        }
+        TokenType::Recognizable(LispType::Str(s)) => {
+            write!(out, ",\"value\":")?;
+            write_string(s, out)?
+        }
+        TokenType::StartStmt
+        | TokenType::EndStmt
+        | TokenType::Dot
+        | TokenType::Recognizable(LispType::Nil) => {}
+        other => {
+            write!(out, ",\"value\":")?;
+            write_string(&other.to_string(), out)?
+        }
+    }
+    write!(out, "}}")
+}
+
+fn write_loc<W: Write>(loc: &Location, out: &mut W) -> io::Result<()> {
+    write!(out, "\"file\":")?;
+    write_string(&loc.filename, out)?;
+    write!(out, ",\"line\":{},\"col\":{}", loc.line, loc.col)
+}
+
+fn write_string<W: Write>(s: &str, out: &mut W) -> io::Result<()> {
+    write!(out, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(out, "\\\"")?,
+            '\\' => write!(out, "\\\\")?,
+            '\n' => write!(out, "\\n")?,
+            '\r' => write!(out, "\\r")?,
+            '\t' => write!(out, "\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{c}")?,
+        }
+    }
+    write!(out, "\"")
+}
@@ -1,19 +1,157 @@
-use std::{error::Error, fmt::Display};
+use std::{error::Error, fmt::Display, path::Path, time::Duration};
 
 use crate::tokens::Location;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A change to the source that fixes a diagnostic: `remove` characters are deleted at `loc`, and then `insert` is inserted there.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub struct Fix {
+    pub loc: Location,
+    pub remove: usize,
+    pub insert: String,
+}
+
+impl Display for Fix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.remove, self.insert.is_empty()) {
+            (0, _) => write!(f, "FIX: {} - Insert {:?}.", self.loc, self.insert),
+            (n, true) => write!(f, "FIX: {} - Remove {n} character(s).", self.loc),
+            (n, false) => write!(
+                f,
+                "FIX: {} - Replace {n} character(s) with {:?}.",
+                self.loc, self.insert
+            ),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub struct Note {
+    pub loc: Option<Location>,
+    pub message: String,
+}
+
+impl Display for Note {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.loc {
+            Some(l) => write!(f, "NOTE: {l} - {}", self.message),
+            None => write!(f, "NOTE: {}", self.message),
+        }
+    }
+}
+
+/// A single error or warning, along with its notes and fixes.
+///
+/// The ordering sorts diagnostics by where they happened first, so sorting gives the same order no matter how they were produced.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub struct Diagnostic {
+    pub loc: Location,
+    pub severity: Severity,
+    pub message: String,
+    pub notes: Vec<Note>,
+    pub fixes: Vec<Fix>,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "{} - {}", self.loc, self.message)?,
+            Severity::Warning => write!(f, "WARNING: {} - {}", self.loc, self.message)?,
+        }
+        for note in &self.notes {
+            write!(f, "\n\t{}", note)?;
+        }
+        for fix in &self.fixes {
+            write!(f, "\n\t{}", fix)?;
+        }
+        Ok(())
+    }
+}
+
+/// How to shorten the file names in diagnostics, so that they don't depend on where the files happen to be.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathTrim {
+    Keep,
+    /// Only keeps the last part of the path, e.g. `/home/me/script.pale` becomes `script.pale`.
+    FileName,
+    /// Removes the prefix from paths that start with it, e.g. a project's directory.
+    StripPrefix(String),
+}
+
+impl PathTrim {
+    pub fn apply(&self, filename: &str) -> String {
+        match self {
+            PathTrim::Keep => filename.to_string(),
+            PathTrim::FileName => Path::new(filename)
+                .file_name()
+                .map(|f| f.to_string_lossy().into_owned())
+                .unwrap_or_else(|| filename.to_string()),
+            PathTrim::StripPrefix(prefix) => match filename.strip_prefix(prefix.as_str()) {
+                Some(rest) => rest.trim_start_matches(['/', '\\']).to_string(),
+                None => filename.to_string(),
+            },
+        }
+    }
+
+    fn apply_loc(&self, loc: &Location) -> Location {
+        Location {
+            filename: self.apply(&loc.filename).into(),
+            ..loc.clone()
+        }
+    }
+}
+
+/// Which of the [`EvalLimits`](crate::EvalLimits) evaluation ran out of, and what it was set to, or that it was stopped by an [`InterruptHandle`](crate::InterruptHandle).
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LimitExceeded {
+    Steps(u64),
+    Depth(usize),
+    Time(Duration),
+    Interrupted,
+}
+
+impl Display for LimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LimitExceeded::Steps(n) => write!(f, "Evaluation took more than {n} steps!"),
+            LimitExceeded::Depth(n) => write!(f, "Calls were nested more than {n} deep!"),
+            LimitExceeded::Time(t) => write!(f, "Evaluation took longer than {t:?}!"),
+            LimitExceeded::Interrupted => write!(f, "Evaluation was interrupted!"),
+        }
+    }
+}
+
+/// Why source couldn't be run, so that the `pale` binary can exit with a code that says what went wrong.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ErrorCategory {
+    /// The source couldn't be read or parsed, so none of it was run.
+    Syntax,
+    /// Something went wrong while the source was running.
+    #[default]
+    Runtime,
+    /// The script asked to stop with `(exit n)`, which isn't a mistake but still stops evaluation.
+    Exit(i32),
+}
+
+#[derive(Debug, PartialEq)]
 pub struct LispErrors {
-    errs: Vec<(String, Vec<String>)>,
+    errs: Vec<Diagnostic>,
+    limit: Option<LimitExceeded>, // Set when evaluation was stopped rather than failing by itself
+    category: ErrorCategory,
 }
 
 impl Display for LispErrors {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for err in &self.errs {
-            write!(f, "{}", err.0)?;
-            for note in &err.1 {
-                write!(f, "\n\t{}", note)?;
+        for (i, err) in self.errs.iter().enumerate() {
+            if i != 0 {
+                writeln!(f)?;
             }
+            write!(f, "{err}")?;
         }
         Ok(())
     }
@@ -23,25 +161,131 @@ impl Error for LispErrors {}
 
 impl LispErrors {
     pub fn new() -> Self {
-        Self { errs: Vec::new() }
+        Self {
+            errs: Vec::new(),
+            limit: None,
+            category: ErrorCategory::Runtime,
+        }
+    }
+    /// Stops evaluation because the script asked to exit with the code.
+    pub fn exit(code: i32) -> Self {
+        Self {
+            category: ErrorCategory::Exit(code),
+            ..Self::new()
+        }
+    }
+    /// Marks the errors as coming from reading or parsing the source, rather than from running it.
+    pub fn syntax(mut self) -> Self {
+        if self.category == ErrorCategory::Runtime {
+            self.category = ErrorCategory::Syntax;
+        }
+        self
+    }
+    pub fn category(&self) -> ErrorCategory {
+        self.category
     }
     pub fn error<T: Display>(mut self, loc: &Location, err: T) -> Self {
-        self.errs.push((format!("{loc} - {err}"), Vec::new()));
+        self.errs.push(Diagnostic {
+            loc: loc.clone(),
+            severity: Severity::Error,
+            message: err.to_string(),
+            notes: Vec::new(),
+            fixes: Vec::new(),
+        });
+        self
+    }
+    pub fn warning<T: Display>(mut self, loc: &Location, warning: T) -> Self {
+        self.errs.push(Diagnostic {
+            loc: loc.clone(),
+            severity: Severity::Warning,
+            message: warning.to_string(),
+            notes: Vec::new(),
+            fixes: Vec::new(),
+        });
         self
     }
     pub fn note<'a, T: Display, L: Into<Option<&'a Location>>>(mut self, loc: L, err: T) -> Self {
         let loc: Option<&Location> = loc.into();
-        if let Some((_, notes)) = self.errs.last_mut() {
-            let msg = if let Some(l) = loc {
-                format!("NOTE: {l} - {err}")
-            } else {
-                format!("NOTE: {err}")
-            };
-            notes.push(msg);
+        if let Some(Diagnostic { notes, .. }) = self.errs.last_mut() {
+            notes.push(Note {
+                loc: loc.cloned(),
+                message: err.to_string(),
+            });
+        }
+        self
+    }
+    /// Adds the error for evaluation being stopped at `loc` because it ran out of the limit.
+    pub fn limit_exceeded(mut self, loc: &Location, limit: LimitExceeded) -> Self {
+        self.limit = Some(limit);
+        self.error(loc, limit)
+    }
+    /// The limit that evaluation ran out of, if that is why it failed.
+    pub fn exceeded(&self) -> Option<LimitExceeded> {
+        self.limit
+    }
+    /// Attaches a fix to the last error or warning.
+    pub fn fix(mut self, fix: Fix) -> Self {
+        if let Some(Diagnostic { fixes, .. }) = self.errs.last_mut() {
+            fixes.push(fix);
         }
         self
     }
     pub fn extend(&mut self, other: Self) {
-        self.errs.extend(other.errs)
+        self.errs.extend(other.errs);
+        self.limit = self.limit.or(other.limit);
+        if self.category == ErrorCategory::Runtime {
+            self.category = other.category;
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.errs.is_empty()
+    }
+    /// Whether any of the diagnostics are errors rather than warnings.
+    pub fn is_fatal(&self) -> bool {
+        self.errs.iter().any(|e| e.severity == Severity::Error)
+    }
+    pub fn fixes(&self) -> impl Iterator<Item = &Fix> {
+        self.errs.iter().flat_map(|e| e.fixes.iter())
+    }
+    /// The diagnostics in the order they were produced.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.errs
+    }
+    /// The diagnostics sorted into a stable order, with their paths trimmed.
+    /// This is meant for tools like fuzzers that need to compare diagnostics between runs.
+    pub fn normalized(&self, paths: &PathTrim) -> Vec<Diagnostic> {
+        let mut diagnostics: Vec<Diagnostic> = self
+            .errs
+            .iter()
+            .map(|d| Diagnostic {
+                loc: paths.apply_loc(&d.loc),
+                notes: d
+                    .notes
+                    .iter()
+                    .map(|n| Note {
+                        loc: n.loc.as_ref().map(|l| paths.apply_loc(l)),
+                        message: n.message.clone(),
+                    })
+                    .collect(),
+                fixes: d
+                    .fixes
+                    .iter()
+                    .map(|f| Fix {
+                        loc: paths.apply_loc(&f.loc),
+                        ..f.clone()
+                    })
+                    .collect(),
+                ..d.clone()
+            })
+            .collect();
+        diagnostics.sort();
+        diagnostics.dedup();
+        diagnostics
+    }
+}
+
+impl Default for LispErrors {
+    fn default() -> Self {
+        Self::new()
     }
 }
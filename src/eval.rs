@@ -0,0 +1,793 @@
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+use crate::ast::{Expr, Lambda, Locals, Loop, Pattern, Var};
+use crate::callable::{expect_args, spread_args, Callable, Function, IntrinsicOp};
+use crate::error::{LimitExceeded, LispErrors};
+use crate::events::Events;
+use crate::options::{EvalLimits, InterruptHandle, Options};
+use crate::profile::{Callee, Profiling};
+use crate::promise::{self, Forcing, Promise};
+use crate::streams::{Input, Output};
+use crate::sync::Shared;
+use crate::types::LispType;
+use crate::Location;
+
+thread_local! {
+    // The call that is being evaluated, so that crashes can say where they happened.
+    static EVALUATING: RefCell<Option<Location>> = const { RefCell::new(None) };
+    // Where the IO intrinsics read from and write to, which is set by whatever is evaluating code.
+    static STREAMS: RefCell<Streams> = RefCell::new(Streams {
+        out: Output::stdout(),
+        err: Output::stderr(),
+        input: Input::stdin(),
+    });
+    // How much work evaluating has done so far, which is checked against the limits on every call.
+    static BUDGET: RefCell<Budget> = RefCell::new(Budget::new(EvalLimits::default(), InterruptHandle::default()));
+    // The values that `parameterize` has given to parameters, by their keys, with the innermost ones last.
+    static PARAMETERS: RefCell<Vec<(Var, Var)>> = const { RefCell::new(Vec::new()) };
+    // The timers that `after` and `every` set, which are the ones in the options that are being evaluated with.
+    static EVENTS: RefCell<Events> = RefCell::new(Events::default());
+    // The calls in progress, if they are being profiled.
+    static PROFILING: RefCell<Option<Profiling>> = const { RefCell::new(None) };
+    // The `pmap` whose function is being called, which only lets it call pure functions.
+    static PURE: RefCell<Option<Location>> = const { RefCell::new(None) };
+}
+
+pub(crate) fn evaluating_location() -> Option<Location> {
+    EVALUATING.with(|e| e.borrow().clone())
+}
+
+#[derive(Clone)]
+pub(crate) struct Streams {
+    pub(crate) out: Output,
+    pub(crate) err: Output,
+    pub(crate) input: Input,
+}
+
+struct Budget {
+    limits: EvalLimits,
+    steps: u64,
+    depth: usize,
+    deadline: Option<Instant>,
+    interrupt: InterruptHandle,
+}
+
+impl Budget {
+    fn new(limits: EvalLimits, interrupt: InterruptHandle) -> Self {
+        Budget {
+            limits,
+            steps: 0,
+            depth: 0,
+            deadline: limits.timeout.map(|t| Instant::now() + t),
+            interrupt,
+        }
+    }
+
+    // Counts a step, which is a call or a time around a loop, unless that would go over the limit on steps or the time limit, or evaluation has been interrupted.
+    fn step(&mut self) -> Result<(), LimitExceeded> {
+        self.check()?;
+        self.steps += 1;
+        match self.limits.max_steps {
+            Some(max) if self.steps > max => Err(LimitExceeded::Steps(max)),
+            _ => Ok(()),
+        }
+    }
+
+    // Checks that evaluation can carry on without taking a step, which it can't once it has run out of time or been interrupted.
+    fn check(&self) -> Result<(), LimitExceeded> {
+        if self.interrupt.is_interrupted() {
+            return Err(LimitExceeded::Interrupted);
+        }
+        match (self.limits.timeout, self.deadline) {
+            (Some(t), Some(d)) if Instant::now() >= d => Err(LimitExceeded::Time(t)),
+            _ => Ok(()),
+        }
+    }
+
+    // Counts a call starting, unless that would go over one of the limits.
+    fn enter(&mut self) -> Result<(), LimitExceeded> {
+        self.step()?;
+        self.nest()
+    }
+
+    // Counts a call as being in progress, unless as many are already in progress as the limit on depth allows.
+    fn nest(&mut self) -> Result<(), LimitExceeded> {
+        match self.limits.max_depth {
+            Some(max) if self.depth >= max => Err(LimitExceeded::Depth(max)),
+            _ => {
+                self.depth += 1;
+                Ok(())
+            }
+        }
+    }
+}
+
+// What evaluating keeps in the thread-locals, which has to be kept somewhere else while an evaluation that is done a piece at a time isn't running.
+struct Context {
+    streams: Streams,
+    budget: Budget,
+    evaluating: Option<Location>,
+    parameters: Vec<(Var, Var)>,
+    events: Events,
+    profiling: Option<Profiling>,
+}
+
+impl Context {
+    // Puts it in the thread-locals, giving back what was there before.
+    fn install(self) -> Context {
+        Context {
+            streams: STREAMS.with(|s| s.replace(self.streams)),
+            budget: BUDGET.with(|b| b.replace(self.budget)),
+            evaluating: EVALUATING.with(|e| e.replace(self.evaluating)),
+            parameters: PARAMETERS.with(|p| p.replace(self.parameters)),
+            events: EVENTS.with(|e| e.replace(self.events)),
+            profiling: PROFILING.with(|p| p.replace(self.profiling)),
+        }
+    }
+}
+
+// Puts the context from before back when evaluating is done, even if it panicked, keeping the one that was used in `used`.
+struct Restore<'a> {
+    previous: Option<Context>,
+    used: &'a mut Context,
+}
+
+impl Drop for Restore<'_> {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            *self.used = previous.install();
+        }
+    }
+}
+
+/// An evaluation that is done a piece at a time, which keeps its streams, how much of its limits it has used, and the call and parameters it was in the middle of in between.
+pub(crate) struct Suspended {
+    context: Context,
+}
+
+impl Suspended {
+    pub(crate) fn new(options: &Options) -> Self {
+        options.interrupt.reset();
+        Suspended {
+            context: Context {
+                streams: Streams {
+                    out: options.on_output.clone(),
+                    err: options.on_error_output.clone(),
+                    input: options.input.clone(),
+                },
+                budget: Budget::new(options.eval_limits, options.interrupt.clone()),
+                evaluating: None,
+                parameters: Vec::new(),
+                events: options.events.clone(),
+                profiling: options.profiler.clone().map(Profiling::new),
+            },
+        }
+    }
+
+    /// Runs `f` as the next piece of the evaluation, carrying on from where the last piece left off.
+    pub(crate) fn resume<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        let unused = Context {
+            streams: streams(),
+            budget: Budget::new(EvalLimits::default(), InterruptHandle::default()),
+            evaluating: None,
+            parameters: Vec::new(),
+            events: events(),
+            profiling: None,
+        };
+        let context = std::mem::replace(&mut self.context, unused);
+        let _restore = Restore {
+            previous: Some(context.install()),
+            used: &mut self.context,
+        };
+        f()
+    }
+}
+
+/// Runs `f` with the IO intrinsics using the streams in the options, and with evaluation limited by its limits.
+pub(crate) fn with_options<T>(options: &Options, f: impl FnOnce() -> T) -> T {
+    Suspended::new(options).resume(f)
+}
+
+/// What a new thread needs to evaluate code the way the one that started it does: its streams, limits, parameters, timers and profiler.
+/// The thread counts its own steps, but stops at the same deadline.
+#[cfg(feature = "sync")]
+pub(crate) struct Inherited {
+    streams: Streams,
+    budget: Budget,
+    parameters: Vec<(Var, Var)>,
+    events: Events,
+    profiling: Option<Profiling>,
+}
+
+#[cfg(feature = "sync")]
+impl Inherited {
+    /// Takes what the current thread is evaluating with.
+    pub(crate) fn capture() -> Self {
+        let budget = BUDGET.with(|b| {
+            let b = b.borrow();
+            Budget {
+                limits: b.limits,
+                steps: 0,
+                depth: 0,
+                deadline: b.deadline,
+                interrupt: b.interrupt.clone(),
+            }
+        });
+        let parameters = PARAMETERS.with(|p| {
+            let p = p.borrow();
+            p.iter().map(|(k, v)| (k.new_ref(), v.new_ref())).collect()
+        });
+        Inherited {
+            streams: streams(),
+            budget,
+            parameters,
+            events: events(),
+            profiling: PROFILING.with(|p| p.borrow().as_ref().map(Profiling::for_thread)),
+        }
+    }
+
+    /// Evaluates with it on the current thread, which should be a new one.
+    pub(crate) fn install(self) {
+        STREAMS.with(|s| s.replace(self.streams));
+        BUDGET.with(|b| b.replace(self.budget));
+        PARAMETERS.with(|p| p.replace(self.parameters));
+        EVENTS.with(|e| e.replace(self.events));
+        PROFILING.with(|p| p.replace(self.profiling));
+    }
+}
+
+/// Runs `f` on a new thread, which evaluates the way the current one does.
+#[cfg(feature = "sync")]
+pub(crate) fn spawn(f: impl FnOnce() + Send + 'static) {
+    let inherited = Inherited::capture();
+    std::thread::spawn(move || {
+        inherited.install();
+        f()
+    });
+}
+
+/// Runs `f` only letting the code it evaluates call pure functions, since it is the function given to the `pmap` at `loc`.
+pub(crate) fn purely<T>(loc: &Location, f: impl FnOnce() -> T) -> T {
+    let outer = PURE.with(|p| p.replace(Some(loc.clone())));
+    let res = f();
+    PURE.with(|p| *p.borrow_mut() = outer);
+    res
+}
+
+// Checks that the function can be called, which it can't be if it isn't pure and this is inside of `purely`.
+fn check_pure(f: &dyn Callable, loc: &Location) -> Result<(), LispErrors> {
+    match PURE.with(|p| p.borrow().clone()) {
+        Some(pmap) if !f.is_pure() => Err(LispErrors::new()
+            .error(loc, "Only pure functions can be called here!")
+            .note(
+                &pmap,
+                "It is called by the function given to `pmap` here, which is called from several threads at once.",
+            )
+            .note(
+                None,
+                "Functions that do more than compute their result, like `print`, can't be.",
+            )),
+        _ => Ok(()),
+    }
+}
+
+/// The streams that the IO intrinsics should use.
+// They are cloned rather than borrowed, since writing to a handler could evaluate more code.
+pub(crate) fn streams() -> Streams {
+    STREAMS.with(|s| s.borrow().clone())
+}
+
+/// The timers that `after` and `every` should set.
+pub(crate) fn events() -> Events {
+    EVENTS.with(|e| e.borrow().clone())
+}
+
+/// Sleeps until `until`, unless evaluation is interrupted or runs out of time first, which is an error at `loc`.
+pub(crate) fn wait_until(until: Instant, loc: &Location) -> Result<(), LispErrors> {
+    // It sleeps a little at a time, so that an interrupt doesn't have to wait for all of it.
+    const SLICE: Duration = Duration::from_millis(10);
+    loop {
+        if let Err(limit) = BUDGET.with(|b| b.borrow().check()) {
+            return Err(LispErrors::new().limit_exceeded(loc, limit));
+        }
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Ok(());
+        }
+        std::thread::sleep(left.min(SLICE));
+    }
+}
+
+// What is left to do while evaluating. They are kept on a stack rather than being function calls, so that deeply nested code doesn't run out of Rust stack.
+enum Task {
+    Eval(Shared<Expr>),
+    // Calls the function on the value stack with the arguments after it, and then the value after a `.` if there is one.
+    Call {
+        args: usize,
+        dotted: bool,
+        loc: Location,
+        arg_locs: Shared<[Location]>,
+    },
+    // The body of a function has been evaluated, so the call is done and what was being evaluated before it is put back, along with the frame of its caller.
+    Return(Option<Location>, Locals),
+    // The body of a promise's function has been evaluated, so its value on the value stack is kept as the promise's.
+    Fulfil(Shared<Promise>),
+    Bind(Shared<Pattern>),
+    // Evaluates one of the branches of an `if`, depending on the condition on the value stack.
+    Branch {
+        then: Shared<Expr>,
+        otherwise: Option<Shared<Expr>>,
+    },
+    // Gives the parameters on the value stack the values after each of them, until the body of the `parameterize` has been evaluated.
+    Parameterize {
+        count: usize,
+        loc: Location,
+    },
+    Unparameterize(usize),
+    // Starts a `do` loop with the starting values of its variables on the value stack.
+    Enter(Shared<Loop>),
+    // Ends the loop if its condition on the value stack isn't `nil`, or evaluates its body and steps otherwise.
+    Test(Shared<Loop>),
+    // Goes around the loop again with the next values of its variables on the value stack.
+    Next(Shared<Loop>),
+    // The loop has ended, so the frame around it is put back.
+    Leave(Locals),
+    Nil,
+    Discard,
+}
+
+/// Evaluates the expression at the top level, giving its value.
+pub(crate) fn eval(expr: &Shared<Expr>) -> Result<Var, LispErrors> {
+    eval_in(expr, Locals::default())
+}
+
+/// Evaluates the expression with `locals` as the frame of the call it is in.
+pub(crate) fn eval_in(expr: &Shared<Expr>, locals: Locals) -> Result<Var, LispErrors> {
+    unwinding(|| run(Shared::clone(expr), locals))
+}
+
+/// Runs `f`, which evaluates code, and cleans up after the calls that were still in progress if it fails.
+pub(crate) fn unwinding(f: impl FnOnce() -> Result<Var, LispErrors>) -> Result<Var, LispErrors> {
+    let outer = evaluating_location();
+    let depth = BUDGET.with(|b| b.borrow().depth);
+    let parameterized = PARAMETERS.with(|p| p.borrow().len());
+    let profiled = PROFILING.with(|p| p.borrow().as_ref().map(Profiling::depth));
+    let res = f();
+    if res.is_err() {
+        // They never got to return, or to put back what their parameters were.
+        EVALUATING.with(|e| *e.borrow_mut() = outer);
+        BUDGET.with(|b| b.borrow_mut().depth = depth);
+        PARAMETERS.with(|p| p.borrow_mut().truncate(parameterized));
+        if let Some(profiled) = profiled {
+            profile(|p| p.truncate(profiled));
+        }
+    }
+    res
+}
+
+fn run(expr: Shared<Expr>, locals: Locals) -> Result<Var, LispErrors> {
+    let done = Machine::new(expr, locals).run(None)?;
+    Ok(done.expect("Evaluating without a number of steps never stops before it is done"))
+}
+
+/// An evaluation in progress, which is kept as what is left to do and the values that have been worked out so far, so that it can be stopped between any two tasks and carried on later.
+pub(crate) struct Machine {
+    tasks: Vec<Task>,
+    values: Vec<Var>,
+    locals: Locals,
+}
+
+impl Machine {
+    pub(crate) fn new(expr: Shared<Expr>, locals: Locals) -> Self {
+        Machine {
+            tasks: vec![Task::Eval(expr)],
+            values: Vec::new(),
+            locals,
+        }
+    }
+
+    /// Evaluates until it is done, giving its value, or until `steps` more steps have been taken, giving `None`.
+    /// Functions that intrinsics like `pmap` call are evaluated all at once, so it can take more steps than that before it stops.
+    pub(crate) fn run(&mut self, steps: Option<u64>) -> Result<Option<Var>, LispErrors> {
+        let Machine {
+            tasks,
+            values,
+            locals,
+        } = self;
+        let stop = steps.map(|steps| steps_taken().saturating_add(steps));
+        while let Some(task) = tasks.pop() {
+            if stop.is_some_and(|stop| steps_taken() >= stop) {
+                tasks.push(task);
+                return Ok(None);
+            }
+            match task {
+                Task::Eval(expr) => match &*expr {
+                    Expr::Literal(value) => values.push(Var::new(value.clone())),
+                    Expr::Var(place) => values.push(place.get(locals)),
+                    // The tasks are done in the opposite order to how they are pushed, so the function is evaluated first.
+                    Expr::Call {
+                        op,
+                        args,
+                        tail,
+                        loc,
+                        arg_locs,
+                    } => {
+                        tasks.push(Task::Call {
+                            args: args.len(),
+                            dotted: tail.is_some(),
+                            loc: loc.clone(),
+                            arg_locs: Shared::clone(arg_locs),
+                        });
+                        tasks.extend(tail.iter().map(|t| Task::Eval(Shared::clone(t))));
+                        tasks.extend(args.iter().rev().map(|a| Task::Eval(Shared::clone(a))));
+                        tasks.push(Task::Eval(Shared::clone(op)));
+                    }
+                    Expr::Let { bindings, body } => {
+                        tasks.push(match body {
+                            Some(body) => Task::Eval(Shared::clone(body)),
+                            None => Task::Nil,
+                        });
+                        for (pattern, value) in bindings.iter().rev() {
+                            tasks.push(Task::Bind(Shared::clone(pattern)));
+                            tasks.push(Task::Eval(Shared::clone(value)));
+                        }
+                    }
+                    Expr::Parameterize {
+                        bindings,
+                        body,
+                        loc,
+                    } => {
+                        tasks.push(Task::Unparameterize(bindings.len()));
+                        tasks.push(Task::Eval(Shared::clone(body)));
+                        tasks.push(Task::Parameterize {
+                            count: bindings.len(),
+                            loc: loc.clone(),
+                        });
+                        for (parameter, value) in bindings.iter().rev() {
+                            tasks.push(Task::Eval(Shared::clone(value)));
+                            tasks.push(Task::Eval(Shared::clone(parameter)));
+                        }
+                    }
+                    Expr::If {
+                        cond,
+                        then,
+                        otherwise,
+                        ..
+                    } => {
+                        tasks.push(Task::Branch {
+                            then: Shared::clone(then),
+                            otherwise: otherwise.clone(),
+                        });
+                        tasks.push(Task::Eval(Shared::clone(cond)));
+                    }
+                    Expr::Lambda(lambda) => {
+                        values.push(Var::new(Function::new(Shared::clone(lambda), locals)))
+                    }
+                    Expr::Do(l) => {
+                        tasks.push(Task::Enter(Shared::clone(l)));
+                        for (_, init) in l.vars.iter().rev() {
+                            tasks.push(Task::Eval(Shared::clone(init)));
+                        }
+                    }
+                    Expr::Seq(exprs) => match exprs.split_last() {
+                        Some((last, rest)) => {
+                            tasks.push(Task::Eval(Shared::clone(last)));
+                            for expr in rest.iter().rev() {
+                                tasks.push(Task::Discard);
+                                tasks.push(Task::Eval(Shared::clone(expr)));
+                            }
+                        }
+                        None => values.push(Var::new(LispType::Nil)),
+                    },
+                    Expr::Import(module) => {
+                        module.run()?;
+                        values.push(Var::new(LispType::Nil));
+                    }
+                },
+                Task::Call {
+                    args,
+                    dotted,
+                    loc,
+                    arg_locs,
+                } => {
+                    let tail = dotted.then(|| pop(values));
+                    // The arguments are passed straight from the stack, so that calls don't need a `Vec` of their own.
+                    let start = values.len() - args;
+                    let called = call(
+                        &values[start - 1],
+                        &values[start..],
+                        tail.as_ref(),
+                        &loc,
+                        &arg_locs,
+                    );
+                    values.truncate(start - 1);
+                    match called? {
+                        Called::Done(value) => values.push(value),
+                        Called::Body {
+                            lambda,
+                            locals: callee,
+                            outer,
+                            forcing,
+                        } => {
+                            tasks.extend(forcing.map(Task::Fulfil));
+                            tasks.push(Task::Return(outer, std::mem::replace(locals, callee)));
+                            tasks.push(Task::Eval(Shared::clone(&lambda.body)));
+                        }
+                    }
+                }
+                Task::Return(outer, caller) => {
+                    returned(outer);
+                    *locals = caller;
+                }
+                Task::Fulfil(promise) => {
+                    let forced = pop(values);
+                    values.push(promise::fulfil(&promise, forced));
+                }
+                Task::Bind(pattern) => pattern.bind(pop(values), locals)?,
+                Task::Branch { then, otherwise } => {
+                    // Only `nil` counts as false.
+                    let cond = pop(values);
+                    if !matches!(*cond.get(), LispType::Nil) {
+                        tasks.push(Task::Eval(then));
+                    } else if let Some(otherwise) = otherwise {
+                        tasks.push(Task::Eval(otherwise));
+                    } else {
+                        values.push(Var::new(LispType::Nil));
+                    }
+                }
+                Task::Parameterize { count, loc } => {
+                    let start = values.len() - 2 * count;
+                    parameterize(&values[start..], &loc)?;
+                    values.truncate(start);
+                }
+                Task::Unparameterize(count) => unparameterize(count),
+                Task::Enter(l) => {
+                    let start = values.len() - l.vars.len();
+                    let frame = l.enter(locals, &values[start..]);
+                    values.truncate(start);
+                    tasks.push(Task::Leave(std::mem::replace(locals, frame)));
+                    tasks.push(Task::Test(Shared::clone(&l)));
+                    tasks.push(Task::Eval(Shared::clone(&l.test)));
+                }
+                Task::Test(l) => {
+                    if !matches!(*pop(values).get(), LispType::Nil) {
+                        tasks.push(match &l.result {
+                            Some(result) => Task::Eval(Shared::clone(result)),
+                            None => Task::Nil,
+                        });
+                        continue;
+                    }
+                    // The body is evaluated first, and then the steps.
+                    tasks.push(Task::Next(Shared::clone(&l)));
+                    tasks.extend(l.steps.iter().rev().map(|s| Task::Eval(Shared::clone(s))));
+                    if let Some(body) = &l.body {
+                        tasks.push(Task::Discard);
+                        tasks.push(Task::Eval(Shared::clone(body)));
+                    }
+                }
+                Task::Next(l) => {
+                    looped(&l.loc)?;
+                    let start = values.len() - l.vars.len();
+                    *locals = l.next(locals, &values[start..]);
+                    values.truncate(start);
+                    tasks.push(Task::Test(Shared::clone(&l)));
+                    tasks.push(Task::Eval(Shared::clone(&l.test)));
+                }
+                Task::Leave(outer) => *locals = outer,
+                Task::Nil => values.push(Var::new(LispType::Nil)),
+                Task::Discard => {
+                    pop(values);
+                }
+            }
+        }
+        Ok(Some(pop(values)))
+    }
+}
+
+// How many steps the evaluation has taken so far.
+fn steps_taken() -> u64 {
+    BUDGET.with(|b| b.borrow().steps)
+}
+
+/// Gives the parameters their values until [`unparameterize`] is called, from `pairs` that alternate between a parameter and its value.
+pub(crate) fn parameterize(pairs: &[Var], loc: &Location) -> Result<(), LispErrors> {
+    let mut given = Vec::with_capacity(pairs.len() / 2);
+    for pair in pairs.chunks(2) {
+        let parameter = pair[0].resolve();
+        let parameter = parameter.get();
+        let key = match &*parameter {
+            LispType::Func(f) => f.as_parameter().map(|p| p.key().new_ref()),
+            _ => None,
+        };
+        let Some(key) = key else {
+            return Err(LispErrors::new()
+                .error(loc, format!("`{}` is not a parameter!", *parameter))
+                .note(
+                    None,
+                    "Only what `make-parameter` makes can be given a value here.",
+                ));
+        };
+        given.push((key, pair[1].resolve()));
+    }
+    PARAMETERS.with(|p| p.borrow_mut().extend(given));
+    Ok(())
+}
+
+/// Puts back what the last `count` parameters given values were before.
+pub(crate) fn unparameterize(count: usize) {
+    PARAMETERS.with(|p| {
+        let mut p = p.borrow_mut();
+        let len = p.len() - count;
+        p.truncate(len);
+    })
+}
+
+/// The value that `parameterize` gave the parameter with this key, if it is in the middle of giving it one.
+pub(crate) fn parameter_value(key: &Var) -> Option<Var> {
+    PARAMETERS.with(|p| {
+        p.borrow()
+            .iter()
+            .rev()
+            .find(|(k, _)| k.same(key))
+            .map(|(_, value)| value.new_ref())
+    })
+}
+
+fn pop(values: &mut Vec<Var>) -> Var {
+    values
+        .pop()
+        .expect("Every task that needs a value comes after one that gives it")
+}
+
+pub(crate) enum Called {
+    Done(Var),
+    /// A function written in Pale, whose arguments have been bound in the frame for the call and whose body still has to be evaluated.
+    /// Once it has been, the call is finished with [`returned`].
+    Body {
+        lambda: Shared<Lambda>,
+        locals: Locals,
+        outer: Option<Location>,
+        /// The promise that is being forced, if this is its function, which keeps the value of the body.
+        forcing: Option<Shared<Promise>>,
+    },
+}
+
+/// Starts calling the function, which is finished straight away unless it is written in Pale.
+/// `arg_locs` are where the arguments start, as far as they are known.
+pub(crate) fn call(
+    op: &Var,
+    args: &[Var],
+    tail: Option<&Var>,
+    loc: &Location,
+    arg_locs: &[Location],
+) -> Result<Called, LispErrors> {
+    if let Err(limit) = BUDGET.with(|b| b.borrow_mut().enter()) {
+        return Err(LispErrors::new().limit_exceeded(loc, limit));
+    }
+    let outer = EVALUATING.with(|e| e.replace(Some(loc.clone())));
+    let this = op;
+    let op = this.get();
+    profile(|p| match &*op {
+        LispType::Func(f) => p.enter(Some(f.callee())),
+        _ => p.enter(None),
+    });
+    let r = match &*op {
+        LispType::Func(f) => match (f.as_function(), tail) {
+            (Some(function), Some(tail)) => spread_args(args, tail, loc)
+                .and_then(|all| function.bind_args(this, &all, loc, arg_locs))
+                .map(|(lambda, locals)| Called::Body {
+                    lambda,
+                    locals,
+                    outer: outer.clone(),
+                    forcing: None,
+                }),
+            (Some(function), None) => {
+                function
+                    .bind_args(this, args, loc, arg_locs)
+                    .map(|(lambda, locals)| Called::Body {
+                        lambda,
+                        locals,
+                        outer: outer.clone(),
+                        forcing: None,
+                    })
+            }
+            (None, tail) if f.as_intrinsic() == Some(&IntrinsicOp::Force) => {
+                check_pure(&**f, loc).and_then(|()| force(args, tail, loc, &outer))
+            }
+            (None, Some(tail)) => check_pure(&**f, loc)
+                .and_then(|()| f.call_dotted(args, tail, loc))
+                .map(Called::Done),
+            (None, None) => check_pure(&**f, loc)
+                .and_then(|()| f.call(args, loc))
+                .map(Called::Done),
+        },
+        other => Err(LispErrors::new()
+            .error(loc, format!("`{other}` is not a function!"))
+            .note(None, "Only a function can come first in a statement.")),
+    };
+    drop(op);
+    if !matches!(r, Ok(Called::Body { .. })) {
+        returned(outer);
+    }
+    r
+}
+
+// Starts `(force promise)`, giving the body of the promise's function to evaluate if it hasn't been forced yet.
+fn force(
+    args: &[Var],
+    tail: Option<&Var>,
+    loc: &Location,
+    outer: &Option<Location>,
+) -> Result<Called, LispErrors> {
+    let spread;
+    let args = match tail {
+        Some(tail) => {
+            spread = spread_args(args, tail, loc)?;
+            &spread[..]
+        }
+        None => args,
+    };
+    expect_args(args, 1, "force", loc)?;
+    let (thunk, promise) = match promise::start(&args[0]) {
+        Forcing::Done(value) => return Ok(Called::Done(value)),
+        Forcing::Call(thunk, promise) => (thunk, promise),
+    };
+    let f = thunk.get();
+    let Some(function) = (match &*f {
+        LispType::Func(f) => f.as_function(),
+        _ => None,
+    }) else {
+        unreachable!("`delay` always makes a function written in Pale")
+    };
+    let (lambda, locals) = function.bind_args(&thunk, &[], loc, &[])?;
+    Ok(Called::Body {
+        lambda,
+        locals,
+        outer: outer.clone(),
+        forcing: Some(promise),
+    })
+}
+
+/// Calls the function from an intrinsic, which evaluates it on the Rust stack rather than the evaluator's.
+/// It counts as a call in progress until it is done, so that intrinsics calling functions that call them again are stopped by the limit on depth.
+pub(crate) fn nested(f: &dyn Callable, args: &[Var], loc: &Location) -> Result<Var, LispErrors> {
+    if let Err(limit) = BUDGET.with(|b| b.borrow_mut().nest()) {
+        return Err(LispErrors::new().limit_exceeded(loc, limit));
+    }
+    let res = f.call(args, loc);
+    BUDGET.with(|b| b.borrow_mut().depth -= 1);
+    res
+}
+
+/// Counts going around the loop at `loc` again, so that loops are stopped by the limits even when they don't call anything.
+pub(crate) fn looped(loc: &Location) -> Result<(), LispErrors> {
+    BUDGET
+        .with(|b| b.borrow_mut().step())
+        .map_err(|limit| LispErrors::new().limit_exceeded(loc, limit))
+}
+
+/// Finishes a call, putting back what was being evaluated before it.
+pub(crate) fn returned(outer: Option<Location>) {
+    EVALUATING.with(|e| *e.borrow_mut() = outer);
+    BUDGET.with(|b| b.borrow_mut().depth -= 1);
+    profile(Profiling::leave);
+}
+
+/// Runs `f` as a call to what `callee` gives, which is profiled if the calls are being profiled.
+/// This is for calls that aren't started by [`call`], which profiles the ones that are.
+pub(crate) fn profiled<T>(callee: impl FnOnce() -> Callee, f: impl FnOnce() -> T) -> T {
+    profile(|p| p.enter(Some(callee())));
+    let res = f();
+    profile(Profiling::leave);
+    res
+}
+
+// Does something with the calls in progress, if they are being profiled.
+fn profile(f: impl FnOnce(&mut Profiling)) {
+    PROFILING.with(|p| {
+        if let Some(p) = &mut *p.borrow_mut() {
+            f(p)
+        }
+    })
+}
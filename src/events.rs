@@ -0,0 +1,174 @@
+use std::time::{Duration, Instant};
+
+use crate::ast::Var;
+use crate::error::LispErrors;
+use crate::eval::{nested, wait_until};
+use crate::sync::{exclusive, Mutable, Shared};
+use crate::types::LispType;
+use crate::Location;
+
+/// The timers that `after` and `every` have set, which every clone shares.
+///
+/// It is kept in the [`Options`](crate::Options), so that a host running its own event loop can call the functions whose time has come with
+/// [`Interpreter::run_due_events`](crate::Interpreter::run_due_events) instead of evaluating `(run-events)`.
+#[derive(Debug, Clone, Default)]
+pub struct Events(Shared<Mutable<Queue>>);
+
+#[derive(Debug, Default)]
+struct Queue {
+    timers: Vec<Timer>,
+    next_id: isize,
+}
+
+#[derive(Debug)]
+struct Timer {
+    id: isize,
+    due: Instant,
+    every: Option<Duration>, // How long until it is due again, if it was set by `every`
+    function: Var,
+    loc: Location, // Where it was set, which is where its function is called from
+}
+
+impl Events {
+    /// When the next timer is due, if any are set.
+    pub fn next_due(&self) -> Option<Instant> {
+        exclusive(&self.0).timers.iter().map(|t| t.due).min()
+    }
+
+    fn set(&self, function: &Var, wait: Duration, every: bool, loc: &Location) -> Var {
+        let mut queue = exclusive(&self.0);
+        let id = queue.next_id;
+        queue.next_id += 1;
+        queue.timers.push(Timer {
+            id,
+            due: Instant::now() + wait,
+            every: every.then_some(wait),
+            function: function.new_ref(),
+            loc: loc.clone(),
+        });
+        Var::new(id)
+    }
+
+    // Takes the timer that has been due for longest, if any of them are due by `now`.
+    // A timer set by `every` is put back to be due again, before its function is called, so that the function can cancel it.
+    fn take_due(&self, now: Instant) -> Option<(Var, Location)> {
+        let mut queue = exclusive(&self.0);
+        // The earliest one set comes first out of the ones that are due at the same time.
+        let (i, _) = queue
+            .timers
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| t.due <= now)
+            .min_by_key(|(_, t)| (t.due, t.id))?;
+        let timer = queue.timers.remove(i);
+        let called = (timer.function.new_ref(), timer.loc.clone());
+        if let Some(every) = timer.every {
+            queue.timers.push(Timer {
+                due: timer.due + every,
+                ..timer
+            });
+        }
+        Some(called)
+    }
+}
+
+/// `(after ms function)`: sets a timer that calls the function with no arguments once, after the time has passed, giving its id.
+pub(crate) fn after(
+    events: &Events,
+    wait: &Var,
+    function: &Var,
+    loc: &Location,
+) -> Result<Var, LispErrors> {
+    let wait = wait_arg(wait, "after", loc)?;
+    check_function(function, "after", loc)?;
+    Ok(events.set(function, wait, false, loc))
+}
+
+/// `(every ms function)`: sets a timer that calls the function with no arguments each time the time passes, giving its id.
+pub(crate) fn every(
+    events: &Events,
+    wait: &Var,
+    function: &Var,
+    loc: &Location,
+) -> Result<Var, LispErrors> {
+    let wait = wait_arg(wait, "every", loc)?;
+    if wait.is_zero() {
+        return Err(LispErrors::new()
+            .error(loc, "`every` can't call a function every 0 milliseconds!")
+            .note(None, "It would never give anything else a chance to run."));
+    }
+    check_function(function, "every", loc)?;
+    Ok(events.set(function, wait, true, loc))
+}
+
+/// `(cancel-timer id)`: stops the timer with the id that `after` or `every` gave, saying whether it was still set.
+pub(crate) fn cancel(events: &Events, id: isize) -> bool {
+    let mut queue = exclusive(&events.0);
+    let before = queue.timers.len();
+    queue.timers.retain(|t| t.id != id);
+    queue.timers.len() < before
+}
+
+/// `(run-events)`: waits for each timer and calls its function when it is due, until none are left.
+pub(crate) fn run_events(events: &Events, loc: &Location) -> Result<Var, LispErrors> {
+    while let Some(due) = events.next_due() {
+        wait_until(due, loc)?;
+        run_due(events)?;
+    }
+    Ok(Var::new(LispType::Nil))
+}
+
+/// Calls the function of every timer that is due, giving how many were called.
+/// Timers that they set which are due straight away are called too.
+pub(crate) fn run_due(events: &Events) -> Result<usize, LispErrors> {
+    let now = Instant::now();
+    let mut called = 0;
+    while let Some((function, loc)) = events.take_due(now) {
+        let f = function.resolve();
+        let res = match &*f.get() {
+            LispType::Func(f) => nested(&**f, &[], &loc),
+            _ => unreachable!("timers are only set with functions"),
+        };
+        res.map_err(|e| {
+            e.note(
+                &loc,
+                "It happened in the function given to the timer set here.",
+            )
+        })?;
+        called += 1;
+    }
+    Ok(called)
+}
+
+// Takes how long a timer waits, which is a number of milliseconds or a duration.
+fn wait_arg(arg: &Var, name: &str, loc: &Location) -> Result<Duration, LispErrors> {
+    let arg = arg.resolve();
+    let ms = match *arg.get() {
+        LispType::Integer(ms) => ms as i64,
+        LispType::Duration(ms) => ms,
+        _ => -1,
+    };
+    u64::try_from(ms).map(Duration::from_millis).map_err(|_| {
+        LispErrors::new()
+            .error(loc, format!("`{name}` can't wait for `{arg}`!"))
+            .note(
+                None,
+                "It needs a number of milliseconds that isn't negative, or a duration.",
+            )
+    })
+}
+
+fn check_function(function: &Var, name: &str, loc: &Location) -> Result<(), LispErrors> {
+    match &*function.resolve().get() {
+        LispType::Func(_) => Ok(()),
+        other => Err(LispErrors::new()
+            .error(
+                loc,
+                format!("`{name}` needs a function, but was given `{other}`!"),
+            )
+            .note(
+                None,
+                format!("Write it like `({name} 1000 (lambda () ...))`."),
+            )),
+    }
+}
@@ -0,0 +1,77 @@
+use std::fmt::Display;
+
+use crate::error::LispErrors;
+use crate::options::Options;
+use crate::tokens::{tokenize_with, Location, Token, TokenType};
+
+/// A piece of source code that has been read but not evaluated, which can be printed back out as source.
+///
+/// Sugar like `[1 2]` and `$` is read as the statement it stands for, and comments are dropped.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Form {
+    Atom(Token),
+    List { loc: Location, items: Vec<Form> },
+}
+
+impl Form {
+    pub fn loc(&self) -> &Location {
+        match self {
+            Form::Atom(t) => &t.loc,
+            Form::List { loc, .. } => loc,
+        }
+    }
+}
+
+impl Display for Form {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Form::Atom(t) => write!(f, "{}", t.dat),
+            Form::List { items, .. } => {
+                write!(f, "(")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// Reads every top-level form in the source.
+pub fn read_forms(source: &str, file: &str, options: &Options) -> Result<Vec<Form>, LispErrors> {
+    let toks = tokenize_with(source, file, options)?;
+    let mut stack: Vec<(Location, Vec<Form>)> = Vec::new();
+    let mut forms = Vec::new();
+    for tok in toks.tokens {
+        let form = match tok.dat {
+            TokenType::StartStmt => {
+                stack.push((tok.loc, Vec::new()));
+                continue;
+            }
+            TokenType::EndStmt => match stack.pop() {
+                Some((loc, items)) => Form::List { loc, items },
+                None => {
+                    return Err(LispErrors::new()
+                        .error(&tok.loc, "Unmatched closing parentheses!")
+                        .note(None, "Delete it.")
+                        .syntax())
+                }
+            },
+            _ => Form::Atom(tok),
+        };
+        match stack.last_mut() {
+            Some((_, items)) => items.push(form),
+            None => forms.push(form),
+        }
+    }
+    if let Some((loc, _)) = stack.pop() {
+        return Err(LispErrors::new()
+            .error(&loc, "Unmatched opening parentheses!")
+            .note(None, "Deleting it might fix this error.")
+            .syntax());
+    }
+    Ok(forms)
+}
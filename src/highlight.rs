@@ -0,0 +1,342 @@
+use std::fmt::Display;
+use std::ops::Range;
+use std::str::FromStr;
+
+use crate::options::Options;
+use crate::pretty::{atom_text, line_starts};
+use crate::tokens::{tokenize_partial, KeyWord, Location, Token, TokenType};
+use crate::types::LispType;
+
+/// What a piece of source is, for colouring it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Highlight {
+    /// `(`, `)`, `[`, `]`, `{`, `}`, `$` and `.`.
+    Delimiter,
+    /// Keywords like `let`, `if` and `lambda`.
+    Keyword,
+    Number,
+    String,
+    /// `nil` and keyword literals like `:name`.
+    Constant,
+    Identifier,
+    /// A name that a `let` or `lambda` binds.
+    Binding,
+    Comment,
+}
+
+impl Highlight {
+    // The name of the kind, which HTML uses for its class.
+    fn name(self) -> &'static str {
+        match self {
+            Highlight::Delimiter => "delimiter",
+            Highlight::Keyword => "keyword",
+            Highlight::Number => "number",
+            Highlight::String => "string",
+            Highlight::Constant => "constant",
+            Highlight::Identifier => "identifier",
+            Highlight::Binding => "binding",
+            Highlight::Comment => "comment",
+        }
+    }
+
+    /// The terminal colour code for the kind, or `None` for names, which are left as they are.
+    pub fn ansi(self) -> Option<&'static str> {
+        match self {
+            Highlight::Delimiter => Some("\x1b[1m"),
+            Highlight::Keyword => Some("\x1b[35m"),
+            Highlight::Number => Some("\x1b[36m"),
+            Highlight::String => Some("\x1b[32m"),
+            Highlight::Constant => Some("\x1b[33m"),
+            Highlight::Binding => Some("\x1b[34m"),
+            Highlight::Comment => Some("\x1b[90m"),
+            Highlight::Identifier => None,
+        }
+    }
+}
+
+/// A piece of source, by its byte offsets, and what it is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub range: Range<usize>,
+    pub kind: Highlight,
+}
+
+/// Splits the source into the pieces that should be coloured, in order. Whitespace isn't part of any of them.
+///
+/// Unlike reading the source, this never fails, so that it can be used while the source is being written.
+/// Everything up to the first mistake is highlighted, and a string or block comment that isn't closed yet runs to the end.
+pub fn highlight(source: &str, options: &Options) -> Vec<Span> {
+    // Stray closing delimiters are skipped rather than ending the highlighting there.
+    let options = Options {
+        friendly_parens: true,
+        ..options.clone()
+    };
+    let partial = tokenize_partial(source, &options);
+    let starts = line_starts(source);
+    let offset = |loc: &Location| {
+        starts
+            .get(loc.line)
+            .map_or(source.len(), |s| s + loc.col)
+            .min(source.len())
+    };
+
+    let mut spans: Vec<Span> = partial
+        .trivia
+        .iter()
+        .map(|t| {
+            let start = offset(&t.loc);
+            Span {
+                range: start..start + t.text.len(),
+                kind: Highlight::Comment,
+            }
+        })
+        .collect();
+    let toks = &partial.tokens;
+    let mut depth = 0;
+    // The depths of the parameter lists of the `let`s, `do`s and `lambda`s we are inside of, and whether each binds like a `let`.
+    let mut binders: Vec<(usize, bool)> = Vec::new();
+    let mut i = 0;
+    while i < toks.len() {
+        let tok = &toks[i];
+        let start = offset(&tok.loc);
+        let kind = match &tok.dat {
+            TokenType::StartStmt if source[start..].starts_with("#\"") => {
+                // Interpolated strings are coloured as a whole, including the code inside of them.
+                let close = closing(toks, i);
+                let end = close.map_or(source.len(), |c| offset(&toks[c].loc) + 1);
+                spans.push(Span {
+                    range: start..end,
+                    kind: Highlight::String,
+                });
+                i = close.map_or(toks.len(), |c| c + 1);
+                continue;
+            }
+            TokenType::StartStmt => {
+                depth += 1;
+                match i.checked_sub(1).map(|p| &toks[p].dat) {
+                    Some(TokenType::KeyWord(KeyWord::Let | KeyWord::LetValues | KeyWord::Do)) => {
+                        binders.push((depth, true))
+                    }
+                    Some(TokenType::KeyWord(KeyWord::Lambda)) => binders.push((depth, false)),
+                    _ => {}
+                }
+                // `[` and `{` stand for an intrinsic, which comes next with the same location.
+                if toks
+                    .get(i + 1)
+                    .is_some_and(|n| n.loc == tok.loc && matches!(n.dat, TokenType::Ident(_)))
+                {
+                    i += 1;
+                }
+                // `#u8(` opens a statement as a whole.
+                let width = if source[start..].starts_with("#u8(") {
+                    4
+                } else {
+                    1
+                };
+                spans.push(Span {
+                    range: start..start + width,
+                    kind: Highlight::Delimiter,
+                });
+                i += 1;
+                continue;
+            }
+            TokenType::EndStmt => {
+                depth -= 1;
+                while binders.last().is_some_and(|(d, _)| *d > depth) {
+                    binders.pop();
+                }
+                // `$` statements are closed along with whatever closes them, or at the end.
+                let delimiter = source[start..].starts_with([')', ']', '}']);
+                let seen = spans.last().is_some_and(|s| s.range.start == start);
+                if !delimiter || seen {
+                    i += 1;
+                    continue;
+                }
+                spans.push(Span {
+                    range: start..start + 1,
+                    kind: Highlight::Delimiter,
+                });
+                i += 1;
+                continue;
+            }
+            TokenType::Ident(id) if id.starts_with('#') => {
+                spans.push(Span {
+                    range: start..start + id.len(),
+                    kind: Highlight::String,
+                });
+                i += 1;
+                continue;
+            }
+            // It marks the parameter after it, rather than being one.
+            TokenType::Ident(id) if id == "&rest" || id == "&key" => Highlight::Keyword,
+            TokenType::Ident(_) => {
+                let after_open = i > 0 && matches!(toks[i - 1].dat, TokenType::StartStmt);
+                let binds = binders.last().is_some_and(|&(d, is_let)| {
+                    d == depth || (is_let && d + 1 == depth && after_open)
+                });
+                if binds {
+                    Highlight::Binding
+                } else {
+                    Highlight::Identifier
+                }
+            }
+            TokenType::KeyWord(_) => Highlight::Keyword,
+            TokenType::Dot => Highlight::Delimiter,
+            TokenType::Recognizable(LispType::Integer(_) | LispType::Floating(_)) => {
+                Highlight::Number
+            }
+            TokenType::Recognizable(LispType::Str(_)) => Highlight::String,
+            TokenType::Recognizable(_) => Highlight::Constant,
+        };
+        let len = atom_text(source, start, tok).len();
+        spans.push(Span {
+            range: start..start + len,
+            kind,
+        });
+        i += 1;
+    }
+    if let Some((loc, kind)) = partial.unfinished {
+        spans.push(Span {
+            range: offset(&loc)..source.len(),
+            kind,
+        });
+    }
+    spans.sort_by_key(|s| s.range.start);
+    spans
+}
+
+// The index of the token that closes the statement opened at `open`, if it has been closed.
+fn closing(toks: &[Token], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, tok) in toks.iter().enumerate().skip(open) {
+        match tok.dat {
+            TokenType::StartStmt => depth += 1,
+            TokenType::EndStmt => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// How [`render_highlighted`] marks up the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HighlightFormat {
+    /// A `<span>` for each piece, with a class like `pale-keyword`, inside of a `<pre class="pale">`.
+    Html,
+    /// Terminal colour codes.
+    Ansi,
+}
+
+impl Display for HighlightFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HighlightFormat::Html => write!(f, "html"),
+            HighlightFormat::Ansi => write!(f, "ansi"),
+        }
+    }
+}
+
+impl FromStr for HighlightFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "html" => Ok(HighlightFormat::Html),
+            "ansi" => Ok(HighlightFormat::Ansi),
+            _ => Err(format!(
+                "Unknown highlight format `{s}`, expected `html` or `ansi`!"
+            )),
+        }
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+// The colours of delimiters by how deeply they are nested, starting over for deeper ones.
+const DEPTH_COLOURS: [&str; 6] = [
+    "\x1b[1;33m",
+    "\x1b[1;35m",
+    "\x1b[1;36m",
+    "\x1b[1;32m",
+    "\x1b[1;34m",
+    "\x1b[1;31m",
+];
+
+/// The source with its highlighting marked up in the format, for showing code samples in docs and on websites.
+///
+/// Delimiters are coloured by how deeply they are nested, which HTML gives as a `pale-depth-N` class from 0 to 5, starting over at 0 after 5.
+pub fn render_highlighted(source: &str, options: &Options, format: HighlightFormat) -> String {
+    let mut out = String::with_capacity(source.len() * 2);
+    if format == HighlightFormat::Html {
+        out.push_str("<pre class=\"pale\">");
+    }
+    let mut opens = Vec::new(); // Whether each open statement is a `$` one, which its parent's delimiter closes
+    let mut last = 0;
+    for span in highlight(source, options) {
+        // Comments inside of interpolated strings are already part of the string.
+        if span.range.start < last {
+            continue;
+        }
+        let text = &source[span.range.clone()];
+        let depth = match (span.kind, text) {
+            (Highlight::Delimiter, "(" | "[" | "{" | "$") => {
+                opens.push(text == "$");
+                Some(opens.len() - 1)
+            }
+            (Highlight::Delimiter, ")" | "]" | "}") => {
+                while opens.pop() == Some(true) {}
+                Some(opens.len())
+            }
+            _ => None,
+        }
+        .map(|d| d % DEPTH_COLOURS.len());
+        match format {
+            HighlightFormat::Html => {
+                escape(&source[last..span.range.start], &mut out);
+                out.push_str("<span class=\"pale-");
+                out.push_str(span.kind.name());
+                if let Some(depth) = depth {
+                    out.push_str(&format!(" pale-depth-{depth}"));
+                }
+                out.push_str("\">");
+                escape(text, &mut out);
+                out.push_str("</span>");
+            }
+            HighlightFormat::Ansi => {
+                out.push_str(&source[last..span.range.start]);
+                match depth.map(|d| DEPTH_COLOURS[d]).or(span.kind.ansi()) {
+                    Some(colour) => {
+                        out.push_str(colour);
+                        out.push_str(text);
+                        out.push_str(RESET);
+                    }
+                    None => out.push_str(text),
+                }
+            }
+        }
+        last = span.range.end;
+    }
+    match format {
+        HighlightFormat::Html => {
+            escape(&source[last..], &mut out);
+            out.push_str("</pre>");
+        }
+        HighlightFormat::Ansi => out.push_str(&source[last..]),
+    }
+    out
+}
+
+fn escape(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+}
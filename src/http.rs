@@ -0,0 +1,111 @@
+use std::io::Read;
+
+use crate::ast::Var;
+use crate::error::LispErrors;
+use crate::types::LispType;
+use crate::Location;
+
+/// `(http-get url headers)`: fetches the url, with the headers if they are given.
+pub(crate) fn get(args: &[Var], loc: &Location) -> Result<Var, LispErrors> {
+    let url = string(&args[0], "http-get", "url", loc)?;
+    let request = with_headers(ureq::get(&url), args.get(1), "http-get", loc)?;
+    respond(request.call(), &url, loc)
+}
+
+/// `(http-post url body headers)`: sends the body, which is a string or bytes, to the url.
+pub(crate) fn post(args: &[Var], loc: &Location) -> Result<Var, LispErrors> {
+    let url = string(&args[0], "http-post", "url", loc)?;
+    let request = with_headers(ureq::post(&url), args.get(2), "http-post", loc)?;
+    let result = match &*args[1].resolve().get() {
+        LispType::Str(body) => request.send_string(body),
+        LispType::Bytes(body) => request.send_bytes(body),
+        other => {
+            return Err(LispErrors::new()
+                .error(
+                    loc,
+                    format!("`http-post` can't send `{other}` as the body!"),
+                )
+                .note(None, "It has to be a string or bytes."))
+        }
+    };
+    respond(result, &url, loc)
+}
+
+// Takes the argument as a string, with `what` saying which argument it was if it isn't one.
+fn string(arg: &Var, name: &str, what: &str, loc: &Location) -> Result<String, LispErrors> {
+    match &*arg.resolve().get() {
+        LispType::Str(s) => Ok(s.clone()),
+        other => Err(LispErrors::new().error(
+            loc,
+            format!("`{name}` needs the {what} as a string, but was given `{other}`!"),
+        )),
+    }
+}
+
+// Sets the headers of the request from a map of strings to strings, if there is one.
+fn with_headers(
+    mut request: ureq::Request,
+    headers: Option<&Var>,
+    name: &str,
+    loc: &Location,
+) -> Result<ureq::Request, LispErrors> {
+    let Some(headers) = headers.map(Var::resolve) else {
+        return Ok(request);
+    };
+    let headers = headers.get();
+    match &*headers {
+        LispType::Map(entries) => {
+            for (k, v) in entries {
+                let k = string(k, name, "name of a header", loc)?;
+                let v = string(v, name, "value of a header", loc)?;
+                request = request.set(&k, &v);
+            }
+            Ok(request)
+        }
+        LispType::Nil => Ok(request),
+        other => Err(LispErrors::new()
+            .error(
+                loc,
+                format!("`{name}` needs the headers as a map, but was given `{other}`!"),
+            )
+            .note(None, "Give them like `{\"accept\" \"text/plain\"}`.")),
+    }
+}
+
+/// The response as `{:status 200 :headers {...} :body "..."}`, with the names of the headers in lowercase.
+/// The body is a string if it is valid UTF-8, and bytes otherwise.
+/// Statuses like 404 aren't errors, since scripts will often want to check for them.
+fn respond(
+    result: Result<ureq::Response, ureq::Error>,
+    url: &str,
+    loc: &Location,
+) -> Result<Var, LispErrors> {
+    let response = match result {
+        Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+        Err(e) => return Err(LispErrors::new().error(loc, format!("Could not reach `{url}`: {e}"))),
+    };
+    let status = response.status() as isize;
+    let mut headers = Vec::new();
+    for name in response.headers_names() {
+        if let Some(value) = response.header(&name) {
+            headers.push((Var::new(name.to_lowercase()), Var::new(value)));
+        }
+    }
+    let mut body = Vec::new();
+    response.into_reader().read_to_end(&mut body).map_err(|e| {
+        LispErrors::new().error(
+            loc,
+            format!("Could not read the response from `{url}`: {e}"),
+        )
+    })?;
+    let body = match String::from_utf8(body) {
+        Ok(text) => LispType::Str(text),
+        Err(e) => LispType::Bytes(e.into_bytes()),
+    };
+    let keyword = |name: &str| Var::new(LispType::Keyword(name.to_string()));
+    Ok(Var::new(LispType::Map(vec![
+        (keyword("status"), Var::new(status)),
+        (keyword("headers"), Var::new(LispType::Map(headers))),
+        (keyword("body"), Var::new(body)),
+    ])))
+}
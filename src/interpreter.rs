@@ -0,0 +1,328 @@
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use crate::ast::{intrinsics, make_program, Expr, Locals, Scope, Var};
+use crate::callable::{Capability, Native};
+use crate::convert::IntoLisp;
+use crate::cycles::Collector;
+use crate::error::LispErrors;
+use crate::eval::{with_options, Machine, Suspended};
+use crate::options::{InterruptHandle, Options};
+use crate::profile::{Profile, Profiler};
+use crate::sync::{Shared, ThreadSafe};
+use crate::tokens::tokenize_with;
+use crate::types::LispType;
+use crate::value::Value;
+
+/// Evaluates one piece of source after another in the same scope, for applications that embed pale.
+///
+/// Whatever one evaluation binds can be used by the ones after it, and the host can look bindings up or make its own.
+#[derive(Debug)]
+pub struct Interpreter {
+    pub(crate) scope: Scope,
+    pub(crate) options: Options,
+    warnings: LispErrors,
+    // The fields are dropped in order, so this collects the cycles among what the others held once they have let go of it.
+    _cycles: Collector,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::with_options(Options::default())
+    }
+
+    pub fn with_options(options: Options) -> Self {
+        Self {
+            scope: Scope::new(&options),
+            options,
+            warnings: LispErrors::new(),
+            _cycles: Collector,
+        }
+    }
+
+    /// Evaluates the source, keeping what it binds for later evaluations.
+    pub fn eval(&mut self, source: &str, name: &str) -> Result<Value, LispErrors> {
+        self.eval_with(source, name, self.options.vm)
+    }
+
+    /// Evaluates the source like [`Interpreter::eval`], but by compiling it to bytecode and running that, whether or not [`Options::vm`] is set.
+    /// The result is always the same as evaluating it, but it can be faster for code that calls functions a lot.
+    pub fn eval_compiled(&mut self, source: &str, name: &str) -> Result<Value, LispErrors> {
+        self.eval_with(source, name, true)
+    }
+
+    fn eval_with(&mut self, source: &str, name: &str, compiled: bool) -> Result<Value, LispErrors> {
+        self.warnings = LispErrors::new();
+        let filename: Shared<str> = name.into();
+        let toks = tokenize_with(source, filename, &self.options)?;
+        self.warnings = toks.warnings;
+        let program = make_program(&toks.tokens, &mut self.scope, toks.edition, &self.options)?;
+        let res = program.run(&self.options, compiled)?;
+        Ok(Value::new(res))
+    }
+
+    /// Evaluates the source like [`Interpreter::eval`], but a piece at a time each time the future is polled, so that a host with an async runtime isn't blocked for the whole of it.
+    ///
+    /// Each poll takes at most [`EvalAsync::STEPS_PER_POLL`] steps, and doesn't go past the end of a top-level form.
+    /// Like [`Interpreter::eval_resumable`], it is always evaluated without compiling it, even if [`Options::vm`] is set.
+    /// The limits in the options apply to the whole of the source, across every poll.
+    pub fn eval_async<'a>(&'a mut self, source: &str, name: &str) -> EvalAsync<'a> {
+        self.warnings = LispErrors::new();
+        let filename: Shared<str> = name.into();
+        let program = tokenize_with(source, filename, &self.options).and_then(|toks| {
+            self.warnings = toks.warnings;
+            make_program(&toks.tokens, &mut self.scope, toks.edition, &self.options)
+        });
+        let (forms, failed) = match program {
+            Ok(program) => (program.forms, None),
+            Err(errors) => (Vec::new(), Some(errors)),
+        };
+        EvalAsync {
+            evaluation: Suspended::new(&self.options),
+            _interpreter: self,
+            forms: forms.into_iter(),
+            machine: None,
+            last: Var::new(LispType::Nil),
+            failed,
+        }
+    }
+
+    /// Evaluates the source like [`Interpreter::eval`], but stops once it has taken `steps` steps, so that a host like a game can spread a long evaluation over several frames.
+    ///
+    /// A step is a call or a time around a loop, the same as for [`EvalLimits::max_steps`](crate::EvalLimits::max_steps).
+    /// If it isn't done by then, it gives a [`Continuation`] that carries on from exactly where it stopped, which can be resumed later with however many steps there are for it then.
+    /// It is always evaluated without compiling it, even if [`Options::vm`] is set, and the limits in the options apply to the whole of it, across every resume.
+    pub fn eval_resumable(
+        &mut self,
+        source: &str,
+        name: &str,
+        steps: u64,
+    ) -> Result<Resumable, LispErrors> {
+        self.warnings = LispErrors::new();
+        let filename: Shared<str> = name.into();
+        let toks = tokenize_with(source, filename, &self.options)?;
+        self.warnings = toks.warnings;
+        let program = make_program(&toks.tokens, &mut self.scope, toks.edition, &self.options)?;
+        let continuation = Continuation {
+            evaluation: Box::new(Suspended::new(&self.options)),
+            machine: Machine::new(Shared::new(Expr::Seq(program.forms)), Locals::default()),
+        };
+        continuation.resume(steps)
+    }
+
+    /// The warnings from the last evaluation.
+    pub fn warnings(&self) -> &LispErrors {
+        &self.warnings
+    }
+
+    pub fn take_warnings(&mut self) -> LispErrors {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// What the name is bound to, if it is bound.
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.scope.global(name).map(|var| Value::new(var.new_ref()))
+    }
+
+    /// Binds the name to the value for the evaluations after this one, replacing whatever it was bound to, so that data can be given to scripts without writing it into their source.
+    /// Code that has already been evaluated keeps using what it was bound to before.
+    pub fn set_global(&mut self, name: &str, value: impl IntoLisp) {
+        let slot = Var::new(LispType::Nil);
+        slot.bind(value.into_lisp().var().new_ref());
+        self.scope.bind_global(name, slot);
+    }
+
+    /// Binds each of the names to its value, like [`Interpreter::set_global`].
+    pub fn with_globals<K, V, I>(mut self, globals: I) -> Self
+    where
+        K: AsRef<str>,
+        V: IntoLisp,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        for (name, value) in globals {
+            self.set_global(name.as_ref(), value);
+        }
+        self
+    }
+
+    /// Binds the name to a function written in Rust, so that scripts can call it like any other function.
+    ///
+    /// If the function returns an error, it is reported at the call as "`name` failed: error".
+    pub fn register_fn<F>(&mut self, name: &str, f: F) -> &mut Self
+    where
+        F: Fn(&[Value]) -> Result<Value, String> + ThreadSafe + 'static,
+    {
+        let native = Native::new(name, Shared::new(f));
+        self.scope
+            .bind_global(name, Var::new(LispType::Func(Box::new(native))));
+        self
+    }
+
+    /// Removes the intrinsics that need the capability, so that scripts can't do it.
+    /// Denying [`Capability::Files`] also stops `import` and `load` from reading other scripts.
+    ///
+    /// This only removes intrinsics, so it should be done before giving scripts functions of your own.
+    pub fn deny(mut self, capability: Capability) -> Self {
+        for (name, op) in intrinsics() {
+            if op.capability() == Some(capability) {
+                self.scope.unbind_global(name);
+            }
+        }
+        if capability == Capability::Files {
+            self.scope.files = false;
+        }
+        self
+    }
+
+    /// Removes every intrinsic and prelude function that isn't one of the names, for scripts that should only be able to do a few things.
+    /// `import` and `load` can only read other scripts if one of the names is an intrinsic that needs [`Capability::Files`].
+    pub fn allow_only<'a>(mut self, names: impl IntoIterator<Item = &'a str>) -> Self {
+        let allowed: BTreeSet<&str> = names.into_iter().collect();
+        for (name, _) in Scope::new(&self.options).globals() {
+            if !allowed.contains(name) {
+                self.scope.unbind_global(name);
+            }
+        }
+        self.scope.files &= intrinsics()
+            .iter()
+            .any(|(name, op)| op.capability() == Some(Capability::Files) && allowed.contains(name));
+        self
+    }
+
+    /// Every file that `import` and `load` have read, so that tools can tell when what a script uses has changed.
+    pub fn files_read(&self) -> impl Iterator<Item = &Path> {
+        self.scope.modules.read.iter().map(PathBuf::as_path)
+    }
+
+    /// Every name that is bound, including the intrinsics.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.scope.globals().into_iter().map(|(name, _)| name)
+    }
+
+    /// A handle that stops whatever this interpreter is evaluating when it is triggered, from another thread or a Ctrl-C handler.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.options.interrupt.clone()
+    }
+
+    /// Calls the functions given to `after` and `every` whose time has come, giving how many were called, for hosts that run their own event loop instead of evaluating `(run-events)`.
+    pub fn run_due_events(&mut self) -> Result<usize, LispErrors> {
+        let events = &self.options.events;
+        with_options(&self.options, || crate::events::run_due(events))
+    }
+
+    /// When the next function given to `after` or `every` is due, or `None` if there aren't any timers set.
+    pub fn next_event(&self) -> Option<Instant> {
+        self.options.events.next_due()
+    }
+
+    /// What the profiler in the options has recorded, if there is one.
+    pub fn profile(&self) -> Option<Profile> {
+        self.options.profiler.as_ref().map(Profiler::profile)
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The evaluation started by [`Interpreter::eval_async`], which gives what [`Interpreter::eval`] would have given once it is finished.
+///
+/// Each poll carries on with the evaluation for a while and then wakes itself to be polled again, so it works with any executor.
+pub struct EvalAsync<'a> {
+    // What the source binds is kept in the interpreter, so nothing else can use it until this is done.
+    _interpreter: &'a mut Interpreter,
+    evaluation: Suspended,
+    forms: std::vec::IntoIter<Shared<Expr>>,
+    machine: Option<Machine>, // The top-level form it is in the middle of
+    last: Var,
+    failed: Option<LispErrors>, // Reading the source failed, which the first poll gives
+}
+
+impl EvalAsync<'_> {
+    /// How many steps each poll takes at most, counted like [`EvalLimits::max_steps`](crate::EvalLimits::max_steps).
+    pub const STEPS_PER_POLL: u64 = 10_000;
+}
+
+impl Future for EvalAsync<'_> {
+    type Output = Result<Value, LispErrors>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        if let Some(errors) = this.failed.take() {
+            return Poll::Ready(Err(errors));
+        }
+        let machine = match &mut this.machine {
+            Some(machine) => machine,
+            None => match this.forms.next() {
+                Some(form) => this.machine.insert(Machine::new(form, Locals::default())),
+                None => return Poll::Ready(Ok(Value::new(this.last.new_ref()))),
+            },
+        };
+        match this
+            .evaluation
+            .resume(|| machine.run(Some(EvalAsync::STEPS_PER_POLL)))
+        {
+            Ok(Some(value)) => {
+                this.last = value;
+                this.machine = None;
+            }
+            Ok(None) => {}
+            Err(errors) => return Poll::Ready(Err(errors)),
+        }
+        if this.machine.is_none() && this.forms.len() == 0 {
+            return Poll::Ready(Ok(Value::new(this.last.new_ref())));
+        }
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+impl std::fmt::Debug for EvalAsync<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EvalAsync")
+            .field("forms_left", &self.forms.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// What [`Interpreter::eval_resumable`] gives: the value, or what is left to do if it ran out of steps first.
+#[derive(Debug)]
+pub enum Resumable {
+    Done(Value),
+    Paused(Continuation),
+}
+
+/// An evaluation that was stopped by running out of steps, with everything it was in the middle of, including the calls it was in and what their variables were.
+///
+/// It doesn't borrow the interpreter, so the interpreter can be used for other evaluations in between.
+pub struct Continuation {
+    evaluation: Box<Suspended>,
+    machine: Machine,
+}
+
+impl Continuation {
+    /// Carries on with the evaluation for up to `steps` more steps.
+    pub fn resume(mut self, steps: u64) -> Result<Resumable, LispErrors> {
+        let machine = &mut self.machine;
+        match self.evaluation.resume(|| machine.run(Some(steps)))? {
+            Some(value) => Ok(Resumable::Done(Value::new(value))),
+            None => Ok(Resumable::Paused(self)),
+        }
+    }
+}
+
+impl std::fmt::Debug for Continuation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Continuation").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,115 @@
+use serde_json::{Map, Number, Value as Json};
+
+use crate::ast::Var;
+use crate::error::LispErrors;
+use crate::types::LispType;
+use crate::Location;
+
+/// Reads JSON into the values that stand for it: objects become maps with string keys, and arrays become lists.
+/// `true` becomes `:true` and both `false` and `null` become `nil`, since only `nil` is false.
+pub(crate) fn parse(source: &str, loc: &Location) -> Result<Var, LispErrors> {
+    let json: Json = serde_json::from_str(source)
+        .map_err(|e| LispErrors::new().error(loc, format!("Could not parse JSON: {e}")))?;
+    Ok(from_json(json))
+}
+
+fn from_json(json: Json) -> Var {
+    Var::new(match json {
+        Json::Null | Json::Bool(false) => LispType::Nil,
+        Json::Bool(true) => LispType::Keyword("true".to_string()),
+        Json::Number(n) => match n.as_i64().and_then(|i| isize::try_from(i).ok()) {
+            Some(i) => LispType::Integer(i),
+            None => LispType::Floating(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Json::String(s) => LispType::Str(s),
+        Json::Array(items) => LispType::List(items.into_iter().map(from_json).collect()),
+        Json::Object(entries) => LispType::Map(
+            entries
+                .into_iter()
+                .map(|(k, v)| (Var::new(k), from_json(v)))
+                .collect(),
+        ),
+    })
+}
+
+/// Writes the value as JSON. Keywords are written as their names, except for `:true`, and `nil` is written as `null`.
+/// Dates are written as strings, the way they are displayed.
+pub(crate) fn stringify(value: &Var, loc: &Location) -> Result<String, LispErrors> {
+    Ok(to_json(value, 0, loc)?.to_string())
+}
+
+// How deeply lists and maps can be nested to be written, which is as deeply as they can be to be read.
+// JSON values are written and dropped by recursing into them, so without a limit deeply nested values would run out of Rust stack.
+const MAX_DEPTH: usize = 128;
+
+fn to_json(value: &Var, depth: usize, loc: &Location) -> Result<Json, LispErrors> {
+    let value = value.resolve();
+    let json = match &*value.get() {
+        LispType::Nil => Json::Null,
+        LispType::Keyword(k) if k == "true" => Json::Bool(true),
+        LispType::Keyword(k) => Json::String(k.clone()),
+        LispType::Integer(i) => Json::Number((*i as i64).into()),
+        LispType::Floating(f) => match Number::from_f64(*f) {
+            Some(n) => Json::Number(n),
+            None => {
+                return Err(LispErrors::new()
+                    .error(loc, format!("`{f}` can't be written as JSON!"))
+                    .note(
+                        None,
+                        "JSON has no infinite numbers, or ones that aren't a number.",
+                    ))
+            }
+        },
+        LispType::Str(s) => Json::String(s.clone()),
+        LispType::List(_) | LispType::Values(_) | LispType::Map(_) if depth == MAX_DEPTH => {
+            return Err(LispErrors::new()
+                .error(loc, "The value is nested too deeply to be written as JSON!")
+                .note(
+                    None,
+                    format!("Lists and maps can only be nested {MAX_DEPTH} deep."),
+                ))
+        }
+        LispType::List(items) | LispType::Values(items) => Json::Array(
+            items
+                .iter()
+                .map(|item| to_json(item, depth + 1, loc))
+                .collect::<Result<_, _>>()?,
+        ),
+        LispType::Map(entries) => {
+            let mut object = Map::new();
+            for (k, v) in entries {
+                object.insert(key(k, loc)?, to_json(v, depth + 1, loc)?);
+            }
+            Json::Object(object)
+        }
+        date @ LispType::Date(_) => Json::String(date.to_string()),
+        other @ (LispType::Pair(..)
+        | LispType::Func(_)
+        | LispType::Bytes(_)
+        | LispType::Socket(_)
+        | LispType::Port(_)
+        | LispType::Channel(_)
+        | LispType::Promise(_)
+        | LispType::Duration(_)) => {
+            return Err(LispErrors::new()
+                .error(loc, format!("`{other}` can't be written as JSON!"))
+                .note(
+                    None,
+                    "Only lists, maps, strings, numbers, keywords and `nil` can be.",
+                ))
+        }
+        LispType::Var(_) => unreachable!("Resolved values aren't slots"),
+    };
+    Ok(json)
+}
+
+// The key of an object for a key of a map, which can be a string, a keyword or an integer.
+fn key(k: &Var, loc: &Location) -> Result<String, LispErrors> {
+    match &*k.resolve().get() {
+        LispType::Str(s) | LispType::Keyword(s) => Ok(s.clone()),
+        LispType::Integer(i) => Ok(i.to_string()),
+        other => Err(LispErrors::new()
+            .error(loc, format!("`{other}` can't be the key of a JSON object!"))
+            .note(None, "Only strings, keywords and integers can be.")),
+    }
+}
@@ -1,60 +1,240 @@
-use error::LispErrors;
-
-use crate::ast::{make_ast, Scope, Var};
-use crate::tokens::{tokenize, Location};
+use crate::ast::{Scope, Var};
+use crate::types::LispType;
+use crate::vm::Code;
 
 mod ast;
+mod bytes;
 mod callable;
+mod channel;
+#[cfg(feature = "config")]
+mod config;
+mod convert;
+mod csv;
+mod cycles;
+mod dates;
+mod dump;
 mod error;
+mod eval;
+mod events;
+mod forms;
+mod highlight;
+#[cfg(feature = "net")]
+mod http;
+mod interpreter;
+#[cfg(feature = "json")]
+mod json;
+mod lint;
+mod names;
+mod options;
+mod parallel;
+mod port;
+mod pretty;
+mod profile;
+mod promise;
+mod readers;
+#[cfg(feature = "regex")]
+mod regex;
+mod session;
+mod socket;
+mod streams;
+mod symbols;
+mod sync;
 mod tokens;
 mod types;
+mod value;
+mod vm;
 
-pub fn run_lisp(source: &str, file: &str) -> Result<String, LispErrors> {
-    let toks = tokenize(source, file.to_string())?;
-    let ast = make_ast(
-        &toks,
-        &mut Scope::default(),
-        &Location {
-            filename: file.to_string(),
+pub use ast::live_values;
+pub use callable::{Capability, NativeFn};
+pub use convert::{FromLisp, IntoLisp};
+pub use cycles::collect_cycles;
+pub use dump::{dump_forms, dump_scope, dump_tokens, DumpFormat};
+pub use error::{
+    Diagnostic, ErrorCategory, Fix, LimitExceeded, LispErrors, Note, PathTrim, Severity,
+};
+pub use events::Events;
+pub use forms::{read_forms, Form};
+pub use highlight::{highlight, render_highlighted, Highlight, HighlightFormat, Span};
+pub use interpreter::{Continuation, EvalAsync, Interpreter, Resumable};
+pub use lint::{lint, Level, LintConfig, Rule};
+pub use options::{Edition, EvalLimits, InterruptHandle, Options, PrintLimits, SourceLimits};
+pub use pretty::{format, format_source, FormatOptions};
+pub use profile::{Profile, ProfileEntry, Profiler};
+pub use readers::{ReaderFn, Readers};
+pub use session::{Definition, Evaluation, Reloaded, Session};
+pub use streams::{Input, Output, OutputFn};
+pub use symbols::{intrinsic_doc, symbols, Reference, Symbol, SymbolKind, Symbols};
+pub use sync::{Mutable, Shared, ThreadSafe};
+pub use tokens::{is_incomplete, Location, ReadChars, Token, TokenStream};
+pub use value::{ConversionError, Value};
+
+#[cfg(feature = "derive")]
+pub use pale_derive::{FromLisp, IntoLisp};
+// Lets the derives' `::pale::` paths work in this crate's own tests.
+#[cfg(all(test, feature = "derive"))]
+extern crate self as pale;
+
+/// The version of the Pale library.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Where the statement currently being evaluated on this thread is, if there is one.
+/// This is mostly useful for reporting where a panic happened from inside of a panic hook.
+pub fn evaluating_location() -> Option<Location> {
+    eval::evaluating_location()
+}
+
+pub fn run_lisp(source: &str, file: &str) -> Result<Value, LispErrors> {
+    run_lisp_with(source, file, &Options::default()).map(|(res, _)| res)
+}
+
+/// Like [`run_lisp`], but with the result printed.
+pub fn run_lisp_to_string(source: &str, file: &str) -> Result<String, LispErrors> {
+    run_lisp(source, file).map(|res| res.to_string())
+}
+
+/// Runs the source with the given options, returning the warnings that were produced alongside the result.
+pub fn run_lisp_with(
+    source: &str,
+    file: &str,
+    options: &Options,
+) -> Result<(Value, LispErrors), LispErrors> {
+    run_lisp_in(
+        &mut Interpreter::with_options(options.clone()),
+        source,
+        file,
+    )
+}
+
+/// Like [`run_lisp_with`], but running the source in the interpreter with its options, so that what it bound can be looked at afterwards.
+pub fn run_lisp_in(
+    interpreter: &mut Interpreter,
+    source: &str,
+    file: &str,
+) -> Result<(Value, LispErrors), LispErrors> {
+    let mut res = interpreter.eval(source, file)?;
+    let options = &interpreter.options;
+    if options.call_main {
+        let start = Location {
+            filename: file.into(),
             col: 0,
             line: 0,
-        },
-    )?;
-    Ok(format!("{}", ast.resolve()?))
+        };
+        let main = eval::with_options(options, || call_main(&interpreter.scope, options, &start))?;
+        if let Some(main) = main {
+            res = Value::new(main);
+        }
+    }
+    Ok((res, interpreter.take_warnings()))
+}
+
+// Calls the `main` function in the scope if there is one, passing it the arguments as a list of strings.
+fn call_main(scope: &Scope, options: &Options, loc: &Location) -> Result<Option<Var>, LispErrors> {
+    let main = match scope.global("main") {
+        Some(m) => m.resolve(),
+        None => return Ok(None),
+    };
+    let args = options
+        .main_args
+        .iter()
+        .map(|a| Var::new(a.as_str()))
+        .collect();
+    let args = [Var::new(LispType::List(args))];
+    let called = match &*main.get() {
+        LispType::Func(_) if options.vm => {
+            vm::run(Shared::new(Code::call(&main, &args, loc))).map(Some)
+        }
+        LispType::Func(f) => f.call(&args, loc).map(Some),
+        other => Err(LispErrors::new()
+            .error(
+                loc,
+                format!("`main` must be a function, but it is `{other}`!"),
+            )
+            .note(None, "Rename it, or run without calling `main`.")),
+    };
+    called
 }
 
 #[cfg(feature = "debug")]
-pub fn run_lisp_dumped(source: &str, file: &str) -> Result<String, LispErrors> {
-    let toks = tokenize(source, file.to_string())?;
-    for tok in &toks {
+pub fn run_lisp_dumped(source: &str, file: &str) -> Result<Value, LispErrors> {
+    use crate::ast::make_program;
+    use crate::tokens::tokenize_with;
+    let toks = tokenize_with(source, file, &Options::default())?;
+    for tok in &toks.tokens {
         println!("{} => {:?}", tok.loc, tok.dat);
     }
-    let ast = make_ast(
-        &toks,
+    let program = make_program(
+        &toks.tokens,
         &mut Scope::default(),
-        &Location {
-            filename: file.to_string(),
-            col: 0,
-            line: 0,
-        },
+        toks.edition,
+        &Options::default(),
     )?;
-    println!("Ast = {ast:#?}");
-    Ok(format!("{}", ast.resolve()?))
+    println!("Ast = {program:#?}");
+    Ok(Value::new(program.resolve()?))
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::sync::{exclusive, Mutable, Shared};
     use crate::{
-        run_lisp, tokenize,
-        tokens::{Location, Token, TokenType},
+        dump_forms, dump_scope, dump_tokens, format_source, highlight, intrinsic_doc,
+        is_incomplete, lint, live_values,
+        names::Names,
+        read_forms, render_highlighted, run_lisp_in, run_lisp_with, symbols,
+        tokens::{tokenize, Location, Token, TokenType},
         types::LispType,
+        Capability, DumpFormat, Edition, ErrorCategory, EvalLimits, Fix, FormatOptions, Highlight,
+        HighlightFormat, Input, Interpreter, LimitExceeded, LintConfig, LispErrors, Options,
+        Output, PathTrim, PrintLimits, Profiler, Resumable, Session, Severity, SourceLimits,
+        SymbolKind, TokenStream, Value,
     };
+    use std::time::Duration;
+
+    // Every source that the tests evaluate with these is compiled and run by the VM as well, which has to give the same result.
+    fn run_lisp(source: &str, file: &str) -> Result<Value, LispErrors> {
+        let walked = crate::run_lisp(source, file);
+        let compiled = Interpreter::new().eval_compiled(source, file);
+        let shown = |res: &Result<Value, LispErrors>| match res {
+            Ok(value) => Ok(value.to_string()),
+            Err(errors) => Err(errors.to_string()),
+        };
+        assert_eq!(shown(&walked), shown(&compiled), "{source}");
+        walked
+    }
+
+    fn run_lisp_to_string(source: &str, file: &str) -> Result<String, LispErrors> {
+        run_lisp(source, file).map(|res| res.to_string())
+    }
+
+    // A directory of a test's own for the files it uses, which is deleted along with them once it is dropped, even if the test fails.
+    struct TempDir(std::path::PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("pale-{name}-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl std::ops::Deref for TempDir {
+        type Target = std::path::Path;
+        fn deref(&self) -> &Self::Target {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
     #[test]
     fn test_tokenizer() {
         let expected_res = [
             Token {
                 loc: Location {
-                    filename: "-".to_string(),
+                    filename: "-".into(),
                     line: 0,
                     col: 0,
                 },
@@ -62,7 +242,7 @@ mod tests {
             },
             Token {
                 loc: Location {
-                    filename: "-".to_string(),
+                    filename: "-".into(),
                     line: 0,
                     col: 1,
                 },
@@ -70,7 +250,7 @@ mod tests {
             },
             Token {
                 loc: Location {
-                    filename: "-".to_string(),
+                    filename: "-".into(),
                     line: 0,
                     col: 3,
                 },
@@ -78,7 +258,7 @@ mod tests {
             },
             Token {
                 loc: Location {
-                    filename: "-".to_string(),
+                    filename: "-".into(),
                     line: 0,
                     col: 4,
                 },
@@ -86,7 +266,7 @@ mod tests {
             },
             Token {
                 loc: Location {
-                    filename: "-".to_string(),
+                    filename: "-".into(),
                     line: 0,
                     col: 6,
                 },
@@ -94,7 +274,7 @@ mod tests {
             },
             Token {
                 loc: Location {
-                    filename: "-".to_string(),
+                    filename: "-".into(),
                     line: 0,
                     col: 8,
                 },
@@ -102,7 +282,7 @@ mod tests {
             },
             Token {
                 loc: Location {
-                    filename: "-".to_string(),
+                    filename: "-".into(),
                     line: 0,
                     col: 11,
                 },
@@ -110,7 +290,7 @@ mod tests {
             },
             Token {
                 loc: Location {
-                    filename: "-".to_string(),
+                    filename: "-".into(),
                     line: 0,
                     col: 19,
                 },
@@ -118,15 +298,15 @@ mod tests {
             },
             Token {
                 loc: Location {
-                    filename: "-".to_string(),
+                    filename: "-".into(),
                     line: 0,
-                    col: 20,
+                    col: 21,
                 },
-                dat: TokenType::Ident("\"sliijioo\"".to_string()),
+                dat: TokenType::Recognizable(LispType::Str("sliijioo".to_string())),
             },
             Token {
                 loc: Location {
-                    filename: "-".to_string(),
+                    filename: "-".into(),
                     line: 0,
                     col: 31,
                 },
@@ -139,11 +319,2719 @@ mod tests {
         );
     }
     #[test]
+    fn test_tokens_share_filename() {
+        let toks = tokenize("(+ 1 \"two\")", "some/long/path.pale").unwrap();
+        assert!(toks
+            .windows(2)
+            .all(|w| Shared::ptr_eq(&w[0].loc.filename, &w[1].loc.filename)));
+    }
+    #[test]
+    fn test_names() {
+        let mut names = Names::default();
+        let first = names.intern("pale-name");
+        assert_eq!(names.intern("pale-name"), first);
+        assert_ne!(names.intern("another"), first);
+        assert_eq!(names.find("pale-name"), Some(first));
+        assert_eq!(names.find("never-bound"), None);
+        assert_eq!(names.text(first), "pale-name");
+        // The top level is still listed in alphabetical order, whatever order the names were bound in.
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("(let ((zz 1) (aa 2)))", "-").unwrap();
+        let listed: Vec<&str> = interpreter.names().collect();
+        assert!(listed.is_sorted(), "{listed:?}");
+        assert!(listed.contains(&"aa") && listed.contains(&"zz"));
+    }
+    #[test]
     fn test_addition() {
         let source = "(+ 34 (+ 34 1))";
+        assert_eq!(run_lisp_to_string(source, "<provided>").unwrap(), "69");
+        assert_eq!(
+            run_lisp_to_string("[(+) (*) (- 5) (+ 2) (* 3 4 5)]", "-").unwrap(),
+            "(0 1 -5 2 60)"
+        );
+        for overflowing in [
+            "(+ 9223372036854775807 1)",
+            "(* 4611686018427387904 2)",
+            "(- -9223372036854775807 2)",
+            "(- (- -9223372036854775807 1))",
+        ] {
+            let err = run_lisp(overflowing, "-").unwrap_err().to_string();
+            assert!(
+                err.contains("doesn't fit in an integer!"),
+                "{overflowing}: {err}"
+            );
+        }
+        let err = run_lisp("(-)", "-").unwrap_err().to_string();
+        assert!(
+            err.contains("`-` takes at least 1 argument(s), but was given 0!"),
+            "{err}"
+        );
+    }
+    #[test]
+    fn test_integer_literals() {
+        assert_eq!(
+            run_lisp_to_string("[0xFF 0o17 0b1010 1_000_000 -0x10 1_0.5]", "-").unwrap(),
+            "(255 15 10 1000000 -16 10.5)"
+        );
+        for malformed in [
+            "0x",
+            "0xZZ",
+            "0b102",
+            "12abc",
+            "0x_",
+            "0xFFFFFFFFFFFFFFFFFF",
+            "1_",
+            "1__0",
+            "0x_FF",
+            "1_.5",
+            "1._5",
+            "1_e5",
+        ] {
+            assert!(tokenize(malformed, "-").is_err(), "{malformed}");
+        }
+        let err = tokenize("(+ 1_ 2)", "-").unwrap_err().to_string();
+        assert!(
+            err.contains("`1_`: underscores can only go between digits!"),
+            "{err}"
+        );
+        assert_eq!(
+            run_lisp_to_string("[0xF_F 1_0e1_0]", "-").unwrap(),
+            "(255 100000000000)"
+        );
+    }
+    #[test]
+    fn test_list_literals() {
+        assert_eq!(
+            run_lisp_to_string("[1 (+ 1 1) 3]", "<provided>").unwrap(),
+            "(1 2 3)"
+        );
+        assert_eq!(
+            run_lisp_to_string("(list [] [1])", "<provided>").unwrap(),
+            "(() (1))"
+        );
+    }
+    #[test]
+    fn test_map_literals() {
+        assert_eq!(
+            run_lisp_to_string("{\"a\" 1 \"b\" (+ 1 1) \"a\" 3}", "<provided>").unwrap(),
+            "{a 3 b 2}"
+        );
+        assert!(run_lisp_to_string("{\"a\" 1 \"b\"}", "<provided>").is_err());
+    }
+    #[test]
+    fn test_unterminated_strings() {
+        let err = tokenize("(print \"abc)\n(+ 1 2)", "-").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("-:0:7 - Unterminated string literal!"));
+        assert_eq!(
+            run_lisp_to_string("(list \"multiple\nlines\")", "<provided>").unwrap(),
+            "(multiple\nlines)"
+        );
+    }
+    #[test]
+    fn test_friendly_parens() {
+        let options = Options {
+            friendly_parens: true,
+            ..Default::default()
+        };
+        let (res, warnings) = run_lisp_with("(+ 1 (+ 2 3)", "-", &options).unwrap();
+        assert_eq!(res.to_string(), "6");
+        assert!(!warnings.is_fatal());
+        let fixes: Vec<_> = warnings.fixes().cloned().collect();
+        assert_eq!(
+            fixes,
+            [Fix {
+                loc: Location {
+                    filename: "-".into(),
+                    line: 0,
+                    col: 12,
+                },
+                remove: 0,
+                insert: ")".to_string(),
+            }]
+        );
+
+        let (res, warnings) = run_lisp_with("(+ 1 2))\n)", "-", &options).unwrap();
+        assert_eq!(res.to_string(), "3");
+        assert_eq!(warnings.fixes().count(), 2);
+        assert!(run_lisp_with("(+ 1 2)) 3", "-", &options).is_err());
+        assert!(run_lisp_to_string("(+ 1 (+ 2 3)", "-").is_err());
+    }
+    #[test]
+    fn test_call_main() {
+        let options = Options {
+            call_main: true,
+            main_args: vec!["a".to_string(), "b".to_string()],
+            ..Default::default()
+        };
+        let (res, _) = run_lisp_with("(let ((main list)) + 1 2)", "-", &options).unwrap();
+        assert_eq!(res.to_string(), "((a b))");
+        let (res, _) = run_lisp_with("(+ 1 2)", "-", &options).unwrap();
+        assert_eq!(res.to_string(), "3");
+        assert!(run_lisp_with("(let ((main 3)) + 1 2)", "-", &options).is_err());
+    }
+    #[test]
+    fn test_token_stream() {
+        let source = "(print {* comment *} [1 2] \"a string\") // comment\n$ + 1 2";
+        let from_str = tokenize(source, "-").unwrap();
+        let options = Options::default();
+        let streamed: Vec<Token> = TokenStream::new(source.chars(), "-", &options)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(from_str, streamed);
+        let read: Vec<Token> = TokenStream::from_reader(source.as_bytes(), "-", &options)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(from_str, read);
+
+        // Tokens are available before the rest of the source has been read.
+        let mut stream = TokenStream::new("(+ 1 \"unterminated".chars(), "-", &options);
+        assert_eq!(stream.next().unwrap().unwrap().dat, TokenType::StartStmt);
+        assert!(stream.by_ref().any(|t| t.is_err()));
+        assert!(stream.next().is_none());
+
+        let invalid: &[u8] = &[b'(', 0xff, b')'];
+        assert!(TokenStream::from_reader(invalid, "-", &options).any(|t| t.is_err()));
+    }
+    #[test]
+    fn test_block_comments() {
+        assert_eq!(
+            run_lisp_to_string("(+ 1 {* outer {* inner *} still a comment *} 2)", "-").unwrap(),
+            "3"
+        );
+        let err = tokenize("(+ 1 2)\n{* outer {* inner *}", "-").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("-:1:0 - Unterminated block comment!"));
+        let err = tokenize("{* outer {* inner", "-").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("-:0:9 - Unterminated block comment!"));
+    }
+    #[test]
+    fn test_edition_directive() {
+        let options = Options::default();
+        let mut stream = TokenStream::new("(pale-edition 1)\n(+ 1 2)".chars(), "-", &options);
+        assert_eq!(stream.next().unwrap().unwrap().loc.line, 1);
+        assert_eq!(stream.edition(), Edition::One);
+        assert_eq!(
+            run_lisp_to_string("(pale-edition 1) (+ 1 2)", "-").unwrap(),
+            "3"
+        );
+        assert!(run_lisp_to_string("(pale-edition 99) (+ 1 2)", "-").is_err());
+        assert!(run_lisp_to_string("(pale-edition \"one\") (+ 1 2)", "-").is_err());
+        assert_eq!("1".parse::<Edition>(), Ok(Edition::One));
+        assert!("0".parse::<Edition>().is_err());
+    }
+    #[test]
+    fn test_keywords() {
+        assert_eq!(
+            tokenize(":name", "-").unwrap()[0].dat,
+            TokenType::Recognizable(LispType::Keyword("name".to_string()))
+        );
+        assert_eq!(
+            run_lisp_to_string("{:a 1 :b [:c :d] :a 2}", "-").unwrap(),
+            "{:a 2 :b (:c :d)}"
+        );
+        assert!(tokenize(":", "-").is_err());
+        assert!(tokenize("(+ ::a 1)", "-").is_err());
+    }
+    #[test]
+    fn test_read_forms() {
+        let source = "(print [1 2.0 \"three\"]) {* gone *} (let ((x :k)) + 1 $ - 2 3)";
+        let forms = read_forms(source, "-", &Options::default()).unwrap();
+        let printed: Vec<String> = forms.iter().map(|f| f.to_string()).collect();
+        assert_eq!(
+            printed,
+            [
+                "(print (list 1 2.0 \"three\"))",
+                "(let ((x :k)) + 1 (- 2 3))"
+            ]
+        );
+        assert!(read_forms("(+ 1 2))", "-", &Options::default()).is_err());
+    }
+    #[test]
+    fn test_normalized_diagnostics() {
+        let loc = |file: &str, line| Location {
+            filename: file.into(),
+            line,
+            col: 0,
+        };
+        let errs = LispErrors::new()
+            .error(&loc("/home/me/b.pale", 3), "second")
+            .note(&loc("/home/me/a.pale", 1), "a note")
+            .warning(&loc("/home/me/b.pale", 1), "first");
+        let normalized = errs.normalized(&PathTrim::FileName);
+        assert_eq!(normalized[0].message, "first");
+        assert_eq!(normalized[1].message, "second");
+        assert_eq!(&*normalized[1].loc.filename, "b.pale");
+        assert_eq!(
+            normalized[1].notes[0].loc.as_ref().map(|l| &*l.filename),
+            Some("a.pale")
+        );
+        let stripped = errs.normalized(&PathTrim::StripPrefix("/home/me".to_string()));
+        assert_eq!(&*stripped[0].loc.filename, "b.pale");
+        assert_eq!(PathTrim::Keep.apply("/home/me/b.pale"), "/home/me/b.pale");
+    }
+    #[test]
+    fn test_multiple_forms() {
+        assert_eq!(run_lisp_to_string("(+ 1 2) (+ 3 4)", "-").unwrap(), "7");
+        assert_eq!(
+            run_lisp_to_string("(let ((x 5)) + 1 2)\n(+ x 1)\nx", "-").unwrap(),
+            "5"
+        );
+        assert_eq!(run_lisp_to_string(":done", "-").unwrap(), ":done");
+        assert_eq!(run_lisp_to_string("", "-").unwrap(), "nil");
+        assert!(run_lisp_to_string("(+ 1 2) (+ 3", "-").is_err());
+        assert!(run_lisp_to_string("(+ 1 2) y", "-").is_err());
+    }
+    #[test]
+    fn test_session_history() {
+        let mut session = Session::default();
+        assert_eq!(session.eval("*1", "-").unwrap().value, "nil");
+        assert_eq!(session.eval("(+ 1 2)", "-").unwrap().value, "3");
+        assert_eq!(session.eval("(list 4 5)", "-").unwrap().value, "(4 5)");
+        assert_eq!(session.eval(":six", "-").unwrap().value, ":six");
+        assert_eq!(
+            session.eval("[*1 *2 *3]", "-").unwrap().value,
+            "(:six (4 5) 3)"
+        );
+        assert!(session.eval("(+ 1 2", "-").is_err());
+        assert!(session.eval("*e", "-").unwrap().value.contains("Unmatched"));
+        let eval = session.eval("(let ((x 100) (p print)) list)", "-").unwrap();
+        assert_eq!(eval.to_string(), "defined: p (fn), x = 100\n()");
+        let eval = session.eval("(let ((y 1)) + x 1)", "-").unwrap();
+        assert_eq!(eval.to_string(), "defined: y = 1\n101");
+        assert!(session.eval("(+ x y)", "-").unwrap().defined.is_empty());
+        assert!(session.eval("*4", "-").is_err());
+        // How long it took includes waiting, like for a timer.
+        let waited = session.eval("(after 20 (lambda () identity 1))\n(run-events)", "-");
+        assert!(waited.unwrap().elapsed >= Duration::from_millis(20));
+    }
+    #[test]
+    fn test_print_limits() {
+        let mut session = Session::default();
+        session.set_print_limits(PrintLimits {
+            length: Some(3),
+            depth: Some(1),
+        });
+        assert_eq!(session.eval("[1 2 3]", "-").unwrap().value, "(1 2 3)");
+        assert_eq!(
+            session.eval("[1 2 3 4 5]", "-").unwrap().value,
+            "(1 2 3 ... 2 more)"
+        );
+        assert_eq!(session.full(), "(1 2 3 4 5)");
+        assert_eq!(
+            session.eval("[[1] {:a 1}]", "-").unwrap().value,
+            "((...) {...})"
+        );
+        assert_eq!(
+            session.eval("{1 2 3 4 5 6 7 8}", "-").unwrap().value,
+            "{1 2 3 4 5 6 ... 1 more}"
+        );
+        session.set_print_limits(PrintLimits::NONE);
+        assert_eq!(
+            session.eval("[*2 *3]", "-").unwrap().value,
+            "(((1) {:a 1}) (1 2 3 4 5))"
+        );
+    }
+    #[test]
+    fn test_dumps() {
+        let options = Options::default();
+        let forms = read_forms("(+ 1 \"a\tb\") [x 2.5]", "-", &options).unwrap();
+        let mut out = Vec::new();
+        dump_forms(&forms, DumpFormat::Sexpr, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "(+ 1 \"a\tb\")\n(list x 2.5)\n"
+        );
+        let mut out = Vec::new();
+        dump_forms(&forms[..1], DumpFormat::Json, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                r#"[{"kind":"list","file":"-","line":0,"col":0,"items":["#,
+                r#"{"kind":"ident","file":"-","line":0,"col":1,"value":"+"},"#,
+                r#"{"kind":"integer","file":"-","line":0,"col":3,"value":1},"#,
+                r#"{"kind":"string","file":"-","line":0,"col":5,"value":"a\tb"}]}]"#,
+                "\n"
+            )
+        );
+        let tokens = TokenStream::new(":k nil".chars(), "-", &options)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let mut out = Vec::new();
+        dump_tokens(&tokens, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                r#"[{"kind":"keyword-literal","file":"-","line":0,"col":0,"value":":k"},"#,
+                r#"{"kind":"nil","file":"-","line":0,"col":3}]"#,
+                "\n"
+            )
+        );
+        assert!("yaml".parse::<DumpFormat>().is_err());
+    }
+    #[test]
+    fn test_format_source() {
+        let options = FormatOptions::default();
+        let fmt = |s| format_source(s, "-", &options).unwrap();
+        assert_eq!(
+            fmt("(pale-edition 1)  ( +  0x10\t1_000 )"),
+            "(pale-edition 1)\n(+ 0x10 1_000)\n"
+        );
         assert_eq!(
-            *run_lisp(source, "<provided>").unwrap().get(),
-            LispType::Integer(69)
+            fmt("[1 2] // two\n\n\n\n{:a  $ + 1 2}"),
+            "[1 2] // two\n\n{:a $ + 1 2}\n"
+        );
+        assert_eq!(
+            fmt("(print {* why *} \"hi\")"),
+            "(print {* why *}\n  \"hi\")\n"
+        );
+        let long =
+            "(let ((x 100) (y 200)) print (+ x y 300 400 500 600 700 800 900 1000 1100 1200 1300 1400))";
+        let expected = concat!(
+            "(let\n",
+            "  ((x 100) (y 200))\n",
+            "  print\n",
+            "  (+ x y 300 400 500 600 700 800 900 1000 1100 1200 1300 1400))\n"
+        );
+        assert_eq!(fmt(long), expected);
+        assert_eq!(fmt(expected), expected);
+        let narrow = FormatOptions { width: 10 };
+        assert_eq!(
+            format_source("[100 200 300] // end", "-", &narrow).unwrap(),
+            "[100\n 200\n 300] // end\n"
+        );
+        assert!(format_source("(+ 1 2))", "-", &options).is_err());
+    }
+    #[test]
+    fn test_dump_scope() {
+        let mut interpreter = Interpreter::new();
+        run_lisp_in(&mut interpreter, "(let ((x 1) (s \"a b\")))", "-").unwrap();
+        let dump = |format| {
+            let mut out = Vec::new();
+            dump_scope(&interpreter, format, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(dump(DumpFormat::Sexpr), "(s a b)\n(x 1)\n");
+        assert_eq!(
+            dump(DumpFormat::Json),
+            "[{\"name\":\"s\",\"value\":\"a b\"},{\"name\":\"x\",\"value\":\"1\"}]\n"
+        );
+    }
+    #[test]
+    fn test_render_highlighted() {
+        let html = render_highlighted(
+            "(f $ g \"<\") // &",
+            &Options::default(),
+            HighlightFormat::Html,
+        );
+        assert_eq!(
+            html,
+            concat!(
+                "<pre class=\"pale\">",
+                "<span class=\"pale-delimiter pale-depth-0\">(</span>",
+                "<span class=\"pale-identifier\">f</span> ",
+                "<span class=\"pale-delimiter pale-depth-1\">$</span> ",
+                "<span class=\"pale-identifier\">g</span> ",
+                "<span class=\"pale-string\">&quot;&lt;&quot;</span>",
+                "<span class=\"pale-delimiter pale-depth-0\">)</span> ",
+                "<span class=\"pale-comment\">// &amp;</span>",
+                "</pre>",
+            )
+        );
+        let ansi = render_highlighted("[1]", &Options::default(), HighlightFormat::Ansi);
+        assert_eq!(ansi, "\x1b[1;33m[\x1b[0m\x1b[36m1\x1b[0m\x1b[1;33m]\x1b[0m");
+    }
+    #[test]
+    fn test_lint() {
+        let source = concat!(
+            "(let ((f (lambda (a _b) a)) (unused 1) (main (lambda () list))))\n",
+            "(f 1)\n",
+            "(car 1 2)\n",
+            "(if 1 (print 2) (print 3))\n",
+            "(+ 1 . [1])\n",
+            "(list (exit 0) (print 4))\n",
+            "(exit)\n",
+            "(print 5)",
+        );
+        let messages = |config: &LintConfig| -> Vec<(Severity, String)> {
+            lint(source, "-", &Options::default(), config)
+                .diagnostics()
+                .iter()
+                .map(|d| {
+                    (
+                        d.severity,
+                        format!("{}:{} {}", d.loc.line, d.loc.col, d.message),
+                    )
+                })
+                .collect()
+        };
+        assert_eq!(
+            messages(&LintConfig::default()),
+            [
+                (
+                    Severity::Warning,
+                    "0:29 `unused` is never used.".to_string()
+                ),
+                (
+                    Severity::Warning,
+                    "1:1 This function takes 2 argument(s), but is given 1.".to_string()
+                ),
+                (
+                    Severity::Warning,
+                    "2:1 `car` takes at most 1 argument(s), but is given 2.".to_string()
+                ),
+                (
+                    Severity::Warning,
+                    "3:1 The condition of this `if` is always `1`.".to_string()
+                ),
+                (
+                    Severity::Warning,
+                    "5:7 Nothing after this `exit` is ever evaluated.".to_string()
+                ),
+                (
+                    Severity::Warning,
+                    "6:1 Nothing after this `exit` is ever evaluated.".to_string()
+                ),
+            ]
+        );
+        let unreachable = lint(source, "-", &Options::default(), &LintConfig::default());
+        assert!(
+            unreachable
+                .to_string()
+                .contains("-:5:15 - This is never reached."),
+            "{unreachable}"
+        );
+        let config = LintConfig::parse(
+            "# Only arity matters here\nunused-binding = allow\n constant-condition=allow\nsuspicious-arity = deny\nunreachable-code = allow",
+        )
+        .unwrap();
+        let denied = messages(&config);
+        assert_eq!(denied.len(), 2);
+        assert!(denied
+            .iter()
+            .all(|(severity, _)| *severity == Severity::Error));
+        assert_eq!(
+            LintConfig::parse("unused = allow").unwrap_err(),
+            "line 1: Unknown lint `unused`!"
+        );
+        assert!(lint("(print x)", "-", &Options::default(), &config).is_fatal());
+    }
+    #[test]
+    fn test_format_idempotent() {
+        let sources = [
+            "(let ((x 1)) // one\n  {* the\n value *} x)",
+            "(defn f (a b) $ + a b // sum\n)\n\n\n\n(f 1 2)",
+            "[1 {:a 2} #\"x is {(+ 1 2)}\"] {* trailing *}",
+            "(print (list 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27))",
+        ];
+        for source in sources {
+            let once = crate::format(source).unwrap();
+            assert_eq!(crate::format(&once).unwrap(), once, "{source}");
+            for comment in ["// one", "{* the\n value *}", "// sum", "{* trailing *}"] {
+                assert_eq!(source.contains(comment), once.contains(comment), "{source}");
+            }
+        }
+    }
+    #[test]
+    fn test_error_recovery() {
+        let errs = run_lisp_to_string(
+            "(+ 1 undefined)\n(+ 2 3))\n(let ((x 1\n(+ 4 y)\n(+ 5 6)",
+            "-",
+        )
+        .unwrap_err();
+        let messages: Vec<_> = errs
+            .diagnostics()
+            .iter()
+            .map(|d| (d.loc.line, d.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                (1, "Unmatched closing parentheses!"),
+                (2, "Unmatched opening parentheses!"),
+                (0, "Unknown identifier `undefined`!"),
+                (3, "Unknown identifier `y`!"),
+            ]
+        );
+        // Statements at the start of a line are only treated as top-level ones when something is unclosed.
+        assert_eq!(run_lisp_to_string("(+ 1\n(+ 2 3))", "-").unwrap(), "6");
+    }
+    #[test]
+    fn test_dotted_pairs() {
+        assert_eq!(run_lisp_to_string("[1 . 2]", "-").unwrap(), "(1 . 2)");
+        assert_eq!(run_lisp_to_string("[1 2 . 3]", "-").unwrap(), "(1 2 . 3)");
+        assert_eq!(
+            run_lisp_to_string("[1 2 . [3 4]]", "-").unwrap(),
+            "(1 2 3 4)"
+        );
+        assert_eq!(
+            run_lisp_to_string("(cons :a (cons :b :c))", "-").unwrap(),
+            "(:a :b . :c)"
+        );
+        assert_eq!(run_lisp_to_string("(cons 1 [2])", "-").unwrap(), "(1 2)");
+        assert_eq!(
+            run_lisp_to_string("(car [[:a . 1] [:b . 2]])", "-").unwrap(),
+            "(:a . 1)"
+        );
+        assert_eq!(
+            run_lisp_to_string("(cdr (car [[:a . 1]]))", "-").unwrap(),
+            "1"
+        );
+        assert_eq!(run_lisp_to_string("(cdr [1 2 3])", "-").unwrap(), "(2 3)");
+        assert_eq!(run_lisp_to_string("(+ 1 . [2 3])", "-").unwrap(), "6");
+        assert!(run_lisp_to_string("(+ 1 . 2)", "-").is_err());
+        assert!(run_lisp_to_string("[1 . 2 3]", "-").is_err());
+        assert!(run_lisp_to_string("[. 2]", "-").is_err());
+        assert!(run_lisp_to_string("(car [])", "-").is_err());
+        let forms = read_forms("[1 . 2]", "-", &Options::default()).unwrap();
+        assert_eq!(forms[0].to_string(), "(list 1 . 2)");
+    }
+    #[test]
+    fn test_interpolated_strings() {
+        assert_eq!(
+            run_lisp_to_string(
+                "(let ((name \"pale\")) str #\"Hi ${name}, ${(+ 1 $ + 1 1)}${:x}!\")",
+                "-"
+            )
+            .unwrap(),
+            "Hi pale, 3:x!"
+        );
+        assert_eq!(
+            run_lisp_to_string("#\"$5 {x} $ \"", "-").unwrap(),
+            "$5 {x} $ "
+        );
+        assert_eq!(run_lisp_to_string("#\"\"", "-").unwrap(), "");
+        let errs = run_lisp_to_string("#\"a\n ${(+ 1 missing)}\"", "-").unwrap_err();
+        assert_eq!(errs.diagnostics()[0].loc.line, 1);
+        assert_eq!(errs.diagnostics()[0].loc.col, 8);
+        assert!(run_lisp_to_string("#\"${(+ 1 2}\"", "-").is_err());
+        assert!(run_lisp_to_string("#\"${1", "-").is_err());
+        let src = "(print #\"a ${ (+ 1  2) }\")\n";
+        assert_eq!(
+            format_source(src, "-", &FormatOptions::default()).unwrap(),
+            src
+        );
+    }
+    #[test]
+    fn test_source_limits() {
+        let deep = "[".repeat(256) + &"]".repeat(256);
+        assert!(run_lisp_to_string(&deep, "-").is_ok());
+        let errs = run_lisp_to_string(&format!("[{deep}]"), "-").unwrap_err();
+        assert_eq!(
+            errs.diagnostics()[0].message,
+            "Statements are nested too deeply!"
+        );
+        assert_eq!(errs.diagnostics()[0].loc.col, 256);
+        let dollars = "(list".to_string() + &" $ list".repeat(300) + ")";
+        assert!(run_lisp_to_string(&dollars, "-").is_err());
+        let options = Options {
+            limits: SourceLimits {
+                max_depth: 256,
+                max_tokens: 10,
+            },
+            ..Options::default()
+        };
+        assert!(run_lisp_with("(+ 1 2 3 4 5 6)", "-", &options).is_ok());
+        let errs = run_lisp_with("(+ 1 2 3 4 5 6 7 8 9 10)", "-", &options).unwrap_err();
+        assert_eq!(
+            errs.diagnostics()[0].message,
+            "The source has too many tokens!"
+        );
+    }
+    #[test]
+    fn test_readers() {
+        let mut options = Options::default();
+        options
+            .readers
+            .register("date", |text| {
+                let parts: Vec<&str> = text.split('-').collect();
+                match parts[..] {
+                    [y, m, d] => Ok(format!("{{:year {y} :month {m} :day {d}}}")),
+                    _ => Err("dates are written as YYYY-MM-DD".to_string()),
+                }
+            })
+            .register("twice", |text| Ok(format!("[{text} {text}]")))
+            .register("broken", |_| Ok("(+ 1".to_string()));
+        let run = |s| run_lisp_with(s, "-", &options).map(|(res, _)| res.to_string());
+        assert_eq!(
+            run("#date\"2024-01-02\"").unwrap(),
+            "{:year 2024 :month 1 :day 2}"
+        );
+        assert_eq!(
+            run("(cdr #twice\"#date\")")
+                .unwrap_err()
+                .diagnostics()
+                .len(),
+            1
+        );
+        assert_eq!(run("(car #twice\":a\")").unwrap(), ":a");
+        let errs = run("(list\n  #date\"yesterday\")").unwrap_err();
+        assert_eq!(errs.diagnostics()[0].loc.line, 1);
+        assert_eq!(errs.diagnostics()[0].loc.col, 2);
+        assert!(errs.diagnostics()[0].message.contains("YYYY-MM-DD"));
+        assert!(run("#unknown\"x\"").is_err());
+        assert!(run("#broken\"x\"").is_err());
+        assert!(run("#date 1").is_err());
+        let src = "(list #date\"2024-01-02\" 3)\n";
+        assert_eq!(
+            format_source(src, "-", &FormatOptions::default()).unwrap(),
+            src
+        );
+    }
+    #[test]
+    fn test_mismatched_delimiters() {
+        assert!(tokenize("(list [1 2) 3]", "-").is_err());
+        assert!(tokenize("{* a comment *} [1 {\"a\" 2}]", "-").is_ok());
+        assert_eq!(
+            run_lisp_to_string("[1 $ - 489 $ + 34 35]", "<provided>").unwrap(),
+            "(1 420)"
+        );
+    }
+    #[test]
+    fn test_let_expressions() {
+        assert_eq!(
+            run_lisp_to_string("(let ((x (+ 1 2))) + x 1)", "-").unwrap(),
+            "4"
+        );
+        assert_eq!(
+            run_lisp_to_string("(let ((x (list 1 $ + 1 1)) (y 3)) cons y x)", "-").unwrap(),
+            "(3 1 2)"
+        );
+        // Values are worked out before the body, even if it doesn't use them.
+        assert!(run_lisp_to_string("(let ((y (+ 1 :a))) + 1 2)", "-").is_err());
+        assert!(run_lisp_to_string("(let ((x (+ 1 2) 3)) + x 1)", "-").is_err());
+    }
+    #[test]
+    fn test_reload() {
+        let mut session = Session::default();
+        let v1 = "(let ((x 1)) list)\n(let ((y [x])) list)\n(let ((z 3)) list)";
+        assert_eq!(session.reload(v1, "-").unwrap().evaluated, vec![0, 1, 2]);
+        assert!(session.reload(v1, "-").unwrap().evaluated.is_empty());
+        // `y` uses `x`, so it is evaluated again along with it, but `z` isn't.
+        let v2 = "(let ((x 2)) list)\n\n(let ((y [x])) list)\n(let ((z 3)) list)";
+        let reloaded = session.reload(v2, "-").unwrap();
+        assert_eq!(reloaded.evaluated, vec![0, 1]);
+        assert_eq!(reloaded.forms, 3);
+        assert_eq!(session.eval("y", "-").unwrap().value, "(2)");
+        let v3 = "(let ((x 2)) list)\n(let ((y [x])) list)\n(let ((z 4)) list)\n(print z)";
+        assert_eq!(session.reload(v3, "-").unwrap().evaluated, vec![2, 3]);
+        // Taking a form out takes what it defined out with it.
+        session.reload("(let ((x 2)) list)", "-").unwrap();
+        assert!(session.eval("z", "-").is_err());
+        assert!(session.reload("(+ 1 :a)", "-").is_err());
+        assert_eq!(
+            session
+                .reload("(+ 1 :a)", "-")
+                .unwrap_err()
+                .diagnostics()
+                .len(),
+            1
+        );
+        // What the forms from an error on defined is taken out, so they can be evaluated again once it is fixed.
+        let ok = "(let ((a 1)) list)\n(print a)\n(let ((b 2)) list)";
+        session.reload(ok, "-").unwrap();
+        assert!(session
+            .reload("(let ((a 1)) list)\n(+ 1 :a)\n(let ((b 2)) list)", "-")
+            .is_err());
+        assert_eq!(session.reload(ok, "-").unwrap().evaluated, vec![1, 2]);
+        assert_eq!(session.eval("b", "-").unwrap().value, "2");
+        let broken = "(let ((a 2)) list)\n(+ 1 :a)";
+        assert!(session.reload(broken, "-").is_err());
+        assert_eq!(
+            session.reload("(let ((a 2)) list)", "-").unwrap().evaluated,
+            Vec::<usize>::new()
+        );
+    }
+    #[test]
+    fn test_sequential_let() {
+        assert_eq!(
+            run_lisp_to_string("(let ((x 8) (y x)) + x y)", "-").unwrap(),
+            "16"
+        );
+        assert_eq!(
+            run_lisp_to_string("(let ((x (+ 1 2)) (y [x]) (z (car y))) + x z)", "-").unwrap(),
+            "6"
+        );
+        assert_eq!(
+            run_lisp_to_string("(let (x (y x)) list y)", "-").unwrap(),
+            "(nil)"
+        );
+        // A binding can only use the ones before it.
+        assert!(run_lisp_to_string("(let ((y x) (x 8)) + x y)", "-").is_err());
+        assert!(run_lisp_to_string("(let ((x x)) + x 1)", "-").is_err());
+    }
+    #[test]
+    fn test_if_and_lambda() {
+        assert_eq!(run_lisp_to_string("(if nil 1 2)", "-").unwrap(), "2");
+        assert_eq!(run_lisp_to_string("(if [] (+ 1 1))", "-").unwrap(), "2");
+        assert_eq!(
+            run_lisp_to_string("(if (car [nil]) 1)", "-").unwrap(),
+            "nil"
+        );
+        assert!(run_lisp_to_string("(if 1)", "-").is_err());
+        assert_eq!(
+            run_lisp_to_string("(let ((add (lambda (a b) + a b))) add 2 3)", "-").unwrap(),
+            "5"
+        );
+        assert_eq!(
+            run_lisp_to_string("((lambda (x) list x x) 1)", "-").unwrap(),
+            "(1 1)"
+        );
+        assert_eq!(run_lisp_to_string("((lambda () + 1 2))", "-").unwrap(), "3");
+        assert_eq!(
+            run_lisp_to_string(
+                "(let ((f (lambda (x) if x :yes :no))) list (f 1) (f nil))",
+                "-"
+            )
+            .unwrap(),
+            "(:yes :no)"
+        );
+        // The parameters are only in scope in the body.
+        assert!(run_lisp_to_string("(lambda (x) list x)\nx", "-").is_err());
+        assert_eq!(
+            run_lisp_to_string(
+                "(lambda (x) list x)\n(lambda (x) list x x)\n(let ((x 1)))",
+                "-"
+            )
+            .unwrap(),
+            "nil"
+        );
+        let errs = run_lisp_to_string("(let ((f (lambda (x) list x)))\n  f 1 2)", "-").unwrap_err();
+        assert_eq!(errs.diagnostics()[0].loc.line, 1);
+        assert!(run_lisp_to_string("(let ((x 1)) x)", "-").is_err());
+        assert!(run_lisp_to_string("(lambda (1) list)", "-").is_err());
+        assert!(run_lisp_to_string("(lambda (x))", "-").is_err());
+        assert!(run_lisp_to_string("(+ 1 let)", "-").is_err());
+    }
+    #[test]
+    fn test_is_incomplete() {
+        let options = Options::default();
+        for source in [
+            "(+ (1",
+            "[1 2",
+            "\"abc",
+            "#\"${(+ 1",
+            "{* comment",
+            "(list\n",
+        ] {
+            assert!(is_incomplete(source, &options), "{source}");
+        }
+        for source in ["(+ 1 2)", "(+ 1 2))", "$ + 1 2", "// (", "", "#\"${1}\""] {
+            assert!(!is_incomplete(source, &options), "{source}");
+        }
+        let friendly = Options {
+            friendly_parens: true,
+            ..Options::default()
+        };
+        assert!(!is_incomplete("(+ (1", &friendly));
+        assert!(is_incomplete("(print \"abc", &friendly));
+    }
+    #[test]
+    fn test_session_bindings() {
+        let mut session = Session::default();
+        assert!(session.bindings().is_empty());
+        session
+            .eval("(let ((x 1) (f (lambda (a) list a))))", "-")
+            .unwrap();
+        session.eval("(f x)", "-").unwrap();
+        let names: Vec<_> = session
+            .bindings()
+            .into_iter()
+            .map(|d| d.to_string())
+            .collect();
+        assert_eq!(names, ["f (fn)", "x = 1"]);
+        session.set_print_limits(PrintLimits::NONE);
+        session.reset();
+        assert!(session.bindings().is_empty());
+        assert!(session.eval("x", "-").is_err());
+        assert_eq!(session.print_limits(), PrintLimits::NONE);
+    }
+    #[test]
+    fn test_interpreter() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval("(let ((double (lambda (x) + x x))))", "setup")
+            .unwrap();
+        let res = interpreter.eval("(double 21)", "-").unwrap();
+        assert_eq!(res.to_string(), "42");
+        interpreter.set_global("answer", res);
+        assert_eq!(
+            interpreter
+                .eval("(double answer)", "-")
+                .unwrap()
+                .to_string(),
+            "84"
+        );
+        assert_eq!(interpreter.get("answer").unwrap().to_string(), "42");
+        assert!(interpreter.get("missing").is_none());
+        assert!(interpreter.names().any(|n| n == "double"));
+        assert!(interpreter.eval("(double", "-").is_err());
+        assert!(interpreter.warnings().is_empty());
+        let configured =
+            Interpreter::new().with_globals([("config-path", "/etc/pale"), ("mode", "test")]);
+        assert_eq!(
+            configured.get("config-path").unwrap(),
+            Value::from("/etc/pale")
+        );
+        assert!(configured.get("mode").is_some());
+    }
+    #[test]
+    fn test_register_fn() {
+        let mut interpreter = Interpreter::new();
+        interpreter.register_fn("second", |args| match args {
+            [_, second, ..] => Ok(second.clone()),
+            _ => Err("it needs two arguments".to_string()),
+        });
+        assert_eq!(
+            interpreter
+                .eval("(second 1 [2 3])", "-")
+                .unwrap()
+                .to_string(),
+            "(2 3)"
+        );
+        let err = interpreter.eval("(second 1)", "-").unwrap_err();
+        assert_eq!(
+            err.diagnostics()[0].message,
+            "`second` failed: it needs two arguments"
+        );
+    }
+    #[test]
+    fn test_value_conversions() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_global("xs", vec![Value::from(1), "two".into(), 3.5.into()]);
+        interpreter.set_global("missing", None::<i64>);
+        let xs = interpreter.eval("xs", "-").unwrap();
+        assert_eq!(xs.to_string(), "(1 two 3.5)");
+        let items: Vec<Value> = xs.try_into().unwrap();
+        assert_eq!(i64::try_from(items[0].clone()), Ok(1));
+        assert_eq!(String::try_from(items[1].clone()).unwrap(), "two");
+        assert_eq!(f64::try_from(items[2].clone()), Ok(3.5));
+        assert_eq!(
+            i64::try_from(items[1].clone()).unwrap_err().to_string(),
+            "expected an integer, but found `two`"
+        );
+        assert!(interpreter.get("missing").unwrap().is_nil());
+        assert_eq!(run_lisp("(+ 1 2)", "-").unwrap(), Value::from(3));
+        assert_eq!(
+            interpreter.eval("(list 1 :a)", "-").unwrap(),
+            Value::from(vec![1.into(), Value::keyword("a")])
+        );
+    }
+    #[test]
+    fn test_lisp_conversions() {
+        use crate::{FromLisp, IntoLisp};
+        let xs = vec![Some(1), None].into_lisp();
+        assert_eq!(xs.to_string(), "(1 nil)");
+        assert_eq!(Vec::<Option<i32>>::from_lisp(xs), Ok(vec![Some(1), None]));
+        assert!(i32::from_lisp(Value::from(1i64 << 40)).is_err());
+        assert!(Vec::<i64>::from_lisp(Value::from("no")).is_err());
+    }
+    #[cfg(all(feature = "serde", feature = "json"))]
+    #[test]
+    fn test_serde_values() {
+        let value: Value =
+            serde_json::from_str(r#"{"name": "pale", "tags": [1, 2.5, true, false, null]}"#)
+                .unwrap();
+        assert_eq!(value.to_string(), "{name pale tags (1 2.5 :true nil nil)}");
+        let mut interpreter = Interpreter::new();
+        interpreter.set_global("config", value);
+        let value = interpreter.eval("(list config :key 3)", "-").unwrap();
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            r#"[{"name":"pale","tags":[1,2.5,true,null,null]},"key",3]"#
+        );
+        let pair = interpreter.eval("(cons 1 2)", "-").unwrap();
+        let err = serde_json::to_string(&pair).unwrap_err();
+        assert!(err.to_string().contains("can't be serialized"), "{err}");
+    }
+    #[cfg(feature = "derive")]
+    #[test]
+    fn test_derive_lisp_conversions() {
+        use crate::{FromLisp, IntoLisp};
+        #[derive(Debug, PartialEq, IntoLisp, FromLisp)]
+        struct Server {
+            host: String,
+            port_number: i64,
+            aliases: Vec<String>,
+            timeout: Option<f64>,
+        }
+        let server = Server {
+            host: "localhost".to_string(),
+            port_number: 8080,
+            aliases: vec!["home".to_string()],
+            timeout: None,
+        };
+        let mut interpreter = Interpreter::new();
+        interpreter.set_global("server", server);
+        let value = interpreter
+            .eval(
+                "{:host \"example.com\" :port-number 80 :aliases [] :timeout 1.5}",
+                "-",
+            )
+            .unwrap();
+        assert_eq!(
+            Server::from_lisp(value),
+            Ok(Server {
+                host: "example.com".to_string(),
+                port_number: 80,
+                aliases: Vec::new(),
+                timeout: Some(1.5),
+            })
+        );
+        let round_trip = Server::from_lisp(interpreter.get("server").unwrap()).unwrap();
+        assert_eq!(round_trip.port_number, 8080);
+        assert_eq!(round_trip.timeout, None);
+        let err = Server::from_lisp(interpreter.eval("{:host 1}", "-").unwrap()).unwrap_err();
+        assert_eq!(err.expected, "a string");
+    }
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_sync() {
+        let out = Shared::new(Mutable::new(Vec::new()));
+        let mut interpreter = Interpreter::with_options(Options {
+            on_output: Output::shared(Shared::clone(&out)),
+            ..Options::default()
+        });
+        interpreter
+            .eval("(let ((double (lambda (x) + x x))))", "setup")
+            .unwrap();
+        interpreter.register_fn("host-inc", |args| match args {
+            [n] => Ok(Value::from(
+                i64::try_from(n.clone()).map_err(|e| e.to_string())? + 1,
+            )),
+            _ => Err("takes one argument".to_string()),
+        });
+        // The interpreter, and the values it gives, can be moved to another thread and used there.
+        let (mut interpreter, value) = std::thread::spawn(move || {
+            let value = interpreter.eval("(print (host-inc (double 20)))", "-");
+            (interpreter, value.unwrap())
+        })
+        .join()
+        .unwrap();
+        assert_eq!(value.to_string(), "0");
+        assert_eq!(*exclusive(&out), b"41\n");
+        assert!(live_values() > 0);
+        let back = interpreter.eval("(double 1)", "-").unwrap();
+        assert_eq!(back.to_string(), "2");
+    }
+    #[test]
+    fn test_eval_async() {
+        use std::future::Future;
+        use std::task::{Context, Poll, Waker};
+        let out = Shared::new(Mutable::new(Vec::new()));
+        let mut interpreter = Interpreter::with_options(Options {
+            on_output: Output::shared(Shared::clone(&out)),
+            ..Options::default()
+        });
+        let mut cx = Context::from_waker(Waker::noop());
+        // Each poll evaluates one top-level form, so the host can do other work in between.
+        let mut evaluation =
+            interpreter.eval_async("(let ((x 20))) (print :one) (print :two) (+ x 22)", "-");
+        let mut polls = 1;
+        assert!(std::pin::Pin::new(&mut evaluation)
+            .poll(&mut cx)
+            .is_pending());
+        assert!(exclusive(&out).is_empty());
+        let value = loop {
+            match std::pin::Pin::new(&mut evaluation).poll(&mut cx) {
+                Poll::Ready(value) => break value,
+                Poll::Pending => polls += 1,
+            }
+            if polls == 2 {
+                assert_eq!(*exclusive(&out), b":one\n");
+            }
+        };
+        assert_eq!(polls, 3);
+        assert_eq!(value.unwrap().to_string(), "42");
+        assert_eq!(interpreter.get("x").unwrap().to_string(), "20");
+        let mut block_on = |source| {
+            let mut evaluation = interpreter.eval_async(source, "-");
+            loop {
+                if let Poll::Ready(value) = std::pin::Pin::new(&mut evaluation).poll(&mut cx) {
+                    break value;
+                }
+            }
+        };
+        assert_eq!(block_on("").unwrap().to_string(), "nil");
+        assert!(block_on("(print").is_err());
+        assert!(block_on("(car 1) (print :never)").is_err());
+        assert_eq!(*exclusive(&out), b":one\n:two\n");
+        // A single form that takes a long time is spread over several polls too.
+        let mut evaluation = interpreter.eval_async("(do ((i 0 (+ i 1))) ((eqv? i 50000) i))", "-");
+        let mut polls = 1;
+        let value = loop {
+            match std::pin::Pin::new(&mut evaluation).poll(&mut cx) {
+                Poll::Ready(value) => break value,
+                Poll::Pending => polls += 1,
+            }
+        };
+        assert_eq!(value.unwrap().to_string(), "50000");
+        assert!(polls > 1, "{polls}");
+        // The limits are for the whole of the source, not each form.
+        let mut limited = Interpreter::with_options(Options {
+            eval_limits: EvalLimits {
+                max_steps: Some(5),
+                ..EvalLimits::default()
+            },
+            ..Options::default()
+        });
+        let mut evaluation =
+            limited.eval_async("(+ 1 1) (+ 1 1) (+ 1 1) (+ 1 1) (+ 1 1) (+ 1 1)", "-");
+        let err = loop {
+            if let Poll::Ready(value) = std::pin::Pin::new(&mut evaluation).poll(&mut cx) {
+                break value.unwrap_err();
+            }
+        };
+        assert_eq!(err.exceeded(), Some(LimitExceeded::Steps(5)));
+    }
+    #[test]
+    fn test_eval_resumable() {
+        let out = Shared::new(Mutable::new(Vec::new()));
+        let mut interpreter = Interpreter::with_options(Options {
+            on_output: Output::shared(Shared::clone(&out)),
+            ..Options::default()
+        });
+        let finish = |mut resumable, steps| {
+            let mut frames = 1;
+            loop {
+                match resumable {
+                    Resumable::Done(value) => break (value, frames),
+                    Resumable::Paused(continuation) => {
+                        resumable = continuation.resume(steps).unwrap();
+                        frames += 1;
+                    }
+                }
+            }
+        };
+        let first = interpreter.eval_resumable("(+ 1 2)", "-", 10).unwrap();
+        assert!(matches!(first, Resumable::Done(ref value) if value.to_string() == "3"));
+        // It stops in the middle of the calls, and carries on with the same variables.
+        let source = "(let ((count (lambda (n) if (eqv? n 0) nil (list (print n) (count (- n 1))))))) (count 3) :done";
+        let Resumable::Paused(paused) = interpreter.eval_resumable(source, "-", 3).unwrap() else {
+            panic!("it should have run out of steps");
+        };
+        assert_eq!(*exclusive(&out), b"3\n");
+        // The interpreter can still be used while it is paused.
+        assert_eq!(interpreter.eval("(+ 2 2)", "-").unwrap().to_string(), "4");
+        let (value, frames) = finish(paused.resume(3).unwrap(), 3);
+        assert_eq!(value.to_string(), ":done");
+        assert!(frames > 1, "{frames}");
+        assert_eq!(*exclusive(&out), b"3\n2\n1\n");
+        let looped = interpreter
+            .eval_resumable("(do ((i 0 (+ i 1))) ((eqv? i 100) i))", "-", 10)
+            .unwrap();
+        let (value, frames) = finish(looped, 10);
+        assert_eq!(value.to_string(), "100");
+        assert!(frames >= 20, "{frames}");
+        // Parameters keep their values across the pauses, but only for the paused evaluation.
+        interpreter
+            .eval("(let ((p (make-parameter 1))))", "-")
+            .unwrap();
+        let source = "(parameterize ((p 2)) do ((i 0 (+ i 1))) ((eqv? i 10) (p)))";
+        let Resumable::Paused(paused) = interpreter.eval_resumable(source, "-", 2).unwrap() else {
+            panic!("it should have run out of steps");
+        };
+        assert_eq!(interpreter.eval("(p)", "-").unwrap().to_string(), "1");
+        assert_eq!(finish(paused.resume(2).unwrap(), 2).0.to_string(), "2");
+        let Resumable::Paused(paused) = interpreter
+            .eval_resumable("(+ 1 1) (car 1)", "-", 1)
+            .unwrap()
+        else {
+            panic!("it should have run out of steps");
+        };
+        assert!(paused.resume(5).is_err());
+        assert!(interpreter.eval_resumable("(print", "-", 5).is_err());
+        // The limits are for the whole evaluation, not each resume.
+        let mut limited = Interpreter::with_options(Options {
+            eval_limits: EvalLimits {
+                max_steps: Some(50),
+                ..EvalLimits::default()
+            },
+            ..Options::default()
+        });
+        let mut resumable = limited
+            .eval_resumable("(do ((i 0 (+ i 1))) ((eqv? i 100) i))", "-", 10)
+            .unwrap();
+        let err = loop {
+            match resumable {
+                Resumable::Done(_) => panic!("it should have run out of its limit"),
+                Resumable::Paused(paused) => match paused.resume(10) {
+                    Ok(next) => resumable = next,
+                    Err(err) => break err,
+                },
+            }
+        };
+        assert_eq!(err.exceeded(), Some(LimitExceeded::Steps(50)));
+    }
+    #[test]
+    fn test_profile() {
+        assert!(Interpreter::new().profile().is_none());
+        let source = "(let ((fib (lambda (n) if (eqv? n 0) 0 (if (eqv? n 1) 1 (+ (fib (- n 1)) (fib (- n 2)))))))) list (fib 10) (pmap (lambda (x) * x x) [1 2 3])";
+        for compiled in [false, true] {
+            let profiler = Profiler::default();
+            let mut interpreter = Interpreter::with_options(Options {
+                profiler: Some(profiler.clone()),
+                vm: compiled,
+                ..Options::default()
+            });
+            interpreter.eval(source, "-").unwrap();
+            let profile = interpreter.profile().unwrap();
+            assert_eq!(profile, profiler.profile());
+            let entry = |name: &str| {
+                profile
+                    .entries
+                    .iter()
+                    .find(|e| e.name == name)
+                    .unwrap_or_else(|| panic!("{name} wasn't profiled:\n{profile}"))
+            };
+            let fib = entry("fib");
+            assert_eq!(fib.calls, 177);
+            assert!(fib.location.is_some());
+            assert!(fib.self_time <= fib.total_time);
+            // Each call is only counted once in the total, however deeply it recurses.
+            let outside: Duration = profile.entries.iter().map(|e| e.self_time).sum();
+            assert!(fib.total_time <= outside, "{profile}");
+            assert_eq!(entry("eqv?").calls, 320);
+            assert_eq!(entry("pmap").calls, 1);
+            // The function that `pmap` calls is counted too.
+            assert_eq!(entry("lambda").calls, 3);
+            assert_eq!(entry("*").calls, 3);
+            assert!(profile.to_string().starts_with("function"));
+            // A call that fails is left out, and doesn't throw off the ones after it.
+            profiler.reset();
+            let err = interpreter.eval("(let ((f (lambda () car 1)))) (f)", "-");
+            assert!(err.is_err());
+            interpreter.eval("(inc 1)", "-").unwrap();
+            let profile = profiler.profile();
+            let names: Vec<_> = profile
+                .entries
+                .iter()
+                .map(|e| (e.name.as_str(), e.calls))
+                .collect();
+            assert!(
+                names.contains(&("inc", 1)) && names.contains(&("+", 1)),
+                "{profile}"
+            );
+            assert!(!names.iter().any(|(name, _)| *name == "f"), "{profile}");
+        }
+    }
+    #[test]
+    fn test_timers() {
+        let out = Shared::new(Mutable::new(Vec::new()));
+        let mut interpreter = Interpreter::with_options(Options {
+            on_output: Output::shared(Shared::clone(&out)),
+            ..Options::default()
+        });
+        let mut eval = |source| interpreter.eval(source, "-").map(|v| v.to_string());
+        // A timer can cancel another one, or set one of its own.
+        let source = "(let ((tick (every 10 (lambda () print :tick))))) (after 25 (lambda () print :later)) (after 35 (lambda () cancel-timer tick)) (after 0 (lambda () after 1 (lambda () print :nested))) (run-events)";
+        assert_eq!(eval(source).unwrap(), "nil");
+        assert_eq!(
+            String::from_utf8(exclusive(&out).clone()).unwrap(),
+            ":nested\n:tick\n:tick\n:later\n:tick\n"
+        );
+        assert_eq!(eval("(cancel-timer tick)").unwrap(), "nil");
+        let err = eval("(after 0 (lambda () car 1)) (run-events)").unwrap_err();
+        assert!(err.to_string().contains("timer set here"), "{err}");
+        assert!(eval("(every 0 (lambda () identity 1))").is_err());
+        assert!(eval("(after -5 (lambda () identity 1))").is_err());
+        assert!(eval("(after (duration :seconds 1) 1)").is_err());
+        // A host can call the timers that are due itself instead.
+        exclusive(&out).clear();
+        interpreter
+            .eval("(after 0 (lambda () print :now)) (let ((later (after 100000 (lambda () print :never)))))", "-")
+            .unwrap();
+        assert!(interpreter.next_event().is_some());
+        assert_eq!(interpreter.run_due_events().unwrap(), 1);
+        assert_eq!(*exclusive(&out), b":now\n");
+        assert_eq!(
+            interpreter
+                .eval("(cancel-timer later)", "-")
+                .unwrap()
+                .to_string(),
+            ":true"
+        );
+        assert!(interpreter.next_event().is_none());
+        // Waiting for a timer still stops at the time limit.
+        let mut limited = Interpreter::with_options(Options {
+            eval_limits: EvalLimits {
+                timeout: Some(Duration::from_millis(20)),
+                ..EvalLimits::default()
+            },
+            ..Options::default()
+        });
+        let err = limited
+            .eval("(after 100000 (lambda () identity 1)) (run-events)", "-")
+            .unwrap_err();
+        assert!(
+            matches!(err.exceeded(), Some(LimitExceeded::Time(_))),
+            "{err}"
+        );
+    }
+    #[test]
+    fn test_channels() {
+        let run = |source| run_lisp_to_string(source, "-");
+        assert_eq!(
+            run("(let ((c (channel))) list (send c 1) (send c [2]) (recv c) (recv c) (close c) (recv c))")
+                .unwrap(),
+            "(1 (2) 1 (2) nil nil)"
+        );
+        assert_eq!(run("(channel? (channel))").unwrap(), ":true");
+        let err = run("(let ((c (channel))) list (close c) (send c 1))").unwrap_err();
+        assert!(err.to_string().contains("closed"), "{err}");
+        let err = run("(recv 1)").unwrap_err();
+        assert!(err.to_string().contains("a socket or a channel"), "{err}");
+        // Nothing else could send to it, so waiting would never end.
+        #[cfg(not(feature = "sync"))]
+        {
+            let err = run("(recv (channel))").unwrap_err();
+            assert!(err.to_string().contains("wait forever"), "{err}");
+        }
+        // Channels don't use the network, so they are still there without it.
+        let mut sandboxed = Interpreter::new().deny(Capability::Network);
+        let got = sandboxed.eval("(let ((c (channel))) list (send c 1) (recv c))", "-");
+        assert_eq!(got.unwrap().to_string(), "(1 1)");
+    }
+    #[test]
+    fn test_pmap() {
+        let run = |source: &str| run_lisp_to_string(source, "-");
+        assert_eq!(
+            run("(pmap (lambda (x) * x x) [1 2 3 4])").unwrap(),
+            "(1 4 9 16)"
+        );
+        assert_eq!(run("(pmap car [[1 2] [3]])").unwrap(), "(1 3)");
+        assert_eq!(run("(pmap inc nil)").unwrap(), "nil");
+        let many: Vec<String> = (0..200).map(|i| i.to_string()).collect();
+        let doubled: Vec<String> = (0..200).map(|i| (2 * i).to_string()).collect();
+        let source = format!("(pmap (lambda (x) + x x) [{}])", many.join(" "));
+        assert_eq!(run(&source).unwrap(), format!("({})", doubled.join(" ")));
+        // The error is from the first item that fails, whichever thread got to it.
+        let err = run("(pmap (lambda (x) car x) [[1] 2 3])").unwrap_err();
+        assert!(err.to_string().contains("given `2`"), "{err}");
+        let err = run("(pmap print [1])").unwrap_err();
+        assert!(err.to_string().contains("a pure function"), "{err}");
+        let err = run("(pmap (lambda (x) print x) [1 2])").unwrap_err();
+        assert!(err.to_string().contains("Only pure functions"), "{err}");
+        // What it calls is checked, however deeply.
+        let err = run("(let ((shout (lambda (x) print x)))) (pmap (lambda (x) shout x) [1])");
+        assert!(err.is_err());
+        let mut interpreter = Interpreter::new();
+        interpreter.register_fn("host", |_| Ok(Value::from(1)));
+        assert!(interpreter.eval("(pmap host [1])", "-").is_err());
+    }
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_spawn() {
+        let out = Shared::new(Mutable::new(Vec::new()));
+        let mut interpreter = Interpreter::with_options(Options {
+            on_output: Output::shared(Shared::clone(&out)),
+            ..Options::default()
+        });
+        let mut eval = |source| interpreter.eval(source, "-").map(|v| v.to_string());
+        eval("(let ((double (lambda (x) + x x))))").unwrap();
+        assert_eq!(eval("(recv (spawn (lambda () double 21)))").unwrap(), "42");
+        // A worker sends to a channel while this thread receives from it.
+        assert_eq!(
+            eval(
+                "(let ((c (channel))) let ((done (spawn (lambda () list (send c 1) (send c 2) (close c))))) list (recv c) (recv c) (recv c) (recv done))"
+            )
+            .unwrap(),
+            "(1 2 nil (1 2 nil))"
+        );
+        // Threads print to the same place as the code that spawned them.
+        eval("(recv (spawn (lambda () print :hi)))").unwrap();
+        assert_eq!(*exclusive(&out), b":hi\n");
+        let err = eval("(recv (spawn (lambda () car 1)))").unwrap_err();
+        assert!(err.to_string().contains("spawned here"), "{err}");
+        let err = eval("(spawn 1)").unwrap_err();
+        assert!(err.to_string().contains("needs a function"), "{err}");
+    }
+    #[test]
+    fn test_output_hook() {
+        let printed = Shared::new(Mutable::new(String::new()));
+        let sink = Shared::clone(&printed);
+        let options = Options {
+            on_output: Output::new(move |text| exclusive(&sink).push_str(text)),
+            ..Options::default()
+        };
+        let mut interpreter = Interpreter::with_options(options.clone());
+        interpreter
+            .eval("(let ((shout (lambda (x) print x)))) (shout :hi)", "-")
+            .unwrap();
+        interpreter.eval("(print [1 2])", "-").unwrap();
+        run_lisp_with("(print \"done\")", "-", &options).unwrap();
+        assert_eq!(*exclusive(&printed), ":hi\n(1 2)\ndone\n");
+    }
+    #[test]
+    fn test_streams() {
+        let out = Shared::new(Mutable::new(Vec::new()));
+        let err = Shared::new(Mutable::new(Vec::new()));
+        let options = Options {
+            on_output: Output::shared(Shared::clone(&out)),
+            on_error_output: Output::shared(Shared::clone(&err)),
+            input: Input::reader("first\r\nsecond".as_bytes()),
+            ..Options::default()
+        };
+        let mut interpreter = Interpreter::with_options(options);
+        interpreter
+            .eval("(print (read-line)) (eprint (read-line))", "-")
+            .unwrap();
+        assert!(interpreter.eval("(read-line)", "-").unwrap().is_nil());
+        assert_eq!(*exclusive(&out), b"first\n");
+        assert_eq!(*exclusive(&err), b"second\n");
+        assert!(interpreter.eval("(read-line 1)", "-").is_err());
+    }
+    #[test]
+    fn test_ports() {
+        let run = |source: &str| run_lisp_to_string(source, "-").unwrap();
+        assert_eq!(
+            run(
+                r#"(let ((p (open-input-string "hé!"))) list (read-char p) (read-char p) (read-char p) (read-char p))"#
+            ),
+            "(h é ! nil)"
+        );
+        assert_eq!(
+            run("(let ((p (open-output-string))) do ((i 0 (+ i 1))) ((eqv? i 3) (get-output-string p)) write-string (int->string i) p)"),
+            "012"
+        );
+        let dir = TempDir::new("ports");
+        let path = dir.join("out.txt").display().to_string();
+        let write = format!(
+            r#"(let ((p (open-output-file "{path}"))) list (write-string "ok" p) (close-port p))"#
+        );
+        run(&write);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "ok");
+        let read = format!(r#"(let ((p (open-input-file "{path}"))) list (read-char p) p)"#);
+        assert_eq!(run(&read), format!("(o <Port input {path}>)"));
+        // The current ports share the interpreter's streams with `read-line` and `print`.
+        let out = Shared::new(Mutable::new(Vec::new()));
+        let options = Options {
+            on_output: Output::shared(Shared::clone(&out)),
+            input: Input::reader("ab\ncd".as_bytes()),
+            ..Options::default()
+        };
+        let mut interpreter = Interpreter::with_options(options);
+        interpreter
+            .eval(
+                "(write-string (read-char (current-input-port)) (current-output-port)) (print (read-line))",
+                "-",
+            )
+            .unwrap();
+        assert_eq!(*exclusive(&out), b"ab\n");
+        for (source, message) in [
+            (
+                "(let ((p (open-output-string))) list (close-port p) (write-string \"x\" p))",
+                "`write-string` can't use `<Port output string>`, which has been closed",
+            ),
+            ("(read-char (open-output-string))", "which is for output"),
+            ("(read-char \"x\")", "`read-char` needs a port"),
+        ] {
+            let err = run_lisp_to_string(source, "-").unwrap_err();
+            assert!(err.to_string().contains(message), "{err}");
+        }
+        let mut sandboxed = Interpreter::new().deny(Capability::Files);
+        assert!(sandboxed.eval(r#"(open-input-file "x")"#, "-").is_err());
+    }
+    #[test]
+    fn test_dates() {
+        let run = |source: &str| run_lisp_to_string(source, "-").unwrap();
+        assert_eq!(run("(date 2024 3 1 9 30)"), "2024-03-01T09:30:00Z");
+        assert_eq!(run("(type-of (now))"), ":date");
+        assert_eq!(run("(duration? (duration :days 1))"), ":true");
+        // Across a leap day, and across the end of a year.
+        assert_eq!(
+            run("(+ (date 2024 2 28) (duration :days 1))"),
+            "2024-02-29T00:00:00Z"
+        );
+        assert_eq!(
+            run("(+ (duration :hours 2) (date 2023 12 31 23))"),
+            "2024-01-01T01:00:00Z"
+        );
+        assert_eq!(
+            run("(- (date 2024 3 1) (duration :weeks 1) (duration :days 1))"),
+            "2024-02-22T00:00:00Z"
+        );
+        assert_eq!(run("(- (date 2024 3 1) (date 2024 1 1))"), "60d");
+        assert_eq!(
+            run("(duration :hours 1 :minutes 30 :milliseconds 500)"),
+            "1h30m0.5s"
+        );
+        assert_eq!(
+            run("(- (duration :minutes 1) (duration :minutes 2))"),
+            "-1m"
+        );
+        assert_eq!(run("(duration->seconds (duration :minutes 2))"), "120");
+        assert_eq!(
+            run(r#"(parse-date "2024-03-01T09:30:00.25+02:00")"#),
+            "2024-03-01T07:30:00.250Z"
+        );
+        assert_eq!(
+            run(r#"(equal? (parse-date "2024-03-01") (date 2024 3 1))"#),
+            ":true"
+        );
+        assert_eq!(
+            run(r#"(format-date (date 2024 3 1 9 5 7) "%a %d %b %Y, %T (day %j) 100%%")"#),
+            "Fri 01 Mar 2024, 09:05:07 (day 061) 100%"
+        );
+        assert_eq!(
+            run("(date-parts (date 2024 3 1 9 30))"),
+            "{:year 2024 :month 3 :day 1 :hour 9 :minute 30 :second 0 :millisecond 0 :weekday :friday}"
+        );
+        assert_eq!(run("(date->epoch (date 1969 12 31 23 59 59))"), "-1");
+        assert_eq!(run("(epoch->date 86400)"), "1970-01-02T00:00:00Z");
+        for (source, message) in [
+            ("(date 2023 2 29)", "February 2023 only has 28 days"),
+            (r#"(parse-date "2024-3-1")"#, "the month should be 2 digits"),
+            (
+                r#"(parse-date "2024-03-01T09:30Q")"#,
+                "it has `Q` after the end",
+            ),
+            ("(+ (now) (now))", "Can't add two dates together"),
+            ("(+ (now) 1)", "Incompatible types for addition"),
+            (
+                "(- (duration :days 1) (now))",
+                "Can't subtract a date from a duration",
+            ),
+            (
+                "(duration :fortnights 1)",
+                "`duration` has no `:fortnights` unit",
+            ),
+            (
+                r#"(format-date (now) "%Q")"#,
+                "`%Q` can't be used to format a date",
+            ),
+            ("(format-date 1)", "`format-date` needs a date"),
+        ] {
+            let err = run_lisp_to_string(source, "-").unwrap_err();
+            assert!(err.to_string().contains(message), "{err}");
+        }
+    }
+    #[test]
+    fn test_eval_limits() {
+        let limited = |eval_limits| Options {
+            eval_limits,
+            ..Options::default()
+        };
+        let steps = limited(EvalLimits {
+            max_steps: Some(2),
+            ..EvalLimits::default()
+        });
+        assert_eq!(
+            run_lisp_with("(+ 1 (+ 2 3))", "-", &steps)
+                .unwrap()
+                .0
+                .to_string(),
+            "6"
+        );
+        let errs = run_lisp_with("(+ 1 (+ 2 3))\n(list 1)", "-", &steps).unwrap_err();
+        assert_eq!(errs.exceeded(), Some(LimitExceeded::Steps(2)));
+        assert_eq!(errs.diagnostics()[0].loc.line, 1);
+
+        let depth = limited(EvalLimits {
+            max_depth: Some(2),
+            ..EvalLimits::default()
+        });
+        assert!(run_lisp_with("((lambda () list 1))", "-", &depth).is_ok());
+        let errs = run_lisp_with("((lambda () ((lambda () + 1 2))))", "-", &depth).unwrap_err();
+        assert_eq!(errs.exceeded(), Some(LimitExceeded::Depth(2)));
+        assert_eq!(errs.diagnostics()[0].loc.col, 24);
+        // A function that an intrinsic calls is a call in progress too, like one that the evaluator makes.
+        let timer = "(after 0 (lambda () list 1))\n(run-events)";
+        let errs = run_lisp_with(timer, "-", &depth).unwrap_err();
+        assert_eq!(errs.exceeded(), Some(LimitExceeded::Depth(2)));
+
+        let timeout = limited(EvalLimits {
+            timeout: Some(Duration::ZERO),
+            ..EvalLimits::default()
+        });
+        let errs = run_lisp_with("(list 1)", "-", &timeout).unwrap_err();
+        assert_eq!(errs.exceeded(), Some(LimitExceeded::Time(Duration::ZERO)));
+        assert_eq!(run_lisp_to_string("(+ 1 2)", "-").unwrap(), "3");
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let forever = "(+ 1 2)\n(do ((i 0 (+ i 1))) ((eqv? i -1) i))";
+        for compiled in [false, true] {
+            let mut interpreter = Interpreter::new();
+            let handle = interpreter.interrupt_handle();
+            let interrupter = std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(50));
+                handle.interrupt();
+            });
+            let errs = match compiled {
+                true => interpreter.eval_compiled(forever, "-"),
+                false => interpreter.eval(forever, "-"),
+            }
+            .unwrap_err();
+            interrupter.join().unwrap();
+            assert_eq!(errs.exceeded(), Some(LimitExceeded::Interrupted));
+            assert_eq!(errs.diagnostics()[0].loc.line, 1);
+            // An interrupt only stops the evaluation that is running when it comes.
+            interpreter.interrupt_handle().interrupt();
+            assert_eq!(interpreter.eval("(+ 1 2)", "-").unwrap().to_string(), "3");
+        }
+    }
+
+    #[test]
+    fn test_deep_calls() {
+        // Each function calls the one before it, so the calls are nested far more deeply than the source is.
+        let calls = 20000;
+        let mut source = "(let ((f0 (lambda () + 1 2))".to_string();
+        for i in 1..calls {
+            source.push_str(&format!("\n(f{i} (lambda () f{}))", i - 1));
+        }
+        source.push_str(&format!(") f{})", calls - 1));
+        assert_eq!(run_lisp_to_string(&source, "-").unwrap(), "3");
+        let compiled = Interpreter::new().eval_compiled(&source, "-").unwrap();
+        assert_eq!(compiled.to_string(), "3");
+        // Data can be nested far more deeply than calls, and can still be printed.
+        let deep = "(do ((i 0 (+ i 1)) (l nil (cons l nil))) ((eqv? i 200000) (str (car l))))";
+        let printed = run_lisp_to_string(deep, "-").unwrap();
+        let depth = 199999;
+        assert_eq!(
+            printed,
+            format!("{}nil{}", "(".repeat(depth), ")".repeat(depth))
+        );
+        let shallow = "(do ((i 0 (+ i 1)) (l nil (cons l nil))) ((eqv? i 3) (str l)))";
+        assert_eq!(run_lisp_to_string(shallow, "-").unwrap(), "(((nil)))");
+    }
+
+    #[test]
+    fn test_vm() {
+        let sources = [
+            "(+ 1 2 (* 3 4))",
+            "(let ((x 1) (y (+ x 1))) list x y)",
+            "(let ((add (lambda (a b) + a b))) add 2 3)",
+            "(let ((f (lambda (x) if x :yes :no))) list (f 1) (f nil))",
+            "(if nil 1)",
+            "((lambda (x) list x x) 1)",
+            "[1 2 . 3]\n(list 1 . [2 3])",
+            "(let ((f (lambda (a b) list b a))) f 1 . [2])",
+            "{:a 1 :b [2 3]}",
+            "(let ((f (lambda (x) list x)))\n  f 1 2)",
+            "(car [])",
+            "(1 2)",
+            "(let ((x 1)))",
+            "",
+        ];
+        for source in sources {
+            let walked = Interpreter::new().eval(source, "-");
+            let compiled = Interpreter::new().eval_compiled(source, "-");
+            assert_eq!(
+                walked.map(|v| v.to_string()),
+                compiled.map(|v| v.to_string()),
+                "{source}"
+            );
+        }
+
+        let vm = Options {
+            vm: true,
+            call_main: true,
+            main_args: vec!["a".to_string()],
+            eval_limits: EvalLimits {
+                max_depth: Some(2),
+                ..EvalLimits::default()
+            },
+            ..Options::default()
+        };
+        let (res, _) = run_lisp_with("(let ((main (lambda (args) car args))))", "-", &vm).unwrap();
+        assert_eq!(res.to_string(), "a");
+        let errs = run_lisp_with("((lambda () ((lambda () + 1 2))))", "-", &vm).unwrap_err();
+        assert_eq!(errs.exceeded(), Some(LimitExceeded::Depth(2)));
+    }
+
+    #[test]
+    fn test_scope_frames() {
+        let source = "(let ((f (lambda (x) let ((y (+ x 1))) list x y)))\n  f 1)";
+        assert_eq!(run_lisp_to_string(source, "-").unwrap(), "(1 2)");
+        // What a function binds goes away with its parameters.
+        let errs = run_lisp_to_string("(lambda (x) let ((y x)) y)\ny", "-").unwrap_err();
+        assert_eq!(errs.diagnostics()[0].message, "Unknown identifier `y`!");
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval("(let ((g (lambda (a) let ((b a)) b))))", "-")
+            .unwrap();
+        assert!(interpreter.get("g").is_some());
+        assert!(interpreter.get("b").is_none());
+        assert!(interpreter.eval("(lambda (g) g)", "-").is_err());
+    }
+
+    #[test]
+    fn test_symbols() {
+        let source = "(let ((x 1)\n      (f (lambda (y) + x y)))\n  f [x])";
+        let found = symbols(source, "-", &Options::default());
+        assert!(found.diagnostics.is_empty());
+        let names: Vec<_> = found
+            .definitions
+            .iter()
+            .map(|d| (d.name.as_str(), d.loc.line, d.loc.col, d.kind))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("x", 0, 7, SymbolKind::Let),
+                ("y", 1, 18, SymbolKind::Parameter),
+                ("f", 1, 7, SymbolKind::Let)
+            ]
+        );
+        // `[` isn't a use of `list`, but `+` is a use of the intrinsic.
+        let refs: Vec<_> = found
+            .references
+            .iter()
+            .map(|r| (r.name.as_str(), r.definition))
+            .collect();
+        assert_eq!(
+            refs,
+            [
+                ("+", None),
+                ("x", Some(0)),
+                ("y", Some(1)),
+                ("f", Some(2)),
+                ("x", Some(0))
+            ]
+        );
+        assert_eq!(found.definition_at(2, 6).unwrap().name, "x");
+        assert_eq!(found.definition_at(1, 24).unwrap().kind, SymbolKind::Let);
+        assert_eq!(found.name_at(1, 22), Some("+"));
+        assert!(intrinsic_doc("+").unwrap().starts_with("(+ "));
+
+        let broken = symbols("(let ((a 1)) a)\n(b)", "-", &Options::default());
+        assert!(broken.diagnostics.is_fatal());
+        assert_eq!(broken.definitions.len(), 1);
+    }
+
+    // With the `sync` feature every thread's values are counted together, so the other tests would change the count.
+    #[cfg(not(feature = "sync"))]
+    #[test]
+    fn test_live_values() {
+        let before = live_values();
+        {
+            let mut interpreter = Interpreter::new();
+            let xs = interpreter
+                .eval("(let ((xs [1 2 3])) list xs xs)", "-")
+                .unwrap();
+            assert!(live_values() > before);
+            drop(interpreter);
+            assert_eq!(xs.to_string(), "((1 2 3) (1 2 3))");
+        }
+        assert_eq!(live_values(), before);
+        {
+            let mut interpreter = Interpreter::new();
+            interpreter
+                .eval("(let ((c (channel))))\n(send c c)", "-")
+                .unwrap();
+            let got = interpreter.eval("(eqv? (recv c) c)", "-").unwrap();
+            assert_eq!(got.to_string(), ":true");
+        }
+        assert_eq!(live_values(), before);
+        // A channel that holds itself is a cycle, which is collected once the interpreter is dropped, along with ones that go through lists and functions.
+        let source = "(let ((c (channel)) (d (channel))))\n(send c c)\n(send d [(lambda () c) d])\n(send c (delay d))";
+        let mut interpreter = Interpreter::new();
+        interpreter.eval(source, "-").unwrap();
+        // The host still has the channel, so nothing is collected.
+        let kept = interpreter.eval("c", "-").unwrap();
+        drop(interpreter);
+        assert!(live_values() > before);
+        assert_eq!(crate::collect_cycles(), 0);
+        assert_eq!(kept.to_string(), "<Channel>");
+        drop(kept);
+        assert!(crate::collect_cycles() > 0);
+        assert_eq!(live_values(), before);
+        {
+            let mut interpreter = Interpreter::new();
+            interpreter.eval(source, "-").unwrap();
+        }
+        assert_eq!(live_values(), before);
+    }
+
+    #[test]
+    fn test_sandbox() {
+        let mut sandboxed = Interpreter::new().deny(Capability::Input);
+        assert!(sandboxed.eval("(read-line)", "-").is_err());
+        assert!(sandboxed.get("print").is_some());
+        let mut minimal = Interpreter::new()
+            .allow_only(["+", "list"])
+            .with_globals([("limit", 3)]);
+        assert_eq!(
+            minimal.eval("[(+ limit 1)]", "-").unwrap().to_string(),
+            "(4)"
+        );
+        assert!(minimal.eval("(print 1)", "-").is_err());
+        assert!(minimal.eval("(car [1])", "-").is_err());
+    }
+    #[test]
+    fn test_highlight() {
+        let options = Options::default();
+        let pieces = |source: &str| -> Vec<(String, Highlight)> {
+            highlight(source, &options)
+                .into_iter()
+                .map(|s| (source[s.range].to_string(), s.kind))
+                .collect()
+        };
+        let expected = [
+            ("(", Highlight::Delimiter),
+            ("let", Highlight::Keyword),
+            ("(", Highlight::Delimiter),
+            ("(", Highlight::Delimiter),
+            ("x", Highlight::Binding),
+            ("0x1F", Highlight::Number),
+            (")", Highlight::Delimiter),
+            (")", Highlight::Delimiter),
+            ("[", Highlight::Delimiter),
+            ("x", Highlight::Identifier),
+            (":a", Highlight::Constant),
+            ("\"b c\"", Highlight::String),
+            ("]", Highlight::Delimiter),
+            (")", Highlight::Delimiter),
+            ("// done", Highlight::Comment),
+        ];
+        let expected: Vec<_> = expected.map(|(t, k)| (t.to_string(), k)).into();
+        assert_eq!(pieces("(let ((x 0x1F)) [x :a \"b c\"]) // done"), expected);
+        // Source that is still being written is highlighted as far as it goes.
+        assert_eq!(
+            pieces("(lambda (a) + a \"unclosed"),
+            [
+                ("(", Highlight::Delimiter),
+                ("lambda", Highlight::Keyword),
+                ("(", Highlight::Delimiter),
+                ("a", Highlight::Binding),
+                (")", Highlight::Delimiter),
+                ("+", Highlight::Identifier),
+                ("a", Highlight::Identifier),
+                ("\"unclosed", Highlight::String),
+            ]
+            .map(|(t, k)| (t.to_string(), k))
+        );
+        assert_eq!(
+            pieces("f) #\"${x}\" {* open"),
+            [
+                ("f", Highlight::Identifier),
+                ("#\"${x}\"", Highlight::String),
+                ("{* open", Highlight::Comment),
+            ]
+            .map(|(t, k)| (t.to_string(), k))
+        );
+        assert_eq!(
+            pieces("#u8(1 2)"),
+            [
+                ("#u8(", Highlight::Delimiter),
+                ("1", Highlight::Number),
+                ("2", Highlight::Number),
+                (")", Highlight::Delimiter),
+            ]
+            .map(|(t, k)| (t.to_string(), k))
+        );
+    }
+    #[test]
+    fn test_error_categories() {
+        let category = |source| run_lisp(source, "-").unwrap_err().category();
+        assert_eq!(category("(+ 1 2"), ErrorCategory::Syntax);
+        assert_eq!(category("(+ 1 unknown)"), ErrorCategory::Syntax);
+        assert_eq!(category("(+ 1 :a)"), ErrorCategory::Runtime);
+        assert_eq!(category("(exit 3)\n(+ 1 :a)"), ErrorCategory::Exit(3));
+        assert_eq!(category("((lambda () exit))"), ErrorCategory::Exit(0));
+        assert_eq!(category("(exit :no)"), ErrorCategory::Runtime);
+        let compiled = Interpreter::new().eval_compiled("(exit 4)", "-");
+        assert_eq!(compiled.unwrap_err().category(), ErrorCategory::Exit(4));
+    }
+    #[test]
+    fn test_modules() {
+        let dir = TempDir::new("modules");
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        let write = |name: &str, source: &str| std::fs::write(dir.join(name), source).unwrap();
+        write(
+            "lib/math.pale",
+            "(let ((square (lambda (x) * x x)) (hidden 1)) list)\n(print :loaded)\n(provide square)",
+        );
+        write("lib/again.pale", "(import \"math.pale\")\n(provide square)");
+        write("a.pale", "(import \"b.pale\")");
+        write("b.pale", "(import \"a.pale\")");
+        let printed = Shared::new(Mutable::new(String::new()));
+        let sink = Shared::clone(&printed);
+        let mut interpreter = Interpreter::with_options(Options {
+            on_output: Output::new(move |text| exclusive(&sink).push_str(text)),
+            ..Options::default()
+        });
+        let main = dir.join("main.pale");
+        let main = main.to_str().unwrap();
+        let source = "(import \"lib/math.pale\")\n(import \"lib/again.pale\" :as m)\n[(square 3) (m/square 4)]";
+        assert_eq!(
+            interpreter.eval(source, main).unwrap().to_string(),
+            "(9 16)"
+        );
+        // The module is only run once, however many times it is imported.
+        assert_eq!(*exclusive(&printed), ":loaded\n");
+        // What a module doesn't provide is private to it, which using it points out.
+        let err = interpreter.eval("hidden", main).unwrap_err().to_string();
+        assert!(
+            err.contains("`hidden` is private to `lib/math.pale`!"),
+            "{err}"
+        );
+        assert!(
+            err.contains("main.pale:0:8 - The module is imported here"),
+            "{err}"
+        );
+        let prefixed = "(import \"lib/math.pale\" :as l)\nl/hidden";
+        let err = interpreter.eval(prefixed, main).unwrap_err().to_string();
+        assert!(
+            err.contains("`l/hidden` is private to `lib/math.pale`!"),
+            "{err}"
+        );
+        let err = run_lisp("m/hidden", main).unwrap_err().to_string();
+        assert!(err.contains("Unknown identifier `m/hidden`"), "{err}");
+        let deferred = "(import \"lib/math.pale\" :defer (hidden))";
+        let err = interpreter.eval(deferred, main).unwrap_err().to_string();
+        assert!(err.contains("doesn't provide `hidden`!"), "{err}");
+        assert!(err.contains("`hidden` is private to it."), "{err}");
+        // Importing it again reuses what was imported before.
+        let again = "(import \"lib/math.pale\")\n(square 5)";
+        assert_eq!(interpreter.eval(again, main).unwrap().to_string(), "25");
+        assert_eq!(*exclusive(&printed), ":loaded\n");
+        // Scripts that can't use files can't import them either.
+        let mut sandboxed = Interpreter::new().deny(Capability::Files);
+        let err = sandboxed.eval("(import \"lib/math.pale\")", main);
+        assert!(err.unwrap_err().to_string().contains("can't read files"));
+        let mut minimal = Interpreter::new().allow_only(["+"]);
+        assert!(minimal.eval("(import \"lib/math.pale\")", main).is_err());
+        let cycle = run_lisp("(import \"a.pale\")", main)
+            .unwrap_err()
+            .to_string();
+        assert!(cycle.contains("would make a cycle"), "{cycle}");
+        // Each link of the cycle is pointed out where it is made.
+        assert!(
+            cycle.contains("a.pale:0:8 - It imports `b.pale` here."),
+            "{cycle}"
+        );
+        assert!(
+            cycle.contains("b.pale:0:8 - It imports `a.pale` here."),
+            "{cycle}"
+        );
+        assert!(run_lisp("((lambda () import \"lib/math.pale\"))", main).is_err());
+    }
+    #[test]
+    fn test_re_export() {
+        let dir = TempDir::new("re-export");
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        let write = |name: &str, source: &str| std::fs::write(dir.join(name), source).unwrap();
+        write(
+            "lib/shapes.pale",
+            "(let ((area (lambda (w h) * w h)) (perimeter (lambda (w h) * 2 (+ w h)))))\n(provide area perimeter)",
+        );
+        write(
+            "lib/all.pale",
+            "(re-export \"shapes.pale\" :rename ((area rect-area)))",
+        );
+        let main = dir.join("main.pale");
+        let main = main.to_str().unwrap();
+        let source = "(import \"lib/all.pale\")\n[(rect-area 2 3) (perimeter 2 3)]";
+        assert_eq!(run_lisp_to_string(source, main).unwrap(), "(6 10)");
+        assert!(run_lisp("(import \"lib/all.pale\")\n(area 2 3)", main).is_err());
+        // Renaming a name keeps it from clashing with one that is already bound.
+        let source =
+            "(let ((area 1)))\n(import \"lib/shapes.pale\" :as s :rename ((area a)))\n(s/a area 5)";
+        assert_eq!(run_lisp_to_string(source, main).unwrap(), "5");
+        let err = run_lisp("(import \"lib/shapes.pale\" :rename ((volume v)))", main);
+        assert!(err
+            .unwrap_err()
+            .to_string()
+            .contains("doesn't provide `volume`"));
+        assert!(run_lisp("(import \"lib/shapes.pale\" :rename (area a))", main).is_err());
+        // Imported names know where the module they came from provides them, even through a re-export.
+        let found = symbols(
+            "(import \"lib/all.pale\")\n(perimeter 1 2)",
+            main,
+            &Options::default(),
+        );
+        let from = found.definition_at(1, 2).unwrap().from.as_ref().unwrap();
+        assert!(from.filename.ends_with("shapes.pale"));
+        assert_eq!((from.line, from.col), (1, 14));
+    }
+    #[test]
+    fn test_deferred_imports() {
+        let dir = TempDir::new("defer");
+        let write = |name: &str, source: &str| std::fs::write(dir.join(name), source).unwrap();
+        write(
+            "even.pale",
+            "(import \"odd.pale\")\n(let ((is-even (lambda (n) if (eqv? n 0) :true (is-odd (- n 1))))))\n(provide is-even)",
+        );
+        write(
+            "odd.pale",
+            "(import \"even.pale\" :defer (is-even))\n(let ((is-odd (lambda (n) if (eqv? n 0) nil (is-even (- n 1))))))\n(provide is-odd)",
+        );
+        let main = dir.join("main.pale");
+        let main = main.to_str().unwrap();
+        let source = "(import \"even.pale\")\n[(is-even 10) (is-even 7)]";
+        assert_eq!(run_lisp_to_string(source, main).unwrap(), "(:true nil)");
+        let compiled = Interpreter::new().eval_compiled(source, main).unwrap();
+        assert_eq!(compiled.to_string(), "(:true nil)");
+        // Only the import that closes the cycle is deferred, so the modules can't be imported the other way around.
+        assert!(run_lisp("(import \"odd.pale\")", main).is_err());
+        // With both deferred, either one can be imported first.
+        write(
+            "even.pale",
+            "(import \"odd.pale\" :defer (is-odd))\n(let ((is-even (lambda (n) if (eqv? n 0) :true (is-odd (- n 1))))))\n(provide is-even)",
+        );
+        let source =
+            "(import \"odd.pale\")\n(import \"even.pale\" :as e)\n[(is-odd 3) (e/is-even 3)]";
+        assert_eq!(run_lisp_to_string(source, main).unwrap(), "(:true nil)");
+        write(
+            "self.pale",
+            "(import \"self.pale\" :defer (missing))\n(provide)",
+        );
+        let err = run_lisp("(import \"self.pale\")", main).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("`self.pale` doesn't provide `missing`"),
+            "{err}"
+        );
+        // The functions of the two modules refer to each other through the slots the deferred imports bound, which are collected with the interpreter.
+        #[cfg(not(feature = "sync"))]
+        {
+            let before = live_values();
+            let mut interpreter = Interpreter::new();
+            let got = interpreter.eval(source, main).unwrap();
+            assert_eq!(got.to_string(), "(:true nil)");
+            drop((interpreter, got));
+            assert_eq!(live_values(), before);
+        }
+    }
+    #[test]
+    fn test_load() {
+        let dir = TempDir::new("load");
+        std::fs::create_dir_all(dir.join("util")).unwrap();
+        let write = |name: &str, source: &str| std::fs::write(dir.join(name), source).unwrap();
+        write(
+            "util/double.pale",
+            "(let ((double (lambda (x) + x x))))\n(double 1)",
+        );
+        write(
+            "util/all.pale",
+            "(load \"double.pale\")\n(let ((quad (lambda (x) double (double x)))))",
+        );
+        write("self.pale", "(load \"self.pale\")");
+        let main = dir.join("main.pale");
+        let main = main.to_str().unwrap();
+        let mut interpreter = Interpreter::new();
+        // What the loaded files bind can be used by the rest of the file, and by later evaluations.
+        let source = "(load \"util/all.pale\")\n(quad 5)";
+        assert_eq!(interpreter.eval(source, main).unwrap().to_string(), "20");
+        assert_eq!(
+            interpreter.eval("(double 4)", main).unwrap().to_string(),
+            "8"
+        );
+        let read: Vec<_> = interpreter
+            .files_read()
+            .map(|p| p.file_name().unwrap())
+            .collect();
+        assert_eq!(read, ["all.pale", "double.pale"]);
+        let value = Interpreter::new().eval_compiled("(load \"util/double.pale\")", main);
+        assert_eq!(value.unwrap().to_string(), "2");
+        assert!(run_lisp("(load \"self.pale\")", main).is_err());
+        assert!(run_lisp("(load \"missing.pale\")", main).is_err());
+        let mut sandboxed = Interpreter::new().deny(Capability::Files);
+        let err = sandboxed.eval("(load \"util/double.pale\")", main);
+        assert!(err.unwrap_err().to_string().contains("can't read files"));
+    }
+    #[test]
+    fn test_prelude() {
+        let run = |source| run_lisp_to_string(source, "-").unwrap();
+        assert_eq!(run("[(inc 1) (dec 1) (identity :x)]"), "(2 0 :x)");
+        assert_eq!(
+            run("[(second [1 2 3]) (third [1 2 3]) (caar [[1] 2])]"),
+            "(2 3 1)"
+        );
+        assert_eq!(run("((compose inc car) [1 2])"), "2");
+        let bare = Options {
+            prelude: false,
+            ..Options::default()
+        };
+        assert!(run_lisp_with("(inc 1)", "-", &bare).is_err());
+        // Without the prelude, its names are free to be bound to something else.
+        let (value, _) = run_lisp_with("(let ((inc 5)) list inc)", "-", &bare).unwrap();
+        assert_eq!(value.to_string(), "(5)");
+        assert!(Session::default().bindings().is_empty());
+        assert!(Interpreter::new().allow_only(["+"]).get("inc").is_none());
+    }
+    #[test]
+    fn test_lazy_streams() {
+        let squares = "(stream-take 5 (stream-map (lambda (x) * x x) (integers-from 1)))";
+        let without_two = "(stream-take 3 (stream-filter (lambda (x) if (eqv? x 2) nil :true) (integers-from 1)))";
+        for compiled in [false, true] {
+            let run = |source| match compiled {
+                true => Interpreter::new().eval_compiled(source, "-"),
+                false => Interpreter::new().eval(source, "-"),
+            };
+            assert_eq!(run(squares).unwrap().to_string(), "(1 4 9 16 25)");
+            assert_eq!(run(without_two).unwrap().to_string(), "(1 3 4)");
+            assert_eq!(
+                run("(stream-take 5 [1 2 3])").unwrap().to_string(),
+                "(1 2 3)"
+            );
+        }
+        let out = Shared::new(Mutable::new(Vec::new()));
+        let mut interpreter = Interpreter::with_options(Options {
+            on_output: Output::shared(Shared::clone(&out)),
+            ..Options::default()
+        });
+        let mut eval = |source| interpreter.eval(source, "-").map(|v| v.to_string());
+        // The body is only run the first time, and its value is kept for every time after that.
+        assert_eq!(
+            eval("(let ((p (delay (print :once)))) list (promise? p) (force p) (force p))")
+                .unwrap(),
+            "(:true 0 0)"
+        );
+        assert_eq!(*exclusive(&out), b":once\n");
+        assert_eq!(eval("(force 3)").unwrap(), "3");
+        // Promises forced inside of promises are evaluated like any other call, so they can go as deep as recursion can.
+        let deep =
+            "(let ((f (lambda (n) if (eqv? n 0) 0 (force (delay (+ 1 (f (- n 1)))))))) f 100000)";
+        assert_eq!(eval(deep).unwrap(), "100000");
+        let compiled = Interpreter::new().eval_compiled(deep, "-").unwrap();
+        assert_eq!(compiled.to_string(), "100000");
+        let err = eval("(delay 1 2)").unwrap_err();
+        assert!(err.to_string().contains("only delay one"), "{err}");
+    }
+    #[test]
+    fn test_rest_parameters() {
+        let source = "(let ((f (lambda (a &rest more) list a more))) list (f 1) (f 1 2 3))";
+        assert_eq!(
+            run_lisp_to_string(source, "-").unwrap(),
+            "((1 ()) (1 (2 3)))"
+        );
+        let spread = "(let ((sum (lambda (&rest xs) + 0 0 . xs))) sum 1 2 3)";
+        assert_eq!(run_lisp_to_string(spread, "-").unwrap(), "6");
+        let compiled = Interpreter::new().eval_compiled(spread, "-").unwrap();
+        assert_eq!(compiled.to_string(), "6");
+        let errs = run_lisp("(let ((f (lambda (a b &rest c) list a))) f 1)", "-").unwrap_err();
+        assert!(errs
+            .to_string()
+            .contains("takes at least 2 argument(s), but was given 1"));
+        assert!(run_lisp("(lambda (a &rest) a)", "-").is_err());
+        assert!(run_lisp("(lambda (&rest a b) a)", "-").is_err());
+        let found = lint(
+            "(let ((f (lambda (a &rest b) list a b))) list (f) (f 1 2 3))",
+            "-",
+            &Options::default(),
+            &LintConfig::default(),
+        );
+        assert_eq!(found.diagnostics().len(), 1);
+    }
+    #[test]
+    fn test_keyword_arguments() {
+        let rect = "(let ((rect (lambda (shape &key width height) list shape width height))) ";
+        let source = format!("{rect}list (rect 1 :height 4 :width 10) (rect 2 :width 3) (rect 3))");
+        assert_eq!(
+            run_lisp_to_string(&source, "-").unwrap(),
+            "((1 10 4) (2 3 nil) (3 nil nil))"
+        );
+        let compiled = Interpreter::new().eval_compiled(&source, "-").unwrap();
+        assert_eq!(compiled.to_string(), "((1 10 4) (2 3 nil) (3 nil nil))");
+        // The errors point at the argument that is wrong.
+        let errs = run_lisp(&format!("{rect}rect 1 :width 2 :depth 3)"), "-").unwrap_err();
+        let text = errs.to_string();
+        assert!(text.contains("no `:depth` parameter"));
+        assert!(text.contains("-:0:89"), "{text}");
+        assert!(text.contains("`:width`, `:height`"));
+        let errs = run_lisp(&format!("{rect}rect 1 :width 2 :width 3)"), "-").unwrap_err();
+        let text = errs.to_string();
+        assert!(text.contains("`:width` was given more than once"));
+        assert!(text.contains("-:0:89") && text.contains("-:0:80"), "{text}");
+        assert!(run_lisp(&format!("{rect}rect 1 :width)"), "-").is_err());
+        assert!(run_lisp(&format!("{rect}rect 1 2 3)"), "-").is_err());
+        assert!(run_lisp("(lambda (&key) list 1)", "-").is_err());
+        assert!(run_lisp("(lambda (&key a &rest b) list a)", "-").is_err());
+    }
+    #[test]
+    fn test_destructuring_let() {
+        let source =
+            "(let (((a b) [1 2]) ((x . rest) [3 4 5]) ((p . q) (cons 6 7))) list a b x rest p q)";
+        assert_eq!(
+            run_lisp_to_string(source, "-").unwrap(),
+            "(1 2 3 (4 5) 6 7)"
+        );
+        let compiled = Interpreter::new().eval_compiled(source, "-").unwrap();
+        assert_eq!(compiled.to_string(), "(1 2 3 (4 5) 6 7)");
+        let nested = "(let (((a (b c)) [1 [2 3]])) list a b c)";
+        assert_eq!(run_lisp_to_string(nested, "-").unwrap(), "(1 2 3)");
+        // The errors point at the pattern that doesn't fit.
+        let errs = run_lisp("(let ((x 1) ((a b) [1 2 3])) a)", "-").unwrap_err();
+        let text = errs.to_string();
+        assert!(text.contains("takes 2 value(s), but the list has 3"));
+        assert!(text.contains("-:0:13"), "{text}");
+        assert!(run_lisp("(let (((a . b) [])) a)", "-").is_err());
+        assert!(run_lisp("(let (((a b) 5)) a)", "-").is_err());
+        assert!(run_lisp("(let (((. b) [1])) b)", "-").is_err());
+        assert!(run_lisp("(let (((a . b c) [1])) b)", "-").is_err());
+        assert!(run_lisp("(let ((() [1])) 1)", "-").is_err());
+    }
+    #[test]
+    fn test_multiple_values() {
+        let source = "(let-values (((q r) (quotient/remainder 17 5)) ((a . more) (values 1 2 3))) list q r a more)";
+        assert_eq!(run_lisp_to_string(source, "-").unwrap(), "(3 2 1 (2 3))");
+        let compiled = Interpreter::new().eval_compiled(source, "-").unwrap();
+        assert_eq!(compiled.to_string(), "(3 2 1 (2 3))");
+        assert_eq!(run_lisp_to_string("(values 1 2)", "-").unwrap(), "1 2");
+        let errs = run_lisp("(let-values (((a b) (values 1))) a)", "-").unwrap_err();
+        assert!(errs
+            .to_string()
+            .contains("takes 2 value(s), but 1 were given"));
+        // `let-values` only unpacks values, and `let` only unpacks lists.
+        assert!(run_lisp("(let-values (((a b) [1 2])) a)", "-").is_err());
+        assert!(run_lisp("(let (((a b) (values 1 2))) a)", "-").is_err());
+        assert!(run_lisp("(let-values ((a 1)) a)", "-").is_err());
+        assert!(run_lisp("(quotient/remainder 1 0)", "-").is_err());
+    }
+    #[test]
+    fn test_closures() {
+        let source = "(let ((adder (lambda (n) lambda (x) + x n))
+                            (add1 (adder 1))
+                            (add10 (adder 10)))
+                        list (add1 5) (add10 5))";
+        assert_eq!(run_lisp_to_string(source, "-").unwrap(), "(6 15)");
+        let compiled = Interpreter::new().eval_compiled(source, "-").unwrap();
+        assert_eq!(compiled.to_string(), "(6 15)");
+        // Names are captured through every function in between.
+        let nested = "(let ((f (lambda (a) lambda (b) lambda (c) list a b c))) ((f 1) 2) 3)";
+        assert_eq!(run_lisp_to_string(nested, "-").unwrap(), "(1 2 3)");
+        let composed = "(let ((first (compose car cdr)) (twice (compose inc inc))) list (first [1 2]) (twice 1))";
+        assert_eq!(run_lisp_to_string(composed, "-").unwrap(), "(2 3)");
+        // What the body binds is kept apart for each call too.
+        let locals = "(let ((f (lambda (x) let ((y (+ x 1))) lambda () list x y)) (g (f 1)) (h (f 5))) list (g) (h))";
+        assert_eq!(run_lisp_to_string(locals, "-").unwrap(), "((1 2) (5 6))");
+    }
+    #[test]
+    fn test_recursion() {
+        // A list that ends in `nil`, since there's nothing to tell whether a list is empty.
+        let items = "[1 [2 [3 nil]]]";
+        // `car l` is only evaluated once the call inside of it has returned, so each call needs its own `l`.
+        let sum =
+            format!("(let ((sum (lambda (l) if l (+ (sum (second l)) (car l)) 0))) sum {items})");
+        assert_eq!(run_lisp_to_string(&sum, "-").unwrap(), "6");
+        let compiled = Interpreter::new().eval_compiled(&sum, "-").unwrap();
+        assert_eq!(compiled.to_string(), "6");
+        // The same function can be in the middle of two calls in one expression.
+        let twice = "(let ((f (lambda (x) let ((y (+ x 1))) list x y))) list (f 1) (car (f (car (cdr (f 2))))))";
+        assert_eq!(run_lisp_to_string(twice, "-").unwrap(), "((1 2) 3)");
+        // Functions made inside of a call can call themselves too, without keeping the call alive.
+        let inner = format!(
+            "(let ((count (lambda (l) let ((go (lambda (rest n) if rest (go (second rest) (+ n 1)) n))) go l 0))) count {items})"
+        );
+        #[cfg(not(feature = "sync"))]
+        let before = live_values();
+        assert_eq!(run_lisp_to_string(&inner, "-").unwrap(), "3");
+        #[cfg(not(feature = "sync"))]
+        assert_eq!(live_values(), before);
+        let found = symbols(&sum, "-", &Options::default());
+        let uses: Vec<_> = found
+            .references
+            .iter()
+            .filter(|r| r.name == "sum")
+            .map(|r| r.definition.map(|d| found.definitions[d].loc.col))
+            .collect();
+        assert_eq!(uses, [Some(7), Some(7)]);
+    }
+
+    #[test]
+    fn test_parameterize() {
+        // `show` doesn't take the indentation as an argument, but still sees what `parameterize` gives it.
+        let source = "(let ((indent (make-parameter 0)) (show (lambda () indent))) list (show) (parameterize ((indent 4)) show) (show))";
+        assert_eq!(run_lisp_to_string(source, "-").unwrap(), "(0 4 0)");
+        let compiled = Interpreter::new().eval_compiled(source, "-").unwrap();
+        assert_eq!(compiled.to_string(), "(0 4 0)");
+        // The innermost `parameterize` wins, until its body is done.
+        let nested =
+            "(let ((p (make-parameter 1))) parameterize ((p 2)) list (p) (parameterize ((p 3)) p) (p))";
+        assert_eq!(run_lisp_to_string(nested, "-").unwrap(), "(2 3 2)");
+        // A crash in the body still puts the parameter back.
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval("(let ((p (make-parameter 1))))", "-")
+            .unwrap();
+        assert!(interpreter
+            .eval("(parameterize ((p 2)) car 1)", "-")
+            .is_err());
+        assert_eq!(interpreter.eval("(p)", "-").unwrap().to_string(), "1");
+        let err = run_lisp_to_string("(let ((p 1)) parameterize ((p 2)) p)", "-").unwrap_err();
+        assert!(err.to_string().contains("`1` is not a parameter!"), "{err}");
+        let err = run_lisp_to_string("(let ((p (make-parameter 1))) parameterize ((p 2)))", "-")
+            .unwrap_err();
+        assert!(err.to_string().contains("must have a body"), "{err}");
+    }
+
+    #[test]
+    fn test_equality() {
+        let compare = |a: &str, b: &str| {
+            ["eq?", "eqv?", "equal?"]
+                .map(|op| {
+                    run_lisp_to_string(
+                        &format!("(let ((l [1 2]) (f (lambda () list 1))) {op} {a} {b})"),
+                        "-",
+                    )
+                    .unwrap()
+                })
+                .join(" ")
+        };
+        assert_eq!(compare("l", "l"), ":true :true :true");
+        assert_eq!(compare("f", "f"), ":true :true :true");
+        assert_eq!(compare("nil", "nil"), ":true :true :true");
+        assert_eq!(compare("1", "1"), "nil :true :true");
+        assert_eq!(compare("\"a\"", "\"a\""), "nil :true :true");
+        assert_eq!(compare(":a", ":a"), "nil :true :true");
+        assert_eq!(compare("l", "[1 2]"), "nil nil :true");
+        assert_eq!(compare("{:a [1]}", "{:a [1]}"), "nil nil :true");
+        assert_eq!(compare("1", "1.0"), "nil nil nil");
+        assert_eq!(compare("[1 2]", "[2 1]"), "nil nil nil");
+        assert_eq!(compare("f", "(lambda () list 1)"), "nil nil nil");
+    }
+
+    #[test]
+    fn test_type_predicates() {
+        let source = "(let ((p (make-parameter 1))) list (type-of 1) (type-of 1.5) (type-of \"a\") (type-of :a) (type-of nil) (type-of [1]) (type-of (cons 1 2)) (type-of {:a 1}) (type-of car) (type-of p))";
+        assert_eq!(
+            run_lisp_to_string(source, "-").unwrap(),
+            "(:integer :float :string :keyword :nil :list :pair :map :function :function)"
+        );
+        let checks = "(list (integer? 1) (integer? \"1\") (string? \"a\") (nil? nil) (nil? [nil]) (list? [1]) (list? nil) (callable? car) (callable? :car))";
+        assert_eq!(
+            run_lisp_to_string(checks, "-").unwrap(),
+            "(:true nil :true :true nil :true nil :true nil)"
+        );
+        // Scripts can branch on what they were given.
+        let describe = "(let ((describe (lambda (x) if (integer? x) (+ x 1) (str x \"!\")))) list (describe 1) (describe \"a\"))";
+        assert_eq!(run_lisp_to_string(describe, "-").unwrap(), "(2 a!)");
+    }
+
+    #[test]
+    fn test_conversions() {
+        let source = "(list (string->int \"-42\") (string->int \"ff\" 16) (int->string 255 2) (int->string -255 16) (string->float \"2.5\") (list->string [\"ab\" 99]))";
+        assert_eq!(
+            run_lisp_to_string(source, "-").unwrap(),
+            "(-42 255 11111111 -ff 2.5 abc)"
+        );
+        let rounding = "(list (float->int -2.5) (float->int -2.5 :floor) (float->int 2.5 :round) (float->int 2.1 :ceil))";
+        assert_eq!(run_lisp_to_string(rounding, "-").unwrap(), "(-2 -3 3 3)");
+        // Bad input is an error where the conversion was called, not `nil`.
+        for (source, message) in [
+            ("(+ 1 (string->int \"12a\"))", "`12a` is not an integer!"),
+            ("(+ 1 (string->int \"12\" 37))", "`37` is not a radix!"),
+            ("(+ 1 (string->float \"x\"))", "`x` is not a float!"),
+            ("(+ 1 (float->int 1e300))", "is too big to be an integer!"),
+            (
+                "(+ 1 (float->int (string->float \"NaN\")))",
+                "`NaN` can't be made an integer!",
+            ),
+            (
+                "(+ 1 (float->int (string->float \"-inf\")))",
+                "`-inf` can't be made an integer!",
+            ),
+            ("(+ 1 (float->int 1.5 :up))", "`:up` is not a way to round!"),
+            (
+                "(+ 1 (list->string [1.5]))",
+                "`1.5` is not a string or a character, at index 0 of the list!",
+            ),
+            (
+                "(+ 1 (int->string))",
+                "`int->string` takes 1 or 2 argument(s), but was given 0!",
+            ),
+        ] {
+            let err = run_lisp_to_string(source, "-").unwrap_err().to_string();
+            assert!(
+                err.starts_with("-:0:6 - ") && err.contains(message),
+                "{err}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_gensym() {
+        let source =
+            "(let ((a (gensym)) (b (gensym \"tmp\"))) list (keyword? a) (eqv? a b) (eqv? a a) b)";
+        // Each evaluation counts on from the last, so it is only evaluated once.
+        let result = crate::run_lisp_to_string(source, "-").unwrap();
+        assert!(result.starts_with("(:true nil :true :tmp#u"), "{result}");
+        // Other interpreters carry on counting, so their keywords are different too.
+        let other = Interpreter::new().eval("(gensym \"tmp\")", "-").unwrap();
+        assert!(!result.ends_with(&format!("{other})")), "{result} {other}");
+        // The source can't write one of them.
+        assert!(run_lisp_to_string("(list :tmp#u1)", "-").is_err());
+    }
+
+    #[test]
+    fn test_do_loop() {
+        let sum = "(do ((i 0 (+ i 1)) (sum 0 (+ sum i))) ((eqv? i 5) sum))";
+        assert_eq!(run_lisp_to_string(sum, "-").unwrap(), "10");
+        assert_eq!(
+            Interpreter::new()
+                .eval_compiled(sum, "-")
+                .unwrap()
+                .to_string(),
+            "10"
+        );
+        // The body runs before the steps, and a variable without a step stays the same.
+        let printed = Shared::new(Mutable::new(String::new()));
+        let sink = Shared::clone(&printed);
+        let options = Options {
+            on_output: Output::new(move |text| exclusive(&sink).push_str(text)),
+            ..Options::default()
+        };
+        let body = "(do ((i 0 (+ i 1)) (step 2)) ((eqv? i 3)) print (* i step))";
+        assert_eq!(
+            run_lisp_with(body, "-", &options).unwrap().0.to_string(),
+            "nil"
+        );
+        assert_eq!(*exclusive(&printed), "0\n2\n4\n");
+        // Each time around has its own variables, so functions made in the body keep the ones they were made with.
+        let closures = "(let ((fs (do ((i 0 (+ i 1)) (fs nil (cons (lambda () list i) fs))) ((eqv? i 3) fs)))) list ((car fs)) ((second fs)) ((third fs)))";
+        assert_eq!(run_lisp_to_string(closures, "-").unwrap(), "((2) (1) (0))");
+        assert_eq!(
+            Interpreter::new()
+                .eval_compiled(closures, "-")
+                .unwrap()
+                .to_string(),
+            "((2) (1) (0))"
+        );
+        // Looping doesn't go any deeper, and counts against the step limit even without calls.
+        let long = "(do ((i 0 (+ i 1))) ((eqv? i 100000) i))";
+        let limits = EvalLimits {
+            max_depth: Some(10),
+            ..EvalLimits::default()
+        };
+        let options = Options {
+            eval_limits: limits,
+            ..Options::default()
+        };
+        assert_eq!(
+            run_lisp_with(long, "-", &options).unwrap().0.to_string(),
+            "100000"
+        );
+        let options = Options {
+            eval_limits: EvalLimits {
+                max_steps: Some(100),
+                ..EvalLimits::default()
+            },
+            ..Options::default()
+        };
+        assert!(run_lisp_with("(do ((i 0)) (nil))", "-", &options).is_err());
+        let err = run_lisp_to_string("(do ((i)) (i))", "-").unwrap_err();
+        assert!(
+            err.to_string().contains("Each variable of a `do` loop"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_csv() {
+        let dir = TempDir::new("csv");
+        let path = dir.join("people.csv").display().to_string();
+        let write = format!(
+            r#"(csv-write "{path}" [{{"name" "Ada" "note" "likes, commas"}} ["Bo" 7]] :headers ["name" "note"])"#
+        );
+        run_lisp_to_string(&write, "-").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "name,note\nAda,\"likes, commas\"\nBo,7\n"
+        );
+        let rows = run_lisp_to_string(&format!(r#"(csv-read "{path}")"#), "-").unwrap();
+        assert_eq!(rows, "((name note) (Ada likes, commas) (Bo 7))");
+        let maps = format!(r#"(csv-read "{path}" :headers :true)"#);
+        assert_eq!(
+            run_lisp_to_string(&maps, "-").unwrap(),
+            "({name Ada note likes, commas} {name Bo note 7})"
+        );
+        std::fs::write(&path, "a;\"b\"\"c\"\r\n\"multi\nline\";").unwrap();
+        let read = format!(r#"(csv-read "{path}" :delimiter ";")"#);
+        assert_eq!(
+            run_lisp_to_string(&read, "-").unwrap(),
+            "((a b\"c) (multi\nline ))"
+        );
+        std::fs::write(&path, "a,\"b\n").unwrap();
+        let err = run_lisp_to_string(&format!(r#"(csv-read "{path}")"#), "-").unwrap_err();
+        assert!(err.to_string().contains("never closed, on line 1"), "{err}");
+        // Files can be kept out of reach like the other capabilities.
+        let mut sandboxed = Interpreter::new().deny(Capability::Files);
+        assert!(sandboxed
+            .eval(&format!(r#"(csv-read "{path}")"#), "-")
+            .is_err());
+    }
+    #[cfg(feature = "config")]
+    #[test]
+    fn test_read_config() {
+        let dir = TempDir::new("config");
+        let toml = dir.join("app.toml");
+        std::fs::write(
+            &toml,
+            "name = \"pale\"\nport = 8080\ndebug = false\n[server]\nhosts = [\"a\", \"b\"]\nratio = 0.5\n",
+        )
+        .unwrap();
+        let read = |path: &std::path::Path| {
+            run_lisp_to_string(&format!(r#"(read-config "{}")"#, path.display()), "-")
+        };
+        assert_eq!(
+            read(&toml).unwrap(),
+            "{debug nil name pale port 8080 server {hosts (a b) ratio 0.5}}"
+        );
+        let yaml = dir.join("app.yml");
+        std::fs::write(
+            &yaml,
+            "name: pale\nverbose: true\nlevels:\n  - 1\n  - ~\n3: three\n",
+        )
+        .unwrap();
+        assert_eq!(
+            read(&yaml).unwrap(),
+            "{name pale verbose :true levels (1 nil) 3 three}"
+        );
+        std::fs::write(&toml, "name = ").unwrap();
+        let err = read(&toml).unwrap_err();
+        assert!(err.to_string().contains("is not valid"), "{err}");
+        let other = dir.join("app.ini");
+        std::fs::write(&other, "").unwrap();
+        let err = read(&other).unwrap_err();
+        assert!(err.to_string().contains("not a config file"), "{err}");
+    }
+    #[test]
+    fn test_bytes() {
+        let run = |source: &str| run_lisp_to_string(source, "-").unwrap();
+        assert_eq!(run("#u8(1 2 255)"), "#u8(1 2 255)");
+        assert_eq!(run("(equal? #u8(1 2) (bytes 1 2))"), ":true");
+        assert_eq!(run("(bytes? #u8())"), ":true");
+        assert_eq!(run("(bytes-length #u8(1 2 3))"), "3");
+        assert_eq!(run("(bytes->list (bytes-slice #u8(1 2 3 4) 1 3))"), "(2 3)");
+        assert_eq!(run("(bytes-slice #u8(1 2 3 4) 2)"), "#u8(3 4)");
+        assert_eq!(run("(bytes->hex #u8(0 15 171 255))"), "000fabff");
+        assert_eq!(run(r#"(hex->bytes "000FabfF")"#), "#u8(0 15 171 255)");
+        // Each length that leaves a different amount of padding.
+        for (text, base64) in [("", ""), ("M", "TQ=="), ("Ma", "TWE="), ("Man", "TWFu")] {
+            let hex: String = text.bytes().map(|b| format!("{b:02x}")).collect();
+            let encoded = run(&format!(r#"(bytes->base64 (hex->bytes "{hex}"))"#));
+            assert_eq!(encoded, base64);
+            let decoded = format!(r#"(bytes->hex (base64->bytes "{base64}"))"#);
+            assert_eq!(run(&decoded), hex);
+        }
+        assert_eq!(run(r#"(base64->bytes "TWE")"#), "#u8(77 97)");
+        let dir = TempDir::new("bytes");
+        let path = dir.join("data.bin");
+        std::fs::write(&path, [0, 1, 254]).unwrap();
+        let read = format!(r#"(read-bytes "{}")"#, path.display());
+        assert_eq!(run(&read), "#u8(0 1 254)");
+        for (source, message) in [
+            ("#u8(1 256)", "`256` is not a byte"),
+            (
+                "(bytes-slice #u8(1 2) 1 3)",
+                "Can't take the bytes from 1 to 3",
+            ),
+            (r#"(hex->bytes "abc")"#, "is not hexadecimal"),
+            (r#"(hex->bytes "+f")"#, "is not hexadecimal"),
+            (r#"(base64->bytes "T*==")"#, "is not base64"),
+            (r#"(bytes->hex "ab")"#, "needs bytes"),
+        ] {
+            let err = run_lisp_to_string(source, "-").unwrap_err();
+            assert!(err.to_string().contains(message), "{err}");
+        }
+        let formatted = format_source("(print  #u8(1 2))", "-", &FormatOptions::default());
+        assert_eq!(formatted.unwrap(), "(print #u8(1 2))\n");
+    }
+    #[test]
+    fn test_sockets() {
+        // Each server only accepts one connection, so each source is only evaluated once.
+        use crate::run_lisp_to_string;
+        use std::io::{Read, Write};
+        // Echoes back what each connection sends, in capitals.
+        fn echo(mut stream: impl Read + Write) {
+            let mut buf = [0; 64];
+            let n = stream.read(&mut buf).unwrap();
+            stream.write_all(&buf[..n].to_ascii_uppercase()).unwrap();
+        }
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            echo(stream);
+        });
+        let source = format!(
+            r#"(let ((s (connect "{address}" 2000)))
+                 list (send s "hello") (utf8->string (recv s)) (close s) (recv s) s)"#
+        );
+        let err = run_lisp_to_string(&source, "-").unwrap_err();
+        assert!(
+            err.to_string().contains(&format!(
+                "`recv` can't use `<Socket {address}>`, which has been closed"
+            )),
+            "{err}"
+        );
+        server.join().unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            echo(stream);
+        });
+        let source = format!(
+            r#"(let ((s (connect "{address}")))
+                 list (send s #u8(104 105)) (recv s 1) (recv s) (recv s) (socket? s))"#
+        );
+        assert_eq!(
+            run_lisp_to_string(&source, "-").unwrap(),
+            "(2 #u8(72) #u8(73) nil :true)"
+        );
+        server.join().unwrap();
+        #[cfg(unix)]
+        {
+            let dir = TempDir::new("socket");
+            let path = dir.join("echo.sock");
+            let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+            let server = std::thread::spawn(move || echo(listener.accept().unwrap().0));
+            let source = format!(
+                r#"(let ((s (connect "unix:{}"))) list (send s "ok") (utf8->string (recv s)))"#,
+                path.display()
+            );
+            assert_eq!(run_lisp_to_string(&source, "-").unwrap(), "(2 OK)");
+            server.join().unwrap();
+        }
+        // Nothing answers, so waiting for it to runs out of time.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let source = format!(
+            r#"(let ((s (connect "{}" 50))) recv s)"#,
+            listener.local_addr().unwrap()
+        );
+        let err = run_lisp_to_string(&source, "-").unwrap_err();
+        assert!(err.to_string().contains("`recv` timed out"), "{err}");
+        let mut sandboxed = Interpreter::new().deny(Capability::Network);
+        assert!(sandboxed.eval(r#"(connect "127.0.0.1:1")"#, "-").is_err());
+    }
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_regex() {
+        let run = |source| run_lisp_to_string(source, "-").unwrap();
+        assert_eq!(run(r#"(regex-match? "^\d+$" "123")"#), ":true");
+        assert_eq!(run(r#"(regex-match? "^\d+$" "12a")"#), "nil");
+        assert_eq!(run(r#"(regex-find "[a-z]+" "12 abc 34")"#), "abc");
+        assert_eq!(run(r#"(regex-find "[a-z]+" "12")"#), "nil");
+        assert_eq!(
+            run(r#"(regex-captures "(\w+)@(\w+)(\.org)?" "me@pale")"#),
+            "(me@pale me pale nil)"
+        );
+        assert_eq!(
+            run(r#"(regex-captures "(?<key>\w+)=(\d+)" "port=80")"#),
+            "{0 port=80 1 port key port 2 80}"
+        );
+        assert_eq!(
+            run(r#"(regex-replace "(\w+)=(\w+)" "a=1 b=2" "$2=$1")"#),
+            "1=a 2=b"
+        );
+        let err = run_lisp_to_string(r#"(regex-find "(a" "a")"#, "-").unwrap_err();
+        assert!(
+            err.to_string().contains("not a valid regular expression"),
+            "{err}"
+        );
+    }
+    #[cfg(feature = "net")]
+    #[test]
+    fn test_http() {
+        // Each server only accepts one connection, so each source is only evaluated once.
+        use crate::run_lisp_to_string;
+        use std::io::{BufRead, BufReader, Read, Write};
+        // Answers each request with its method, its `x-name` header and its body.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            for stream in listener.incoming().take(3) {
+                let mut reader = BufReader::new(stream.unwrap());
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                let (mut name, mut length) = (String::new(), 0);
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let Some((key, value)) = line.trim_end().split_once(": ") else {
+                        break;
+                    };
+                    match key.to_lowercase().as_str() {
+                        "x-name" => name = value.to_string(),
+                        "content-length" => length = value.parse().unwrap(),
+                        _ => {}
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let method = request.split(' ').next().unwrap();
+                let status = if request.contains("/missing") {
+                    "404 Not Found"
+                } else {
+                    "200 OK"
+                };
+                let reply = format!("{method} {name} {}", String::from_utf8(body).unwrap());
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {status}\r\nX-Reply: yes\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{reply}",
+                    reply.len()
+                )
+                .unwrap();
+            }
+        });
+        let run = |source: String| run_lisp_to_string(&source, "-").unwrap();
+        assert_eq!(
+            run(format!(r#"(http-get "{url}/" {{"x-name" "pale"}})"#)),
+            "{:status 200 :headers {x-reply yes content-length 9 connection close} :body GET pale }"
+        );
+        assert_eq!(
+            run(format!(r#"(http-post "{url}/" "hi")"#)),
+            "{:status 200 :headers {x-reply yes content-length 8 connection close} :body POST  hi}"
+        );
+        assert_eq!(
+            run(format!(r#"(http-get "{url}/missing")"#)),
+            "{:status 404 :headers {x-reply yes content-length 5 connection close} :body GET  }"
+        );
+        server.join().unwrap();
+        let err = run_lisp_to_string(r#"(http-get "not a url")"#, "-").unwrap_err();
+        assert!(err.to_string().contains("Could not reach"), "{err}");
+        let mut sandboxed = Interpreter::new().deny(Capability::Network);
+        assert!(sandboxed
+            .eval(&format!(r#"(http-get "{url}")"#), "-")
+            .is_err());
+    }
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json() {
+        // Strings can't have `"` in them, so the JSON is put together from code points.
+        let parsed = r#"(json-parse (list->string ["{" 34 "name" 34 ": " 34 "pale" 34 ", " 34 "tags" 34 ": [1, 2.5, true, false, null]}"]))"#;
+        assert_eq!(
+            run_lisp_to_string(parsed, "-").unwrap(),
+            "{name pale tags (1 2.5 :true nil nil)}"
+        );
+        let written = r#"(json-stringify {:a [1 "x" :true nil] "b" {1 2.5}})"#;
+        assert_eq!(
+            run_lisp_to_string(written, "-").unwrap(),
+            r#"{"a":[1,"x",true,null],"b":{"1":2.5}}"#
+        );
+        let round_trip = r#"(json-parse (json-stringify {"a" [1 {"b" -2}]}))"#;
+        assert_eq!(
+            run_lisp_to_string(round_trip, "-").unwrap(),
+            "{a (1 {b -2})}"
+        );
+        let dated = "(json-stringify [(date 2024 3 1 9 30)])";
+        assert_eq!(
+            run_lisp_to_string(dated, "-").unwrap(),
+            r#"["2024-03-01T09:30:00Z"]"#
+        );
+        let err = run_lisp_to_string(r#"(json-parse "[1,")"#, "-").unwrap_err();
+        assert!(err.to_string().contains("Could not parse JSON"), "{err}");
+        let err = run_lisp_to_string("(json-stringify [car])", "-").unwrap_err();
+        assert!(
+            err.to_string().contains("can't be written as JSON"),
+            "{err}"
         );
+        let nested = |depth| {
+            format!(
+                "(do ((i 0 (+ i 1)) (l nil (cons l nil))) ((eqv? i {depth}) (json-stringify l)))"
+            )
+        };
+        assert!(run_lisp_to_string(&nested(100), "-").is_ok());
+        let err = run_lisp_to_string(&nested(200000), "-").unwrap_err();
+        assert!(err.to_string().contains("nested too deeply"), "{err}");
     }
 }
@@ -0,0 +1,422 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::ast::{intrinsics, trace_program, Expr, Lambda, NameUse, Pattern, Scope, Var};
+use crate::callable::IntrinsicOp;
+use crate::error::LispErrors;
+use crate::options::Options;
+use crate::symbols::SymbolKind;
+use crate::sync::Shared;
+use crate::tokens::{tokenize_with, Location};
+use crate::types::LispType;
+
+// TODO(#synth-3851): A `shadowed-binding` rule, once an edition lets `let` shadow names.
+// Until then shadowing is an error while parsing, so there is nothing for it to find.
+/// A check that [`lint`] makes, which can be allowed, warned about or denied by its id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Rule {
+    /// A `let` binding or a parameter that is never used. Names starting with `_` are left alone.
+    UnusedBinding,
+    /// A call to an intrinsic, or to a function bound by `let`, with a number of arguments that it doesn't take.
+    SuspiciousArity,
+    /// An `if` whose condition is a literal, so that it always goes the same way.
+    ConstantCondition,
+    /// Code after a call to `exit` that would be evaluated after it, like a later argument or top-level form, which never is.
+    UnreachableCode,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 4] = [
+        Rule::UnusedBinding,
+        Rule::SuspiciousArity,
+        Rule::ConstantCondition,
+        Rule::UnreachableCode,
+    ];
+
+    /// The name that the rule is allowed or denied by, like `unused-binding`.
+    pub fn id(self) -> &'static str {
+        match self {
+            Rule::UnusedBinding => "unused-binding",
+            Rule::SuspiciousArity => "suspicious-arity",
+            Rule::ConstantCondition => "constant-condition",
+            Rule::UnreachableCode => "unreachable-code",
+        }
+    }
+
+    // The pass over the program that finds what the rule is about.
+    fn pass(self) -> fn(&Context) -> Vec<Finding> {
+        match self {
+            Rule::UnusedBinding => unused_binding,
+            Rule::SuspiciousArity => suspicious_arity,
+            Rule::ConstantCondition => constant_condition,
+            Rule::UnreachableCode => unreachable_code,
+        }
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.id())
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Rule::ALL
+            .into_iter()
+            .find(|rule| rule.id() == s)
+            .ok_or_else(|| format!("Unknown lint `{s}`!"))
+    }
+}
+
+/// What to do about what a [`Rule`] finds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Allow,
+    Warn,
+    /// Reports it as an error.
+    Deny,
+}
+
+impl FromStr for Level {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(Level::Allow),
+            "warn" => Ok(Level::Warn),
+            "deny" => Ok(Level::Deny),
+            _ => Err(format!(
+                "Unknown level `{s}`! Use `allow`, `warn` or `deny`."
+            )),
+        }
+    }
+}
+
+/// The level of each rule. Every rule warns unless it is set to something else.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LintConfig {
+    levels: BTreeMap<Rule, Level>,
+}
+
+impl LintConfig {
+    /// Reads a config file, which has a `rule = level` on each line, like `unused-binding = allow`.
+    /// Lines starting with `#` are comments.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = LintConfig::default();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parsed = match line.split_once('=') {
+                Some((rule, level)) => rule
+                    .trim()
+                    .parse()
+                    .and_then(|rule| Ok((rule, level.trim().parse()?))),
+                None => Err("Expected `rule = level`!".to_string()),
+            };
+            let (rule, level) = parsed.map_err(|e| format!("line {}: {e}", n + 1))?;
+            config.set(rule, level);
+        }
+        Ok(config)
+    }
+
+    pub fn level(&self, rule: Rule) -> Level {
+        self.levels.get(&rule).copied().unwrap_or(Level::Warn)
+    }
+
+    pub fn set(&mut self, rule: Rule, level: Level) {
+        self.levels.insert(rule, level);
+    }
+}
+
+// What a rule found, before its level decides whether it is a warning or an error.
+struct Finding {
+    loc: Location,
+    message: String,
+    notes: Vec<(Option<Location>, String)>,
+}
+
+impl Finding {
+    fn new<T: Display>(loc: &Location, message: T) -> Self {
+        Self {
+            loc: loc.clone(),
+            message: message.to_string(),
+            notes: Vec::new(),
+        }
+    }
+
+    fn note<'a, T: Display, L: Into<Option<&'a Location>>>(mut self, loc: L, note: T) -> Self {
+        let loc: Option<&Location> = loc.into();
+        self.notes.push((loc.cloned(), note.to_string()));
+        self
+    }
+}
+
+// What the passes look at: the program, every name in it, and the slots the intrinsics are bound to.
+struct Context {
+    forms: Vec<Shared<Expr>>,
+    uses: Vec<NameUse>,
+    intrinsics: Vec<(&'static str, Var, IntrinsicOp)>,
+}
+
+impl Context {
+    // Calls `f` on every expression in the program, each one before the ones inside of it.
+    fn walk(&self, mut f: impl FnMut(&Expr)) {
+        fn visit(expr: &Expr, f: &mut impl FnMut(&Expr)) {
+            f(expr);
+            match expr {
+                Expr::Literal(_) | Expr::Var(_) | Expr::Import(_) => {}
+                Expr::Call { op, args, tail, .. } => {
+                    visit(op, f);
+                    for arg in args.iter().chain(tail) {
+                        visit(arg, f);
+                    }
+                }
+                Expr::Let { bindings, body } => {
+                    for (_, value) in bindings {
+                        visit(value, f);
+                    }
+                    if let Some(body) = body {
+                        visit(body, f);
+                    }
+                }
+                Expr::Parameterize { bindings, body, .. } => {
+                    for (parameter, value) in bindings {
+                        visit(parameter, f);
+                        visit(value, f);
+                    }
+                    visit(body, f);
+                }
+                Expr::If {
+                    cond,
+                    then,
+                    otherwise,
+                    ..
+                } => {
+                    for part in [cond, then].into_iter().chain(otherwise) {
+                        visit(part, f);
+                    }
+                }
+                Expr::Lambda(lambda) => visit(&lambda.body, f),
+                Expr::Do(l) => {
+                    let inits = l.vars.iter().map(|(_, init)| init);
+                    let parts = [&l.test].into_iter().chain(&l.result).chain(&l.body);
+                    for part in inits.chain(parts).chain(&l.steps) {
+                        visit(part, f);
+                    }
+                }
+                Expr::Seq(exprs) => {
+                    for expr in exprs {
+                        visit(expr, f);
+                    }
+                }
+            }
+        }
+        for form in &self.forms {
+            visit(form, &mut f);
+        }
+    }
+}
+
+fn unused_binding(cx: &Context) -> Vec<Finding> {
+    cx.uses
+        .iter()
+        .filter(|def| match def.binds {
+            // `--call-main` calls `main`, which nothing else has to.
+            Some(SymbolKind::Let) => def.name != "main",
+            Some(SymbolKind::Parameter) => true,
+            // A module can provide more than what each file that imports it needs.
+            Some(SymbolKind::Import) | None => false,
+        })
+        .filter(|def| !def.name.starts_with('_'))
+        .filter(|def| {
+            !cx.uses
+                .iter()
+                .any(|u| u.binds.is_none() && u.var.same(&def.var))
+        })
+        .map(|def| {
+            Finding::new(&def.loc, format!("`{}` is never used.", def.name))
+                .note(None, "Start its name with `_` if that is on purpose.")
+        })
+        .collect()
+}
+
+fn suspicious_arity(cx: &Context) -> Vec<Finding> {
+    let mut found = Vec::new();
+    // The functions bound by `let`, which stay bound to the same function since nothing can change what a name is bound to.
+    let mut functions: Vec<(Var, Shared<Lambda>)> = Vec::new();
+    cx.walk(|expr| match expr {
+        Expr::Let { bindings, .. } => {
+            for (pattern, value) in bindings {
+                if let (Pattern::Name(place), Expr::Lambda(lambda)) = (&**pattern, &**value) {
+                    functions.push((place.id().new_ref(), Shared::clone(lambda)));
+                }
+            }
+        }
+        // How many arguments come after a `.` isn't known until it is evaluated.
+        Expr::Call {
+            op,
+            args,
+            tail: None,
+            loc,
+            ..
+        } => {
+            let Expr::Var(place) = &**op else {
+                return;
+            };
+            let var = place.id();
+            if let Some((_, lambda)) = functions.iter().find(|(f, _)| f.same(var)) {
+                let takes = match lambda.arity() {
+                    (min, Some(max)) if min == max && args.len() != min => min.to_string(),
+                    (min, _) if args.len() < min => format!("at least {min}"),
+                    (_, Some(max)) if args.len() > max => format!("at most {max}"),
+                    _ => return,
+                };
+                found.push(
+                    Finding::new(
+                        loc,
+                        format!(
+                            "This function takes {takes} argument(s), but is given {}.",
+                            args.len()
+                        ),
+                    )
+                    .note(&lambda.loc, "It is defined here."),
+                );
+            } else if let Some((name, _, op)) = cx.intrinsics.iter().find(|(_, v, _)| v.same(var)) {
+                let takes = match op.arity() {
+                    (min, _) if args.len() < min => format!("at least {min}"),
+                    (_, Some(max)) if args.len() > max => format!("at most {max}"),
+                    _ => return,
+                };
+                found.push(Finding::new(
+                    loc,
+                    format!(
+                        "`{name}` takes {takes} argument(s), but is given {}.",
+                        args.len()
+                    ),
+                ));
+            }
+        }
+        _ => {}
+    });
+    found
+}
+
+fn constant_condition(cx: &Context) -> Vec<Finding> {
+    let mut found = Vec::new();
+    cx.walk(|expr| {
+        if let Expr::If { cond, loc, .. } = expr {
+            if let Expr::Literal(value) = &**cond {
+                let never = match value {
+                    LispType::Nil => "first",
+                    _ => "second",
+                };
+                found.push(
+                    Finding::new(
+                        loc,
+                        format!("The condition of this `if` is always `{value}`."),
+                    )
+                    .note(
+                        None,
+                        format!("Its {never} value is never used, since only `nil` is false."),
+                    ),
+                );
+            }
+        }
+    });
+    found
+}
+
+fn unreachable_code(cx: &Context) -> Vec<Finding> {
+    let Some((_, exit, _)) = cx
+        .intrinsics
+        .iter()
+        .find(|(_, _, op)| *op == IntrinsicOp::Exit)
+    else {
+        return Vec::new();
+    };
+    // Where the expression calls `exit`, if it is a call to it.
+    let exits = |expr: &Expr| match expr {
+        Expr::Call { op, loc, .. } => match &**op {
+            Expr::Var(place) if place.id().same(exit) => Some(loc.clone()),
+            _ => None,
+        },
+        _ => None,
+    };
+    let mut found = Vec::new();
+    // Expressions that are evaluated one after another, along with where each one starts if that is known.
+    let mut check = |exprs: &mut dyn Iterator<Item = &Shared<Expr>>, locs: &[Location]| {
+        let mut exprs = exprs.enumerate();
+        let Some(loc) = exprs.by_ref().find_map(|(_, expr)| exits(expr)) else {
+            return;
+        };
+        if let Some((next, _)) = exprs.next() {
+            let finding = Finding::new(&loc, "Nothing after this `exit` is ever evaluated.");
+            found.push(match locs.get(next) {
+                Some(at) => finding.note(at, "This is never reached."),
+                None => finding,
+            });
+        }
+    };
+    cx.walk(|expr| match expr {
+        Expr::Call {
+            args,
+            tail,
+            arg_locs,
+            ..
+        } => check(&mut args.iter().chain(tail), arg_locs),
+        Expr::Seq(exprs) => check(&mut exprs.iter(), &[]),
+        _ => {}
+    });
+    check(&mut cx.forms.iter(), &[]);
+    found
+}
+
+/// Checks the source for code that is probably a mistake, without running it.
+///
+/// The rules are only checked once the source can be read, so until then this gives the errors that stop it from being read.
+/// Otherwise it gives what the rules found, as warnings or as errors for the rules that are denied.
+pub fn lint(source: &str, file: &str, options: &Options, config: &LintConfig) -> LispErrors {
+    let toks = match tokenize_with(source, file, options) {
+        Ok(toks) => toks,
+        Err(errs) => return errs,
+    };
+    let mut scope = Scope::new(options);
+    let (program, uses) = trace_program(&toks.tokens, &mut scope, toks.edition, options);
+    let mut diagnostics = toks.warnings;
+    let program = match program {
+        Ok(program) => program,
+        Err(errs) => {
+            diagnostics.extend(errs);
+            return diagnostics;
+        }
+    };
+    let cx = Context {
+        forms: program.forms,
+        uses,
+        intrinsics: intrinsics()
+            .into_iter()
+            .filter_map(|(name, op)| Some((name, scope.global(name)?.new_ref(), op)))
+            .collect(),
+    };
+    for rule in Rule::ALL {
+        let level = config.level(rule);
+        if level == Level::Allow {
+            continue;
+        }
+        for finding in rule.pass()(&cx) {
+            let mut diagnostic = match level {
+                Level::Deny => LispErrors::new().error(&finding.loc, finding.message),
+                _ => LispErrors::new().warning(&finding.loc, finding.message),
+            };
+            for (loc, note) in &finding.notes {
+                diagnostic = diagnostic.note(loc, note);
+            }
+            diagnostics.extend(diagnostic.note(None, format!("From the `{rule}` lint.")));
+        }
+    }
+    diagnostics
+}
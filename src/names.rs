@@ -0,0 +1,39 @@
+use std::collections::HashMap;
+
+use crate::sync::Shared;
+
+/// A name that has been interned in a [`Names`], so that it can be copied, compared and hashed as cheaply as a number.
+/// Names from different tables can't be compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) struct Name(u32);
+
+/// The symbol table of a scope and the modules it imports, which gives every name that is bound in them an id.
+/// It goes away with the interpreter, so the names that scripts use aren't kept for as long as the process runs.
+#[derive(Debug, Default)]
+pub(crate) struct Names {
+    ids: HashMap<Shared<str>, Name>,
+    texts: Vec<Shared<str>>,
+}
+
+impl Names {
+    /// The name with the text, which is given the next id if it hasn't been interned yet.
+    pub(crate) fn intern(&mut self, text: &str) -> Name {
+        if let Some(&name) = self.ids.get(text) {
+            return name;
+        }
+        let name = Name(u32::try_from(self.texts.len()).expect("Fewer than 2^32 names are bound"));
+        let text: Shared<str> = text.into();
+        self.texts.push(Shared::clone(&text));
+        self.ids.insert(text, name);
+        name
+    }
+
+    /// The name with the text if it has been interned, which it hasn't if nothing has ever been bound to it.
+    pub(crate) fn find(&self, text: &str) -> Option<Name> {
+        self.ids.get(text).copied()
+    }
+
+    pub(crate) fn text(&self, name: Name) -> &str {
+        &self.texts[name.0 as usize]
+    }
+}
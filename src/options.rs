@@ -0,0 +1,204 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::{fmt::Display, str::FromStr, time::Duration};
+
+use crate::events::Events;
+use crate::profile::Profiler;
+use crate::readers::Readers;
+use crate::streams::{Input, Output};
+
+/// A version of the language. Changes that would break existing scripts only happen in a new edition, and a script can say which edition it was written for by starting with `(pale-edition 1)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Edition {
+    #[default]
+    One,
+}
+
+impl Edition {
+    pub const LATEST: Edition = Edition::One;
+
+    pub fn from_number(n: isize) -> Option<Self> {
+        match n {
+            1 => Some(Edition::One),
+            _ => None,
+        }
+    }
+
+    pub fn number(self) -> isize {
+        match self {
+            Edition::One => 1,
+        }
+    }
+
+    /// Whether `$` opens a statement that lasts until the next closing parenthesis, rather than being part of a name.
+    pub fn dollar_operator(self) -> bool {
+        match self {
+            Edition::One => true,
+        }
+    }
+
+    /// Whether `let` can bind a name that is already defined.
+    pub fn allows_shadowing(self) -> bool {
+        match self {
+            Edition::One => false,
+        }
+    }
+}
+
+impl Display for Edition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.number())
+    }
+}
+
+impl FromStr for Edition {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.trim()
+            .parse()
+            .ok()
+            .and_then(Edition::from_number)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown edition `{s}`! The latest edition is {}.",
+                    Edition::LATEST
+                )
+            })
+    }
+}
+
+/// Settings that change how Pale reads and runs source code.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Closes statements that are left open at the end of the source (with a warning), and ignores extra closing parentheses at the end of the source.
+    pub friendly_parens: bool,
+    /// After running the source, calls the `main` function with `main_args` as a list of strings if it has been defined.
+    pub call_main: bool,
+    pub main_args: Vec<String>,
+    /// The edition to use for source that doesn't start with `(pale-edition N)`.
+    pub edition: Edition,
+    /// Limits on the size of the source, for running source that can't be trusted.
+    pub limits: SourceLimits,
+    /// Limits on how much work running the source can do, for the same reason.
+    pub eval_limits: EvalLimits,
+    /// What stops evaluation early when it is triggered, from another thread or a Ctrl-C handler.
+    pub interrupt: InterruptHandle,
+    /// The timers that `after` and `every` set, which every clone of the options shares.
+    pub events: Events,
+    /// Records how many times each function is called and how long the calls take, if it is set.
+    pub profiler: Option<Profiler>,
+    /// Runs the source by compiling it to bytecode for a VM, rather than by walking through the parsed code.
+    /// The results are the same either way.
+    pub vm: bool,
+    /// Starts every scope with the functions that the prelude defines in Pale, like `inc` and `second`.
+    pub prelude: bool,
+    /// Handlers for `#name"..."` literals.
+    pub readers: Readers,
+    /// Where `print` writes to.
+    pub on_output: Output,
+    /// Where `eprint` writes to.
+    pub on_error_output: Output,
+    /// Where `read-line` reads from.
+    pub input: Input,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            friendly_parens: false,
+            call_main: false,
+            main_args: Vec::new(),
+            edition: Edition::default(),
+            limits: SourceLimits::default(),
+            eval_limits: EvalLimits::default(),
+            interrupt: InterruptHandle::default(),
+            events: Events::default(),
+            profiler: None,
+            vm: false,
+            prelude: true,
+            readers: Readers::default(),
+            on_output: Output::stdout(),
+            on_error_output: Output::stderr(),
+            input: Input::stdin(),
+        }
+    }
+}
+
+/// How big source code is allowed to get before it is rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceLimits {
+    /// How deeply statements can be nested, counting each `$` as a level.
+    pub max_depth: usize,
+    /// How many tokens the source can have, including the ones added by sugar like `[` and `$`.
+    pub max_tokens: usize,
+}
+
+impl Default for SourceLimits {
+    fn default() -> Self {
+        SourceLimits {
+            max_depth: 256,
+            max_tokens: 1 << 20,
+        }
+    }
+}
+
+/// How much work evaluating is allowed to do before it is stopped with a [`LimitExceeded`](crate::LimitExceeded) error.
+/// `None` means there is no limit, which is the default for all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EvalLimits {
+    /// How many calls can be made, counting calls to intrinsics and each time a `do` loop goes around.
+    pub max_steps: Option<u64>,
+    /// How many calls can be in progress at once, e.g. how deep recursion can go.
+    pub max_depth: Option<usize>,
+    /// How long evaluating can take, counted from when it starts.
+    pub timeout: Option<Duration>,
+}
+
+/// Stops whatever evaluation is running with the options it belongs to, which [`Interpreter::interrupt_handle`](crate::Interpreter::interrupt_handle) gives.
+/// It can be cloned and sent to other threads, and every clone stops the same evaluations.
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle(Arc<AtomicBool>);
+
+impl InterruptHandle {
+    /// Stops the evaluation that is running at the next call or time around a loop, which fails with an error whose
+    /// [`exceeded()`](crate::LispErrors::exceeded) is [`LimitExceeded::Interrupted`](crate::LimitExceeded::Interrupted).
+    /// Evaluations started after it aren't stopped, and it does nothing if nothing is running.
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    // Forgets an interrupt that came before the evaluation that is starting.
+    pub(crate) fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_interrupted(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// How much of a value to print, so that printing a huge list doesn't flood the terminal.
+/// `None` means there is no limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrintLimits {
+    /// How many items of a list or entries of a map to print before eliding the rest.
+    pub length: Option<usize>,
+    /// How deeply nested lists and maps can be before their contents are elided.
+    pub depth: Option<usize>,
+}
+
+impl PrintLimits {
+    pub const NONE: PrintLimits = PrintLimits {
+        length: None,
+        depth: None,
+    };
+}
+
+impl Default for PrintLimits {
+    fn default() -> Self {
+        PrintLimits {
+            length: Some(100),
+            depth: None,
+        }
+    }
+}
@@ -0,0 +1,107 @@
+#[cfg(feature = "sync")]
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::ast::Var;
+use crate::callable::Callable;
+use crate::error::LispErrors;
+#[cfg(feature = "sync")]
+use crate::eval::Inherited;
+use crate::eval::{nested, purely};
+use crate::types::LispType;
+use crate::Location;
+
+/// `(pmap function list)`: calls the function on each item of the list, on as many threads at once as there are cores, giving the results in the same order.
+/// The function can only call pure functions, so that it doesn't matter which thread does which item or when.
+pub(crate) fn pmap(function: &Var, list: &Var, loc: &Location) -> Result<Var, LispErrors> {
+    let list = list.resolve();
+    let items = match &*list.get() {
+        LispType::List(items) => items.iter().map(Var::new_ref).collect(),
+        LispType::Nil => Vec::new(),
+        other => {
+            return Err(LispErrors::new().error(
+                loc,
+                format!("`pmap` needs a list, but was given `{other}`!"),
+            ))
+        }
+    };
+    let function = function.resolve();
+    let f = function.get();
+    let LispType::Func(f) = &*f else {
+        return Err(LispErrors::new()
+            .error(
+                loc,
+                format!("`pmap` needs a function, but was given `{f}`!"),
+            )
+            .note(None, "Write it like `(pmap (lambda (x) ...) list)`."));
+    };
+    if !f.is_pure() {
+        return Err(LispErrors::new()
+            .error(loc, "`pmap` can only be given a pure function!")
+            .note(
+                None,
+                "Functions that do more than compute their result, like `print`, can't be called from several threads at once.",
+            ));
+    }
+    let results = map_all(&**f, &items, loc)?;
+    match results.is_empty() {
+        true => Ok(Var::new(LispType::Nil)),
+        false => Ok(Var::new(LispType::List(results))),
+    }
+}
+
+// Without the `sync` feature values can't be sent to other threads, so the items are done one after another.
+#[cfg(not(feature = "sync"))]
+fn map_all(f: &dyn Callable, items: &[Var], loc: &Location) -> Result<Vec<Var>, LispErrors> {
+    purely(loc, || {
+        items
+            .iter()
+            .map(|item| nested(f, &[item.new_ref()], loc))
+            .collect()
+    })
+}
+
+// Each worker takes the next item that nobody has taken until they run out, or one of them fails.
+// Items are taken in order, so every item before one that failed has been done, and the error is the same one as doing them in order would give.
+#[cfg(feature = "sync")]
+fn map_all(f: &dyn Callable, items: &[Var], loc: &Location) -> Result<Vec<Var>, LispErrors> {
+    let workers = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(items.len());
+    let (next, failed) = (&AtomicUsize::new(0), &AtomicBool::new(false));
+    let mut results: Vec<Option<Result<Var, LispErrors>>> = items.iter().map(|_| None).collect();
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                let inherited = Inherited::capture();
+                scope.spawn(move || {
+                    inherited.install();
+                    let mut done = Vec::new();
+                    purely(loc, || {
+                        while !failed.load(Ordering::Relaxed) {
+                            let i = next.fetch_add(1, Ordering::Relaxed);
+                            let Some(item) = items.get(i) else { break };
+                            let result = nested(f, &[item.new_ref()], loc);
+                            if result.is_err() {
+                                failed.store(true, Ordering::Relaxed);
+                            }
+                            done.push((i, result));
+                        }
+                    });
+                    done
+                })
+            })
+            .collect();
+        for handle in handles {
+            let done = handle
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+            for (i, result) in done {
+                results[i] = Some(result);
+            }
+        }
+    });
+    results
+        .into_iter()
+        .map(|result| result.expect("every item before one that failed has been done"))
+        .collect()
+}
@@ -0,0 +1,138 @@
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+
+use crate::ast::Var;
+use crate::error::LispErrors;
+use crate::eval::streams;
+use crate::streams::{Input, Output};
+use crate::sync::{exclusive, Mutable, Shared};
+use crate::types::LispType;
+use crate::Location;
+
+/// Something to read characters from or write text to, like a string, a file, or the interpreter's own input and output.
+/// Like sockets, every copy of a port is the same one, so closing one closes them all.
+#[derive(Debug)]
+pub(crate) struct Port {
+    name: String,
+    kind: Mutable<Option<PortKind>>, // `None` once it is closed
+}
+
+#[derive(Debug)]
+enum PortKind {
+    Input(Input),
+    // Output ports made by `open-output-string` keep what was written to them for `get-output-string`.
+    Output(Output, Option<Shared<Mutable<Vec<u8>>>>),
+}
+
+impl std::fmt::Display for Port {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<Port {}>", self.name)
+    }
+}
+
+fn port(name: impl Into<String>, kind: PortKind) -> Var {
+    Var::new(LispType::Port(Shared::new(Port {
+        name: name.into(),
+        kind: Mutable::new(Some(kind)),
+    })))
+}
+
+/// `(open-input-string s)`: a port that reads the characters of the string.
+pub(crate) fn input_string(text: String) -> Var {
+    let input = Input::reader(Cursor::new(text.into_bytes()));
+    port("input string", PortKind::Input(input))
+}
+
+/// `(open-output-string)`: a port that keeps what is written to it, for `get-output-string` to give.
+pub(crate) fn output_string() -> Var {
+    let buffer = Shared::new(Mutable::new(Vec::new()));
+    let output = Output::shared(buffer.clone());
+    port("output string", PortKind::Output(output, Some(buffer)))
+}
+
+/// `(open-input-file path)`: a port that reads the file.
+pub(crate) fn input_file(path: &str, loc: &Location) -> Result<Var, LispErrors> {
+    let file = File::open(path)
+        .map_err(|e| LispErrors::new().error(loc, format!("Could not open `{path}`: {e}")))?;
+    let input = Input::reader(BufReader::new(file));
+    Ok(port(format!("input {path}"), PortKind::Input(input)))
+}
+
+/// `(open-output-file path)`: a port that writes to the file, which is emptied first if it already exists.
+pub(crate) fn output_file(path: &str, loc: &Location) -> Result<Var, LispErrors> {
+    let file = File::create(path)
+        .map_err(|e| LispErrors::new().error(loc, format!("Could not create `{path}`: {e}")))?;
+    Ok(port(
+        format!("output {path}"),
+        PortKind::Output(Output::writer(file), None),
+    ))
+}
+
+/// `(current-input-port)`: a port that reads from wherever `read-line` does.
+pub(crate) fn current_input() -> Var {
+    port("current input", PortKind::Input(streams().input))
+}
+
+/// `(current-output-port)`: a port that writes to wherever `print` does.
+pub(crate) fn current_output() -> Var {
+    port("current output", PortKind::Output(streams().out, None))
+}
+
+impl Port {
+    // The reason that the intrinsic called `name` can't use the port, which has been closed or goes the wrong way.
+    fn unusable(&self, name: &str, problem: &str, loc: &Location) -> LispErrors {
+        LispErrors::new().error(loc, format!("`{name}` can't use `{self}`, {problem}!"))
+    }
+
+    /// `(read-char port)`: the next character as a string, or `nil` at the end.
+    pub(crate) fn read_char(&self, loc: &Location) -> Result<Var, LispErrors> {
+        let input = match &*exclusive(&self.kind) {
+            Some(PortKind::Input(input)) => input.clone(),
+            Some(PortKind::Output(..)) => {
+                return Err(self.unusable("read-char", "which is for output", loc))
+            }
+            None => return Err(self.unusable("read-char", "which has been closed", loc)),
+        };
+        match input.read_char() {
+            Ok(Some(c)) => Ok(Var::new(c.to_string())),
+            Ok(None) => Ok(Var::new(LispType::Nil)),
+            Err(e) => {
+                Err(LispErrors::new().error(loc, format!("Could not read from `{self}`: {e}")))
+            }
+        }
+    }
+
+    /// `(write-string s port)`: writes the string to the port.
+    pub(crate) fn write_string(&self, text: &str, loc: &Location) -> Result<(), LispErrors> {
+        let output = match &*exclusive(&self.kind) {
+            Some(PortKind::Output(output, _)) => output.clone(),
+            Some(PortKind::Input(_)) => {
+                return Err(self.unusable("write-string", "which is for input", loc))
+            }
+            None => return Err(self.unusable("write-string", "which has been closed", loc)),
+        };
+        output
+            .write(text)
+            .map_err(|e| LispErrors::new().error(loc, format!("Could not write to `{self}`: {e}")))
+    }
+
+    /// `(get-output-string port)`: everything that has been written to a port made by `open-output-string`.
+    pub(crate) fn output_string(&self, loc: &Location) -> Result<Var, LispErrors> {
+        match &*exclusive(&self.kind) {
+            Some(PortKind::Output(_, Some(buffer))) => Ok(Var::new(
+                String::from_utf8_lossy(&exclusive(buffer)).into_owned(),
+            )),
+            Some(_) => Err(self.unusable(
+                "get-output-string",
+                "which wasn't made by `open-output-string`",
+                loc,
+            )),
+            None => Err(self.unusable("get-output-string", "which has been closed", loc)),
+        }
+    }
+
+    /// `(close-port port)`: closes the port, after which it can't be used. Closing it again does nothing.
+    pub(crate) fn close(&self) {
+        exclusive(&self.kind).take();
+    }
+}
@@ -0,0 +1,282 @@
+use crate::error::LispErrors;
+use crate::options::Options;
+use crate::tokens::{tokenize_with_trivia, Location, Token, TokenType, Trivia};
+use crate::types::LispType;
+
+/// How `format_source` lays out code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// Statements longer than this are broken over several lines.
+    pub width: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self { width: 80 }
+    }
+}
+
+// The source as it was written, including sugar and comments, unlike `Form`.
+#[derive(Debug)]
+enum Node {
+    Atom {
+        loc: Location,
+        text: String,
+    },
+    Comment(Trivia),
+    List {
+        loc: Location,
+        open: char, // `$` statements have no closing delimiter
+        items: Vec<Node>,
+        close: Option<Location>,
+    },
+}
+
+impl Node {
+    fn start_line(&self) -> usize {
+        match self {
+            Node::Atom { loc, .. } | Node::List { loc, .. } => loc.line,
+            Node::Comment(t) => t.loc.line,
+        }
+    }
+
+    fn end_line(&self) -> usize {
+        match self {
+            Node::Atom { loc, text } => loc.line + text.matches('\n').count(),
+            Node::Comment(t) => t.loc.line + t.text.matches('\n').count(),
+            Node::List {
+                loc, items, close, ..
+            } => match (close, items.last()) {
+                (Some(c), _) => c.line,
+                (None, Some(last)) => last.end_line(),
+                (None, None) => loc.line,
+            },
+        }
+    }
+
+    // The node on a single line, if it can be put on one.
+    fn flat(&self) -> Option<String> {
+        match self {
+            Node::Atom { text, .. } if !text.contains('\n') => Some(text.clone()),
+            Node::Atom { .. } | Node::Comment(_) => None,
+            Node::List { open, items, .. } => {
+                let items = items.iter().map(Node::flat).collect::<Option<Vec<_>>>()?;
+                Some(match open {
+                    '$' if items.is_empty() => "$".to_string(),
+                    '$' => format!("$ {}", items.join(" ")),
+                    _ => format!("{open}{}{}", items.join(" "), closing(*open)),
+                })
+            }
+        }
+    }
+}
+
+fn closing(open: char) -> char {
+    match open {
+        '[' => ']',
+        '{' => '}',
+        _ => ')',
+    }
+}
+
+// Where each line starts in the source, so that locations can be turned into offsets.
+pub(crate) fn line_starts(source: &str) -> Vec<usize> {
+    let mut starts = vec![0];
+    starts.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+    starts
+}
+
+// The text of a token as it was written, since printing the token would lose things like `0x` and `_`.
+pub(crate) fn atom_text(source: &str, offset: usize, tok: &Token) -> String {
+    let rest = &source[offset..];
+    if let TokenType::Recognizable(LispType::Str(_)) = tok.dat {
+        let len = rest[1..].find('"').map_or(rest.len(), |i| i + 2);
+        return rest[..len].to_string();
+    }
+    let len = rest
+        .char_indices()
+        .find(|&(i, c)| c.is_whitespace() || "()[]{}$\"".contains(c) || rest[i..].starts_with("//"))
+        .map_or(rest.len(), |(i, _)| i);
+    rest[..len].to_string()
+}
+
+fn build(source: &str, file: &str) -> Result<Vec<Node>, LispErrors> {
+    let toks = tokenize_with_trivia(source, file, &Options::default())?;
+    let starts = line_starts(source);
+    let offset = |loc: &Location| starts[loc.line] + loc.col;
+
+    let mut trivia = toks.trivia.into_iter().peekable();
+    let mut stack: Vec<(Location, char, Vec<Node>)> = Vec::new();
+    let mut top = Vec::new();
+    let mut sugar = false; // Whether the next token is the intrinsic that `[` or `{` stands for
+    for tok in toks.tokens {
+        while let Some(t) =
+            trivia.next_if(|t| (t.loc.line, t.loc.col) < (tok.loc.line, tok.loc.col))
+        {
+            match stack.last_mut() {
+                Some((_, _, items)) => items.push(Node::Comment(t)),
+                None => top.push(Node::Comment(t)),
+            }
+        }
+        let node = match &tok.dat {
+            TokenType::StartStmt => {
+                let open = source[offset(&tok.loc)..].chars().next().unwrap_or('(');
+                sugar = matches!(open, '[' | '{' | '#');
+                stack.push((tok.loc, open, Vec::new()));
+                continue;
+            }
+            TokenType::Ident(_) if sugar => {
+                sugar = false;
+                continue;
+            }
+            // The closing delimiter also closes any `$` statements inside of it, which have no delimiter of their own.
+            TokenType::EndStmt => match stack.pop() {
+                // Interpolated strings and `#u8(...)` are kept as they were written, including what is inside of them.
+                Some((loc, '#', _)) => Node::Atom {
+                    text: source[offset(&loc)..=offset(&tok.loc)].to_string(),
+                    loc,
+                },
+                Some((loc, open, items)) => Node::List {
+                    loc,
+                    open,
+                    items,
+                    close: (open != '$').then_some(tok.loc),
+                },
+                None => {
+                    return Err(LispErrors::new()
+                        .error(&tok.loc, "Unmatched closing parentheses!")
+                        .note(None, "Delete it.")
+                        .syntax())
+                }
+            },
+            // `#name"..."` literals are kept as they were written.
+            TokenType::Ident(id) if id.starts_with('#') => Node::Atom {
+                text: id.clone(),
+                loc: tok.loc,
+            },
+            _ => Node::Atom {
+                text: atom_text(source, offset(&tok.loc), &tok),
+                loc: tok.loc,
+            },
+        };
+        match stack.last_mut() {
+            Some((_, _, items)) => items.push(node),
+            None => top.push(node),
+        }
+    }
+    if let Some((loc, _, _)) = stack.pop() {
+        return Err(LispErrors::new()
+            .error(&loc, "Unmatched opening parentheses!")
+            .note(None, "Deleting it might fix this error.")
+            .syntax());
+    }
+    top.extend(trivia.map(Node::Comment));
+    Ok(top)
+}
+
+struct Printer {
+    out: String,
+    width: usize,
+}
+
+impl Printer {
+    fn newline(&mut self, indent: usize) {
+        self.out.push('\n');
+        self.out.extend(std::iter::repeat_n(' ', indent));
+    }
+
+    // Writes the node starting at column `col`.
+    fn node(&mut self, node: &Node, col: usize) {
+        if let Some(flat) = node.flat() {
+            if col + flat.chars().count() <= self.width {
+                self.out.push_str(&flat);
+                return;
+            }
+        }
+        match node {
+            Node::Atom { text, .. } => self.out.push_str(text),
+            Node::Comment(t) => self.out.push_str(&t.text),
+            Node::List { open, items, .. } => {
+                let open_text = match open {
+                    '$' => "$ ".to_string(),
+                    o => o.to_string(),
+                };
+                self.out.push_str(&open_text);
+                // Calls indent their arguments, while the items of lists and maps line up.
+                let indent = match open {
+                    '[' | '{' => col + 1,
+                    _ => col + 2,
+                };
+                self.items(items, col + open_text.len(), indent);
+                if let Some(Node::Comment(t)) = items.last() {
+                    if t.text.starts_with("//") {
+                        self.newline(col);
+                    }
+                }
+                if *open != '$' {
+                    self.out.push(closing(*open));
+                }
+            }
+        }
+    }
+
+    // Writes the first item at `first`, and each one after it on its own line at `indent`.
+    // Comments that were on the same line as the item before them stay there.
+    fn items(&mut self, items: &[Node], first: usize, indent: usize) {
+        for (i, item) in items.iter().enumerate() {
+            if i == 0 {
+                self.node(item, first);
+                continue;
+            }
+            let prev = &items[i - 1];
+            if matches!(item, Node::Comment(_)) && item.start_line() == prev.end_line() {
+                self.out.push(' ');
+                self.node(item, 0);
+            } else {
+                self.newline(indent);
+                self.node(item, indent);
+            }
+        }
+    }
+}
+
+/// Prints the source back out with canonical indentation and spacing, keeping its comments.
+///
+/// Top-level statements are separated by at most one blank line, and statements that don't fit in the width are broken over several lines.
+pub fn format_source(
+    source: &str,
+    file: &str,
+    options: &FormatOptions,
+) -> Result<String, LispErrors> {
+    let nodes = build(source, file)?;
+    let mut printer = Printer {
+        out: String::with_capacity(source.len()),
+        width: options.width,
+    };
+    for (i, node) in nodes.iter().enumerate() {
+        if i != 0 {
+            let prev = &nodes[i - 1];
+            if matches!(node, Node::Comment(_)) && node.start_line() == prev.end_line() {
+                printer.out.push(' ');
+                printer.node(node, 0);
+                continue;
+            }
+            printer.out.push('\n');
+            if node.start_line() > prev.end_line() + 1 {
+                printer.out.push('\n');
+            }
+        }
+        printer.node(node, 0);
+    }
+    if !nodes.is_empty() {
+        printer.out.push('\n');
+    }
+    Ok(printer.out)
+}
+
+/// Formats the source with the default options, the way `pale fmt` does.
+///
+/// Formatting the output again gives the same output, and every comment is kept.
+pub fn format(source: &str) -> Result<String, LispErrors> {
+    format_source(source, "-", &FormatOptions::default())
+}
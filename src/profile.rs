@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::time::{Duration, Instant};
+
+use crate::ast::intrinsics;
+use crate::callable::IntrinsicOp;
+use crate::sync::{exclusive, Mutable, Shared};
+use crate::Location;
+
+/// Records how many times each function is called and how long the calls take, for the evaluations with the options it is given to.
+/// Every clone records to the same place.
+#[derive(Debug, Clone, Default)]
+pub struct Profiler(Shared<Mutable<HashMap<Callee, Stats>>>);
+
+/// What the profiler counts calls to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum Callee {
+    Intrinsic(IntrinsicOp),
+    /// A function written in Pale, with the name that a `let` bound it to, and where it was written.
+    Function(Option<Shared<str>>, Location),
+    /// A function that the host registered, by its name.
+    Native(Shared<str>),
+    Parameter,
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    calls: u64,
+    total: Duration,
+    own: Duration,
+}
+
+impl Profiler {
+    /// What has been recorded so far, with the functions that took the longest by themselves first.
+    pub fn profile(&self) -> Profile {
+        let names = intrinsics();
+        let mut entries: Vec<ProfileEntry> = exclusive(&self.0)
+            .iter()
+            .map(|(callee, stats)| {
+                let (name, location) = match callee {
+                    Callee::Intrinsic(op) => {
+                        let name = names.iter().find(|(_, named)| named == op);
+                        // `delay` is a keyword, which calls an intrinsic that has no name of its own.
+                        (name.map_or("delay", |(name, _)| name).to_string(), None)
+                    }
+                    Callee::Function(name, loc) => (
+                        name.as_deref().unwrap_or("lambda").to_string(),
+                        Some(loc.clone()),
+                    ),
+                    Callee::Native(name) => (name.to_string(), None),
+                    Callee::Parameter => ("parameter".to_string(), None),
+                };
+                ProfileEntry {
+                    name,
+                    location,
+                    calls: stats.calls,
+                    total_time: stats.total,
+                    self_time: stats.own,
+                }
+            })
+            .collect();
+        entries.sort_by(|a, b| {
+            (b.self_time, b.calls)
+                .cmp(&(a.self_time, a.calls))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        Profile { entries }
+    }
+
+    /// Forgets everything that has been recorded.
+    pub fn reset(&self) {
+        exclusive(&self.0).clear();
+    }
+}
+
+/// What a [`Profiler`] recorded, which displays as a table.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Profile {
+    pub entries: Vec<ProfileEntry>,
+}
+
+/// How many times one function was called, and how long the calls took.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileEntry {
+    /// The name of the intrinsic or host function, or the name a function written in Pale was bound to by a `let`, which is `lambda` if it wasn't.
+    pub name: String,
+    /// Where a function written in Pale was written, which tells apart functions with the same name.
+    pub location: Option<Location>,
+    pub calls: u64,
+    /// How long the calls took, including the functions they called.
+    /// A recursive function's calls are only counted while it isn't already being called, so the time isn't counted more than once.
+    pub total_time: Duration,
+    /// How long the calls took, leaving out the time spent in the functions they called.
+    pub self_time: Duration,
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<String> = self
+            .entries
+            .iter()
+            .map(|e| match &e.location {
+                Some(loc) => format!("{} ({loc})", e.name),
+                None => e.name.clone(),
+            })
+            .collect();
+        let width = names.iter().map(String::len).chain([8]).max().unwrap_or(0);
+        write!(
+            f,
+            "{:<width$} {:>10} {:>12} {:>12}",
+            "function", "calls", "total ms", "self ms"
+        )?;
+        for (name, e) in names.iter().zip(&self.entries) {
+            write!(
+                f,
+                "\n{name:<width$} {:>10} {:>12.3} {:>12.3}",
+                e.calls,
+                e.total_time.as_secs_f64() * 1000.0,
+                e.self_time.as_secs_f64() * 1000.0,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The calls that are in progress on one thread, while evaluating with a [`Profiler`].
+#[derive(Debug)]
+pub(crate) struct Profiling {
+    profiler: Profiler,
+    calls: Vec<Call>,
+    active: HashMap<Callee, usize>, // How many of the calls in progress are to each function
+}
+
+#[derive(Debug)]
+struct Call {
+    callee: Option<Callee>,
+    start: Instant,
+    children: Duration, // How long the calls it made have taken
+}
+
+impl Profiling {
+    pub(crate) fn new(profiler: Profiler) -> Self {
+        Profiling {
+            profiler,
+            calls: Vec::new(),
+            active: HashMap::new(),
+        }
+    }
+
+    /// Starts timing a call, which [`Profiling::leave`] finishes.
+    /// A call to something that isn't a function is `None`, which fails straight away, so it is only kept for `leave` to finish.
+    pub(crate) fn enter(&mut self, callee: Option<Callee>) {
+        if let Some(callee) = &callee {
+            *self.active.entry(callee.clone()).or_default() += 1;
+        }
+        self.calls.push(Call {
+            callee,
+            start: Instant::now(),
+            children: Duration::ZERO,
+        });
+    }
+
+    /// Finishes timing the last call that was started, and records it.
+    pub(crate) fn leave(&mut self) {
+        let Some(call) = self.calls.pop() else {
+            return;
+        };
+        let took = call.start.elapsed();
+        if let Some(caller) = self.calls.last_mut() {
+            caller.children += took;
+        }
+        let Some(callee) = call.callee else {
+            return;
+        };
+        let active = self.finished(&callee);
+        let mut stats = exclusive(&self.profiler.0);
+        let stats = stats.entry(callee).or_default();
+        stats.calls += 1;
+        stats.own += took.saturating_sub(call.children);
+        if active == 0 {
+            stats.total += took;
+        }
+    }
+
+    /// How many calls are in progress, so that the ones started after this can be forgotten if evaluating fails before they finish.
+    pub(crate) fn depth(&self) -> usize {
+        self.calls.len()
+    }
+
+    /// Forgets the calls started after there were `depth` in progress, without recording them.
+    pub(crate) fn truncate(&mut self, depth: usize) {
+        let calls: Vec<Call> = self.calls.drain(depth..).collect();
+        for callee in calls.iter().filter_map(|call| call.callee.as_ref()) {
+            self.finished(callee);
+        }
+    }
+
+    // Counts a call to the function as no longer in progress, giving how many still are.
+    fn finished(&mut self, callee: &Callee) -> usize {
+        let Some(n) = self.active.get_mut(callee) else {
+            return 0;
+        };
+        *n -= 1;
+        let left = *n;
+        if left == 0 {
+            self.active.remove(callee);
+        }
+        left
+    }
+
+    /// The same profiler, for another thread, which has no calls in progress yet.
+    #[cfg(feature = "sync")]
+    pub(crate) fn for_thread(&self) -> Self {
+        Profiling::new(self.profiler.clone())
+    }
+}
@@ -0,0 +1,89 @@
+use crate::ast::Var;
+use crate::error::LispErrors;
+use crate::eval::nested;
+use crate::sync::{exclusive, Mutable, Shared};
+use crate::types::LispType;
+use crate::Location;
+
+/// What `delay` makes: a function with no parameters that is only called the first time the promise is forced, after which its value is kept.
+#[derive(Debug)]
+pub(crate) struct Promise {
+    state: Mutable<State>,
+}
+
+#[derive(Debug)]
+enum State {
+    Delayed(Var), // The function that gives its value
+    Forced(Var),
+}
+
+impl std::fmt::Display for Promise {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<Promise>")
+    }
+}
+
+impl Promise {
+    /// The function that gives its value, or the value once it has been forced, for the cycle collector.
+    pub(crate) fn held(&self) -> Var {
+        match &*exclusive(&self.state) {
+            State::Delayed(var) | State::Forced(var) => var.new_ref(),
+        }
+    }
+}
+
+/// `(delay expr)`, once the body has been made into a function: a promise that hasn't been forced yet.
+pub(crate) fn delay(thunk: &Var) -> Var {
+    Var::new(LispType::Promise(Shared::new(Promise {
+        state: Mutable::new(State::Delayed(thunk.new_ref())),
+    })))
+}
+
+/// What forcing a value has to do.
+pub(crate) enum Forcing {
+    Done(Var),
+    /// Call the promise's function with no arguments, and then keep its value with [`fulfil`].
+    Call(Var, Shared<Promise>),
+}
+
+/// Starts `(force value)`, which only calls the promise's function if that hasn't been done yet. Anything that isn't a promise is given back as it is.
+/// The evaluator calls the function itself, like the body of any other call, so that forcing promises inside of promises doesn't use up the Rust stack.
+pub(crate) fn start(value: &Var) -> Forcing {
+    let value = value.resolve();
+    let promise = match &*value.get() {
+        LispType::Promise(promise) => Shared::clone(promise),
+        _ => return Forcing::Done(value.new_ref()),
+    };
+    // The lock isn't held while the function is called, since it could force the same promise.
+    let thunk = match &*exclusive(&promise.state) {
+        State::Delayed(thunk) => thunk.resolve(),
+        State::Forced(value) => return Forcing::Done(value.new_ref()),
+    };
+    Forcing::Call(thunk, promise)
+}
+
+/// Keeps the value that the promise's function gave, giving the value of the promise.
+pub(crate) fn fulfil(promise: &Promise, forced: Var) -> Var {
+    let mut state = exclusive(&promise.state);
+    // If forcing it forced it too, the value from then is the one that is kept.
+    if let State::Delayed(_) = &*state {
+        *state = State::Forced(forced);
+    }
+    match &*state {
+        State::Forced(value) => value.new_ref(),
+        State::Delayed(_) => unreachable!("it was just forced"),
+    }
+}
+
+/// `(force value)` called from outside of the evaluator, which calls the promise's function straight away.
+pub(crate) fn force(value: &Var, loc: &Location) -> Result<Var, LispErrors> {
+    let (thunk, promise) = match start(value) {
+        Forcing::Done(value) => return Ok(value),
+        Forcing::Call(thunk, promise) => (thunk, promise),
+    };
+    let forced = match &*thunk.get() {
+        LispType::Func(f) => nested(&**f, &[], loc)?,
+        _ => unreachable!("`delay` always makes a function"),
+    };
+    Ok(fulfil(&promise, forced))
+}
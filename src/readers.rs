@@ -0,0 +1,49 @@
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+
+use crate::sync::{Shared, ThreadSafe};
+
+/// Turns the text of a `#name"text"` literal into the source code it stands for, or explains why it can't.
+#[cfg(not(feature = "sync"))]
+pub type ReaderFn = Shared<dyn Fn(&str) -> Result<String, String>>;
+/// Turns the text of a `#name"text"` literal into the source code it stands for, or explains why it can't.
+#[cfg(feature = "sync")]
+pub type ReaderFn = Shared<dyn Fn(&str) -> Result<String, String> + Send + Sync>;
+
+/// Handlers for dispatch literals like `#date"2024-01-01"`, so that embedders can add literal syntax of their own.
+///
+/// When the tokenizer reads `#name"text"`, it calls the handler registered for `name` with `text`, and reads whatever source it returns in the literal's place.
+#[derive(Clone, Default)]
+pub struct Readers {
+    handlers: BTreeMap<String, ReaderFn>,
+}
+
+impl Readers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the handler for `#name"..."`, replacing any that was already registered for it.
+    /// Names are made of letters, digits and `-`, and start with a letter.
+    pub fn register<F>(&mut self, name: &str, handler: F) -> &mut Self
+    where
+        F: Fn(&str) -> Result<String, String> + ThreadSafe + 'static,
+    {
+        self.handlers.insert(name.to_string(), Shared::new(handler));
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ReaderFn> {
+        self.handlers.get(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.handlers.keys().map(String::as_str)
+    }
+}
+
+impl Debug for Readers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.names()).finish()
+    }
+}
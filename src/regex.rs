@@ -0,0 +1,68 @@
+use ::regex::{Captures, Regex};
+
+use crate::ast::Var;
+use crate::error::LispErrors;
+use crate::types::LispType;
+use crate::Location;
+
+// Compiles the pattern that the intrinsic called `name` was given, saying what is wrong with it if it isn't valid.
+fn compile(pattern: &str, name: &str, loc: &Location) -> Result<Regex, LispErrors> {
+    Regex::new(pattern).map_err(|e| {
+        LispErrors::new()
+            .error(
+                loc,
+                format!("`{name}` was given `{pattern}`, which is not a valid regular expression!"),
+            )
+            .note(None, e.to_string())
+    })
+}
+
+/// Whether the pattern matches anywhere in the text.
+pub(crate) fn is_match(pattern: &str, text: &str, loc: &Location) -> Result<bool, LispErrors> {
+    Ok(compile(pattern, "regex-match?", loc)?.is_match(text))
+}
+
+/// The first part of the text that the pattern matches, or `nil` if there isn't one.
+pub(crate) fn find(pattern: &str, text: &str, loc: &Location) -> Result<Var, LispErrors> {
+    let found = compile(pattern, "regex-find", loc)?.find(text);
+    Ok(found.map_or_else(|| Var::new(LispType::Nil), |m| Var::new(m.as_str())))
+}
+
+/// The groups of the first match, or `nil` if there isn't one.
+/// They are a list with the whole match first, unless some of the groups have names,
+/// in which case they are a map from both their numbers and their names.
+/// Groups that didn't take part in the match are `nil`.
+pub(crate) fn captures(pattern: &str, text: &str, loc: &Location) -> Result<Var, LispErrors> {
+    let regex = compile(pattern, "regex-captures", loc)?;
+    let Some(caps) = regex.captures(text) else {
+        return Ok(Var::new(LispType::Nil));
+    };
+    let group = |caps: &Captures, i: usize| {
+        caps.get(i)
+            .map_or_else(|| Var::new(LispType::Nil), |m| Var::new(m.as_str()))
+    };
+    if regex.capture_names().all(|name| name.is_none()) {
+        let groups = (0..caps.len()).map(|i| group(&caps, i)).collect();
+        return Ok(Var::new(LispType::List(groups)));
+    }
+    let mut entries = Vec::new();
+    for (i, name) in regex.capture_names().enumerate() {
+        entries.push((Var::new(i as isize), group(&caps, i)));
+        if let Some(name) = name {
+            entries.push((Var::new(name), group(&caps, i)));
+        }
+    }
+    Ok(Var::new(LispType::Map(entries)))
+}
+
+/// The text with every match of the pattern replaced.
+/// The replacement can use the groups of each match, like `$1` or `${name}`.
+pub(crate) fn replace(
+    pattern: &str,
+    text: &str,
+    replacement: &str,
+    loc: &Location,
+) -> Result<Var, LispErrors> {
+    let regex = compile(pattern, "regex-replace", loc)?;
+    Ok(Var::new(regex.replace_all(text, replacement).into_owned()))
+}
@@ -0,0 +1,345 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::ast::{make_program, split_forms, Scope, Var};
+use crate::error::LispErrors;
+use crate::interpreter::Interpreter;
+use crate::options::{Options, PrintLimits};
+use crate::sync::Shared;
+use crate::tokens::{tokenize_with, TokenType};
+use crate::types::{Limited, LispType};
+
+/// The names that the results of the last few inputs are bound to, most recent first.
+const HISTORY: [&str; 3] = ["*1", "*2", "*3"];
+/// The name that the message of the last error is bound to.
+const LAST_ERROR: &str = "*e";
+
+/// A binding that an input introduced, e.g. with `let`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub name: String,
+    /// `None` for functions, which don't have a useful value to show.
+    pub value: Option<String>,
+}
+
+impl Display for Definition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.value {
+            Some(v) => write!(f, "{} = {v}", self.name),
+            None => write!(f, "{} (fn)", self.name),
+        }
+    }
+}
+
+/// The outcome of evaluating one input in a [`Session`].
+#[derive(Debug, PartialEq)]
+pub struct Evaluation {
+    pub value: String,
+    /// Whether the value is worth showing, which `nil` isn't.
+    pub interesting: bool,
+    pub defined: Vec<Definition>,
+    pub warnings: LispErrors,
+    /// How long parsing and evaluating the input took.
+    pub elapsed: Duration,
+}
+
+impl Display for Evaluation {
+    /// Summarises the definitions if there were any, followed by the value if it is interesting.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.defined.is_empty() {
+            return write!(f, "{}", self.value);
+        }
+        write!(f, "defined: ")?;
+        for (i, def) in self.defined.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{def}")?;
+        }
+        if self.interesting {
+            write!(f, "\n{}", self.value)?;
+        }
+        Ok(())
+    }
+}
+
+/// What [`Session::reload`] did with each version of a file.
+#[derive(Debug, PartialEq)]
+pub struct Reloaded {
+    /// The indices of the top-level forms that were evaluated, in order.
+    pub evaluated: Vec<usize>,
+    /// How many top-level forms the file has.
+    pub forms: usize,
+    pub warnings: LispErrors,
+}
+
+/// Evaluates a series of inputs in one scope, the way a REPL does.
+///
+/// Bindings made by one input can be used by later ones, and after each input the last three results are bound to `*1`, `*2` and `*3` and the last error to `*e`.
+/// Results are printed within the session's print limits.
+#[derive(Debug)]
+pub struct Session {
+    interpreter: Interpreter,
+    limits: PrintLimits,
+    forms: Vec<LoadedForm>, // The top-level forms as they were at the last reload
+}
+
+#[derive(Debug)]
+struct LoadedForm {
+    tokens: Vec<TokenType>,
+    defined: Vec<String>,
+}
+
+impl Session {
+    pub fn new(options: Options) -> Self {
+        let mut interpreter = Interpreter::with_options(options);
+        for name in HISTORY.into_iter().chain([LAST_ERROR]) {
+            interpreter.scope.bind_global(name, Var::new(LispType::Nil));
+        }
+        Self {
+            interpreter,
+            limits: PrintLimits::default(),
+            forms: Vec::new(),
+        }
+    }
+
+    /// Evaluates one input, along with what it defined and the warnings that were produced.
+    pub fn eval(&mut self, source: &str, file: &str) -> Result<Evaluation, LispErrors> {
+        let known: BTreeSet<String> = self.interpreter.names().map(str::to_string).collect();
+        let started = Instant::now();
+        match self.run(source, file) {
+            Ok((res, warnings)) => {
+                let elapsed = started.elapsed();
+                let defined = self
+                    .interpreter
+                    .scope
+                    .globals()
+                    .into_iter()
+                    .filter(|(name, _)| !known.contains(*name))
+                    .map(|(name, var)| describe(name, var))
+                    .collect();
+                let value = self.limited(&res);
+                let interesting = !matches!(*res.resolve().get(), LispType::Nil);
+                self.remember(res);
+                Ok(Evaluation {
+                    value,
+                    interesting,
+                    defined,
+                    warnings,
+                    elapsed,
+                })
+            }
+            Err(e) => {
+                self.interpreter
+                    .scope
+                    .bind_global(LAST_ERROR, Var::new(e.to_string().as_str()));
+                Err(e)
+            }
+        }
+    }
+
+    /// Evaluates a new version of a file, skipping the top-level forms that were already evaluated by the last reload.
+    ///
+    /// A form is evaluated again if it is new or has changed, or if it uses a name that an earlier form in this reload (re)defined.
+    /// Everything else keeps the state it had, so only the parts of a file that are being worked on are run again.
+    pub fn reload(&mut self, source: &str, file: &str) -> Result<Reloaded, LispErrors> {
+        let filename: Shared<str> = file.into();
+        let toks = tokenize_with(source, filename, &self.interpreter.options)?;
+        let (ranges, errs) = split_forms(&toks.tokens);
+        if !errs.is_empty() {
+            return Err(errs.syntax());
+        }
+        let keys: Vec<Vec<TokenType>> = ranges
+            .iter()
+            .map(|r| {
+                toks.tokens[r.clone()]
+                    .iter()
+                    .map(|t| t.dat.clone())
+                    .collect()
+            })
+            .collect();
+        let previous = std::mem::take(&mut self.forms);
+        let mut changed = BTreeSet::new();
+        // What the forms that are gone defined goes with them, so that nothing uses it by accident.
+        for old in previous.iter().filter(|old| !keys.contains(&old.tokens)) {
+            for name in &old.defined {
+                self.interpreter.scope.unbind_global(name);
+                changed.insert(name.clone());
+            }
+        }
+        let mut evaluated = Vec::new();
+        for (i, (range, key)) in ranges.iter().zip(&keys).enumerate() {
+            let form = &toks.tokens[range.clone()];
+            let uses_changed = form
+                .iter()
+                .any(|t| matches!(&t.dat, TokenType::Ident(id) if changed.contains(id)));
+            match previous.iter().find(|old| old.tokens == *key) {
+                Some(old) if !uses_changed => {
+                    self.forms.push(LoadedForm {
+                        tokens: key.clone(),
+                        defined: old.defined.clone(),
+                    });
+                    continue;
+                }
+                // It has to define the same things again, which would otherwise be shadowing them.
+                Some(old) => {
+                    for name in &old.defined {
+                        self.interpreter.scope.unbind_global(name);
+                    }
+                }
+                None => {}
+            }
+            let before: BTreeMap<String, Var> = self
+                .interpreter
+                .scope
+                .globals()
+                .into_iter()
+                .map(|(name, var)| (name.to_string(), var.new_ref()))
+                .collect();
+            let ran = make_program(
+                form,
+                &mut self.interpreter.scope,
+                toks.edition,
+                &self.interpreter.options,
+            )
+            .and_then(|program| {
+                let options = &self.interpreter.options;
+                program.run(options, options.vm)
+            });
+            let defined: Vec<String> = self
+                .interpreter
+                .scope
+                .globals()
+                .into_iter()
+                .filter(|(name, var)| before.get(*name).is_none_or(|b| !b.same(var)))
+                .map(|(name, _)| name.to_string())
+                .collect();
+            if let Err(e) = ran {
+                // The forms that weren't reached count as gone, so that the next reload takes out what they defined before evaluating them again.
+                let rest: Vec<LoadedForm> = previous
+                    .into_iter()
+                    .filter(|old| keys.contains(&old.tokens))
+                    .filter(|old| !self.forms.iter().any(|f| f.tokens == old.tokens))
+                    .map(|old| LoadedForm {
+                        tokens: Vec::new(),
+                        defined: old.defined,
+                    })
+                    .collect();
+                self.forms.push(LoadedForm {
+                    tokens: Vec::new(),
+                    defined,
+                });
+                self.forms.extend(rest);
+                return Err(e);
+            }
+            changed.extend(defined.iter().cloned());
+            evaluated.push(i);
+            self.forms.push(LoadedForm {
+                tokens: key.clone(),
+                defined,
+            });
+        }
+        Ok(Reloaded {
+            evaluated,
+            forms: ranges.len(),
+            warnings: toks.warnings,
+        })
+    }
+
+    /// The bindings that inputs have made, leaving out the intrinsics and the history.
+    pub fn bindings(&self) -> Vec<Definition> {
+        let builtin = Scope::new(self.options());
+        self.interpreter
+            .scope
+            .globals()
+            .into_iter()
+            .filter(|(name, _)| {
+                builtin.global(name).is_none() && !HISTORY.contains(name) && *name != LAST_ERROR
+            })
+            .map(|(name, var)| describe(name, var))
+            .collect()
+    }
+
+    /// Every name that is bound, including the intrinsics and the history.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.interpreter.names()
+    }
+
+    /// Every file that `import` and `load` have read since the session started.
+    pub fn files_read(&self) -> impl Iterator<Item = &Path> {
+        self.interpreter.files_read()
+    }
+
+    /// Forgets every binding and the history, going back to how the session started.
+    /// The print limits are kept.
+    pub fn reset(&mut self) {
+        let limits = self.limits;
+        *self = Session::new(std::mem::take(&mut self.interpreter.options));
+        self.limits = limits;
+    }
+
+    pub fn options(&self) -> &Options {
+        self.interpreter.options()
+    }
+
+    pub fn print_limits(&self) -> PrintLimits {
+        self.limits
+    }
+
+    pub fn set_print_limits(&mut self, limits: PrintLimits) {
+        self.limits = limits;
+    }
+
+    /// The last result printed in full, ignoring the print limits.
+    pub fn full(&self) -> String {
+        match self.interpreter.scope.global(HISTORY[0]) {
+            Some(last) => last.to_string(),
+            None => LispType::Nil.to_string(),
+        }
+    }
+
+    fn limited(&self, var: &Var) -> String {
+        let value = var.get();
+        Limited {
+            value: &value,
+            limits: self.limits,
+        }
+        .to_string()
+    }
+
+    fn run(&mut self, source: &str, file: &str) -> Result<(Var, LispErrors), LispErrors> {
+        let value = self.interpreter.eval(source, file)?;
+        Ok((value.var().new_ref(), self.interpreter.take_warnings()))
+    }
+
+    // Shifts the history along by one, so that `*1` is the newest result.
+    fn remember(&mut self, res: Var) {
+        let mut next = res;
+        for name in HISTORY {
+            next = match self.interpreter.scope.bind_global(name, next) {
+                Some(prev) => prev,
+                None => break,
+            };
+        }
+    }
+}
+
+fn describe(name: &str, var: &Var) -> Definition {
+    let var = var.resolve();
+    let value = match &*var.get() {
+        LispType::Func(_) => None,
+        other => Some(other.to_string()),
+    };
+    Definition {
+        name: name.to_string(),
+        value,
+    }
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new(Options::default())
+    }
+}
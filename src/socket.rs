@@ -0,0 +1,162 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+
+use crate::ast::Var;
+use crate::error::LispErrors;
+use crate::sync::{exclusive, Mutable, Shared};
+use crate::types::LispType;
+use crate::Location;
+
+/// A connection made by `connect`, which every copy of the value shares, so closing one closes them all.
+#[derive(Debug)]
+pub(crate) struct Socket {
+    address: String,
+    stream: Mutable<Option<Stream>>, // `None` once it is closed
+}
+
+#[derive(Debug)]
+enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Socket {
+    // Runs `f` on the stream, or says that the socket has already been closed.
+    fn with<T>(
+        &self,
+        name: &str,
+        loc: &Location,
+        f: impl FnOnce(&mut dyn ReadWrite) -> io::Result<T>,
+    ) -> Result<T, LispErrors> {
+        let mut stream = exclusive(&self.stream);
+        let stream: &mut dyn ReadWrite = match stream.as_mut() {
+            Some(Stream::Tcp(s)) => s,
+            #[cfg(unix)]
+            Some(Stream::Unix(s)) => s,
+            None => {
+                return Err(LispErrors::new().error(
+                    loc,
+                    format!("`{name}` can't use `{self}`, which has been closed!"),
+                ))
+            }
+        };
+        f(stream).map_err(|e| failed(name, &self.address, e, loc))
+    }
+}
+
+impl std::fmt::Display for Socket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<Socket {}>", self.address)
+    }
+}
+
+trait ReadWrite: Read + Write {}
+impl<T: Read + Write> ReadWrite for T {}
+
+// The error for an operation on the socket at `address` that failed, which says so plainly if it timed out.
+fn failed(name: &str, address: &str, e: io::Error, loc: &Location) -> LispErrors {
+    match e.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => LispErrors::new()
+            .error(loc, format!("`{name}` timed out on `{address}`!"))
+            .note(
+                None,
+                "Give `connect` a longer timeout, in milliseconds, to wait for longer.",
+            ),
+        _ => LispErrors::new().error(loc, format!("`{name}` failed on `{address}`: {e}")),
+    }
+}
+
+/// `(connect address timeout)`: connects to `host:port` over TCP, or to `unix:path` over a Unix socket.
+/// The timeout is in milliseconds, and applies to connecting and to each `send` and `recv` afterwards.
+pub(crate) fn connect(
+    address: &str,
+    timeout: Option<isize>,
+    loc: &Location,
+) -> Result<Var, LispErrors> {
+    let timeout = match timeout {
+        Some(ms) if ms > 0 => Some(Duration::from_millis(ms as u64)),
+        Some(ms) => {
+            return Err(LispErrors::new()
+                .error(loc, format!("`{ms}` can't be a timeout!"))
+                .note(None, "It has to be a positive number of milliseconds."))
+        }
+        None => None,
+    };
+    let err = |e| failed("connect", address, e, loc);
+    let stream = match address.strip_prefix("unix:") {
+        #[cfg(unix)]
+        Some(path) => {
+            let stream = UnixStream::connect(path).map_err(err)?;
+            stream.set_read_timeout(timeout).map_err(err)?;
+            stream.set_write_timeout(timeout).map_err(err)?;
+            Stream::Unix(stream)
+        }
+        #[cfg(not(unix))]
+        Some(_) => {
+            return Err(LispErrors::new().error(
+                loc,
+                format!("`{address}` can't be connected to, since there are no Unix sockets here!"),
+            ))
+        }
+        None => {
+            let stream = connect_tcp(address, timeout).map_err(err)?;
+            stream.set_read_timeout(timeout).map_err(err)?;
+            stream.set_write_timeout(timeout).map_err(err)?;
+            Stream::Tcp(stream)
+        }
+    };
+    Ok(Var::new(LispType::Socket(Shared::new(Socket {
+        address: address.to_string(),
+        stream: Mutable::new(Some(stream)),
+    }))))
+}
+
+// Tries each of the addresses that the host resolves to in turn, giving the last error if none of them work.
+fn connect_tcp(address: &str, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let mut last = io::Error::new(io::ErrorKind::InvalidInput, "it has no addresses");
+    for addr in address.to_socket_addrs()? {
+        let stream = match timeout {
+            Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+            None => TcpStream::connect(addr),
+        };
+        match stream {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last = e,
+        }
+    }
+    Err(last)
+}
+
+/// `(send socket data)`: writes all of the string or bytes, giving how many bytes were sent.
+pub(crate) fn send(socket: &Socket, data: &[u8], loc: &Location) -> Result<Var, LispErrors> {
+    socket.with("send", loc, |stream| {
+        stream.write_all(data)?;
+        stream.flush()
+    })?;
+    Ok(Var::new(data.len() as isize))
+}
+
+/// `(recv socket most)`: waits for some bytes to arrive, giving at most `most` of them, or `nil` once the other end has finished.
+pub(crate) fn recv(socket: &Socket, most: usize, loc: &Location) -> Result<Var, LispErrors> {
+    let mut buf = vec![0; most];
+    let read = socket.with("recv", loc, |stream| stream.read(&mut buf))?;
+    if read == 0 {
+        return Ok(Var::new(LispType::Nil));
+    }
+    buf.truncate(read);
+    Ok(Var::new(buf))
+}
+
+/// `(close socket)`: closes the connection, after which the socket can't be used. Closing it again does nothing.
+pub(crate) fn close(socket: &Socket) {
+    match exclusive(&socket.stream).take() {
+        Some(Stream::Tcp(s)) => drop(s.shutdown(std::net::Shutdown::Both)),
+        #[cfg(unix)]
+        Some(Stream::Unix(s)) => drop(s.shutdown(std::net::Shutdown::Both)),
+        None => {}
+    }
+}
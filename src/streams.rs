@@ -0,0 +1,171 @@
+use std::fmt::Debug;
+use std::io::{self, BufRead, Write};
+
+use crate::sync::{exclusive, Mutable, Shared, ThreadSafe};
+
+/// Receives the text that `print` writes, including the newline at its end.
+#[cfg(not(feature = "sync"))]
+pub type OutputFn = Shared<dyn Fn(&str)>;
+/// Receives the text that `print` writes, including the newline at its end.
+#[cfg(feature = "sync")]
+pub type OutputFn = Shared<dyn Fn(&str) + Send + Sync>;
+
+// The writers and readers that the host gives, which have to be able to go to other threads with the `sync` feature.
+#[cfg(not(feature = "sync"))]
+type SharedWriter = Shared<Mutable<dyn Write>>;
+#[cfg(feature = "sync")]
+type SharedWriter = Shared<Mutable<dyn Write + Send>>;
+#[cfg(not(feature = "sync"))]
+type SharedReader = Shared<Mutable<dyn BufRead>>;
+#[cfg(feature = "sync")]
+type SharedReader = Shared<Mutable<dyn BufRead + Send>>;
+
+/// Where `print` or `eprint` writes to, so that embedders can send it somewhere other than the terminal, like a log file, a GUI, or a buffer to check in tests.
+#[derive(Clone)]
+pub struct Output {
+    kind: OutputKind,
+}
+
+#[derive(Clone)]
+enum OutputKind {
+    Stdout,
+    Stderr,
+    Handler(OutputFn),
+    Writer(SharedWriter),
+}
+
+impl Output {
+    /// Calls the handler with each piece of text that is written.
+    pub fn new<F: Fn(&str) + ThreadSafe + 'static>(handler: F) -> Self {
+        Self {
+            kind: OutputKind::Handler(Shared::new(handler)),
+        }
+    }
+
+    /// Writes to the writer, e.g. a file.
+    pub fn writer<W: Write + ThreadSafe + 'static>(writer: W) -> Self {
+        Self::shared(Shared::new(Mutable::new(writer)))
+    }
+
+    /// Writes to a writer that the embedder keeps a handle to, e.g. a `Vec<u8>` to read what was written from.
+    pub fn shared<W: Write + ThreadSafe + 'static>(writer: Shared<Mutable<W>>) -> Self {
+        Self {
+            kind: OutputKind::Writer(writer),
+        }
+    }
+
+    pub fn stdout() -> Self {
+        Self {
+            kind: OutputKind::Stdout,
+        }
+    }
+
+    pub fn stderr() -> Self {
+        Self {
+            kind: OutputKind::Stderr,
+        }
+    }
+
+    pub(crate) fn write(&self, text: &str) -> io::Result<()> {
+        match &self.kind {
+            OutputKind::Stdout => io::stdout().lock().write_all(text.as_bytes()),
+            OutputKind::Stderr => io::stderr().lock().write_all(text.as_bytes()),
+            OutputKind::Handler(handler) => {
+                handler(text);
+                Ok(())
+            }
+            OutputKind::Writer(writer) => {
+                let mut writer = exclusive(writer);
+                writer.write_all(text.as_bytes())?;
+                writer.flush()
+            }
+        }
+    }
+}
+
+impl Debug for Output {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            OutputKind::Stdout => write!(f, "Output(stdout)"),
+            OutputKind::Stderr => write!(f, "Output(stderr)"),
+            OutputKind::Handler(_) => write!(f, "Output(handler)"),
+            OutputKind::Writer(_) => write!(f, "Output(writer)"),
+        }
+    }
+}
+
+/// Where `read-line` reads from, so that embedders can give scripts input of their own.
+#[derive(Clone)]
+pub struct Input {
+    reader: Option<SharedReader>, // Standard input if there isn't one
+}
+
+impl Input {
+    /// Reads from the reader, e.g. a file or a `&[u8]`.
+    pub fn reader<R: BufRead + ThreadSafe + 'static>(reader: R) -> Self {
+        Self {
+            reader: Some(Shared::new(Mutable::new(reader))),
+        }
+    }
+
+    pub fn stdin() -> Self {
+        Self { reader: None }
+    }
+
+    /// The next line without its line ending, or `None` at the end of the input.
+    pub(crate) fn read_line(&self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        let read = match &self.reader {
+            Some(reader) => exclusive(reader).read_line(&mut line)?,
+            None => io::stdin().lock().read_line(&mut line)?,
+        };
+        if read == 0 {
+            return Ok(None);
+        }
+        let len = line.trim_end_matches(['\r', '\n']).len();
+        line.truncate(len);
+        Ok(Some(line))
+    }
+
+    /// The next character, or `None` at the end of the input.
+    pub(crate) fn read_char(&self) -> io::Result<Option<char>> {
+        match &self.reader {
+            Some(reader) => read_char(&mut *exclusive(reader)),
+            None => read_char(&mut io::stdin().lock()),
+        }
+    }
+}
+
+// Reads one character's worth of UTF-8, going by how many bytes its first byte says it has.
+fn read_char(reader: &mut dyn BufRead) -> io::Result<Option<char>> {
+    let mut buf = [0; 4];
+    if reader.read(&mut buf[..1])? == 0 {
+        return Ok(None);
+    }
+    let len = match buf[0] {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 0,
+    };
+    if len > 1 {
+        reader.read_exact(&mut buf[1..len])?;
+    }
+    match std::str::from_utf8(&buf[..len]) {
+        Ok(s) if len != 0 => Ok(s.chars().next()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the input isn't valid UTF-8",
+        )),
+    }
+}
+
+impl Debug for Input {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.reader {
+            Some(_) => write!(f, "Input(reader)"),
+            None => write!(f, "Input(stdin)"),
+        }
+    }
+}
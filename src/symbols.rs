@@ -0,0 +1,150 @@
+use crate::ast::{intrinsics, trace_program, Scope};
+use crate::error::LispErrors;
+use crate::options::Options;
+use crate::pretty::line_starts;
+use crate::tokens::{tokenize_with, Location};
+
+/// How a name was bound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymbolKind {
+    Let,
+    Parameter,
+    /// A name that a module provides, which is bound where it is imported.
+    Import,
+}
+
+/// A name that the source binds, and where.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub loc: Location,
+    pub kind: SymbolKind,
+    /// For an imported name, where the module that it came from provides it, which can be in another file.
+    pub from: Option<Location>,
+}
+
+/// A use of a name in the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub name: String,
+    pub loc: Location,
+    /// Which of the [`Symbols::definitions`] it refers to, or `None` for an intrinsic.
+    pub definition: Option<usize>,
+}
+
+/// What the names in a piece of source are and what they refer to, for editors and other tools.
+#[derive(Debug, Default)]
+pub struct Symbols {
+    /// In the order they appear in the source.
+    pub definitions: Vec<Symbol>,
+    /// In the order they appear in the source.
+    pub references: Vec<Reference>,
+    /// The errors and warnings from reading the source. The names in the forms that could be read are still found.
+    pub diagnostics: LispErrors,
+}
+
+impl Symbols {
+    /// The definition of the name at the position, whether it is where the name is used or where it is bound.
+    pub fn definition_at(&self, line: usize, col: usize) -> Option<&Symbol> {
+        match self
+            .references
+            .iter()
+            .find(|r| covers(&r.name, &r.loc, line, col))
+        {
+            Some(r) => r.definition.map(|d| &self.definitions[d]),
+            None => self
+                .definitions
+                .iter()
+                .find(|d| covers(&d.name, &d.loc, line, col)),
+        }
+    }
+
+    /// The name at the position, if there is one.
+    pub fn name_at(&self, line: usize, col: usize) -> Option<&str> {
+        self.references
+            .iter()
+            .map(|r| (&r.name, &r.loc))
+            .chain(self.definitions.iter().map(|d| (&d.name, &d.loc)))
+            .find(|(name, loc)| covers(name, loc, line, col))
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+// Whether the name written at `loc` includes the position, counting the position just after it.
+fn covers(name: &str, loc: &Location, line: usize, col: usize) -> bool {
+    loc.line == line && (loc.col..=loc.col + name.chars().count()).contains(&col)
+}
+
+/// Finds where every name in the source is bound and used, without running it.
+pub fn symbols(source: &str, file: &str, options: &Options) -> Symbols {
+    let toks = match tokenize_with(source, file, options) {
+        Ok(toks) => toks,
+        Err(diagnostics) => {
+            return Symbols {
+                diagnostics,
+                ..Symbols::default()
+            }
+        }
+    };
+    let (program, uses) = trace_program(
+        &toks.tokens,
+        &mut Scope::new(options),
+        toks.edition,
+        options,
+    );
+    let mut diagnostics = toks.warnings;
+    if let Err(errs) = program {
+        diagnostics.extend(errs);
+    }
+
+    let starts = line_starts(source);
+    let written = |name: &str, loc: &Location| {
+        starts
+            .get(loc.line)
+            .and_then(|s| source.get(s + loc.col..))
+            .is_some_and(|rest| rest.starts_with(name))
+    };
+    let mut symbols = Symbols {
+        diagnostics,
+        ..Symbols::default()
+    };
+    let mut slots = Vec::new(); // The slot of each definition, to match the references to
+    let mut references = Vec::new();
+    for use_ in uses {
+        // `[` and `{` are read as names of intrinsics, which aren't written in the source.
+        // Imported names are bound where the module's path is written, unless they are renamed.
+        if use_.binds != Some(SymbolKind::Import) && !written(&use_.name, &use_.loc) {
+            continue;
+        }
+        match use_.binds {
+            Some(kind) => {
+                symbols.definitions.push(Symbol {
+                    name: use_.name,
+                    loc: use_.loc,
+                    kind,
+                    from: use_.from,
+                });
+                slots.push(use_.var);
+            }
+            None => references.push(use_),
+        }
+    }
+    // A function that calls itself uses its name before the name is bound, so the references are matched once every definition is known.
+    symbols.references = references
+        .into_iter()
+        .map(|use_| Reference {
+            definition: slots.iter().position(|slot| slot.same(&use_.var)),
+            name: use_.name,
+            loc: use_.loc,
+        })
+        .collect();
+    symbols
+}
+
+/// How the intrinsic with the name is called and what it does, if there is one.
+pub fn intrinsic_doc(name: &str) -> Option<&'static str> {
+    intrinsics()
+        .into_iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, op)| op.doc())
+}
@@ -0,0 +1,116 @@
+// The handles that values are shared and changed through.
+// Normally they are `Rc` and `RefCell`, which are cheap but tie an `Interpreter` to the thread that made it.
+// With the `sync` feature they are `Arc` and locks instead, so that an interpreter and its values can be sent to other threads.
+
+#[cfg(not(feature = "sync"))]
+use std::cell::RefCell;
+#[cfg(feature = "sync")]
+use std::sync::{MutexGuard, PoisonError, RwLock};
+
+/// What reading from or writing to a [`Lock`] gives, which releases it when it is dropped.
+#[cfg(not(feature = "sync"))]
+pub(crate) use std::cell::{Ref as ReadGuard, RefMut as WriteGuard};
+#[cfg(feature = "sync")]
+pub(crate) use std::sync::{RwLockReadGuard as ReadGuard, RwLockWriteGuard as WriteGuard};
+
+/// A handle that every clone of shares what it points to: `Rc`, or `Arc` with the `sync` feature.
+#[cfg(not(feature = "sync"))]
+pub use std::rc::Rc as Shared;
+#[cfg(feature = "sync")]
+pub use std::sync::Arc as Shared;
+
+/// A handle that doesn't keep what a [`Shared`] points to alive: `rc::Weak`, or `sync::Weak` with the `sync` feature.
+#[cfg(not(feature = "sync"))]
+pub(crate) use std::rc::Weak;
+#[cfg(feature = "sync")]
+pub(crate) use std::sync::Weak;
+
+/// Something that can be changed through a [`Shared`] handle, like a writer given to [`Output::shared`](crate::Output::shared):
+/// `RefCell`, or `Mutex` with the `sync` feature.
+#[cfg(not(feature = "sync"))]
+pub use std::cell::RefCell as Mutable;
+#[cfg(feature = "sync")]
+pub use std::sync::Mutex as Mutable;
+
+/// What the host's functions, writers and readers have to be to be given to Pale: anything at all, or `Send + Sync` with the `sync` feature.
+#[cfg(not(feature = "sync"))]
+pub trait ThreadSafe {}
+#[cfg(not(feature = "sync"))]
+impl<T: ?Sized> ThreadSafe for T {}
+#[cfg(feature = "sync")]
+pub trait ThreadSafe: Send + Sync {}
+#[cfg(feature = "sync")]
+impl<T: ?Sized + Send + Sync> ThreadSafe for T {}
+
+/// A value computed the first time it is needed: `OnceCell`, or `OnceLock` with the `sync` feature.
+#[cfg(not(feature = "sync"))]
+pub(crate) use std::cell::OnceCell as Once;
+#[cfg(feature = "sync")]
+pub(crate) use std::sync::OnceLock as Once;
+
+/// Borrows a [`Mutable`] to change it, which nothing else can do until the guard is dropped.
+/// A lock that was poisoned by a panic is used anyway, since every use of one leaves it whole.
+#[cfg(not(feature = "sync"))]
+pub(crate) fn exclusive<T: ?Sized>(mutable: &Mutable<T>) -> WriteGuard<'_, T> {
+    mutable.borrow_mut()
+}
+#[cfg(feature = "sync")]
+pub(crate) fn exclusive<T: ?Sized>(mutable: &Mutable<T>) -> MutexGuard<'_, T> {
+    mutable.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// What a name's slot keeps its value in, which can be read from in several places at once, like when a value is compared with itself.
+/// It is a `RefCell`, or an `RwLock` with the `sync` feature.
+#[derive(Debug)]
+pub(crate) struct Lock<T> {
+    #[cfg(not(feature = "sync"))]
+    inner: RefCell<T>,
+    #[cfg(feature = "sync")]
+    inner: RwLock<T>,
+}
+
+#[cfg(not(feature = "sync"))]
+impl<T> Lock<T> {
+    pub(crate) fn new(value: T) -> Self {
+        Self {
+            inner: RefCell::new(value),
+        }
+    }
+
+    pub(crate) fn read(&self) -> ReadGuard<'_, T> {
+        self.inner.borrow()
+    }
+
+    pub(crate) fn write(&self) -> WriteGuard<'_, T> {
+        self.inner.borrow_mut()
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<T> Lock<T> {
+    pub(crate) fn new(value: T) -> Self {
+        Self {
+            inner: RwLock::new(value),
+        }
+    }
+
+    pub(crate) fn read(&self) -> ReadGuard<'_, T> {
+        self.inner.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn write(&self) -> WriteGuard<'_, T> {
+        self.inner.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T> Lock<T> {
+    pub(crate) fn replace(&self, value: T) -> T {
+        std::mem::replace(&mut *self.write(), value)
+    }
+}
+
+impl<T: PartialEq> PartialEq for Lock<T> {
+    fn eq(&self, other: &Self) -> bool {
+        *self.read() == *other.read()
+    }
+}
@@ -1,8 +1,16 @@
+use std::collections::VecDeque;
 use std::fmt::Display;
+use std::io::{self, BufReader, Read};
+use std::iter::{self, Peekable};
 use std::mem;
+use std::num::IntErrorKind;
 use std::str::FromStr;
 
-use crate::error::LispErrors;
+use crate::error::{Fix, LispErrors};
+use crate::highlight::Highlight;
+use crate::options::{Edition, Options};
+use crate::readers::Readers;
+use crate::sync::Shared;
 use crate::types::LispType;
 
 #[derive(Debug, PartialEq, Clone)]
@@ -11,9 +19,15 @@ pub struct Token {
     pub(crate) dat: TokenType,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+impl Token {
+    pub fn loc(&self) -> &Location {
+        &self.loc
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
 pub struct Location {
-    pub filename: String,
+    pub filename: Shared<str>, // Shared between every location in the same file
     pub line: usize,
     pub col: usize,
 }
@@ -26,6 +40,16 @@ impl Display for Location {
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub(crate) enum KeyWord {
     Let,
+    LetValues,
+    Parameterize,
+    Do,
+    If,
+    Lambda,
+    Delay,
+    Import,
+    Provide,
+    ReExport,
+    Load,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -33,15 +57,61 @@ pub(crate) enum TokenType {
     StartStmt,
     EndStmt,
     KeyWord(KeyWord),
+    Dot, // Separates the last item of an improper list, as in `[1 2 . 3]`
     Recognizable(LispType),
     Ident(String),
 }
 
+impl Display for KeyWord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyWord::Let => write!(f, "let"),
+            KeyWord::LetValues => write!(f, "let-values"),
+            KeyWord::Parameterize => write!(f, "parameterize"),
+            KeyWord::Do => write!(f, "do"),
+            KeyWord::If => write!(f, "if"),
+            KeyWord::Lambda => write!(f, "lambda"),
+            KeyWord::Delay => write!(f, "delay"),
+            KeyWord::Import => write!(f, "import"),
+            KeyWord::Provide => write!(f, "provide"),
+            KeyWord::ReExport => write!(f, "re-export"),
+            KeyWord::Load => write!(f, "load"),
+        }
+    }
+}
+
+// Writes the token the way it would appear in source code.
+impl Display for TokenType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenType::StartStmt => write!(f, "("),
+            TokenType::EndStmt => write!(f, ")"),
+            TokenType::KeyWord(k) => write!(f, "{k}"),
+            TokenType::Dot => write!(f, "."),
+            TokenType::Ident(id) => write!(f, "{id}"),
+            TokenType::Recognizable(LispType::Str(s)) => write!(f, "\"{s}\""),
+            // Debug formatting keeps the decimal point, so that the float isn't read back as an integer.
+            TokenType::Recognizable(LispType::Floating(fl)) => write!(f, "{fl:?}"),
+            TokenType::Recognizable(r) => write!(f, "{r}"),
+        }
+    }
+}
+
 impl FromStr for KeyWord {
     type Err = &'static str;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.trim().to_ascii_lowercase().as_str() {
             "let" => Ok(Self::Let),
+            "let-values" => Ok(Self::LetValues),
+            "parameterize" => Ok(Self::Parameterize),
+            "do" => Ok(Self::Do),
+            "if" => Ok(Self::If),
+            "lambda" => Ok(Self::Lambda),
+            "delay" => Ok(Self::Delay),
+            "import" => Ok(Self::Import),
+            "provide" => Ok(Self::Provide),
+            "re-export" => Ok(Self::ReExport),
+            "load" => Ok(Self::Load),
             _ => Err("Unknown keyword!"),
         }
     }
@@ -53,200 +123,1010 @@ impl TokenType {
     }
 }
 
-impl<T: ToString> From<T> for TokenType {
-    fn from(orig: T) -> Self {
-        let s = orig.to_string().trim().to_string();
+// Parses a number literal, returning `None` if the text doesn't start like a number.
+// Integers can be written in hexadecimal (`0xFF`), octal (`0o17`), or binary (`0b1010`), and any number can have underscores between its digits (`1_000_000`).
+fn parse_number(s: &str) -> Option<Result<LispType, String>> {
+    let (negative, body) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    if !body.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let (radix, digits) = match body.get(..2).map(|p| p.to_ascii_lowercase()).as_deref() {
+        Some("0x") => (16, &body[2..]),
+        Some("0o") => (8, &body[2..]),
+        Some("0b") => (2, &body[2..]),
+        _ => (10, body),
+    };
+    // An underscore has to have a digit on each side, so `1_` and `1__0` are mistakes rather than `1` and `10`.
+    let is_digit = |c: char| c.is_digit(radix);
+    let pieces: Vec<&str> = digits.split('_').collect();
+    if !pieces
+        .windows(2)
+        .all(|w| w[0].ends_with(is_digit) && w[1].starts_with(is_digit))
+    {
+        return Some(Err(format!(
+            "Malformed number literal `{s}`: underscores can only go between digits!"
+        )));
+    }
+    let digits = pieces.concat();
+    if digits.is_empty() || digits.starts_with(['+', '-']) {
+        return Some(Err(format!("Malformed number literal `{s}`!")));
+    }
+    let sign = if negative { "-" } else { "" };
+    Some(
+        match isize::from_str_radix(&format!("{sign}{digits}"), radix) {
+            Ok(i) => Ok(i.into()),
+            Err(e) => match e.kind() {
+                IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => {
+                    Err(format!("Integer literal `{s}` is too large!"))
+                }
+                _ if radix == 10 => format!("{sign}{digits}")
+                    .parse::<f64>()
+                    .map(|f| f.into())
+                    .map_err(|_| format!("Malformed number literal `{s}`!")),
+                _ => Err(format!("Malformed number literal `{s}`!")),
+            },
+        },
+    )
+}
+
+impl FromStr for TokenType {
+    type Err = String;
+    fn from_str(orig: &str) -> Result<Self, Self::Err> {
+        let s = orig.trim();
         if let Ok(k) = s.parse::<KeyWord>() {
-            Self::KeyWord(k)
-        } else if let Ok(i) = s.parse::<isize>() {
-            Self::Recognizable(i.into())
-        } else if let Ok(f) = s.parse::<f64>() {
-            Self::Recognizable(f.into())
-        } else if &s == "nil" {
-            Self::Recognizable(LispType::Nil)
+            Ok(Self::KeyWord(k))
+        } else if s == "." {
+            Ok(Self::Dot)
+        } else if let Some(n) = parse_number(s) {
+            n.map(Self::Recognizable)
+        } else if let Some(name) = s.strip_prefix(':') {
+            if name.is_empty() || name.starts_with(':') {
+                Err(format!("Malformed keyword `{s}`!"))
+            } else {
+                Ok(Self::Recognizable(LispType::Keyword(name.to_string())))
+            }
+        } else if s == "nil" {
+            Ok(Self::Recognizable(LispType::Nil))
         } else {
-            Self::Ident(orig.to_string())
+            Ok(Self::Ident(orig.to_string()))
         }
     }
 }
 
+/// Source text that doesn't become a token, like a comment, kept so that the source can be printed back out.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Trivia {
+    pub(crate) loc: Location,
+    pub(crate) text: String,
+}
+
 #[derive(Debug, Clone, Copy)]
 enum TokenizerStatus {
     String,
+    Interpolated,   // The text of a `#"..."` string, outside of any `${...}`
+    Dispatch,       // The name of a `#name"..."` literal
+    DispatchString, // The text of a `#name"..."` literal
     Normal,
     Comment,
+    LineComment,
 }
 
 #[derive(Debug)]
 struct Tokenizer<'a> {
-    tokens: Vec<Token>,
+    tokens: VecDeque<Token>, // Tokens that have been lexed but not yet taken
+    emitted: usize,
     right_assocs: usize,
     pos: (usize, usize),
     pos_locked: bool,
+    cursor: (usize, usize), // The position of the next character
     token_buf: String,
     status: TokenizerStatus,
-    default_buf_len: usize,
-    filename: String,
-    source: &'a str,
-    last_character: char,
+    filename: Shared<str>,
+    delims: Vec<(char, Location)>, // The opening delimiters that haven't been closed yet, with `#` for `${`
+    interps: Vec<usize>, // The `right_assocs` from outside of each `${` that we are inside of
+    dispatch: String,    // The name of the `#name"..."` literal being read
+    comments: Vec<Location>, // Where each of the block comments we are inside of started
+    options: &'a Options,
+    edition: Edition,
+    preamble: bool, // Whether the tokens could still be a `(pale-edition N)` directive
+    warnings: LispErrors,
+    surplus: Vec<(Location, usize)>, // Ignored closing delimiters, and how many tokens there were when they were found
+    trivia: Option<Vec<Trivia>>,     // Only kept when asked for, since evaluating doesn't need it
+    trivia_buf: String,
 }
 
 impl<'a> Tokenizer<'a> {
-    fn new(input: &'a str, filename: String) -> Self {
+    fn new(filename: Shared<str>, options: &'a Options) -> Self {
         // This number can and might change, or I might change the method of getting it.
         let default_buf_len = 16;
         Tokenizer {
-            tokens: Vec::with_capacity(default_buf_len),
+            tokens: VecDeque::with_capacity(default_buf_len),
+            emitted: 0,
             pos: (0, 0),
             pos_locked: false,
+            cursor: (0, 0),
             token_buf: String::with_capacity(default_buf_len),
             status: TokenizerStatus::Normal,
-            default_buf_len,
             filename,
-            source: input,
             right_assocs: 0,
-            last_character: ' ',
+            delims: Vec::new(),
+            interps: Vec::new(),
+            dispatch: String::new(),
+            comments: Vec::new(),
+            options,
+            edition: options.edition,
+            preamble: true,
+            warnings: LispErrors::new(),
+            surplus: Vec::new(),
+            trivia: None,
+            trivia_buf: String::new(),
+        }
+    }
+
+    fn loc(&self) -> Location {
+        Location {
+            filename: Shared::clone(&self.filename),
+            line: self.pos.1,
+            col: self.pos.0,
         }
     }
 
-    fn push_tok(&mut self) {
+    fn emit(&mut self, tok: Token) {
+        self.tokens.push_back(tok);
+        self.emitted += 1;
+    }
+
+    // Adds to the comment being read, if trivia is being kept.
+    fn push_trivia(&mut self, text: &str) {
+        if self.trivia.is_some() {
+            self.trivia_buf.push_str(text);
+        }
+    }
+
+    // Finishes the comment being read, which started at `pos`.
+    fn end_trivia(&mut self) {
+        self.pos_locked = false;
+        if let Some(trivia) = &mut self.trivia {
+            trivia.push(Trivia {
+                loc: Location {
+                    filename: Shared::clone(&self.filename),
+                    line: self.pos.1,
+                    col: self.pos.0,
+                },
+                text: mem::take(&mut self.trivia_buf),
+            });
+        }
+    }
+
+    // Adds a character to the current token, remembering where the token started.
+    fn push_char(&mut self, character: char) {
+        self.token_buf.push(character);
+        self.pos_locked = true;
+    }
+
+    fn push_tok(&mut self) -> Result<(), LispErrors> {
         match self.status {
             TokenizerStatus::Normal => {
                 if self.token_buf.trim() != "" {
+                    // The buffer is reused, so only identifiers need an allocation of their own.
                     let tok = Token {
-                        loc: Location {
-                            line: self.pos.1,
-                            col: self.pos.0,
-                            filename: self.filename.clone(),
-                        },
-                        dat: mem::replace(
-                            &mut self.token_buf,
-                            String::with_capacity(self.default_buf_len),
-                        )
-                        .into(),
+                        loc: self.loc(),
+                        dat: self
+                            .token_buf
+                            .parse()
+                            .map_err(|e| LispErrors::new().error(&self.loc(), e))?,
                     };
-                    self.tokens.push(tok);
-                    self.pos_locked = false;
+                    self.token_buf.clear();
+                    self.emit(tok);
                 }
+                self.pos_locked = false;
             }
-            TokenizerStatus::Comment => unreachable!(),
+            TokenizerStatus::Comment
+            | TokenizerStatus::LineComment
+            | TokenizerStatus::Interpolated
+            | TokenizerStatus::Dispatch
+            | TokenizerStatus::DispatchString => unreachable!(),
             TokenizerStatus::String => {
                 let tok = Token {
-                    loc: Location {
-                        line: self.pos.1,
-                        col: self.pos.0,
-                        filename: self.filename.clone(),
-                    },
-                    dat: TokenType::new_str_lit(mem::replace(
-                        &mut self.token_buf,
-                        String::with_capacity(self.default_buf_len),
-                    )),
+                    loc: self.loc(),
+                    dat: TokenType::new_str_lit(mem::take(&mut self.token_buf)),
                 };
-                self.tokens.push(tok);
+                self.emit(tok);
                 self.pos_locked = false;
                 self.status = TokenizerStatus::Normal;
             }
         }
+        Ok(())
     }
 
-    fn start_stmt(&mut self) {
+    // Emits the text of an interpolated string read so far, and starts the next piece of it at `next`.
+    fn push_chunk(&mut self, next: (usize, usize)) {
+        if !self.token_buf.is_empty() {
+            let tok = Token {
+                loc: self.loc(),
+                dat: TokenType::new_str_lit(mem::take(&mut self.token_buf)),
+            };
+            self.emit(tok);
+        }
+        self.pos = next;
+    }
+
+    fn start_stmt(&mut self, delim: char) {
         let tok = Token {
-            loc: Location {
-                filename: self.filename.clone(),
-                line: self.pos.1,
-                col: self.pos.0,
-            },
+            loc: self.loc(),
             dat: TokenType::StartStmt,
         };
-        self.tokens.push(tok);
+        if delim != '$' {
+            self.delims.push((delim, tok.loc.clone()));
+        }
+        self.emit(tok);
+        // Brackets and braces are sugar for calling the intrinsics that build lists and maps.
+        let desugared = match delim {
+            '[' => "list",
+            '{' => "make-map",
+            _ => return,
+        };
+        let tok = Token {
+            loc: self.loc(),
+            dat: TokenType::Ident(desugared.to_string()),
+        };
+        self.emit(tok);
     }
 
-    fn end_stmt(&mut self) {
-        self.token_buf = self.token_buf.trim().to_string();
-        if !self.token_buf.is_empty() {
-            let tok = Token {
-                loc: Location {
-                    filename: self.filename.clone(),
-                    line: self.pos.1,
-                    col: self.pos.0,
-                },
-                dat: mem::replace(
-                    &mut self.token_buf,
-                    String::with_capacity(self.default_buf_len),
-                )
-                .into(),
-            };
-            self.token_buf = String::with_capacity(self.default_buf_len);
-            self.tokens.push(tok);
+    fn end_stmt(&mut self, delim: char) -> Result<(), LispErrors> {
+        let expected = match delim {
+            ']' => '[',
+            '}' => '{',
+            _ => '(',
+        };
+        // Unmatched closing delimiters are reported by the parser, only mixing them up is an error here.
+        match self.delims.pop() {
+            Some(('#', loc)) => {
+                return Err(LispErrors::new()
+                    .error(
+                        &self.loc(),
+                        format!("Mismatched closing delimiter `{delim}`!"),
+                    )
+                    .note(
+                        &loc,
+                        "The `${` is here, which has to be closed with `}` first.",
+                    ));
+            }
+            Some((open, loc)) if open != expected => {
+                return Err(LispErrors::new()
+                    .error(
+                        &self.loc(),
+                        format!("Mismatched closing delimiter `{delim}`!"),
+                    )
+                    .note(&loc, format!("The opening `{open}` is here.")));
+            }
+            None if self.options.friendly_parens => {
+                self.surplus.push((self.loc(), self.emitted));
+                return Ok(());
+            }
+            _ => {}
         }
         for _ in 0..self.right_assocs {
             let tok = Token {
-                loc: Location {
-                    filename: self.filename.clone(),
-                    line: self.pos.1,
-                    col: self.pos.0,
-                },
+                loc: self.loc(),
                 dat: TokenType::EndStmt,
             };
-            self.tokens.push(tok);
+            self.emit(tok);
         }
         self.right_assocs = 0;
-        self.pos_locked = false;
         self.status = TokenizerStatus::Normal;
         let tok = Token {
-            loc: Location {
-                filename: self.filename.clone(),
-                line: self.pos.1,
-                col: self.pos.0,
-            },
+            loc: self.loc(),
             dat: TokenType::EndStmt,
         };
-        self.tokens.push(tok);
-    }
-
-    fn tokenize(mut self) -> Result<Vec<Token>, LispErrors> {
-        'lines: for (line_number, line_data) in self.source.lines().enumerate() {
-            for (col_number, character) in line_data.trim().char_indices() {
-                match (character, self.status, self.last_character) {
-                    ('\"', TokenizerStatus::String, _) => self.push_tok(),
-                    (_, TokenizerStatus::String, _) => self.token_buf.push(character),
-                    ('\"', TokenizerStatus::Normal, _) => self.status = TokenizerStatus::String,
-                    (' ', TokenizerStatus::Normal, _) => self.push_tok(),
-                    ('(', TokenizerStatus::Normal, _) => self.start_stmt(),
-                    (')', TokenizerStatus::Normal, _) => self.end_stmt(),
-                    ('/', TokenizerStatus::Normal, '/') => continue 'lines,
-                    ('$', TokenizerStatus::Normal, _) => {
-                        self.start_stmt();
-                        self.right_assocs += 1;
+        self.emit(tok);
+        Ok(())
+    }
+
+    // Closes everything that is still open at the end of the source, and reports the closing delimiters that were ignored.
+    fn finish_friendly(&mut self) -> Result<(), LispErrors> {
+        let eof = self.loc();
+        while let Some((open, loc)) = self.delims.last().cloned() {
+            let close = match open {
+                '[' => ']',
+                '{' => '}',
+                _ => ')',
+            };
+            self.end_stmt(close)?;
+            self.warnings = mem::take(&mut self.warnings)
+                .warning(
+                    &loc,
+                    format!("Unclosed `{open}` was closed at the end of the file."),
+                )
+                .fix(Fix {
+                    loc: eof.clone(),
+                    remove: 0,
+                    insert: close.to_string(),
+                });
+        }
+        let mut errs = LispErrors::new();
+        for (loc, tokens_before) in mem::take(&mut self.surplus) {
+            if tokens_before == self.emitted {
+                self.warnings = mem::take(&mut self.warnings)
+                    .warning(&loc, "Extra closing delimiter was ignored.")
+                    .fix(Fix {
+                        loc,
+                        remove: 1,
+                        insert: String::new(),
+                    });
+            } else {
+                errs = errs
+                    .error(&loc, "Unmatched closing parentheses!")
+                    .note(None, "Delete it.");
+            }
+        }
+        if errs.is_empty() {
+            Ok(())
+        } else {
+            Err(errs)
+        }
+    }
+
+    // Processes a single character of the source, given the one after it.
+    // Returns whether the next character was used up as well.
+    fn feed(&mut self, character: char, next: Option<char>) -> Result<bool, LispErrors> {
+        let (col_number, line_number) = self.cursor;
+        if !self.pos_locked {
+            self.pos = self.cursor;
+        }
+        self.cursor.0 += character.len_utf8();
+        let mut skip_next = false;
+        match (character, self.status, next) {
+            ('\r', _, Some('\n')) => {}
+            ('\n', status, _) => {
+                match status {
+                    TokenizerStatus::Normal => self.push_tok()?,
+                    // Strings can span multiple lines, and keep the line breaks inside of them.
+                    TokenizerStatus::String
+                    | TokenizerStatus::Interpolated
+                    | TokenizerStatus::DispatchString => self.token_buf.push('\n'),
+                    TokenizerStatus::Dispatch => return Err(self.malformed_dispatch()),
+                    TokenizerStatus::LineComment => {
+                        self.end_trivia();
+                        self.status = TokenizerStatus::Normal;
                     }
-                    ('*', TokenizerStatus::Normal, '{') => self.status = TokenizerStatus::Comment,
-                    (_, TokenizerStatus::Normal, _) => self.token_buf.push(character),
-                    ('}', TokenizerStatus::Comment, '*') => self.status = TokenizerStatus::Normal,
-                    (_, TokenizerStatus::Comment, _) => {}
+                    TokenizerStatus::Comment => self.push_trivia("\n"),
+                }
+                self.cursor = (0, line_number + 1);
+            }
+            (_, TokenizerStatus::LineComment, _) => {
+                self.push_trivia(character.encode_utf8(&mut [0; 4]))
+            }
+            ('\"', TokenizerStatus::Interpolated, _) => {
+                self.push_chunk((col_number, line_number));
+                let tok = Token {
+                    loc: self.loc(),
+                    dat: TokenType::EndStmt,
+                };
+                self.emit(tok);
+                self.pos_locked = false;
+                self.status = TokenizerStatus::Normal;
+            }
+            ('$', TokenizerStatus::Interpolated, Some('{')) => {
+                skip_next = true;
+                self.push_chunk((col_number, line_number));
+                self.delims.push(('#', self.loc()));
+                self.interps.push(mem::take(&mut self.right_assocs));
+                self.pos_locked = false;
+                self.status = TokenizerStatus::Normal;
+            }
+            (_, TokenizerStatus::Interpolated, _) => self.token_buf.push(character),
+            ('\"', TokenizerStatus::String, _) => self.push_tok()?,
+            (_, TokenizerStatus::String, _) => self.token_buf.push(character),
+            ('"', TokenizerStatus::Dispatch, _) => {
+                self.dispatch = mem::take(&mut self.token_buf);
+                self.status = TokenizerStatus::DispatchString;
+            }
+            // `#u8(1 2 3)` is read as `(bytes 1 2 3)`, like `[...]` is read as `(list ...)`.
+            ('(', TokenizerStatus::Dispatch, _) if self.token_buf == "u8" => {
+                self.token_buf.clear();
+                self.pos_locked = false;
+                self.status = TokenizerStatus::Normal;
+                self.start_stmt('(');
+                let tok = Token {
+                    loc: self.loc(),
+                    dat: TokenType::Ident("bytes".to_string()),
+                };
+                self.emit(tok);
+            }
+            (c, TokenizerStatus::Dispatch, _) if c.is_alphanumeric() || c == '-' => {
+                self.token_buf.push(c)
+            }
+            (_, TokenizerStatus::Dispatch, _) => return Err(self.malformed_dispatch()),
+            ('"', TokenizerStatus::DispatchString, _) => self.expand_dispatch()?,
+            (_, TokenizerStatus::DispatchString, _) => self.token_buf.push(character),
+            ('#', TokenizerStatus::Normal, Some(c)) if c.is_alphabetic() => {
+                self.push_tok()?;
+                self.pos = (col_number, line_number);
+                self.pos_locked = true;
+                self.status = TokenizerStatus::Dispatch;
+            }
+            // `#"a ${b} c"` is read as `(str "a " b " c")`.
+            ('#', TokenizerStatus::Normal, Some('"')) => {
+                self.push_tok()?;
+                skip_next = true;
+                self.pos = (col_number, line_number);
+                for dat in [TokenType::StartStmt, TokenType::Ident("str".to_string())] {
+                    let tok = Token {
+                        loc: self.loc(),
+                        dat,
+                    };
+                    self.emit(tok);
+                }
+                self.pos = (col_number + 2, line_number);
+                self.pos_locked = true;
+                self.status = TokenizerStatus::Interpolated;
+            }
+            ('}', TokenizerStatus::Normal, _) if matches!(self.delims.last(), Some(('#', _))) => {
+                self.push_tok()?;
+                self.delims.pop();
+                self.pos = (col_number, line_number);
+                for _ in 0..self.right_assocs {
+                    let tok = Token {
+                        loc: self.loc(),
+                        dat: TokenType::EndStmt,
+                    };
+                    self.emit(tok);
                 }
-                self.last_character = character;
-                if !self.pos_locked {
+                self.right_assocs = self.interps.pop().unwrap_or(0);
+                self.pos = (col_number + 1, line_number);
+                self.pos_locked = true;
+                self.status = TokenizerStatus::Interpolated;
+            }
+            ('\"', TokenizerStatus::Normal, _) => {
+                self.push_tok()?;
+                self.pos = (col_number, line_number);
+                self.pos_locked = true;
+                self.status = TokenizerStatus::String;
+            }
+            ('/', TokenizerStatus::Normal, Some('/')) => {
+                self.push_tok()?;
+                self.pos = (col_number, line_number);
+                self.pos_locked = true;
+                self.push_trivia("/");
+                self.status = TokenizerStatus::LineComment;
+            }
+            ('{', TokenizerStatus::Normal | TokenizerStatus::Comment, Some('*')) => {
+                if let TokenizerStatus::Normal = self.status {
+                    self.push_tok()?;
                     self.pos = (col_number, line_number);
+                    self.pos_locked = true;
+                }
+                skip_next = true;
+                self.push_trivia("{*");
+                self.comments.push(Location {
+                    filename: Shared::clone(&self.filename),
+                    line: line_number,
+                    col: col_number,
+                });
+                self.status = TokenizerStatus::Comment;
+            }
+            ('*', TokenizerStatus::Comment, Some('}')) => {
+                skip_next = true;
+                self.push_trivia("*}");
+                self.comments.pop();
+                if self.comments.is_empty() {
+                    self.end_trivia();
+                    self.status = TokenizerStatus::Normal;
+                }
+            }
+            (_, TokenizerStatus::Comment, _) => {
+                self.push_trivia(character.encode_utf8(&mut [0; 4]))
+            }
+            (c, TokenizerStatus::Normal, _) if c.is_whitespace() => self.push_tok()?,
+            ('(' | '[' | '{', TokenizerStatus::Normal, _) => {
+                self.push_tok()?;
+                self.pos = (col_number, line_number);
+                self.start_stmt(character);
+            }
+            (')' | ']' | '}', TokenizerStatus::Normal, _) => {
+                self.push_tok()?;
+                self.pos = (col_number, line_number);
+                self.end_stmt(character)?;
+            }
+            ('$', TokenizerStatus::Normal, _) if self.edition.dollar_operator() => {
+                self.push_tok()?;
+                self.pos = (col_number, line_number);
+                self.start_stmt(character);
+                self.right_assocs += 1;
+            }
+            (_, TokenizerStatus::Normal, _) => self.push_char(character),
+        }
+        if skip_next {
+            self.cursor.0 += next.map_or(0, char::len_utf8);
+        }
+        self.check_limits((col_number, line_number))?;
+        Ok(skip_next)
+    }
+
+    fn malformed_dispatch(&self) -> LispErrors {
+        let name = &self.token_buf;
+        LispErrors::new()
+            .error(
+                &self.loc(),
+                format!("`#{name}` must be followed by a string, like `#{name}\"...\"`!"),
+            )
+            .note(
+                None,
+                "Reader names can only have letters, digits and `-` in them.",
+            )
+    }
+
+    // Replaces a `#name"text"` literal with the source that its reader turns it into.
+    fn expand_dispatch(&mut self) -> Result<(), LispErrors> {
+        let loc = self.loc();
+        let name = mem::take(&mut self.dispatch);
+        let text = mem::take(&mut self.token_buf);
+        self.pos_locked = false;
+        self.status = TokenizerStatus::Normal;
+        // Tools that print the source back out don't have the readers, and want the literal as it was written anyway.
+        if self.trivia.is_some() {
+            self.emit(Token {
+                loc,
+                dat: TokenType::Ident(format!("#{name}\"{text}\"")),
+            });
+            return Ok(());
+        }
+        let handler = self.options.readers.get(&name).ok_or_else(|| {
+            LispErrors::new()
+                .error(&loc, format!("Unknown reader `#{name}`!"))
+                .note(
+                    None,
+                    "Readers have to be registered in the options before reading.",
+                )
+        })?;
+        let source = handler(&text).map_err(|e| {
+            LispErrors::new().error(&loc, format!("`#{name}` could not read {text:?}: {e}"))
+        })?;
+        // Readers can't expand into other readers, so that they can't expand forever.
+        let inner = Options {
+            readers: Readers::new(),
+            ..self.options.clone()
+        };
+        let invalid = |e: Option<LispErrors>| {
+            let mut err = LispErrors::new()
+                .error(&loc, format!("`#{name}` was read as invalid code!"))
+                .note(None, format!("It was read as `{source}`."));
+            for d in e.iter().flat_map(LispErrors::diagnostics) {
+                err = err.note(None, &d.message);
+            }
+            err
+        };
+        let expanded = tokenize_with(&source, Shared::clone(&self.filename), &inner)
+            .map_err(|e| invalid(Some(e)))?;
+        let mut depth = 0isize;
+        for tok in &expanded.tokens {
+            match tok.dat {
+                TokenType::StartStmt => depth += 1,
+                TokenType::EndStmt => depth -= 1,
+                _ => {}
+            }
+            if depth < 0 {
+                break;
+            }
+        }
+        if depth != 0 {
+            return Err(invalid(None).note(None, "It has to be made of whole statements."));
+        }
+        for tok in expanded.tokens {
+            self.emit(Token {
+                loc: loc.clone(),
+                dat: tok.dat,
+            });
+        }
+        Ok(())
+    }
+
+    // Rejects source that has gone past the limits, given where the character that was just read is.
+    fn check_limits(&self, at: (usize, usize)) -> Result<(), LispErrors> {
+        let limits = self.options.limits;
+        let loc = Location {
+            filename: Shared::clone(&self.filename),
+            line: at.1,
+            col: at.0,
+        };
+        if self.delims.len() + self.right_assocs > limits.max_depth {
+            return Err(LispErrors::new()
+                .error(&loc, "Statements are nested too deeply!")
+                .note(
+                    None,
+                    format!("They can only be nested {} deep.", limits.max_depth),
+                ));
+        }
+        if self.emitted > limits.max_tokens {
+            return Err(LispErrors::new()
+                .error(&loc, "The source has too many tokens!")
+                .note(
+                    None,
+                    format!("It can only have {} of them.", limits.max_tokens),
+                ));
+        }
+        Ok(())
+    }
+
+    // Takes a `(pale-edition N)` directive from the start of the source, setting the edition for the rest of it.
+    // Until it's known whether the first tokens are a directive, they have to be held back.
+    fn check_preamble(&mut self) -> Result<(), LispErrors> {
+        if !self.preamble {
+            return Ok(());
+        }
+        let mut toks = self.tokens.iter().map(|t| &t.dat);
+        match (toks.next(), toks.next()) {
+            (None, _) | (Some(TokenType::StartStmt), None) => return Ok(()),
+            (Some(TokenType::StartStmt), Some(TokenType::Ident(id))) if id == "pale-edition" => {}
+            _ => {
+                self.preamble = false;
+                return Ok(());
+            }
+        }
+        let edition = match (toks.next(), toks.next()) {
+            (None, _) | (Some(TokenType::Recognizable(LispType::Integer(_))), None) => {
+                return Ok(())
+            }
+            (Some(TokenType::Recognizable(LispType::Integer(n))), Some(TokenType::EndStmt)) => {
+                Edition::from_number(*n).ok_or_else(|| {
+                    LispErrors::new()
+                        .error(&self.tokens[2].loc, format!("Unknown edition {n}!"))
+                        .note(None, format!("The latest edition is {}.", Edition::LATEST))
+                })?
+            }
+            _ => {
+                return Err(LispErrors::new()
+                    .error(
+                        &self.tokens[1].loc,
+                        "`pale-edition` takes a single edition number!",
+                    )
+                    .note(None, "Try `(pale-edition 1)`."))
+            }
+        };
+        self.edition = edition;
+        if let Some(trivia) = &mut self.trivia {
+            trivia.push(Trivia {
+                loc: self.tokens[0].loc.clone(),
+                text: format!("(pale-edition {})", edition.number()),
+            });
+        }
+        self.tokens.drain(..4);
+        self.preamble = false;
+        Ok(())
+    }
+
+    // Finishes off the source once there are no characters left.
+    fn finish(&mut self) -> Result<(), LispErrors> {
+        if let Some((_, loc)) = self.delims.iter().find(|(open, _)| *open == '#') {
+            return Err(LispErrors::new()
+                .error(loc, "Unterminated `${` in a string!")
+                .note(None, "Close it with `}`."));
+        }
+        match self.status {
+            TokenizerStatus::Normal => self.push_tok()?,
+            TokenizerStatus::Dispatch => return Err(self.malformed_dispatch()),
+            TokenizerStatus::String
+            | TokenizerStatus::Interpolated
+            | TokenizerStatus::DispatchString => {
+                return Err(LispErrors::new()
+                    .error(&self.loc(), "Unterminated string literal!")
+                    .note(None, "Add a `\"` to the end of the string."))
+            }
+            TokenizerStatus::Comment => {
+                let mut err = LispErrors::new()
+                    .error(self.comments.last().unwrap(), "Unterminated block comment!");
+                if self.comments.len() > 1 {
+                    err = err.note(
+                        &self.comments[0],
+                        "It is nested inside of this comment, which also needs to be closed.",
+                    );
                 }
+                return Err(err.note(None, "Block comments are closed with `*}`."));
             }
+            TokenizerStatus::LineComment => self.end_trivia(),
+        }
+        self.pos = self.cursor;
+
+        if self.options.friendly_parens {
+            self.finish_friendly()?;
         }
 
         for _ in 0..self.right_assocs {
             let tok = Token {
-                loc: Location {
-                    filename: self.filename.clone(),
-                    line: self.pos.1,
-                    col: self.pos.0,
-                },
+                loc: self.loc(),
                 dat: TokenType::EndStmt,
             };
-            self.tokens.push(tok);
+            self.emit(tok);
         }
-        Ok(self.tokens)
+        self.right_assocs = 0;
+        Ok(())
+    }
+}
+
+/// An iterator that lexes tokens on demand from a stream of characters.
+///
+/// After an error is returned, the stream ends.
+pub struct TokenStream<'a, I: Iterator<Item = io::Result<char>>> {
+    chars: Peekable<I>,
+    tokenizer: Tokenizer<'a>,
+    finished: bool,
+}
+
+impl<'a, C: Iterator<Item = char>> TokenStream<'a, iter::Map<C, fn(char) -> io::Result<char>>> {
+    pub fn new(chars: C, filename: impl Into<Shared<str>>, options: &'a Options) -> Self {
+        TokenStream::from_results(
+            chars.map(Ok as fn(char) -> io::Result<char>),
+            filename,
+            options,
+        )
     }
 }
 
-pub fn tokenize(source: &str, filename: String) -> Result<Vec<Token>, LispErrors> {
-    let tokenizer = Tokenizer::new(source, filename);
-    tokenizer.tokenize()
+impl<'a, R: Read> TokenStream<'a, ReadChars<R>> {
+    /// Lexes UTF-8 source from a reader, reading it as the tokens are needed.
+    pub fn from_reader(reader: R, filename: impl Into<Shared<str>>, options: &'a Options) -> Self {
+        TokenStream::from_results(ReadChars::new(reader), filename, options)
+    }
+}
+
+impl<'a, I: Iterator<Item = io::Result<char>>> TokenStream<'a, I> {
+    fn from_results(chars: I, filename: impl Into<Shared<str>>, options: &'a Options) -> Self {
+        TokenStream {
+            chars: chars.peekable(),
+            tokenizer: Tokenizer::new(filename.into(), options),
+            finished: false,
+        }
+    }
+
+    /// The edition of the source, which can change once the stream has read a `(pale-edition N)` directive at its start.
+    pub fn edition(&self) -> Edition {
+        self.tokenizer.edition
+    }
+
+    /// The warnings produced so far. Most of them are only known once the stream has ended.
+    pub fn warnings(&self) -> &LispErrors {
+        &self.tokenizer.warnings
+    }
+
+    pub fn take_warnings(&mut self) -> LispErrors {
+        mem::take(&mut self.tokenizer.warnings)
+    }
+
+    // Keeps the comments and the edition directive, which are otherwise thrown away.
+    pub(crate) fn keep_trivia(mut self) -> Self {
+        self.tokenizer.trivia = Some(Vec::new());
+        self
+    }
+
+    pub(crate) fn take_trivia(&mut self) -> Vec<Trivia> {
+        self.tokenizer.trivia.take().unwrap_or_default()
+    }
+
+    fn advance(&mut self) -> Result<(), LispErrors> {
+        match self.chars.next() {
+            Some(Ok(c)) => {
+                let next = match self.chars.peek() {
+                    Some(Ok(n)) => Some(*n),
+                    _ => None,
+                };
+                if self.tokenizer.feed(c, next)? {
+                    self.chars.next();
+                }
+                Ok(())
+            }
+            Some(Err(e)) => {
+                self.tokenizer.pos = self.tokenizer.cursor;
+                Err(LispErrors::new().error(
+                    &self.tokenizer.loc(),
+                    format!("Could not read the source: {e}"),
+                ))
+            }
+            None => {
+                self.finished = true;
+                self.tokenizer.finish()
+            }
+        }
+    }
+}
+
+impl<'a, I: Iterator<Item = io::Result<char>>> Iterator for TokenStream<'a, I> {
+    type Item = Result<Token, LispErrors>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if !self.tokenizer.preamble || self.finished {
+                if let Some(tok) = self.tokenizer.tokens.pop_front() {
+                    return Some(Ok(tok));
+                }
+                if self.finished {
+                    return None;
+                }
+            }
+            if let Err(e) = self.advance().and_then(|_| self.tokenizer.check_preamble()) {
+                self.finished = true;
+                self.tokenizer.tokens.clear();
+                return Some(Err(e.syntax()));
+            }
+        }
+    }
+}
+
+/// Decodes the characters of UTF-8 text from a reader.
+pub struct ReadChars<R: Read> {
+    bytes: io::Bytes<BufReader<R>>,
+}
+
+impl<R: Read> ReadChars<R> {
+    pub fn new(reader: R) -> Self {
+        ReadChars {
+            bytes: BufReader::new(reader).bytes(),
+        }
+    }
+}
+
+impl<R: Read> Iterator for ReadChars<R> {
+    type Item = io::Result<char>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = match self.bytes.next()? {
+            Ok(b) => b,
+            Err(e) => return Some(Err(e)),
+        };
+        let len = match first.leading_ones() {
+            0 => 1,
+            n @ 2..=4 => n as usize,
+            _ => 0,
+        };
+        let mut buf = [first, 0, 0, 0];
+        for b in buf.iter_mut().take(len).skip(1) {
+            match self.bytes.next() {
+                Some(Ok(byte)) => *b = byte,
+                Some(Err(e)) => return Some(Err(e)),
+                None => break,
+            }
+        }
+        let invalid =
+            || io::Error::new(io::ErrorKind::InvalidData, "the source is not valid UTF-8");
+        Some(
+            std::str::from_utf8(&buf[..len])
+                .ok()
+                .and_then(|s| s.chars().next())
+                .ok_or_else(invalid),
+        )
+    }
+}
+
+/// The tokens of a whole source, along with everything else learnt while lexing it.
+#[derive(Debug)]
+pub(crate) struct Tokenized {
+    pub(crate) tokens: Vec<Token>,
+    pub(crate) warnings: LispErrors,
+    pub(crate) edition: Edition,
+    pub(crate) trivia: Vec<Trivia>,
+}
+
+/// Whether the source stops partway through a statement, string or block comment, so that more of it could complete it.
+///
+/// Other mistakes, like a stray `)`, don't count, since more source can't fix them.
+/// Statements are never left open when `friendly_parens` is on, because they are closed at the end of the source.
+pub fn is_incomplete(source: &str, options: &Options) -> bool {
+    let mut tokenizer = Tokenizer::new("-".into(), options);
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match tokenizer.feed(c, chars.peek().copied()) {
+            Ok(true) => {
+                chars.next();
+            }
+            Ok(false) => {}
+            Err(_) => return false,
+        }
+        tokenizer.tokens.clear();
+    }
+    match tokenizer.status {
+        TokenizerStatus::String
+        | TokenizerStatus::Interpolated
+        | TokenizerStatus::DispatchString
+        | TokenizerStatus::Comment => true,
+        _ => {
+            let interpolating = tokenizer.delims.iter().any(|(open, _)| *open == '#');
+            interpolating || (!options.friendly_parens && !tokenizer.delims.is_empty())
+        }
+    }
+}
+
+/// The tokens and comments of as much of a source as could be lexed, for tools that work on source that is still being written.
+pub(crate) struct Partial {
+    pub(crate) tokens: Vec<Token>,
+    pub(crate) trivia: Vec<Trivia>,
+    /// Where the string or block comment that the source stops inside of started, if it does.
+    pub(crate) unfinished: Option<(Location, Highlight)>,
+}
+
+/// Lexes the source up to the end or the first mistake, whichever comes first, instead of failing.
+pub(crate) fn tokenize_partial(source: &str, options: &Options) -> Partial {
+    let mut tokenizer = Tokenizer::new("-".into(), options);
+    tokenizer.trivia = Some(Vec::new());
+    let mut chars = source.chars().peekable();
+    let mut failed = false;
+    while let Some(c) = chars.next() {
+        match tokenizer.feed(c, chars.peek().copied()) {
+            Ok(true) => {
+                chars.next();
+            }
+            Ok(false) => {}
+            Err(_) => {
+                failed = true;
+                break;
+            }
+        }
+    }
+    let unfinished = match tokenizer.status {
+        TokenizerStatus::String | TokenizerStatus::Dispatch | TokenizerStatus::DispatchString => {
+            Some((tokenizer.loc(), Highlight::String))
+        }
+        TokenizerStatus::Comment => tokenizer
+            .comments
+            .first()
+            .map(|loc| (loc.clone(), Highlight::Comment)),
+        _ => None,
+    };
+    if !failed && unfinished.is_none() {
+        // Only the last token is wanted, so anything that finishing finds wrong doesn't matter.
+        let _ = tokenizer.finish();
+    }
+    Partial {
+        tokens: tokenizer.tokens.into(),
+        trivia: tokenizer.trivia.unwrap_or_default(),
+        unfinished,
+    }
+}
+
+#[allow(dead_code)]
+pub fn tokenize(source: &str, filename: impl Into<Shared<str>>) -> Result<Vec<Token>, LispErrors> {
+    tokenize_with(source, filename, &Options::default()).map(|t| t.tokens)
+}
+
+pub(crate) fn tokenize_with(
+    source: &str,
+    filename: impl Into<Shared<str>>,
+    options: &Options,
+) -> Result<Tokenized, LispErrors> {
+    let stream = TokenStream::new(source.chars(), filename, options);
+    collect_tokens(stream, guess_capacity(source))
+}
+
+/// Like `tokenize_with`, but keeping the comments for tools that print the source back out.
+pub(crate) fn tokenize_with_trivia(
+    source: &str,
+    filename: impl Into<Shared<str>>,
+    options: &Options,
+) -> Result<Tokenized, LispErrors> {
+    let stream = TokenStream::new(source.chars(), filename, options).keep_trivia();
+    collect_tokens(stream, guess_capacity(source))
+}
+
+// About how many tokens the source will have, so that they don't have to be moved as they are collected.
+// Typical code has a token for every four or five bytes, so this overestimates a little rather than growing at the end.
+fn guess_capacity(source: &str) -> usize {
+    source.len() / 4 + 1
+}
+
+fn collect_tokens<I: Iterator<Item = io::Result<char>>>(
+    mut stream: TokenStream<'_, I>,
+    capacity: usize,
+) -> Result<Tokenized, LispErrors> {
+    let mut tokens = Vec::with_capacity(capacity);
+    for tok in stream.by_ref() {
+        tokens.push(tok?);
+    }
+    Ok(Tokenized {
+        tokens,
+        edition: stream.edition(),
+        warnings: stream.take_warnings(),
+        trivia: stream.take_trivia(),
+    })
 }
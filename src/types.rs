@@ -1,29 +1,172 @@
-use crate::ast::{Statement, Var};
+use crate::ast::Var;
 use crate::callable::Callable;
+use crate::channel::Channel;
+use crate::options::PrintLimits;
+use crate::port::Port;
+use crate::promise::Promise;
+use crate::socket::Socket;
+use crate::sync::Shared;
 use std::fmt::Display;
 
 #[derive(Debug)]
 pub(crate) enum LispType {
     Integer(isize),
     Str(String),
+    Keyword(String), // Written as `:name`, and always evaluates to itself
     Func(Box<dyn Callable>),
-    Statement(Statement),
-    #[allow(dead_code)]
+    Var(Var), // The slot that a name is bound to, which refers to its value
     List(Vec<Var>),
+    Pair(Var, Var), // A cons cell, which is only made when the second half isn't a list
+    Map(Vec<(Var, Var)>),
+    Values(Vec<Var>), // Several values given at once by `values`, for `let-values` to bind
+    Bytes(Vec<u8>),   // Written as `#u8(1 2 3)`
+    Socket(Shared<Socket>),
+    Port(Shared<Port>),
+    Channel(Shared<Channel>),
+    Promise(Shared<Promise>),
+    Date(i64),     // Milliseconds since 1970-01-01T00:00:00Z
+    Duration(i64), // Milliseconds
     Floating(f64),
     Nil,
     // TODO(#2): Add custom newtypes.
 }
 
+/// What kind of value something is, which `type-of` gives the name of and the predicates like `integer?` check for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum TypeName {
+    Integer,
+    Float,
+    String,
+    Keyword,
+    Nil,
+    List,
+    Pair,
+    Map,
+    Function,
+    Values,
+    Bytes,
+    Socket,
+    Port,
+    Channel,
+    Promise,
+    Date,
+    Duration,
+}
+
+impl TypeName {
+    pub(crate) const ALL: [TypeName; 17] = [
+        TypeName::Integer,
+        TypeName::Float,
+        TypeName::String,
+        TypeName::Keyword,
+        TypeName::Nil,
+        TypeName::List,
+        TypeName::Pair,
+        TypeName::Map,
+        TypeName::Function,
+        TypeName::Values,
+        TypeName::Bytes,
+        TypeName::Socket,
+        TypeName::Port,
+        TypeName::Channel,
+        TypeName::Promise,
+        TypeName::Date,
+        TypeName::Duration,
+    ];
+
+    /// The name that `type-of` gives as a keyword.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            TypeName::Integer => "integer",
+            TypeName::Float => "float",
+            TypeName::String => "string",
+            TypeName::Keyword => "keyword",
+            TypeName::Nil => "nil",
+            TypeName::List => "list",
+            TypeName::Pair => "pair",
+            TypeName::Map => "map",
+            TypeName::Function => "function",
+            TypeName::Values => "values",
+            TypeName::Bytes => "bytes",
+            TypeName::Socket => "socket",
+            TypeName::Port => "port",
+            TypeName::Channel => "channel",
+            TypeName::Promise => "promise",
+            TypeName::Date => "date",
+            TypeName::Duration => "duration",
+        }
+    }
+
+    /// The name of the intrinsic that checks for it.
+    pub(crate) fn predicate(self) -> &'static str {
+        match self {
+            TypeName::Integer => "integer?",
+            TypeName::Float => "float?",
+            TypeName::String => "string?",
+            TypeName::Keyword => "keyword?",
+            TypeName::Nil => "nil?",
+            TypeName::List => "list?",
+            TypeName::Pair => "pair?",
+            TypeName::Map => "map?",
+            TypeName::Function => "callable?",
+            TypeName::Values => "values?",
+            TypeName::Bytes => "bytes?",
+            TypeName::Socket => "socket?",
+            TypeName::Port => "port?",
+            TypeName::Channel => "channel?",
+            TypeName::Promise => "promise?",
+            TypeName::Date => "date?",
+            TypeName::Duration => "duration?",
+        }
+    }
+}
+
+impl LispType {
+    /// What kind of value this is, looking through the slots that names are bound to.
+    pub(crate) fn type_name(&self) -> TypeName {
+        match self {
+            LispType::Integer(_) => TypeName::Integer,
+            LispType::Floating(_) => TypeName::Float,
+            LispType::Str(_) => TypeName::String,
+            LispType::Keyword(_) => TypeName::Keyword,
+            LispType::Nil => TypeName::Nil,
+            LispType::List(_) => TypeName::List,
+            LispType::Pair(..) => TypeName::Pair,
+            LispType::Map(_) => TypeName::Map,
+            LispType::Func(_) => TypeName::Function,
+            LispType::Values(_) => TypeName::Values,
+            LispType::Bytes(_) => TypeName::Bytes,
+            LispType::Socket(_) => TypeName::Socket,
+            LispType::Port(_) => TypeName::Port,
+            LispType::Channel(_) => TypeName::Channel,
+            LispType::Promise(_) => TypeName::Promise,
+            LispType::Date(_) => TypeName::Date,
+            LispType::Duration(_) => TypeName::Duration,
+            LispType::Var(v) => v.resolve().get().type_name(),
+        }
+    }
+}
+
 impl Clone for LispType {
     fn clone(&self) -> Self {
         match self {
-            Self::Integer(item) => Self::Integer(item.clone()),
+            Self::Integer(item) => Self::Integer(*item),
             Self::Str(item) => Self::Str(item.clone()),
+            Self::Keyword(item) => Self::Keyword(item.clone()),
             Self::Func(_) => panic!("Tried to clone a function! If you see this, this is an internal error and you should report it at <https://github.com/FeistyKit/pale/issues/new>!"),
-            Self::Statement(_) => panic!("Tried to clone a statement! If you see this, this is an internal error and you should report it at <https://github.com/FeistyKit/pale/issues/new>!"),
+            Self::Var(v) => Self::Var(v.new_ref()),
             Self::List(_) => panic!("Tried to clone a list! If you see this, this is an internal error and you should report it at <https://github.com/FeistyKit/pale/issues/new>!"),
-            Self::Floating(item) => Self::Floating(item.clone()),
+            Self::Pair(..) => panic!("Tried to clone a pair! If you see this, this is an internal error and you should report it at <https://github.com/FeistyKit/pale/issues/new>!"),
+            Self::Map(_) => panic!("Tried to clone a map! If you see this, this is an internal error and you should report it at <https://github.com/FeistyKit/pale/issues/new>!"),
+            Self::Values(_) => panic!("Tried to clone multiple values! If you see this, this is an internal error and you should report it at <https://github.com/FeistyKit/pale/issues/new>!"),
+            Self::Bytes(item) => Self::Bytes(item.clone()),
+            Self::Socket(socket) => Self::Socket(socket.clone()),
+            Self::Port(port) => Self::Port(port.clone()),
+            Self::Channel(channel) => Self::Channel(channel.clone()),
+            Self::Promise(promise) => Self::Promise(promise.clone()),
+            Self::Date(millis) => Self::Date(*millis),
+            Self::Duration(millis) => Self::Duration(*millis),
+            Self::Floating(item) => Self::Floating(*item),
             Self::Nil => Self::Nil,
         }
     }
@@ -31,52 +174,203 @@ impl Clone for LispType {
 
 const FLOATING_EQ_RANGE: f64 = 0.001; // If two floats are less than this far apart, they are considered equal
 
+// These are the rules that `equal?` follows, and that `eqv?` follows for everything but lists, pairs, maps and functions.
 impl PartialEq for LispType {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (&LispType::Integer(lhs), &LispType::Integer(rhs)) => lhs == rhs,
             (LispType::Str(lhs), LispType::Str(rhs)) => lhs == rhs,
-            (LispType::Statement(lhs), LispType::Statement(rhs)) => lhs == rhs,
+            (LispType::Keyword(lhs), LispType::Keyword(rhs)) => lhs == rhs,
+            (LispType::Bytes(lhs), LispType::Bytes(rhs)) => lhs == rhs,
+            (LispType::Socket(lhs), LispType::Socket(rhs)) => Shared::ptr_eq(lhs, rhs),
+            (LispType::Port(lhs), LispType::Port(rhs)) => Shared::ptr_eq(lhs, rhs),
+            (LispType::Channel(lhs), LispType::Channel(rhs)) => Shared::ptr_eq(lhs, rhs),
+            (LispType::Promise(lhs), LispType::Promise(rhs)) => Shared::ptr_eq(lhs, rhs),
+            (LispType::Date(lhs), LispType::Date(rhs)) => lhs == rhs,
+            (LispType::Duration(lhs), LispType::Duration(rhs)) => lhs == rhs,
             (LispType::Func(_), LispType::Func(_)) => false,
             (LispType::Nil, LispType::Nil) => true,
             (LispType::Floating(lhs), LispType::Floating(rhs)) => {
                 (lhs - rhs).abs() < FLOATING_EQ_RANGE
             }
             (LispType::List(lhs), LispType::List(rhs)) => lhs == rhs,
+            (LispType::Values(lhs), LispType::Values(rhs)) => lhs == rhs,
+            (LispType::Pair(lcar, lcdr), LispType::Pair(rcar, rcdr)) => {
+                lcar == rcar && lcdr == rcdr
+            }
+            (LispType::Map(lhs), LispType::Map(rhs)) => {
+                lhs.len() == rhs.len()
+                    && lhs
+                        .iter()
+                        .all(|(k, v)| rhs.iter().any(|(rk, rv)| k == rk && v == rv))
+            }
+            (LispType::Var(lhs), rhs) => *lhs.resolve().get() == *rhs,
+            (lhs, LispType::Var(rhs)) => *lhs == *rhs.resolve().get(),
             // TODOO(#10): Comparing floats and integers
             _ => false,
         }
     }
 }
 
-impl LispType {
-    pub(crate) fn unwrap_func(&self) -> &dyn Callable {
-        match self {
-            LispType::Func(f) => f.as_ref(),
-            _ => panic!("Expected to be LispType::Func but was actually {self}!"),
-        }
+impl Display for LispType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_value(self, PrintLimits::NONE, f)
     }
 }
 
-impl Display for LispType {
+/// Displays a value within some print limits, eliding whatever goes past them.
+pub(crate) struct Limited<'a> {
+    pub(crate) value: &'a LispType,
+    pub(crate) limits: PrintLimits,
+}
+
+impl Display for Limited<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            LispType::Integer(i) => write!(f, "{i}"),
-            LispType::Str(s) => write!(f, "{s}"),
-            LispType::Func(_) => write!(f, "<Function>"),
-            LispType::Statement(s) => match s.resolve() {
-                Ok(s) => write!(f, "{s}"),
-                Err(e) => write!(f, "{e}"),
-            },
-            LispType::List(l) => {
-                let mut t = String::new();
-                for item in l {
-                    t = format!("{t} {item}");
+        write_value(self.value, self.limits, f)
+    }
+}
+
+// What is left to write of a value, last first.
+enum Piece {
+    Value(Var, usize), // A value inside of a list, pair or map, and how deeply it is nested
+    Text(&'static str),
+    More(usize), // How many items were elided
+}
+
+// Writes the value within the limits.
+// The values inside of it are written one after another rather than inside each other, so that deeply nested values don't run out of Rust stack.
+fn write_value(
+    value: &LispType,
+    limits: PrintLimits,
+    f: &mut std::fmt::Formatter<'_>,
+) -> std::fmt::Result {
+    let mut pieces = Vec::new();
+    write_piece(value, 0, limits, &mut pieces, f)?;
+    while let Some(piece) = pieces.pop() {
+        match piece {
+            Piece::Value(var, depth) => {
+                let var = var.resolve();
+                write_piece(&var.get(), depth, limits, &mut pieces, f)?;
+            }
+            Piece::Text(text) => f.write_str(text)?,
+            Piece::More(more) => write!(f, " ... {more} more")?,
+        }
+    }
+    Ok(())
+}
+
+// Writes the start of the value, leaving what it holds in the pieces to be written next.
+fn write_piece(
+    value: &LispType,
+    depth: usize,
+    limits: PrintLimits,
+    pieces: &mut Vec<Piece>,
+    f: &mut std::fmt::Formatter<'_>,
+) -> std::fmt::Result {
+    let too_deep = limits.depth.is_some_and(|d| depth >= d);
+    // How many of the items to print, and how many are left over.
+    let shown = |len: usize| {
+        let shown = limits.length.map_or(len, |l| l.min(len));
+        (shown, len - shown)
+    };
+    match value {
+        LispType::Integer(i) => write!(f, "{i}"),
+        LispType::Str(s) => write!(f, "{s}"),
+        LispType::Keyword(k) => write!(f, ":{k}"),
+        LispType::Func(_) => write!(f, "<Function>"),
+        LispType::Var(v) => {
+            pieces.push(Piece::Value(v.new_ref(), depth));
+            Ok(())
+        }
+        LispType::List(_) | LispType::Pair(..) if too_deep => write!(f, "(...)"),
+        LispType::Map(_) if too_deep => write!(f, "{{...}}"),
+        LispType::List(l) => {
+            let (shown, more) = shown(l.len());
+            pieces.push(Piece::Text(")"));
+            if more != 0 {
+                pieces.push(Piece::More(more));
+            }
+            push_items(l[..shown].iter().map(Var::new_ref), depth + 1, pieces);
+            write!(f, "(")
+        }
+        LispType::Pair(car, cdr) => {
+            let mut items = vec![car.new_ref()];
+            let mut tail = None;
+            let mut rest = cdr.new_ref();
+            loop {
+                let next = match &*rest.get() {
+                    LispType::Pair(car, cdr) => {
+                        items.push(car.new_ref());
+                        cdr.new_ref()
+                    }
+                    LispType::List(l) => {
+                        items.extend(l.iter().map(Var::new_ref));
+                        break;
+                    }
+                    LispType::Nil => break,
+                    _ => {
+                        tail = Some(rest.new_ref());
+                        break;
+                    }
+                };
+                rest = next;
+            }
+            let (shown, more) = shown(items.len());
+            pieces.push(Piece::Text(")"));
+            match tail {
+                Some(tail) if more == 0 => {
+                    pieces.push(Piece::Value(tail, depth + 1));
+                    pieces.push(Piece::Text(" . "));
+                }
+                _ if more != 0 => pieces.push(Piece::More(more)),
+                _ => {}
+            }
+            items.truncate(shown);
+            push_items(items.into_iter(), depth + 1, pieces);
+            write!(f, "(")
+        }
+        LispType::Map(m) => {
+            let (shown, more) = shown(m.len());
+            pieces.push(Piece::Text("}"));
+            if more != 0 {
+                pieces.push(Piece::More(more));
+            }
+            for (i, (k, v)) in m[..shown].iter().enumerate().rev() {
+                pieces.push(Piece::Value(v.new_ref(), depth + 1));
+                pieces.push(Piece::Text(" "));
+                pieces.push(Piece::Value(k.new_ref(), depth + 1));
+                if i != 0 {
+                    pieces.push(Piece::Text(" "));
                 }
-                write!(f, "({t})")
             }
-            LispType::Floating(fl) => write!(f, "{fl}"),
-            LispType::Nil => write!(f, "nil"),
+            write!(f, "{{")
+        }
+        LispType::Values(values) => {
+            push_items(values.iter().map(Var::new_ref), depth, pieces);
+            Ok(())
+        }
+        LispType::Bytes(bytes) => {
+            let items: Vec<String> = bytes.iter().map(|b| b.to_string()).collect();
+            write!(f, "#u8({})", items.join(" "))
+        }
+        LispType::Socket(socket) => write!(f, "{socket}"),
+        LispType::Port(port) => write!(f, "{port}"),
+        LispType::Channel(channel) => write!(f, "{channel}"),
+        LispType::Promise(promise) => write!(f, "{promise}"),
+        LispType::Date(millis) => crate::dates::display_date(*millis, f),
+        LispType::Duration(millis) => crate::dates::display_duration(*millis, f),
+        LispType::Floating(fl) => write!(f, "{fl}"),
+        LispType::Nil => write!(f, "nil"),
+    }
+}
+
+// Leaves the items to be written next, with spaces between them.
+fn push_items(items: impl DoubleEndedIterator<Item = Var>, depth: usize, pieces: &mut Vec<Piece>) {
+    let mut items = items.rev().peekable();
+    while let Some(item) = items.next() {
+        pieces.push(Piece::Value(item, depth));
+        if items.peek().is_some() {
+            pieces.push(Piece::Text(" "));
         }
     }
 }
@@ -101,9 +395,9 @@ impl<T: Callable + 'static> From<T> for LispType {
         LispType::Func(Box::new(i))
     }
 }
-impl From<Statement> for LispType {
-    fn from(i: Statement) -> Self {
-        LispType::Statement(i)
+impl From<Vec<u8>> for LispType {
+    fn from(i: Vec<u8>) -> Self {
+        LispType::Bytes(i)
     }
 }
 impl From<f64> for LispType {
@@ -0,0 +1,353 @@
+use std::fmt::Display;
+
+use crate::ast::Var;
+use crate::types::LispType;
+
+/// A value that was made by evaluating pale code, or that the host made to give to it.
+///
+/// Values are shared rather than copied, so cloning one is cheap.
+/// They can be made from Rust's numbers, strings and vectors with `From`, and turned back into them with `TryFrom`.
+#[derive(Debug)]
+pub struct Value {
+    var: Var,
+}
+
+impl Value {
+    pub(crate) fn new(var: Var) -> Self {
+        Self { var: var.resolve() }
+    }
+
+    pub(crate) fn var(&self) -> &Var {
+        &self.var
+    }
+
+    pub fn nil() -> Self {
+        Self::new(Var::new(LispType::Nil))
+    }
+
+    /// The keyword literal that is written as `:name`.
+    pub fn keyword(name: &str) -> Self {
+        Self::new(Var::new(LispType::Keyword(name.to_string())))
+    }
+
+    pub fn is_nil(&self) -> bool {
+        matches!(*self.var.get(), LispType::Nil)
+    }
+
+    /// What the `:name` key of a map is, or `nil` if it doesn't have one.
+    pub fn field(&self, name: &str) -> Result<Value, ConversionError> {
+        let key = LispType::Keyword(name.to_string());
+        match &*self.var.get() {
+            LispType::Map(entries) => Ok(entries
+                .iter()
+                .find(|(k, _)| *k.get() == key)
+                .map_or_else(Value::nil, |(_, v)| Value::new(v.new_ref()))),
+            _ => Err(self.mismatch("a map")),
+        }
+    }
+}
+
+// Clones share the value rather than copying it, the same as binding it to another name does.
+impl Clone for Value {
+    fn clone(&self) -> Self {
+        Self {
+            var: self.var.new_ref(),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.var)
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        *self.var.get() == *other.var.get()
+    }
+}
+
+impl From<i32> for Value {
+    fn from(i: i32) -> Self {
+        Self::new(Var::new(i as isize))
+    }
+}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Self::new(Var::new(i as isize))
+    }
+}
+
+impl From<f64> for Value {
+    fn from(f: f64) -> Self {
+        Self::new(Var::new(f))
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Self::new(Var::new(s))
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Self::new(Var::new(s))
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(items: Vec<Value>) -> Self {
+        let items = items.into_iter().map(|v| v.var).collect();
+        Self::new(Var::new(LispType::List(items)))
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::new(Var::new(bytes))
+    }
+}
+
+/// Makes a map from its keys and values.
+impl From<Vec<(Value, Value)>> for Value {
+    fn from(entries: Vec<(Value, Value)>) -> Self {
+        let entries = entries.into_iter().map(|(k, v)| (k.var, v.var)).collect();
+        Self::new(Var::new(LispType::Map(entries)))
+    }
+}
+
+/// `None` becomes `nil`.
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(o: Option<T>) -> Self {
+        o.map_or_else(Value::nil, Into::into)
+    }
+}
+
+/// Why a [`Value`] couldn't be turned into a Rust type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConversionError {
+    /// What the value would have had to be, like "an integer".
+    pub expected: &'static str,
+    /// The value, as it is printed.
+    pub found: String,
+}
+
+impl Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "expected {}, but found `{}`", self.expected, self.found)
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+impl Value {
+    pub(crate) fn mismatch(&self, expected: &'static str) -> ConversionError {
+        ConversionError {
+            expected,
+            found: self.to_string(),
+        }
+    }
+}
+
+impl TryFrom<Value> for i64 {
+    type Error = ConversionError;
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        if let LispType::Integer(i) = *value.var.get() {
+            return Ok(i as i64);
+        }
+        Err(value.mismatch("an integer"))
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = ConversionError;
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        if let LispType::Floating(f) = *value.var.get() {
+            return Ok(f);
+        }
+        Err(value.mismatch("a float"))
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = ConversionError;
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        if let LispType::Str(s) = &*value.var.get() {
+            return Ok(s.clone());
+        }
+        Err(value.mismatch("a string"))
+    }
+}
+
+impl TryFrom<Value> for Vec<Value> {
+    type Error = ConversionError;
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        if let LispType::List(items) = &*value.var.get() {
+            return Ok(items.iter().map(|v| Value::new(v.new_ref())).collect());
+        }
+        Err(value.mismatch("a list"))
+    }
+}
+
+impl TryFrom<Value> for Vec<u8> {
+    type Error = ConversionError;
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        if let LispType::Bytes(bytes) = &*value.var.get() {
+            return Ok(bytes.clone());
+        }
+        Err(value.mismatch("bytes"))
+    }
+}
+
+impl TryFrom<Value> for Vec<(Value, Value)> {
+    type Error = ConversionError;
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        if let LispType::Map(entries) = &*value.var.get() {
+            return Ok(entries
+                .iter()
+                .map(|(k, v)| (Value::new(k.new_ref()), Value::new(v.new_ref())))
+                .collect());
+        }
+        Err(value.mismatch("a map"))
+    }
+}
+
+/// Values are written the way `json-stringify` writes them: `nil` as a unit, `:true` as `true`,
+/// other keywords as strings, lists as sequences and maps as maps. Bytes are written as bytes,
+/// and dates as strings like `2024-03-01T09:30:00Z`. Pairs, functions, sockets, ports and durations can't be written, and are an error.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Value {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::{Error, SerializeMap, SerializeSeq};
+        match &*self.var.get() {
+            LispType::Nil => serializer.serialize_unit(),
+            LispType::Keyword(k) if k == "true" => serializer.serialize_bool(true),
+            LispType::Keyword(k) | LispType::Str(k) => serializer.serialize_str(k),
+            LispType::Integer(i) => serializer.serialize_i64(*i as i64),
+            LispType::Floating(f) => serializer.serialize_f64(*f),
+            LispType::Bytes(bytes) => serializer.serialize_bytes(bytes),
+            LispType::List(items) | LispType::Values(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(&Value::new(item.new_ref()))?;
+                }
+                seq.end()
+            }
+            LispType::Map(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (k, v) in entries {
+                    map.serialize_entry(&Value::new(k.new_ref()), &Value::new(v.new_ref()))?;
+                }
+                map.end()
+            }
+            LispType::Date(_) => serializer.collect_str(&*self.var.get()),
+            other @ (LispType::Pair(..)
+            | LispType::Func(_)
+            | LispType::Socket(_)
+            | LispType::Port(_)
+            | LispType::Channel(_)
+            | LispType::Promise(_)
+            | LispType::Duration(_)) => {
+                Err(S::Error::custom(format!("`{other}` can't be serialized")))
+            }
+            LispType::Var(_) => unreachable!("Values are resolved when they are made"),
+        }
+    }
+}
+
+/// Values are read the way `json-parse` reads them: `true` becomes `:true`, both `false` and
+/// units like `null` become `nil`, sequences become lists and maps become maps.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Value {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+#[cfg(feature = "serde")]
+struct ValueVisitor;
+
+#[cfg(feature = "serde")]
+impl<'de> serde::de::Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "a value that pale has a type for")
+    }
+
+    fn visit_bool<E>(self, b: bool) -> Result<Value, E> {
+        Ok(match b {
+            true => Value::keyword("true"),
+            false => Value::nil(),
+        })
+    }
+
+    fn visit_i64<E>(self, i: i64) -> Result<Value, E> {
+        Ok(match isize::try_from(i) {
+            Ok(i) => Value::new(Var::new(i)),
+            Err(_) => Value::from(i as f64),
+        })
+    }
+
+    // Integers that are too big for an integer become floats, like they do in `json-parse`.
+    fn visit_u64<E>(self, u: u64) -> Result<Value, E> {
+        Ok(match isize::try_from(u) {
+            Ok(i) => Value::new(Var::new(i)),
+            Err(_) => Value::from(u as f64),
+        })
+    }
+
+    fn visit_f64<E>(self, f: f64) -> Result<Value, E> {
+        Ok(Value::from(f))
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<Value, E> {
+        Ok(Value::from(s))
+    }
+
+    fn visit_string<E>(self, s: String) -> Result<Value, E> {
+        Ok(Value::from(s))
+    }
+
+    fn visit_bytes<E>(self, bytes: &[u8]) -> Result<Value, E> {
+        Ok(Value::from(bytes.to_vec()))
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::nil())
+    }
+
+    fn visit_none<E>(self) -> Result<Value, E> {
+        Ok(Value::nil())
+    }
+
+    fn visit_some<D: serde::Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        serde::Deserialize::deserialize(deserializer)
+    }
+
+    fn visit_newtype_struct<D: serde::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Value, D::Error> {
+        serde::Deserialize::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element::<Value>()? {
+            items.push(item);
+        }
+        Ok(Value::from(items))
+    }
+
+    fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut entries = Vec::new();
+        while let Some(entry) = map.next_entry::<Value, Value>()? {
+            entries.push(entry);
+        }
+        Ok(Value::from(entries))
+    }
+}
@@ -0,0 +1,339 @@
+use crate::ast::{Expr, Lambda, Locals, Loop, Module, Pattern, Place, Var};
+use crate::callable::Function;
+use crate::error::LispErrors;
+use crate::eval::{call, looped, parameterize, returned, unparameterize, unwinding, Called};
+use crate::promise::{self, Promise};
+use crate::sync::Shared;
+use crate::types::LispType;
+use crate::Location;
+
+/// One instruction for the VM.
+#[derive(Debug)]
+pub(crate) enum Op {
+    Const(LispType),
+    Load(Var),
+    /// Loads the value in the slot of the frame of the call that is being run.
+    LoadLocal(usize),
+    /// Calls the function on the value stack with the arguments after it, and then the value after a `.` if there is one.
+    Call {
+        args: usize,
+        dotted: bool,
+        loc: Location,
+        arg_locs: Shared<[Location]>,
+    },
+    /// Binds the value on top of the stack to the pattern, taking it off of the stack.
+    Bind(Shared<Pattern>),
+    Lambda(Shared<Lambda>),
+    /// Runs the module unless it has already been run, leaving `nil`.
+    Import(Shared<Module>),
+    /// Gives the parameters on the stack the values after each of them, taking them all off of the stack.
+    Parameterize {
+        count: usize,
+        loc: Location,
+    },
+    /// Puts back what the last parameters that were given values were before.
+    Unparameterize(usize),
+    /// Starts a `do` loop with the starting values of its variables, which are taken off of the stack.
+    Enter(Shared<Loop>),
+    /// Goes around the loop again with the next values of its variables, which are taken off of the stack.
+    Next(Shared<Loop>),
+    /// Puts back the frame from around the loop that was entered last.
+    Leave,
+    /// Takes the value on top of the stack off of it, and jumps to the instruction if it is `nil`.
+    JumpIfNil(usize),
+    Jump(usize),
+    Discard,
+}
+
+/// Compiled code, which leaves its value on the value stack when it has been run.
+#[derive(Debug, Default)]
+pub(crate) struct Code {
+    ops: Vec<Op>,
+}
+
+impl Code {
+    /// The code for the forms, which are run in order for the value of the last one.
+    pub(crate) fn program(forms: &[Shared<Expr>]) -> Self {
+        let mut code = Code::default();
+        for (i, form) in forms.iter().enumerate() {
+            if i != 0 {
+                code.ops.push(Op::Discard);
+            }
+            code.emit(form);
+        }
+        if forms.is_empty() {
+            code.ops.push(Op::Const(LispType::Nil));
+        }
+        code
+    }
+
+    /// The code for calling the function with the arguments.
+    pub(crate) fn call(op: &Var, args: &[Var], loc: &Location) -> Self {
+        let mut ops: Vec<Op> = std::iter::once(op)
+            .chain(args)
+            .map(|v| Op::Load(v.new_ref()))
+            .collect();
+        ops.push(Op::Call {
+            args: args.len(),
+            dotted: false,
+            loc: loc.clone(),
+            arg_locs: Shared::new([]),
+        });
+        Code { ops }
+    }
+
+    fn emit(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal(value) => self.ops.push(Op::Const(value.clone())),
+            Expr::Var(Place::Global(var)) => self.ops.push(Op::Load(var.new_ref())),
+            Expr::Var(Place::Local(slot, _)) => self.ops.push(Op::LoadLocal(*slot)),
+            Expr::Call {
+                op,
+                args,
+                tail,
+                loc,
+                arg_locs,
+            } => {
+                self.emit(op);
+                for arg in args.iter().chain(tail) {
+                    self.emit(arg);
+                }
+                self.ops.push(Op::Call {
+                    args: args.len(),
+                    dotted: tail.is_some(),
+                    loc: loc.clone(),
+                    arg_locs: Shared::clone(arg_locs),
+                });
+            }
+            Expr::Let { bindings, body } => {
+                for (pattern, value) in bindings {
+                    self.emit(value);
+                    self.ops.push(Op::Bind(Shared::clone(pattern)));
+                }
+                match body {
+                    Some(body) => self.emit(body),
+                    None => self.ops.push(Op::Const(LispType::Nil)),
+                }
+            }
+            Expr::Parameterize {
+                bindings,
+                body,
+                loc,
+            } => {
+                for (parameter, value) in bindings {
+                    self.emit(parameter);
+                    self.emit(value);
+                }
+                self.ops.push(Op::Parameterize {
+                    count: bindings.len(),
+                    loc: loc.clone(),
+                });
+                self.emit(body);
+                self.ops.push(Op::Unparameterize(bindings.len()));
+            }
+            Expr::If {
+                cond,
+                then,
+                otherwise,
+                ..
+            } => {
+                self.emit(cond);
+                let jump_to_otherwise = self.placeholder();
+                self.emit(then);
+                let jump_to_end = self.placeholder();
+                self.ops[jump_to_otherwise] = Op::JumpIfNil(self.ops.len());
+                match otherwise {
+                    Some(otherwise) => self.emit(otherwise),
+                    None => self.ops.push(Op::Const(LispType::Nil)),
+                }
+                self.ops[jump_to_end] = Op::Jump(self.ops.len());
+            }
+            Expr::Lambda(lambda) => self.ops.push(Op::Lambda(Shared::clone(lambda))),
+            Expr::Do(l) => {
+                for (_, init) in &l.vars {
+                    self.emit(init);
+                }
+                self.ops.push(Op::Enter(Shared::clone(l)));
+                let top = self.ops.len();
+                self.emit(&l.test);
+                let jump_to_body = self.placeholder();
+                match &l.result {
+                    Some(result) => self.emit(result),
+                    None => self.ops.push(Op::Const(LispType::Nil)),
+                }
+                self.ops.push(Op::Leave);
+                let jump_to_end = self.placeholder();
+                self.ops[jump_to_body] = Op::JumpIfNil(self.ops.len());
+                if let Some(body) = &l.body {
+                    self.emit(body);
+                    self.ops.push(Op::Discard);
+                }
+                for step in &l.steps {
+                    self.emit(step);
+                }
+                self.ops.push(Op::Next(Shared::clone(l)));
+                self.ops.push(Op::Jump(top));
+                self.ops[jump_to_end] = Op::Jump(self.ops.len());
+            }
+            Expr::Seq(exprs) => {
+                for (i, expr) in exprs.iter().enumerate() {
+                    if i != 0 {
+                        self.ops.push(Op::Discard);
+                    }
+                    self.emit(expr);
+                }
+                if exprs.is_empty() {
+                    self.ops.push(Op::Const(LispType::Nil));
+                }
+            }
+            Expr::Import(module) => self.ops.push(Op::Import(Shared::clone(module))),
+        }
+    }
+
+    // A jump whose target isn't known yet, giving where it is so that it can be filled in.
+    fn placeholder(&mut self) -> usize {
+        self.ops.push(Op::Discard);
+        self.ops.len() - 1
+    }
+}
+
+impl Lambda {
+    /// The body compiled for the VM, which is only compiled the first time it is needed.
+    fn compiled(&self) -> Shared<Code> {
+        Shared::clone(self.code.get_or_init(|| {
+            let mut code = Code::default();
+            code.emit(&self.body);
+            Shared::new(code)
+        }))
+    }
+}
+
+// A call whose function's body is being run, along with where to carry on from once it has finished.
+struct Frame {
+    code: Shared<Code>,
+    pc: usize,
+    locals: Locals, // The frame of the caller
+    outer: Option<Location>,
+    forcing: Option<Shared<Promise>>, // The promise that keeps the value, if the function is its
+}
+
+/// Runs the code, giving the value it leaves.
+pub(crate) fn run(code: Shared<Code>) -> Result<Var, LispErrors> {
+    unwinding(|| execute(code))
+}
+
+fn execute(mut code: Shared<Code>) -> Result<Var, LispErrors> {
+    let mut pc = 0;
+    let mut locals = Locals::default();
+    let mut frames: Vec<Frame> = Vec::new();
+    let mut values: Vec<Var> = Vec::new();
+    let mut loops: Vec<Locals> = Vec::new(); // The frames from around the loops that are being run
+    loop {
+        let Some(op) = code.ops.get(pc) else {
+            match frames.pop() {
+                Some(frame) => {
+                    returned(frame.outer);
+                    if let Some(promise) = frame.forcing {
+                        let forced = pop(&mut values);
+                        values.push(promise::fulfil(&promise, forced));
+                    }
+                    code = frame.code;
+                    pc = frame.pc;
+                    locals = frame.locals;
+                    continue;
+                }
+                None => break,
+            }
+        };
+        pc += 1;
+        match op {
+            Op::Const(value) => values.push(Var::new(value.clone())),
+            Op::Load(var) => values.push(var.resolve()),
+            Op::LoadLocal(slot) => values.push(locals[*slot].resolve()),
+            Op::Call {
+                args,
+                dotted,
+                loc,
+                arg_locs,
+            } => {
+                let tail = dotted.then(|| pop(&mut values));
+                let start = values.len() - args;
+                let called = call(
+                    &values[start - 1],
+                    &values[start..],
+                    tail.as_ref(),
+                    loc,
+                    arg_locs,
+                );
+                values.truncate(start - 1);
+                match called? {
+                    Called::Done(value) => values.push(value),
+                    Called::Body {
+                        lambda,
+                        locals: callee,
+                        outer,
+                        forcing,
+                    } => {
+                        let caller = std::mem::replace(&mut code, lambda.compiled());
+                        frames.push(Frame {
+                            code: caller,
+                            pc,
+                            locals: std::mem::replace(&mut locals, callee),
+                            outer,
+                            forcing,
+                        });
+                        pc = 0;
+                    }
+                }
+            }
+            Op::Bind(pattern) => pattern.bind(pop(&mut values), &locals)?,
+            Op::Lambda(lambda) => {
+                values.push(Var::new(Function::new(Shared::clone(lambda), &locals)))
+            }
+            Op::Import(module) => {
+                module.run()?;
+                values.push(Var::new(LispType::Nil));
+            }
+            Op::Parameterize { count, loc } => {
+                let start = values.len() - 2 * count;
+                parameterize(&values[start..], loc)?;
+                values.truncate(start);
+            }
+            Op::Unparameterize(count) => unparameterize(*count),
+            Op::Enter(l) => {
+                let start = values.len() - l.vars.len();
+                let frame = l.enter(&locals, &values[start..]);
+                values.truncate(start);
+                loops.push(std::mem::replace(&mut locals, frame));
+            }
+            Op::Next(l) => {
+                looped(&l.loc)?;
+                let start = values.len() - l.vars.len();
+                locals = l.next(&locals, &values[start..]);
+                values.truncate(start);
+            }
+            Op::Leave => {
+                locals = loops
+                    .pop()
+                    .expect("Every loop is left after it has been entered");
+            }
+            Op::JumpIfNil(target) => {
+                // Only `nil` counts as false.
+                if matches!(*pop(&mut values).get(), LispType::Nil) {
+                    pc = *target;
+                }
+            }
+            Op::Jump(target) => pc = *target,
+            Op::Discard => {
+                pop(&mut values);
+            }
+        }
+    }
+    Ok(pop(&mut values))
+}
+
+fn pop(values: &mut Vec<Var>) -> Var {
+    values
+        .pop()
+        .expect("Every instruction that needs a value comes after one that gives it")
+}